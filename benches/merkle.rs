@@ -0,0 +1,101 @@
+//! Compares building Merkle trees with every level paired on the calling
+//! thread and with the large levels hashed on all available threads, and
+//! the arena the tree is stored in with the boxed nodes it used to be made
+//! of.
+
+use std::{num::NonZeroUsize, thread};
+
+use blockchain_simulator::{
+    model::blockchain::{merkle_parent, Hash256, NULL_HASH},
+    node::miner::{construct_merkle_tree, construct_merkle_tree_on},
+};
+use criterion::{criterion_group, criterion_main, BatchSize, BenchmarkId, Criterion};
+
+fn leaf_hashes(count: usize) -> Vec<Hash256> {
+    (0..count as u32)
+        .map(|leaf| {
+            let mut hash = [0; 32];
+            hash[..4].copy_from_slice(&leaf.to_be_bytes());
+            Hash256(hash)
+        })
+        .collect()
+}
+
+fn construction(c: &mut Criterion) {
+    let threads = thread::available_parallelism().map_or(1, NonZeroUsize::get);
+    let mut group = c.benchmark_group("construct_merkle_tree");
+    group.sample_size(20);
+    for leaves in [5_000, 50_000, 200_000] {
+        let hashes = leaf_hashes(leaves);
+        for (name, threads) in [("sequential", 1), ("parallel", threads)] {
+            group.bench_with_input(BenchmarkId::new(name, leaves), &hashes, |b, hashes| {
+                b.iter_batched(
+                    || hashes.clone(),
+                    |hashes| construct_merkle_tree_on(hashes, threads),
+                    BatchSize::LargeInput,
+                )
+            });
+        }
+    }
+    group.finish();
+}
+
+/// Node of the Merkle tree as it was built before the arena, boxing copies
+/// of its children.
+#[derive(Clone)]
+struct BoxedNode {
+    hash: Hash256,
+    _left: Option<Box<BoxedNode>>,
+    _right: Option<Box<BoxedNode>>,
+}
+
+fn boxed_tree(transaction_hashes: Vec<Hash256>) -> BoxedNode {
+    let leaf = |hash| BoxedNode {
+        hash,
+        _left: None,
+        _right: None,
+    };
+    let mut nodes: Vec<BoxedNode> = transaction_hashes.into_iter().map(leaf).collect();
+    while nodes.len() > 1 {
+        if !nodes.len().is_multiple_of(2) {
+            nodes.push(leaf(NULL_HASH));
+        }
+        nodes = nodes
+            .chunks(2)
+            .map(|pair| BoxedNode {
+                hash: merkle_parent(&pair[0].hash, &pair[1].hash),
+                _left: Some(Box::new(pair[0].clone())),
+                _right: Some(Box::new(pair[1].clone())),
+            })
+            .collect();
+    }
+    nodes.remove(0)
+}
+
+fn arena(c: &mut Criterion) {
+    let mut group = c.benchmark_group("merkle_tree");
+    group.sample_size(20);
+    let hashes = leaf_hashes(50_000);
+    group.bench_function("boxed", |b| {
+        b.iter_batched(|| hashes.clone(), boxed_tree, BatchSize::LargeInput)
+    });
+    group.bench_function("arena", |b| {
+        b.iter_batched(
+            || hashes.clone(),
+            construct_merkle_tree,
+            BatchSize::LargeInput,
+        )
+    });
+    let tree = construct_merkle_tree(hashes).unwrap();
+    group.bench_function("sibling_hashes", |b| {
+        b.iter(|| {
+            (0..tree.leaves_count())
+                .map(|leaf| tree.sibling_hashes(leaf).unwrap().len())
+                .sum::<usize>()
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(benches, construction, arena);
+criterion_main!(benches);
@@ -0,0 +1,5 @@
+fn main() {
+    // Node.js addons resolve the N-API symbols from the node process.
+    #[cfg(feature = "napi")]
+    napi_build::setup();
+}
@@ -0,0 +1,40 @@
+/*
+ * C interface of the blockchain simulator library, built with the `ffi`
+ * feature:
+ *
+ *     cargo build --release --lib --no-default-features --features ffi
+ *
+ * and linked against target/release/libblockchain_simulator.so. All inputs
+ * are NUL-terminated JSON strings in the format of the simulator's files.
+ */
+#ifndef BLOCKCHAIN_SIMULATOR_H
+#define BLOCKCHAIN_SIMULATOR_H
+
+#include <stddef.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+#define BSIM_VALID 1
+#define BSIM_INVALID 0
+#define BSIM_MALFORMED_INPUT (-1)
+#define BSIM_BUFFER_TOO_SMALL (-2)
+
+/* Checks that the inclusion proof leads to its merkle root. */
+int bsim_verify_inclusion_proof(const char *proof_json);
+
+/* Checks that the header hashes to its stored hash and satisfies the proof of
+ * work required by its difficulty. */
+int bsim_verify_header_pow(const char *header_json);
+
+/* Writes the hash of the transaction into the buffer as 64 hex digits and a
+ * terminating NUL, so the buffer needs at least 65 bytes. Returns zero on
+ * success. */
+int bsim_transaction_hash(const char *transaction_json, char *output, size_t output_len);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
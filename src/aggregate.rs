@@ -0,0 +1,249 @@
+// This module combines the reports of several simulation runs into summary
+// statistics
+pub mod aggregate {
+    use std::{collections::BTreeMap, fmt::Write};
+
+    use clap::ValueEnum;
+    use log::info;
+    use serde::Serialize;
+
+    use crate::{
+        args::args::AggregateRunsArgs,
+        config::config::SimulatorContext,
+        data_sourcing::data_provider::{load_json, write_json, write_text},
+        error::error::SimulatorError,
+        model::blockchain::Address,
+        network::network::{short_address, NetworkBlockRow},
+    };
+
+    /// Outcome of a single run of the network simulation.
+    #[derive(Debug, Clone, PartialEq)]
+    pub struct RunMetrics {
+        /// Share of the mined blocks that ended up stale
+        pub orphan_rate: f64,
+        /// Share of the blocks of the main chain mined by every miner
+        pub revenue_shares: BTreeMap<Address, f64>,
+        /// Average number of seconds it took the transactions of the main
+        /// chain to be included, missing if the chain confirms none
+        pub mean_confirmation_time: Option<f64>,
+    }
+
+    impl RunMetrics {
+        /// Metrics of a run from the rows of its `SimulateNetwork` report.
+        pub fn from_report(rows: &[NetworkBlockRow]) -> Self {
+            let stale = rows.iter().filter(|row| !row.in_main_chain).count();
+            let main_chain: Vec<&NetworkBlockRow> =
+                rows.iter().filter(|row| row.in_main_chain).collect();
+
+            let mut revenue_shares = BTreeMap::new();
+            for row in rows {
+                let blocks = revenue_shares.entry(row.miner).or_insert(0.0);
+                if row.in_main_chain {
+                    *blocks += 1.0;
+                }
+            }
+            for share in revenue_shares.values_mut() {
+                *share /= main_chain.len().max(1) as f64;
+            }
+
+            let (confirmed, total_time) = main_chain
+                .iter()
+                .filter_map(|row| Some((row.transactions, row.confirmation_time?)))
+                .fold((0, 0.0), |(confirmed, total_time), (transactions, time)| {
+                    (
+                        confirmed + transactions,
+                        total_time + time * transactions as f64,
+                    )
+                });
+
+            RunMetrics {
+                orphan_rate: stale as f64 / rows.len().max(1) as f64,
+                revenue_shares,
+                mean_confirmation_time: (confirmed > 0).then(|| total_time / confirmed as f64),
+            }
+        }
+    }
+
+    /// Distribution of a metric over the runs.
+    #[derive(Serialize, Debug, Clone, PartialEq)]
+    pub struct Summary {
+        /// Number of runs the metric was measured in
+        pub runs: usize,
+        pub mean: f64,
+        /// Sample standard deviation, zero for a single run
+        pub stddev: f64,
+        pub min: f64,
+        pub p5: f64,
+        pub p50: f64,
+        pub p95: f64,
+        pub max: f64,
+    }
+
+    impl Summary {
+        /// Summary of the values, none if there are no values.
+        pub fn of(values: &[f64]) -> Option<Self> {
+            if values.is_empty() {
+                return None;
+            }
+            let mut sorted = values.to_vec();
+            sorted.sort_by(f64::total_cmp);
+            let runs = sorted.len();
+            let mean = sorted.iter().sum::<f64>() / runs as f64;
+            let variance = match runs {
+                1 => 0.0,
+                _ => {
+                    sorted
+                        .iter()
+                        .map(|value| (value - mean).powi(2))
+                        .sum::<f64>()
+                        / (runs - 1) as f64
+                }
+            };
+            Some(Summary {
+                runs,
+                mean,
+                stddev: variance.sqrt(),
+                min: sorted[0],
+                p5: percentile(&sorted, 5.0),
+                p50: percentile(&sorted, 50.0),
+                p95: percentile(&sorted, 95.0),
+                max: sorted[runs - 1],
+            })
+        }
+    }
+
+    /// Percentile of the sorted values, interpolated linearly between the
+    /// two closest ranks.
+    pub fn percentile(sorted: &[f64], percent: f64) -> f64 {
+        let rank = percent / 100.0 * (sorted.len() - 1) as f64;
+        let (lower, upper) = (rank.floor() as usize, rank.ceil() as usize);
+        sorted[lower] + (sorted[upper] - sorted[lower]) * (rank - lower as f64)
+    }
+
+    #[derive(Serialize, Debug, Clone, PartialEq)]
+    pub struct MinerSummary {
+        pub miner: Address,
+        /// Revenue share of the miner, counted as zero in the runs in which
+        /// it mined no blocks
+        pub revenue_share: Summary,
+    }
+
+    /// Summary statistics of the metrics over all runs.
+    #[derive(Serialize, Debug, Clone, PartialEq)]
+    pub struct AggregateReport {
+        pub runs: usize,
+        pub orphan_rate: Summary,
+        /// Missing if no run confirmed any transactions
+        pub mean_confirmation_time: Option<Summary>,
+        pub revenue_shares: Vec<MinerSummary>,
+    }
+
+    impl AggregateReport {
+        /// Summarises the runs, none if there are no runs.
+        pub fn of(runs: &[RunMetrics]) -> Option<Self> {
+            let orphan_rates: Vec<f64> = runs.iter().map(|run| run.orphan_rate).collect();
+            let confirmation_times: Vec<f64> = runs
+                .iter()
+                .filter_map(|run| run.mean_confirmation_time)
+                .collect();
+            let mut miners: Vec<Address> = runs
+                .iter()
+                .flat_map(|run| run.revenue_shares.keys().copied())
+                .collect();
+            miners.sort();
+            miners.dedup();
+            let revenue_shares = miners
+                .into_iter()
+                .map(|miner| {
+                    let shares: Vec<f64> = runs
+                        .iter()
+                        .map(|run| run.revenue_shares.get(&miner).copied().unwrap_or(0.0))
+                        .collect();
+                    MinerSummary {
+                        miner,
+                        revenue_share: Summary::of(&shares).unwrap(),
+                    }
+                })
+                .collect();
+            Some(AggregateReport {
+                runs: runs.len(),
+                orphan_rate: Summary::of(&orphan_rates)?,
+                mean_confirmation_time: Summary::of(&confirmation_times),
+                revenue_shares,
+            })
+        }
+
+        /// Renders the summaries as a table with one metric per line.
+        pub fn to_table(&self) -> String {
+            let mut table = String::new();
+            writeln!(
+                table,
+                "{:<28} {:>5} {:>10} {:>10} {:>10} {:>10} {:>10}",
+                "metric", "runs", "mean", "stddev", "p5", "p50", "p95"
+            )
+            .unwrap();
+            let mut row = |metric: &str, summary: &Summary| {
+                writeln!(
+                    table,
+                    "{:<28} {:>5} {:>10.4} {:>10.4} {:>10.4} {:>10.4} {:>10.4}",
+                    metric,
+                    summary.runs,
+                    summary.mean,
+                    summary.stddev,
+                    summary.p5,
+                    summary.p50,
+                    summary.p95
+                )
+                .unwrap();
+            };
+            row("orphan rate", &self.orphan_rate);
+            if let Some(confirmation_time) = &self.mean_confirmation_time {
+                row("confirmation time (s)", confirmation_time);
+            }
+            for miner in &self.revenue_shares {
+                let metric = format!("revenue share {}", short_address(&miner.miner));
+                row(&metric, &miner.revenue_share);
+            }
+            table
+        }
+    }
+
+    #[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+    pub enum SummaryFormat {
+        /// JSON object with the summary of every metric
+        Json,
+        /// Table with one line per metric for reading in the terminal
+        Table,
+    }
+
+    /// Combines the reports written by `SimulateNetwork` or `RunScenario` for
+    /// several runs, typically of the same experiment with different seeds,
+    /// into the mean, standard deviation and percentiles of the orphan rate,
+    /// the average confirmation time and the revenue share of every miner.
+    pub fn aggregate_runs(
+        args: AggregateRunsArgs,
+        context: &SimulatorContext,
+    ) -> Result<(), SimulatorError> {
+        let runs = args
+            .reports
+            .iter()
+            .map(|report| {
+                info!("Loading the report of a run from {}", report);
+                let rows: Vec<NetworkBlockRow> = load_json(report)?;
+                Ok(RunMetrics::from_report(&rows))
+            })
+            .collect::<Result<Vec<RunMetrics>, SimulatorError>>()?;
+        // clap requires at least one report.
+        let report = AggregateReport::of(&runs).unwrap();
+        info!(
+            "The orphan rate over {} runs is {:.4} ± {:.4}",
+            report.runs, report.orphan_rate.mean, report.orphan_rate.stddev
+        );
+        match args.format {
+            SummaryFormat::Json => write_json(&args.report_output, context.output, &report),
+            SummaryFormat::Table => {
+                write_text(&args.report_output, context.output, &report.to_table())
+            }
+        }
+    }
+}
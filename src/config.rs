@@ -0,0 +1,262 @@
+pub mod config {
+    use std::path::Path;
+
+    use log::info;
+    use serde::Deserialize;
+
+    use crate::{
+        args::args::Args,
+        data_sourcing::data_provider::{load_toml, DataFormat, OutputOptions, ResultFormat},
+        error::error::SimulatorError,
+        hasher::hasher::HasherSpec,
+        model::blockchain::{Amount, Checkpoint, Denomination, HeaderFormat},
+        state::state::SubsidySchedule,
+        strategy::strategy::{MiningStrategyKind, NonceSearch},
+        upgrades::upgrades::Deployment,
+    };
+
+    /// Name of the configuration file that is picked up from the working
+    /// directory if no other file is specified with `--config`.
+    pub const DEFAULT_CONFIG_FILE: &str = "simulator.toml";
+
+    /// Settings that can be stored in the `simulator.toml` file so that they
+    /// don't need to be repeated on the command line. Every field is optional
+    /// and any flag passed on the command line takes precedence over the value
+    /// specified here.
+    #[derive(Deserialize, Debug, Default)]
+    #[serde(deny_unknown_fields)]
+    pub struct SimulatorConfig {
+        /// Difficulty (number of leading zeros) of the mined blocks
+        pub difficulty: Option<u32>,
+        /// Number of seconds between the timestamps of consecutive blocks
+        pub block_interval: Option<u64>,
+        /// Maximum number of transactions included in a single block
+        pub block_capacity: Option<usize>,
+        /// File storing the initial state of the blockchain
+        pub blockchain_state: Option<String>,
+        /// File storing the final and intermediate state of the blockchain
+        pub blockchain_state_output: Option<String>,
+        /// Name of the file storing the initial mempool
+        pub mempool: Option<String>,
+        /// Name of the file storing the intermediate and final mempool
+        pub mempool_output: Option<String>,
+        /// Seed of the random number generator used by the simulation
+        pub seed: Option<u64>,
+        /// Coinbase subsidy of the first blocks
+        pub initial_subsidy: Option<Amount>,
+        /// Number of blocks after which the subsidy is halved
+        pub halving_interval: Option<u32>,
+        /// Share of the total hashrate controlled by an attacker
+        pub attacker_hashrate: Option<f64>,
+        /// Finalized blocks that every chain has to contain, given as
+        /// `[[checkpoints]]` tables with a height and a hash
+        #[serde(default)]
+        pub checkpoints: Vec<Checkpoint>,
+        /// Consensus rules activated at a height or by version-bit
+        /// signaling, given as `[[deployments]]` tables
+        #[serde(default)]
+        pub deployments: Vec<Deployment>,
+    }
+
+    impl SimulatorConfig {
+        /// Pairs of argument ids and their values for all settings present in
+        /// the configuration file, used as defaults when parsing the arguments.
+        pub fn defaults(&self) -> Vec<(&'static str, String)> {
+            let mut defaults = vec![];
+            let mut add = |id: &'static str, value: Option<String>| {
+                if let Some(value) = value {
+                    defaults.push((id, value));
+                }
+            };
+            add("difficulty", self.difficulty.map(|d| d.to_string()));
+            add("block_interval", self.block_interval.map(|i| i.to_string()));
+            add("block_capacity", self.block_capacity.map(|c| c.to_string()));
+            add("blockchain_state", self.blockchain_state.clone());
+            add(
+                "blockchain_state_output",
+                self.blockchain_state_output.clone(),
+            );
+            add("mempool", self.mempool.clone());
+            add("mempool_output", self.mempool_output.clone());
+            add("seed", self.seed.map(|s| s.to_string()));
+            add(
+                "initial_subsidy",
+                self.initial_subsidy.map(|s| s.to_string()),
+            );
+            add(
+                "halving_interval",
+                self.halving_interval.map(|i| i.to_string()),
+            );
+            add(
+                "attacker_hashrate",
+                self.attacker_hashrate.map(|h| h.to_string()),
+            );
+            defaults
+        }
+    }
+
+    /// Settings shared by the commands, which come from the configuration file,
+    /// the global flags and the flags of the subcommand that configure how
+    /// the files are loaded and written and how the blocks are mined.
+    #[derive(Debug, Clone, Default)]
+    pub struct SimulatorContext {
+        /// Finalized blocks that every chain has to contain
+        pub checkpoints: Vec<Checkpoint>,
+        /// Consensus rules activated at a height or by version-bit signaling
+        pub deployments: Vec<Deployment>,
+        /// Compression and JSON form of the output files
+        pub output: OutputOptions,
+        /// Encoding of the written data files whose extension doesn't select one
+        pub data_format: DataFormat,
+        pub subsidy_schedule: SubsidySchedule,
+        /// Hasher used to search for the nonces of the mined blocks
+        pub hasher: HasherSpec,
+        /// Layout in which the headers of the mined blocks are hashed
+        pub header_format: HeaderFormat,
+        /// Order in which the nonces of the mined blocks are searched
+        pub mining_strategy: MiningStrategyKind,
+        /// Number of headers hashed per second at most while mining
+        pub hashrate: Option<f64>,
+        /// Format of the results printed to the standard output
+        pub result_format: ResultFormat,
+        /// Whether the structure of loaded blockchains is checked
+        pub strict_load: bool,
+        /// Unit in which amounts are shown
+        pub denomination: Denomination,
+    }
+
+    impl SimulatorContext {
+        /// Context of the subcommand, with the settings it has no flags for
+        /// left at their defaults.
+        pub fn new(config: SimulatorConfig, args: &Args) -> Self {
+            let mut context = SimulatorContext {
+                checkpoints: config.checkpoints,
+                deployments: config.deployments,
+                result_format: args.result_format,
+                ..SimulatorContext::default()
+            };
+            args.command.context_args().configure(&mut context);
+            context
+        }
+
+        /// How the nonces of the mined blocks are searched for.
+        pub fn nonce_search(&self) -> NonceSearch<'_> {
+            NonceSearch {
+                hasher: &self.hasher,
+                strategy: self.mining_strategy.strategy(),
+                hashrate: self.hashrate,
+            }
+        }
+    }
+
+    /// Arguments of a subcommand, or a group of them flattened into several
+    /// subcommands, that set the settings of the context the subcommand
+    /// reads.
+    pub trait ContextArgs {
+        fn configure(&self, context: &mut SimulatorContext);
+    }
+
+    /// Finds the value of the `--config` option in the raw command line
+    /// arguments. The configuration needs to be loaded before clap parses the
+    /// arguments as it provides the defaults for the subcommand options.
+    pub fn find_config_file(args: &[String]) -> Option<String> {
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            if arg == "--config" {
+                return args.next().cloned();
+            }
+            if let Some(file_name) = arg.strip_prefix("--config=") {
+                return Some(file_name.to_string());
+            }
+        }
+        None
+    }
+
+    /// Loads the configuration file. If the file was requested explicitly it
+    /// must exist, otherwise the default `simulator.toml` is only used if it is
+    /// present in the working directory.
+    pub fn load_config(config_file: Option<&str>) -> Result<SimulatorConfig, SimulatorError> {
+        let file_name = match config_file {
+            Some(file_name) => file_name,
+            None if Path::new(DEFAULT_CONFIG_FILE).exists() => DEFAULT_CONFIG_FILE,
+            None => return Ok(SimulatorConfig::default()),
+        };
+
+        info!("Loading the simulator configuration from {}", file_name);
+        load_toml(file_name)
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use clap::Parser;
+
+        use crate::{
+            args::args::Args,
+            data_sourcing::data_provider::Compression,
+            model::blockchain::{Amount, HeaderFormat},
+        };
+
+        use super::{find_config_file, SimulatorConfig, SimulatorContext};
+
+        fn raw_args(args: &[&str]) -> Vec<String> {
+            args.iter().map(|arg| arg.to_string()).collect()
+        }
+
+        #[test]
+        fn config_file_is_found_in_both_forms() {
+            let separate = raw_args(&["simulator", "--config", "a.toml", "list-blocks"]);
+            let joined = raw_args(&["simulator", "list-blocks", "--config=b.toml"]);
+            let missing = raw_args(&["simulator", "list-blocks"]);
+            assert_eq!(find_config_file(&separate).as_deref(), Some("a.toml"));
+            assert_eq!(find_config_file(&joined).as_deref(), Some("b.toml"));
+            assert_eq!(find_config_file(&missing), None);
+        }
+
+        #[test]
+        fn defaults_hold_only_the_stored_settings() {
+            let config = SimulatorConfig {
+                difficulty: Some(3),
+                initial_subsidy: Some(Amount(50)),
+                ..SimulatorConfig::default()
+            };
+            assert_eq!(
+                config.defaults(),
+                vec![
+                    ("difficulty", "3".to_string()),
+                    ("initial_subsidy", "50".to_string())
+                ]
+            );
+        }
+
+        #[test]
+        fn context_takes_the_flags_of_the_subcommand() {
+            let args = Args::try_parse_from([
+                "simulator",
+                "validate-chain",
+                "--blockchain-state",
+                "chain.json",
+                "--header-format",
+                "bitcoin",
+                "--strict-load",
+            ])
+            .unwrap();
+            let context = SimulatorContext::new(SimulatorConfig::default(), &args);
+            assert_eq!(context.header_format, HeaderFormat::Bitcoin);
+            assert!(context.strict_load);
+            assert_eq!(context.output.compression, Compression::None);
+        }
+
+        #[test]
+        fn flags_the_subcommand_does_not_read_are_rejected() {
+            let args = Args::try_parse_from([
+                "simulator",
+                "validate-chain",
+                "--blockchain-state",
+                "chain.json",
+                "--compress",
+                "gzip",
+            ]);
+            assert!(args.is_err());
+        }
+    }
+}
@@ -0,0 +1,109 @@
+// This module serves the web dashboard of a running node
+pub mod dashboard {
+    use std::{
+        io::{Read, Write},
+        net::{TcpListener, TcpStream},
+        sync::Arc,
+        thread,
+        time::Duration,
+    };
+
+    use log::{debug, info, warn};
+    use tungstenite::Message;
+
+    use crate::{error::error::SimulatorError, p2p::p2p::Node};
+
+    /// Page of the dashboard, which subscribes to the feed of the node.
+    const DASHBOARD_PAGE: &str = include_str!("dashboard.html");
+
+    /// Path of the WebSocket streaming the status of the node.
+    const FEED_PATH: &str = "/feed";
+
+    /// Number of the most recent blocks shown on the dashboard.
+    const RECENT_BLOCKS: usize = 10;
+
+    /// Interval between the status updates sent over the feed.
+    const UPDATE_INTERVAL: Duration = Duration::from_secs(1);
+
+    /// Serves the dashboard page over HTTP on the address, and the status of
+    /// the node as JSON over a WebSocket at /feed, on a background thread.
+    pub fn serve_dashboard(node: Arc<Node>, address: &str) -> Result<(), SimulatorError> {
+        let listener =
+            TcpListener::bind(address).map_err(|source| SimulatorError::PeerConnection {
+                peer: address.to_string(),
+                source,
+            })?;
+        info!("Serving the dashboard on http://{}", address);
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                match stream {
+                    Ok(stream) => {
+                        let node = Arc::clone(&node);
+                        thread::spawn(move || serve(&node, stream));
+                    }
+                    Err(error) => warn!("Unable to accept a dashboard connection: {}", error),
+                }
+            }
+        });
+        Ok(())
+    }
+
+    /// Upgrades requests for the feed to a WebSocket and answers any other
+    /// request with the dashboard page. The request line is only peeked at,
+    /// so that the WebSocket handshake can read the whole request.
+    fn serve(node: &Node, mut stream: TcpStream) {
+        let mut request = [0u8; 512];
+        let read = match stream.peek(&mut request) {
+            Ok(read) => read,
+            Err(error) => {
+                debug!("Unable to read a dashboard request: {}", error);
+                return;
+            }
+        };
+        let request_line = String::from_utf8_lossy(&request[..read]);
+        let path = request_line.split_whitespace().nth(1).unwrap_or("/");
+        if path == FEED_PATH {
+            stream_status(node, stream);
+            return;
+        }
+
+        // The request itself is not needed, but it has to be consumed before
+        // the connection is closed.
+        let _ = stream.set_read_timeout(Some(Duration::from_millis(100)));
+        let _ = stream.read(&mut [0u8; 4096]);
+        let response = format!(
+            "HTTP/1.1 200 OK\r\nContent-Type: text/html; charset=utf-8\r\n\
+             Content-Length: {}\r\nConnection: close\r\n\r\n{}",
+            DASHBOARD_PAGE.len(),
+            DASHBOARD_PAGE
+        );
+        if let Err(error) = stream.write_all(response.as_bytes()) {
+            debug!("Unable to send the dashboard: {}", error);
+        }
+    }
+
+    /// Sends the status of the node every second until the client leaves.
+    fn stream_status(node: &Node, stream: TcpStream) {
+        let mut socket = match tungstenite::accept(stream) {
+            Ok(socket) => socket,
+            Err(error) => {
+                debug!("Rejected a dashboard feed: {}", error);
+                return;
+            }
+        };
+        loop {
+            let status = match serde_json::to_string(&node.status(RECENT_BLOCKS)) {
+                Ok(status) => status,
+                Err(error) => {
+                    warn!("Unable to encode the status of the node: {}", error);
+                    return;
+                }
+            };
+            if let Err(error) = socket.send(Message::Text(status)) {
+                debug!("Closed a dashboard feed: {}", error);
+                return;
+            }
+            thread::sleep(UPDATE_INTERVAL);
+        }
+    }
+}
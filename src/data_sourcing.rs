@@ -1,35 +1,920 @@
 pub mod data_provider {
     use std::{
-        fs::File,
-        io::{self, Read},
-        str::from_utf8,
+        collections::BTreeMap,
+        fmt::Display,
+        fs::{self, File},
+        io::{self, BufRead, BufReader, BufWriter, Read, StdoutLock, Write},
+        ops::Deref,
+        path::{Path, PathBuf},
+        process,
     };
 
-    use crate::model::blockchain::{Block, Transaction, InclusionProof};
+    use clap::ValueEnum;
+    use flate2::{read::MultiGzDecoder, write::GzEncoder};
+    use log::error;
+    use memmap2::Mmap;
+    use prost::Message;
+    use serde::{de::DeserializeOwned, Serialize};
+    use serde_json::error::Category;
 
-    pub fn load_blockchain(source_file_name: &str) -> Result<Vec<Block>, String> {
-        let file_str_contents = read_file_contents(source_file_name).unwrap();
-        let blockchain: Vec<Block> = serde_json::from_str(&file_str_contents).unwrap();
+    use crate::{
+        config::config::SimulatorContext,
+        error::error::SimulatorError,
+        model::blockchain::{
+            Address, Amount, Blockchain, DifficultySchedule, DifficultyStep, Header, HeaderChain,
+            InclusionProof, Transaction,
+        },
+        proto::proto,
+        snapshot::snapshot::Snapshot,
+        validation::validation::{json_path, structural_problems, CheckFields, FieldError},
+    };
+
+    pub fn load_blockchain(
+        source_file_name: &str,
+        context: &SimulatorContext,
+    ) -> Result<Blockchain, SimulatorError> {
+        let blockchain: Blockchain = load_data(source_file_name, |message: proto::Blockchain| {
+            message.try_into()
+        })?;
+        if context.strict_load {
+            check_structure(
+                source_file_name,
+                blockchain.iter().map(|block| &block.header),
+            )?;
+        }
         Ok(blockchain)
     }
 
-    pub fn load_inclusion_proof(source_file_name: &str) -> Result<InclusionProof, String> {
-        let file_str_contents = read_file_contents(source_file_name).unwrap();
-        let proof: InclusionProof = serde_json::from_str(&file_str_contents).unwrap();
-        Ok(proof)
+    /// Loads only the block headers from a blockchain file, skipping over the
+    /// transactions which is much faster for long chains.
+    pub fn load_headers(
+        source_file_name: &str,
+        context: &SimulatorContext,
+    ) -> Result<HeaderChain, SimulatorError> {
+        let headers = load_data(source_file_name, |message: proto::Blockchain| {
+            Blockchain::try_from(message).map(|blockchain| HeaderChain::from(&blockchain))
+        })?;
+        if context.strict_load {
+            check_structure(source_file_name, &headers)?;
+        }
+        Ok(headers)
+    }
+
+    /// Fails if the chain has any of the `structural_problems`, logging each
+    /// of them first so that they can all be fixed at once.
+    fn check_structure<'a>(
+        file_name: &str,
+        headers: impl IntoIterator<Item = &'a Header>,
+    ) -> Result<(), SimulatorError> {
+        let problems = structural_problems(headers);
+        for problem in &problems {
+            error!("{}", problem);
+        }
+        if problems.is_empty() {
+            Ok(())
+        } else {
+            Err(SimulatorError::UnsoundChain {
+                file_name: file_name.to_string(),
+                problems: problems.len(),
+            })
+        }
+    }
+
+    pub fn load_snapshot(file_name: &str) -> Result<Snapshot, SimulatorError> {
+        load_json(file_name)
+    }
+
+    pub fn load_inclusion_proof(source_file_name: &str) -> Result<InclusionProof, SimulatorError> {
+        load_data(source_file_name, |message: proto::InclusionProof| {
+            message.try_into()
+        })
+    }
+
+    pub fn load_transactions(file_name: &str) -> Result<Vec<Transaction>, SimulatorError> {
+        load_data(file_name, |message: proto::Mempool| {
+            proto::transactions(message.transactions)
+        })
+    }
+
+    /// Encoding of the blockchain, headers, mempool and inclusion proof files.
+    #[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq, Default)]
+    pub enum DataFormat {
+        #[default]
+        Json,
+        /// The protobuf messages of proto/blockchain.proto
+        Proto,
+    }
+
+    /// Files with the .pb extension, possibly followed by the extension of
+    /// the compression, are always written as protobuf, other files (and the
+    /// standard output) use the default format requested with the
+    /// `--data-format` flag.
+    fn output_format(file_name: &str, default: DataFormat) -> DataFormat {
+        data_extension(file_name).unwrap_or(default)
+    }
+
+    /// Format named by the extension of the file, ignoring the extension of
+    /// its compression.
+    fn data_extension(file_name: &str) -> Option<DataFormat> {
+        let file_name = file_name.trim_end_matches(".gz").trim_end_matches(".zst");
+        match Path::new(file_name).extension().and_then(|e| e.to_str()) {
+            Some("json") => Some(DataFormat::Json),
+            Some("pb") => Some(DataFormat::Proto),
+            _ => None,
+        }
+    }
+
+    /// Loads a data file written either as JSON or as the protobuf message,
+    /// which is converted into the model. Protobuf has no marker telling it
+    /// apart from JSON, and its messages may well start with bytes that look
+    /// like JSON, so contents that look like JSON are only decoded as
+    /// protobuf if they fail to parse as JSON. Files with the .json extension
+    /// that fail both ways are reported as malformed JSON. Values of the
+    /// wrong type or violating the constraints of `CheckFields` are reported
+    /// with their path in the file.
+    fn load_data<T, M, F>(file_name: &str, convert: F) -> Result<T, SimulatorError>
+    where
+        T: DeserializeOwned + CheckFields,
+        M: Message + Default,
+        F: FnOnce(M) -> Result<T, String>,
+    {
+        let contents = read_input(file_name)?;
+        let decode = |contents: &[u8]| {
+            M::decode(contents)
+                .map_err(|error| error.to_string())
+                .and_then(convert)
+                .map_err(|reason| SimulatorError::MalformedProto {
+                    file_name: file_name.to_string(),
+                    reason,
+                })
+        };
+        let parse = |contents: &[u8]| {
+            serde_json::from_slice(contents).map_err(|source| {
+                let path = json_path(contents, source.line(), source.column(), T::ROOT);
+                if source.classify() != Category::Data || path.is_empty() {
+                    return SimulatorError::MalformedJson {
+                        file_name: file_name.to_string(),
+                        source,
+                    };
+                }
+                // The path replaces the position serde_json reports.
+                let position = format!(" at line {} column {}", source.line(), source.column());
+                SimulatorError::InvalidField {
+                    file_name: file_name.to_string(),
+                    path,
+                    reason: source.to_string().trim_end_matches(&position).to_string(),
+                }
+            })
+        };
+        let looks_like_json = contents
+            .iter()
+            .find(|byte| !byte.is_ascii_whitespace())
+            .is_some_and(|byte| matches!(byte, b'[' | b'{'));
+        let data: T = match data_extension(file_name) {
+            Some(DataFormat::Proto) => decode(&contents),
+            Some(DataFormat::Json) if !looks_like_json => {
+                decode(&contents).or_else(|_| parse(&contents))
+            }
+            _ if !looks_like_json => decode(&contents),
+            _ => parse(&contents).or_else(|error| decode(&contents).map_err(|_| error)),
+        }?;
+        data.check_fields().map_err(|FieldError { path, reason }| {
+            SimulatorError::InvalidField {
+                file_name: file_name.to_string(),
+                path,
+                reason,
+            }
+        })?;
+        Ok(data)
+    }
+
+    /// Writes the value to the file as pretty-printed JSON or as the
+    /// protobuf message, compressing it if requested.
+    fn write_data<T: Serialize, M: Message>(
+        file_name: &str,
+        output: OutputOptions,
+        format: DataFormat,
+        value: &T,
+        message: impl FnOnce() -> M,
+    ) -> Result<(), SimulatorError> {
+        match output_format(file_name, format) {
+            DataFormat::Json => write_json(file_name, output, value),
+            DataFormat::Proto => write_bytes(file_name, output, &message().encode_to_vec()),
+        }
+    }
+
+    pub fn write_blockchain(
+        file_name: &str,
+        output: OutputOptions,
+        format: DataFormat,
+        blockchain: &Blockchain,
+    ) -> Result<(), SimulatorError> {
+        write_data(file_name, output, format, blockchain, || {
+            proto::Blockchain::from(blockchain)
+        })
+    }
+
+    /// Writes the headers as a blockchain whose blocks have no transactions.
+    pub fn write_headers(
+        file_name: &str,
+        output: OutputOptions,
+        format: DataFormat,
+        headers: &HeaderChain,
+    ) -> Result<(), SimulatorError> {
+        write_data(file_name, output, format, headers, || {
+            proto::Blockchain::from(headers)
+        })
+    }
+
+    pub fn write_transactions(
+        file_name: &str,
+        output: OutputOptions,
+        format: DataFormat,
+        transactions: &[Transaction],
+    ) -> Result<(), SimulatorError> {
+        write_data(file_name, output, format, &transactions, || {
+            proto::Mempool {
+                transactions: transactions.iter().map(proto::Transaction::from).collect(),
+            }
+        })
+    }
+
+    pub fn write_inclusion_proof(
+        file_name: &str,
+        output: OutputOptions,
+        format: DataFormat,
+        proof: &InclusionProof,
+    ) -> Result<(), SimulatorError> {
+        write_data(file_name, output, format, proof, || {
+            proto::InclusionProof::from(proof)
+        })
+    }
+
+    /// Allocations map addresses to the amounts they should be pre-funded with
+    /// in the genesis block. A BTreeMap keeps the resulting transactions in a
+    /// deterministic order.
+    pub fn load_allocations(file_name: &str) -> Result<BTreeMap<Address, Amount>, SimulatorError> {
+        load_json(file_name)
+    }
+
+    pub fn load_difficulty_schedule(file_name: &str) -> Result<DifficultySchedule, SimulatorError> {
+        let steps: Vec<DifficultyStep> = load_json(file_name)?;
+        DifficultySchedule::new(steps).map_err(|reason| SimulatorError::InvalidDifficultySchedule {
+            file_name: file_name.to_string(),
+            reason,
+        })
+    }
+
+    /// File name standing for the standard input when reading and the standard
+    /// output when writing, allowing the commands to be chained in pipelines.
+    pub const STDIO_FILE_NAME: &str = "-";
+
+    pub fn load_json<T: DeserializeOwned>(file_name: &str) -> Result<T, SimulatorError> {
+        let contents = read_input(file_name)?;
+        serde_json::from_slice(&contents).map_err(|source| SimulatorError::MalformedJson {
+            file_name: file_name.to_string(),
+            source,
+        })
+    }
+
+    pub fn load_toml<T: DeserializeOwned>(file_name: &str) -> Result<T, SimulatorError> {
+        parse_toml(file_name, &read_file_contents(file_name)?)
+    }
+
+    /// Parses the contents already read from the file, which is only used
+    /// to report errors.
+    pub fn parse_toml<T: DeserializeOwned>(
+        file_name: &str,
+        contents: &str,
+    ) -> Result<T, SimulatorError> {
+        toml::from_str(contents).map_err(|source| SimulatorError::MalformedConfig {
+            file_name: file_name.to_string(),
+            source,
+        })
+    }
+
+    /// Parses the contents already read from the file, which is only used
+    /// to report errors.
+    pub fn parse_yaml<T: DeserializeOwned>(
+        file_name: &str,
+        contents: &str,
+    ) -> Result<T, SimulatorError> {
+        serde_yaml::from_str(contents).map_err(|source| SimulatorError::MalformedYaml {
+            file_name: file_name.to_string(),
+            source,
+        })
+    }
+
+    /// Compression applied to the files written by the simulator.
+    #[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq, Default)]
+    pub enum Compression {
+        #[default]
+        None,
+        Gzip,
+        Zstd,
+    }
+
+    /// Options of the written files chosen with the `--compress` and
+    /// `--canonical` flags.
+    #[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+    pub struct OutputOptions {
+        /// Compression of the files whose extension doesn't select one
+        pub compression: Compression,
+        /// Whether JSON is written in its canonical form, compact and with
+        /// the keys of every object sorted, so that equal contents are
+        /// always written as the same bytes
+        pub canonical: bool,
+    }
+
+    const GZIP_MAGIC_BYTES: [u8; 2] = [0x1f, 0x8b];
+    const ZSTD_MAGIC_BYTES: [u8; 4] = [0x28, 0xb5, 0x2f, 0xfd];
+
+    /// The .gz and .zst extensions always select the matching compression,
+    /// other files (and the standard output) use the default one requested
+    /// with the `--compress` flag.
+    fn output_compression(file_name: &str, default: Compression) -> Compression {
+        match Path::new(file_name).extension().and_then(|e| e.to_str()) {
+            Some("gz") => Compression::Gzip,
+            Some("zst") => Compression::Zstd,
+            _ => default,
+        }
+    }
+
+    /// Writes the value to the file as pretty-printed or canonical JSON,
+    /// compressing it if requested. The file is replaced atomically, so it is
+    /// safe for the output file to be the same as the file the state was
+    /// loaded from.
+    pub fn write_json<T: Serialize>(
+        file_name: &str,
+        output: OutputOptions,
+        value: &T,
+    ) -> Result<(), SimulatorError> {
+        write_output(file_name, output.compression, |writer| {
+            if output.canonical {
+                write_canonical_json(writer, file_name, value)
+            } else {
+                write_pretty_json(writer, file_name, value)
+            }
+        })
+    }
+
+    /// Writes the text to the file as is, compressing it if requested.
+    pub fn write_text(
+        file_name: &str,
+        output: OutputOptions,
+        text: &str,
+    ) -> Result<(), SimulatorError> {
+        write_bytes(file_name, output, text.as_bytes())
+    }
+
+    /// Writes the binary contents to the file as is, compressing them if
+    /// requested.
+    pub fn write_bytes(
+        file_name: &str,
+        output: OutputOptions,
+        bytes: &[u8],
+    ) -> Result<(), SimulatorError> {
+        write_output(file_name, output.compression, |writer| {
+            writer
+                .write_all(bytes)
+                .map_err(|source| SimulatorError::Io {
+                    file_name: file_name.to_string(),
+                    source,
+                })
+        })
+    }
+
+    /// Format in which the commands print their result.
+    #[derive(ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+    pub enum ResultFormat {
+        /// The main value of the result alone, such as a hash or a count
+        #[default]
+        Plain,
+        /// All the fields of the result as a JSON object on a single line
+        Json,
     }
 
-    pub fn load_transactions(file_name: &str) -> Result<Vec<Transaction>, String> {
-        let file_str_contents = read_file_contents(file_name).unwrap();
-        let transactions: Vec<Transaction> = serde_json::from_str(&file_str_contents).unwrap();
-        Ok(transactions)
+    /// Prints the result of a command to the standard output, which is kept
+    /// free of the log lines written to the standard error so that scripts
+    /// can read the result. The plain format prints only the main value.
+    pub fn print_result<T: Serialize>(
+        format: ResultFormat,
+        main_value: impl Display,
+        result: &T,
+    ) -> Result<(), SimulatorError> {
+        let mut stdout = io::stdout().lock();
+        match format {
+            ResultFormat::Plain => {
+                writeln!(stdout, "{}", main_value).map_err(|source| SimulatorError::Io {
+                    file_name: STDIO_FILE_NAME.to_string(),
+                    source,
+                })
+            }
+            ResultFormat::Json => write_json_line(&mut stdout, STDIO_FILE_NAME, false, result),
+        }
     }
 
-    pub fn read_file_contents(file_name: &str) -> Result<String, io::Error> {
+    /// Format of the reports produced by the analytics commands.
+    #[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+    pub enum ReportFormat {
+        Json,
+        Csv,
+    }
+
+    /// Writes the rows of a report as a JSON array or as CSV with a header
+    /// line naming the fields of the rows.
+    pub fn write_report<T: Serialize>(
+        file_name: &str,
+        output: OutputOptions,
+        format: ReportFormat,
+        rows: &[T],
+    ) -> Result<(), SimulatorError> {
+        match format {
+            ReportFormat::Json => write_json(file_name, output, &rows),
+            ReportFormat::Csv => write_output(file_name, output.compression, |writer| {
+                let mut csv_writer = csv::Writer::from_writer(writer);
+                for row in rows {
+                    csv_writer
+                        .serialize(row)
+                        .map_err(|error| SimulatorError::Io {
+                            file_name: file_name.to_string(),
+                            source: error.into(),
+                        })?;
+                }
+                csv_writer.flush().map_err(|source| SimulatorError::Io {
+                    file_name: file_name.to_string(),
+                    source,
+                })
+            }),
+        }
+    }
+
+    /// Opens the output, lets `write` fill it, compressing the contents if
+    /// requested, and commits it.
+    fn write_output<F>(
+        file_name: &str,
+        compression: Compression,
+        write: F,
+    ) -> Result<(), SimulatorError>
+    where
+        F: FnOnce(&mut dyn Write) -> Result<(), SimulatorError>,
+    {
+        let io_error = |source| SimulatorError::Io {
+            file_name: file_name.to_string(),
+            source,
+        };
+        let output = open_output(file_name)?;
+        let output = match output_compression(file_name, compression) {
+            Compression::None => {
+                let mut output = output;
+                write(&mut output)?;
+                output
+            }
+            Compression::Gzip => {
+                let mut encoder = GzEncoder::new(output, flate2::Compression::default());
+                write(&mut encoder)?;
+                encoder.finish().map_err(io_error)?
+            }
+            Compression::Zstd => {
+                let mut encoder = zstd::Encoder::new(output, 0).map_err(io_error)?;
+                write(&mut encoder)?;
+                encoder.finish().map_err(io_error)?
+            }
+        };
+        output.commit()
+    }
+
+    fn write_pretty_json<W: Write + ?Sized, T: Serialize>(
+        writer: &mut W,
+        file_name: &str,
+        value: &T,
+    ) -> Result<(), SimulatorError> {
+        serde_json::to_writer_pretty(&mut *writer, value).map_err(|source| {
+            SimulatorError::SerializationFailed {
+                file_name: file_name.to_string(),
+                source,
+            }
+        })?;
+        writeln!(writer).map_err(|source| SimulatorError::Io {
+            file_name: file_name.to_string(),
+            source,
+        })
+    }
+
+    /// Writes the value compactly with the keys of every object sorted,
+    /// without the trailing newline of the pretty-printed files.
+    fn write_canonical_json<W: Write + ?Sized, T: Serialize>(
+        writer: &mut W,
+        file_name: &str,
+        value: &T,
+    ) -> Result<(), SimulatorError> {
+        serde_json::to_value(value)
+            .and_then(|value| serde_json::to_writer(&mut *writer, &sorted_keys(value)))
+            .map_err(|source| SimulatorError::SerializationFailed {
+                file_name: file_name.to_string(),
+                source,
+            })
+    }
+
+    /// The value with the keys of its objects sorted, which the map of
+    /// serde_json only does itself unless its preserve_order feature is
+    /// enabled by another dependency.
+    fn sorted_keys(value: serde_json::Value) -> serde_json::Value {
+        match value {
+            serde_json::Value::Object(object) => {
+                let mut entries: Vec<(String, serde_json::Value)> = object.into_iter().collect();
+                entries.sort_by(|(a, _), (b, _)| a.cmp(b));
+                serde_json::Value::Object(
+                    entries
+                        .into_iter()
+                        .map(|(key, value)| (key, sorted_keys(value)))
+                        .collect(),
+                )
+            }
+            serde_json::Value::Array(values) => {
+                serde_json::Value::Array(values.into_iter().map(sorted_keys).collect())
+            }
+            value => value,
+        }
+    }
+
+    /// Writes the value as compact JSON on a single line, the format of the
+    /// JSONL logs, with the keys of every object sorted if it is canonical.
+    pub fn write_json_line<W: Write + ?Sized, T: Serialize>(
+        writer: &mut W,
+        file_name: &str,
+        canonical: bool,
+        value: &T,
+    ) -> Result<(), SimulatorError> {
+        if canonical {
+            write_canonical_json(writer, file_name, value)?;
+        } else {
+            serde_json::to_writer(&mut *writer, value).map_err(|source| {
+                SimulatorError::SerializationFailed {
+                    file_name: file_name.to_string(),
+                    source,
+                }
+            })?;
+        }
+        writeln!(writer).map_err(|source| SimulatorError::Io {
+            file_name: file_name.to_string(),
+            source,
+        })
+    }
+
+    /// Contents of an input file, which are memory-mapped rather than read
+    /// into memory when the file is a regular file stored uncompressed.
+    pub enum InputContents {
+        Mapped(Mmap),
+        Read(Vec<u8>),
+    }
+
+    impl Deref for InputContents {
+        type Target = [u8];
+
+        fn deref(&self) -> &[u8] {
+            match self {
+                InputContents::Mapped(map) => map,
+                InputContents::Read(buffer) => buffer,
+            }
+        }
+    }
+
+    /// Contents of an input file that are valid UTF-8.
+    pub struct TextContents(InputContents);
+
+    impl Deref for TextContents {
+        type Target = str;
+
+        fn deref(&self) -> &str {
+            // SAFETY: the contents are checked to be UTF-8 when they are read.
+            unsafe { std::str::from_utf8_unchecked(&self.0) }
+        }
+    }
+
+    /// Reads all contents of the file, or of the standard input if the file
+    /// name is "-". Regular files that aren't compressed are memory-mapped,
+    /// so that large chain files are parsed without copying them into
+    /// memory first, other files are decompressed and read as by
+    /// `open_input`.
+    pub fn read_input(file_name: &str) -> Result<InputContents, SimulatorError> {
+        let io_error = |source| SimulatorError::Io {
+            file_name: file_name.to_string(),
+            source,
+        };
+        if file_name != STDIO_FILE_NAME {
+            let file = File::open(file_name).map_err(io_error)?;
+            if file.metadata().map_err(io_error)?.is_file() {
+                // SAFETY: the input files aren't written while the simulator
+                // reads them, a file truncated by another process meanwhile
+                // would make reading the mapped contents fault.
+                let map = unsafe { Mmap::map(&file) }.map_err(io_error)?;
+                if !map.starts_with(&GZIP_MAGIC_BYTES) && !map.starts_with(&ZSTD_MAGIC_BYTES) {
+                    return Ok(InputContents::Mapped(map));
+                }
+            }
+        }
         let mut buffer = Vec::new();
-        let mut file = File::open(file_name)?;
-        file.read_to_end(&mut buffer)?;
-        let file_contents: &str = from_utf8(&buffer).unwrap();
-        Ok(file_contents.to_string())
+        open_input(file_name)?
+            .read_to_end(&mut buffer)
+            .map_err(io_error)?;
+        Ok(InputContents::Read(buffer))
+    }
+
+    pub fn read_file_contents(file_name: &str) -> Result<TextContents, SimulatorError> {
+        let contents = read_input(file_name)?;
+        if std::str::from_utf8(&contents).is_err() {
+            return Err(SimulatorError::InvalidUtf8 {
+                file_name: file_name.to_string(),
+            });
+        }
+        Ok(TextContents(contents))
+    }
+
+    /// Opens the file for buffered reading, or the standard input if the file
+    /// name is "-". Gzip and zstd compressed contents are detected from their
+    /// magic bytes and decompressed transparently.
+    pub fn open_input(file_name: &str) -> Result<Box<dyn Read>, SimulatorError> {
+        let io_error = |source| SimulatorError::Io {
+            file_name: file_name.to_string(),
+            source,
+        };
+        let mut reader: Box<dyn BufRead> = if file_name == STDIO_FILE_NAME {
+            Box::new(BufReader::new(io::stdin().lock()))
+        } else {
+            Box::new(BufReader::new(File::open(file_name).map_err(io_error)?))
+        };
+
+        let leading_bytes = reader.fill_buf().map_err(io_error)?;
+        if leading_bytes.starts_with(&GZIP_MAGIC_BYTES) {
+            Ok(Box::new(MultiGzDecoder::new(reader)))
+        } else if leading_bytes.starts_with(&ZSTD_MAGIC_BYTES) {
+            Ok(Box::new(
+                zstd::Decoder::with_buffer(reader).map_err(io_error)?,
+            ))
+        } else {
+            Ok(reader)
+        }
+    }
+
+    /// Destination of the output of a command. Files are first written to a
+    /// temporary file in the same directory which is only renamed over the
+    /// destination once all contents have been written, so that a crash
+    /// mid-write never leaves behind a truncated file. Nothing is written to
+    /// the destination unless `commit` is called. Special files such as
+    /// /dev/null or named pipes can't be replaced, so they are written to
+    /// directly.
+    pub enum Output {
+        Stdout(BufWriter<StdoutLock<'static>>),
+        Special {
+            writer: BufWriter<File>,
+            file_name: String,
+        },
+        File {
+            writer: BufWriter<File>,
+            temporary_path: PathBuf,
+            file_name: String,
+        },
+    }
+
+    impl Output {
+        /// Flushes all the written contents and moves them to the destination.
+        pub fn commit(mut self) -> Result<(), SimulatorError> {
+            match &mut self {
+                Output::Stdout(writer) => writer.flush().map_err(|source| SimulatorError::Io {
+                    file_name: STDIO_FILE_NAME.to_string(),
+                    source,
+                }),
+                Output::Special { writer, file_name } => {
+                    writer.flush().map_err(|source| SimulatorError::Io {
+                        file_name: file_name.clone(),
+                        source,
+                    })
+                }
+                Output::File {
+                    writer,
+                    temporary_path,
+                    file_name,
+                } => writer
+                    .flush()
+                    .and_then(|_| writer.get_ref().sync_all())
+                    .and_then(|_| fs::rename(&temporary_path, &file_name))
+                    .map_err(|source| SimulatorError::Io {
+                        file_name: file_name.clone(),
+                        source,
+                    }),
+            }
+        }
+    }
+
+    impl Write for Output {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            match self {
+                Output::Stdout(writer) => writer.write(buf),
+                Output::Special { writer, .. } | Output::File { writer, .. } => writer.write(buf),
+            }
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            match self {
+                Output::Stdout(writer) => writer.flush(),
+                Output::Special { writer, .. } | Output::File { writer, .. } => writer.flush(),
+            }
+        }
+    }
+
+    impl Drop for Output {
+        /// Removes the temporary file if the output was never committed, after
+        /// a successful commit the file has already been renamed.
+        fn drop(&mut self) {
+            if let Output::File { temporary_path, .. } = self {
+                let _ = fs::remove_file(temporary_path);
+            }
+        }
+    }
+
+    /// Opens the file for buffered atomic writing, or the standard output if
+    /// the file name is "-".
+    pub fn open_output(file_name: &str) -> Result<Output, SimulatorError> {
+        if file_name == STDIO_FILE_NAME {
+            return Ok(Output::Stdout(BufWriter::new(io::stdout().lock())));
+        }
+        let io_error = |source| SimulatorError::Io {
+            file_name: file_name.to_string(),
+            source,
+        };
+        let special =
+            fs::metadata(file_name).is_ok_and(|metadata| !metadata.is_file() && !metadata.is_dir());
+        if special {
+            return Ok(Output::Special {
+                writer: BufWriter::new(File::create(file_name).map_err(io_error)?),
+                file_name: file_name.to_string(),
+            });
+        }
+        let temporary_path = temporary_path(file_name);
+        let file = File::create(&temporary_path).map_err(io_error)?;
+        Ok(Output::File {
+            writer: BufWriter::new(file),
+            temporary_path,
+            file_name: file_name.to_string(),
+        })
+    }
+
+    /// Hidden file next to the destination, on the same filesystem so that it
+    /// can be atomically renamed over it.
+    fn temporary_path(file_name: &str) -> PathBuf {
+        let path = Path::new(file_name);
+        let base_name = path
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_default();
+        path.with_file_name(format!(".{}.{}.tmp", base_name, process::id()))
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use std::{env, fs, io::Write, process};
+
+        use serde_json::json;
+
+        use crate::{
+            config::config::SimulatorContext,
+            model::blockchain::{Address, Amount, Block, Blockchain, Transaction},
+            node::miner::BlockBuilder,
+        };
+
+        use super::{
+            load_blockchain, load_headers, open_output, temporary_path, write_blockchain,
+            write_json_line, write_text, DataFormat, Output, OutputOptions,
+        };
+
+        /// Name of a file in the temporary directory unique to the process.
+        fn temporary_file(name: &str) -> String {
+            env::temp_dir()
+                .join(format!("{}-{}", process::id(), name))
+                .to_string_lossy()
+                .to_string()
+        }
+
+        /// Chain of three blocks with a transaction each, mined at
+        /// difficulty 0.
+        fn sample_chain() -> Blockchain {
+            let context = SimulatorContext::default();
+            let mut blocks: Vec<Block> = vec![];
+            for height in 0..3 {
+                let transaction = Transaction::builder()
+                    .sender(Address([1; 20]))
+                    .receiver(Address([2; 20]))
+                    .amount(Amount(height + 1))
+                    .build()
+                    .unwrap();
+                let builder = match blocks.last() {
+                    Some(previous) => BlockBuilder::on_top_of(&previous.header),
+                    None => BlockBuilder::genesis(),
+                };
+                let block = builder
+                    .difficulty(0)
+                    .timestamp(1_700_000_000 + height)
+                    .transactions(vec![transaction])
+                    .build()
+                    .mine(0, &context.nonce_search())
+                    .unwrap();
+                blocks.push(block);
+            }
+            Blockchain::from(blocks)
+        }
+
+        #[test]
+        fn files_are_replaced_through_a_temporary_file() {
+            let file_name = temporary_file("replaced.txt");
+            fs::write(&file_name, "old").unwrap();
+            write_text(&file_name, OutputOptions::default(), "new").unwrap();
+            assert_eq!(fs::read_to_string(&file_name).unwrap(), "new");
+            assert!(!temporary_path(&file_name).exists());
+
+            // Until the output is committed only the temporary file changes,
+            // and dropping the output removes it.
+            let mut output = open_output(&file_name).unwrap();
+            output.write_all(b"partial").unwrap();
+            output.flush().unwrap();
+            assert!(temporary_path(&file_name).exists());
+            drop(output);
+            assert!(!temporary_path(&file_name).exists());
+            assert_eq!(fs::read_to_string(&file_name).unwrap(), "new");
+            fs::remove_file(&file_name).unwrap();
+        }
+
+        #[cfg(unix)]
+        #[test]
+        fn special_files_are_written_in_place() {
+            let mut output = open_output("/dev/null").unwrap();
+            assert!(matches!(output, Output::Special { .. }));
+            output.write_all(b"discarded").unwrap();
+            output.commit().unwrap();
+            assert!(!fs::metadata("/dev/null").unwrap().is_file());
+            assert!(!temporary_path("/dev/null").exists());
+        }
+
+        #[test]
+        fn canonical_blockchain_round_trips_to_the_same_bytes() {
+            let chain = sample_chain();
+            let canonical = OutputOptions {
+                canonical: true,
+                ..OutputOptions::default()
+            };
+            let (first, second) = (
+                temporary_file("canonical-1.json"),
+                temporary_file("canonical-2.json"),
+            );
+            write_blockchain(&first, canonical, DataFormat::Json, &chain).unwrap();
+            let loaded = load_blockchain(&first, &SimulatorContext::default()).unwrap();
+            write_blockchain(&second, canonical, DataFormat::Json, &loaded).unwrap();
+            let (first_bytes, second_bytes) =
+                (fs::read(&first).unwrap(), fs::read(&second).unwrap());
+            fs::remove_file(&first).unwrap();
+            fs::remove_file(&second).unwrap();
+
+            assert_eq!(first_bytes, second_bytes);
+            assert!(!first_bytes.contains(&b'\n'));
+            let hashes = |chain: &Blockchain| -> Vec<_> {
+                chain.iter().map(|block| block.header.hash).collect()
+            };
+            assert_eq!(hashes(&loaded), hashes(&chain));
+        }
+
+        #[test]
+        fn headers_are_loaded_from_blockchain_files() {
+            let chain = sample_chain();
+            let context = SimulatorContext::default();
+            for file_name in [temporary_file("headers.json"), temporary_file("headers.pb")] {
+                write_blockchain(
+                    &file_name,
+                    OutputOptions::default(),
+                    DataFormat::Json,
+                    &chain,
+                )
+                .unwrap();
+                let headers = load_headers(&file_name, &context);
+                fs::remove_file(&file_name).unwrap();
+
+                let headers = headers.unwrap();
+                assert_eq!(headers.len(), chain.len());
+                for (header, block) in headers.iter().zip(chain.iter()) {
+                    assert_eq!(header.hash, block.header.hash);
+                    assert_eq!(header.transactions_count, 1);
+                }
+            }
+        }
+
+        #[test]
+        fn canonical_json_lines_have_sorted_keys() {
+            let value = json!({ "b": 1, "a": { "d": [{ "f": 2, "e": 3 }], "c": null } });
+            let mut canonical = vec![];
+            write_json_line(&mut canonical, "log.jsonl", true, &value).unwrap();
+            assert_eq!(
+                String::from_utf8(canonical).unwrap(),
+                "{\"a\":{\"c\":null,\"d\":[{\"e\":3,\"f\":2}]},\"b\":1}\n"
+            );
+        }
     }
 }
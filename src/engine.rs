@@ -0,0 +1,96 @@
+// This module provides the discrete-event engine driving the simulations on
+// a virtual clock
+pub mod engine {
+    use std::{
+        cmp::{Ordering, Reverse},
+        collections::BinaryHeap,
+    };
+
+    /// Event scheduled at a point of the virtual time.
+    struct Scheduled<E> {
+        time: f64,
+        /// Order in which the events were scheduled, so that the events
+        /// scheduled for the same time happen in that order.
+        sequence: u64,
+        event: E,
+    }
+
+    impl<E> PartialEq for Scheduled<E> {
+        fn eq(&self, other: &Self) -> bool {
+            self.cmp(other) == Ordering::Equal
+        }
+    }
+
+    impl<E> Eq for Scheduled<E> {}
+
+    impl<E> PartialOrd for Scheduled<E> {
+        fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+            Some(self.cmp(other))
+        }
+    }
+
+    impl<E> Ord for Scheduled<E> {
+        fn cmp(&self, other: &Self) -> Ordering {
+            self.time
+                .total_cmp(&other.time)
+                .then(self.sequence.cmp(&other.sequence))
+        }
+    }
+
+    /// Priority queue of the events of a simulation. The virtual clock jumps
+    /// from one event to the next, so the simulated time spans don't depend
+    /// on how fast the events are processed.
+    pub struct EventQueue<E> {
+        events: BinaryHeap<Reverse<Scheduled<E>>>,
+        sequence: u64,
+        /// Seconds from the start of the simulation at which the last event
+        /// happened
+        now: f64,
+    }
+
+    impl<E> Default for EventQueue<E> {
+        fn default() -> Self {
+            EventQueue {
+                events: BinaryHeap::new(),
+                sequence: 0,
+                now: 0.0,
+            }
+        }
+    }
+
+    impl<E> EventQueue<E> {
+        pub fn schedule(&mut self, time: f64, event: E) {
+            self.sequence += 1;
+            self.events.push(Reverse(Scheduled {
+                time,
+                sequence: self.sequence,
+                event,
+            }));
+        }
+
+        /// Advances the clock to the earliest event and returns it with its
+        /// time.
+        pub fn pop(&mut self) -> Option<(f64, E)> {
+            let Reverse(scheduled) = self.events.pop()?;
+            self.now = scheduled.time;
+            Some((scheduled.time, scheduled.event))
+        }
+
+        /// Like `pop`, but only returns events happening until the given time.
+        pub fn pop_until(&mut self, time: f64) -> Option<(f64, E)> {
+            if self
+                .events
+                .peek()
+                .is_some_and(|Reverse(scheduled)| scheduled.time <= time)
+            {
+                self.pop()
+            } else {
+                None
+            }
+        }
+
+        pub fn now(&self) -> f64 {
+            self.now
+        }
+    }
+}
@@ -0,0 +1,274 @@
+pub mod error {
+    use std::io;
+
+    use thiserror::Error;
+
+    use crate::model::blockchain::Address;
+
+    /// Errors that can occur while running any of the simulator commands. Each
+    /// kind of error maps to a distinct exit code so that scripts invoking the
+    /// simulator can tell them apart.
+    #[derive(Error, Debug)]
+    pub enum SimulatorError {
+        #[error("Unable to access the file {file_name}: {source}")]
+        Io {
+            file_name: String,
+            source: io::Error,
+        },
+
+        #[error("Connection to the peer {peer} failed: {source}")]
+        PeerConnection { peer: String, source: io::Error },
+
+        #[error("The JSON-RPC endpoint {endpoint} failed: {reason}")]
+        RemoteRpc { endpoint: String, reason: String },
+
+        #[error("The file {file_name} is not valid UTF-8")]
+        InvalidUtf8 { file_name: String },
+
+        #[error("The file {file_name} does not contain valid JSON: {source}")]
+        MalformedJson {
+            file_name: String,
+            source: serde_json::Error,
+        },
+
+        #[error("The file {file_name} does not contain a valid protobuf message: {reason}")]
+        MalformedProto { file_name: String, reason: String },
+
+        #[error("The value of {path} in the file {file_name} is invalid: {reason}")]
+        InvalidField {
+            file_name: String,
+            path: String,
+            reason: String,
+        },
+
+        #[error("Unable to serialize the contents of {file_name}: {source}")]
+        SerializationFailed {
+            file_name: String,
+            source: serde_json::Error,
+        },
+
+        #[error("The configuration file {file_name} is invalid: {source}")]
+        MalformedConfig {
+            file_name: String,
+            source: toml::de::Error,
+        },
+
+        #[error("The file {file_name} does not contain valid YAML: {source}")]
+        MalformedYaml {
+            file_name: String,
+            source: serde_yaml::Error,
+        },
+
+        #[error("The difficulty schedule {file_name} is invalid: {reason}")]
+        InvalidDifficultySchedule { file_name: String, reason: String },
+
+        #[error("The scenario is invalid: {0}")]
+        InvalidScenario(String),
+
+        #[error("The blockchain does not contain any blocks")]
+        EmptyBlockchain,
+
+        #[error("Block number {0} not found in the blockchain")]
+        BlockNotFound(usize),
+
+        #[error("Block number {0} has no transactions")]
+        EmptyBlock(usize),
+
+        #[error("No header at height {0} found in the header chain")]
+        HeightNotFound(u32),
+
+        #[error("The range of heights from {from} to {to} is empty")]
+        EmptyRange { from: u32, to: u32 },
+
+        #[error("Transaction number {transaction_number} not found in block {block_number}")]
+        TransactionNotFound {
+            block_number: usize,
+            transaction_number: usize,
+        },
+
+        #[error("Transaction {0} not found in the block")]
+        TransactionNotInBlock(String),
+
+        #[error("Transaction {0} not found in the blockchain")]
+        TransactionNotInChain(String),
+
+        #[error("Block {height} cannot be appended to the blockchain: {reason}")]
+        InvalidBlock { height: u32, reason: String },
+
+        #[error("The header of block {height} is invalid: {reason}")]
+        InvalidHeader { height: u32, reason: String },
+
+        #[error(
+            "No nonce from {first_nonce} up to the largest one gives block {height} a valid hash"
+        )]
+        NonceSpaceExhausted { height: u32, first_nonce: u32 },
+
+        #[error(
+            "The blockchain in {file_name} failed the strict load checks with {problems} problems"
+        )]
+        UnsoundChain { file_name: String, problems: usize },
+
+        #[error("Merkle root in the proof does not match the block merkle root")]
+        MerkleRootMismatch,
+
+        #[error("Inclusion proof verification failed")]
+        InvalidProof,
+
+        #[error("No block of the chain has the merkle root {0}")]
+        UnknownMerkleRoot(String),
+
+        #[error("{0} of the inclusion proofs failed verification")]
+        InvalidProofs(usize),
+
+        #[error("Invalid address {address}: {reason}")]
+        MalformedAddress { address: String, reason: String },
+
+        #[error("Invalid hash {hash}: {reason}")]
+        MalformedHash { hash: String, reason: String },
+
+        #[error(
+            "Invalid amount {0}: expected a number of base units or of coins, such as 1.5coins"
+        )]
+        MalformedAmount(String),
+
+        #[error("Invalid transaction: {0}")]
+        InvalidTransaction(String),
+
+        #[error("The miner {address} is invalid: {reason}")]
+        InvalidMiner { address: Address, reason: String },
+
+        #[error("No private key for the address {0} found in the wallet")]
+        KeyNotFound(Address),
+
+        #[error("The private key {0} is not a valid DER-encoded P-256 key")]
+        MalformedKey(String),
+
+        #[error("The private key belongs to {address} but the transaction is sent by {sender}")]
+        KeyMismatch { address: Address, sender: Address },
+
+        #[error("Invalid key derivation path {0}")]
+        InvalidDerivationPath(String),
+
+        #[error("Invalid mnemonic phrase: {0}")]
+        MalformedMnemonic(String),
+
+        #[error("Unable to sign the transaction")]
+        SigningFailed,
+
+        #[error("The blockchain contains {0} transactions with invalid signatures")]
+        InvalidSignatures(usize),
+
+        #[error("Block {height} has the hash {hash} instead of the checkpoint {expected}")]
+        CheckpointMismatch {
+            height: u32,
+            hash: String,
+            expected: String,
+        },
+
+        #[error("Coins were created or destroyed incorrectly in {0} blocks")]
+        SupplyMismatch(usize),
+
+        #[error("The merkle roots of {0} blocks don't match their transactions")]
+        MerkleRootsMismatch(usize),
+
+        #[error("{0} headers failed the audit of their hash, proof of work or link")]
+        InvalidHeaders(usize),
+
+        #[error("{0} blocks failed the integrity checks")]
+        TamperedBlocks(usize),
+
+        #[error("The {0} overflow the 64 bits of an amount")]
+        AmountOverflow(String),
+
+        #[error("The replay diverged from the recorded run: {0}")]
+        ReplayMismatch(String),
+
+        #[error("Invalid peer graph: {0}")]
+        InvalidTopology(String),
+
+        #[error("No node with the address {0} found in the fleet")]
+        NodeNotFound(Address),
+
+        #[error("No miner is active at time {0} and none joins later")]
+        NoActiveMiners(f64),
+
+        #[error("The chart can't be drawn: {0}")]
+        InvalidChart(String),
+
+        #[error("Unable to draw the chart: {0}")]
+        PlotFailed(String),
+
+        #[error("The hasher {hasher} failed: {reason}")]
+        HasherFailed { hasher: String, reason: String },
+    }
+
+    impl SimulatorError {
+        /// Exit code of the process when the command fails with this error:
+        /// 2 for I/O errors, 3 for malformed input, 4 if a block, transaction
+        /// or node isn't found, 5 for invalid blocks and failed audits, 6 for
+        /// key errors, 7 for invalid signatures, 8 if the hasher fails, 9 if
+        /// no miners are active and 10 for invalid inclusion proofs.
+        pub fn exit_code(&self) -> i32 {
+            match self {
+                SimulatorError::Io { .. }
+                | SimulatorError::PeerConnection { .. }
+                | SimulatorError::RemoteRpc { .. }
+                | SimulatorError::SerializationFailed { .. }
+                | SimulatorError::PlotFailed(_) => 2,
+                SimulatorError::InvalidUtf8 { .. }
+                | SimulatorError::MalformedJson { .. }
+                | SimulatorError::MalformedProto { .. }
+                | SimulatorError::InvalidField { .. }
+                | SimulatorError::MalformedConfig { .. }
+                | SimulatorError::MalformedYaml { .. }
+                | SimulatorError::InvalidDifficultySchedule { .. }
+                | SimulatorError::InvalidScenario(_)
+                | SimulatorError::MalformedAddress { .. }
+                | SimulatorError::MalformedHash { .. }
+                | SimulatorError::MalformedAmount(_)
+                | SimulatorError::InvalidTransaction(_)
+                | SimulatorError::InvalidTopology(_)
+                | SimulatorError::InvalidMiner { .. }
+                | SimulatorError::InvalidChart(_) => 3,
+                SimulatorError::EmptyBlockchain
+                | SimulatorError::BlockNotFound(_)
+                | SimulatorError::EmptyBlock(_)
+                | SimulatorError::HeightNotFound(_)
+                | SimulatorError::EmptyRange { .. }
+                | SimulatorError::TransactionNotFound { .. }
+                | SimulatorError::TransactionNotInBlock(_)
+                | SimulatorError::TransactionNotInChain(_)
+                | SimulatorError::NodeNotFound(_) => 4,
+                SimulatorError::InvalidBlock { .. }
+                | SimulatorError::InvalidHeader { .. }
+                | SimulatorError::NonceSpaceExhausted { .. }
+                | SimulatorError::UnsoundChain { .. }
+                | SimulatorError::CheckpointMismatch { .. }
+                | SimulatorError::SupplyMismatch(_)
+                | SimulatorError::MerkleRootsMismatch(_)
+                | SimulatorError::InvalidHeaders(_)
+                | SimulatorError::TamperedBlocks(_)
+                | SimulatorError::AmountOverflow(_)
+                | SimulatorError::ReplayMismatch(_) => 5,
+                SimulatorError::KeyNotFound(_)
+                | SimulatorError::MalformedKey(_)
+                | SimulatorError::KeyMismatch { .. }
+                | SimulatorError::InvalidDerivationPath(_)
+                | SimulatorError::MalformedMnemonic(_)
+                | SimulatorError::SigningFailed => 6,
+                SimulatorError::InvalidSignatures(_) => 7,
+                SimulatorError::HasherFailed { .. } => 8,
+                SimulatorError::NoActiveMiners(_) => 9,
+                SimulatorError::MerkleRootMismatch
+                | SimulatorError::InvalidProof
+                | SimulatorError::UnknownMerkleRoot(_)
+                | SimulatorError::InvalidProofs(_) => 10,
+            }
+        }
+    }
+
+    /// Exit code of the process when the command line can't be parsed, which
+    /// is EX_USAGE from sysexits.h. Clap would exit with 2, the code of the
+    /// I/O errors.
+    pub const USAGE_EXIT_CODE: i32 = 64;
+}
@@ -0,0 +1,307 @@
+// This module exports the blockchain in the layout of the REST API of the
+// Esplora block explorer
+pub mod explorer {
+    use std::{fs, path::Path};
+
+    use log::info;
+    use serde::Serialize;
+
+    use crate::{
+        args::args::ExportExplorerArgs,
+        config::config::SimulatorContext,
+        data_sourcing::data_provider::{
+            load_blockchain, write_json, write_text, Compression, OutputOptions,
+        },
+        error::error::SimulatorError,
+        hashing::hashing::Hashable,
+        model::blockchain::{
+            compact_target, Address, Amount, Block, Blockchain, Hash256, Header, Transaction,
+            NULL_ADDRESS,
+        },
+    };
+
+    /// Number of transactions in a page of `block/<hash>/txs`.
+    const TRANSACTIONS_PER_PAGE: usize = 25;
+
+    /// Number of blocks listed by `blocks/<height>`.
+    const BLOCKS_PER_PAGE: usize = 10;
+
+    /// Number of blocks whose median timestamp is the median time of a block.
+    const MEDIAN_TIME_SPAN: usize = 11;
+
+    /// Index of the output spent by coinbase inputs, which spend none.
+    const COINBASE_VOUT: u32 = u32::MAX;
+
+    #[derive(Serialize)]
+    struct EsploraBlock {
+        id: String,
+        height: u32,
+        version: u32,
+        timestamp: u64,
+        tx_count: u32,
+        size: usize,
+        weight: usize,
+        merkle_root: String,
+        previousblockhash: Option<String>,
+        mediantime: u64,
+        nonce: u32,
+        bits: u32,
+        difficulty: f64,
+    }
+
+    #[derive(Serialize)]
+    struct BlockStatus {
+        in_best_chain: bool,
+        height: u32,
+        next_best: Option<String>,
+    }
+
+    #[derive(Serialize)]
+    struct TransactionStatus {
+        confirmed: bool,
+        block_height: u32,
+        block_hash: String,
+        block_time: u64,
+    }
+
+    /// Output paying to an address, as a witness program of the 20 bytes of
+    /// the address.
+    #[derive(Serialize)]
+    struct Output {
+        scriptpubkey: String,
+        scriptpubkey_asm: String,
+        scriptpubkey_type: String,
+        scriptpubkey_address: String,
+        value: Amount,
+    }
+
+    #[derive(Serialize)]
+    struct Input {
+        txid: String,
+        vout: u32,
+        prevout: Option<Output>,
+        scriptsig: String,
+        scriptsig_asm: String,
+        is_coinbase: bool,
+        sequence: u32,
+    }
+
+    #[derive(Serialize)]
+    struct EsploraTransaction {
+        txid: String,
+        version: u32,
+        locktime: u32,
+        vin: Vec<Input>,
+        vout: Vec<Output>,
+        size: usize,
+        weight: usize,
+        fee: Amount,
+        status: TransactionStatus,
+    }
+
+    /// Hashes are shown by Esplora without the 0x prefix.
+    fn esplora_hash(hash: &Hash256) -> String {
+        hash.hex()
+    }
+
+    fn output(address: Address, value: Amount) -> Output {
+        let program = hex::encode(address.0);
+        Output {
+            scriptpubkey: format!("0014{}", program),
+            scriptpubkey_asm: format!("OP_0 OP_PUSHBYTES_20 {}", program),
+            scriptpubkey_type: "v0_p2wpkh".to_string(),
+            scriptpubkey_address: address.to_string(),
+            value,
+        }
+    }
+
+    /// The transfer as a transaction with a single input spending the
+    /// amount and the fee from the sender and a single output paying the
+    /// amount to the receiver. Accounts have no previous outputs, so the
+    /// input refers to none, and transactions from the null address are
+    /// shown as coinbase transactions.
+    fn esplora_transaction(
+        transaction: &Transaction,
+        header: &Header,
+    ) -> Result<EsploraTransaction, SimulatorError> {
+        let is_coinbase = transaction.sender == NULL_ADDRESS;
+        let size = transaction.serialized_size();
+        let spent = transaction
+            .amount
+            .checked_add(transaction.transaction_fee)
+            .ok_or_else(|| {
+                SimulatorError::AmountOverflow(format!(
+                    "amount and fee of the transaction {}",
+                    transaction.hash()
+                ))
+            })?;
+        Ok(EsploraTransaction {
+            txid: esplora_hash(&transaction.hash()),
+            version: 1,
+            locktime: transaction.lock_time,
+            vin: vec![Input {
+                txid: "0".repeat(64),
+                vout: if is_coinbase { COINBASE_VOUT } else { 0 },
+                prevout: (!is_coinbase).then(|| output(transaction.sender, spent)),
+                scriptsig: "".to_string(),
+                scriptsig_asm: "".to_string(),
+                is_coinbase,
+                sequence: u32::MAX,
+            }],
+            vout: vec![output(transaction.receiver, transaction.amount)],
+            size,
+            weight: 4 * size,
+            fee: transaction.transaction_fee,
+            status: TransactionStatus {
+                confirmed: true,
+                block_height: header.height,
+                block_hash: esplora_hash(&header.hash),
+                block_time: header.timestamp,
+            },
+        })
+    }
+
+    /// The block, with the timestamps of the blocks up to and including it
+    /// to compute its median time from.
+    fn esplora_block(block: &Block, timestamps: &[u64]) -> EsploraBlock {
+        let header = &block.header;
+        let mut recent = timestamps[timestamps.len().saturating_sub(MEDIAN_TIME_SPAN)..].to_vec();
+        recent.sort_unstable();
+        let size = serde_json::to_vec(block)
+            .expect("blocks always serialize")
+            .len();
+        EsploraBlock {
+            id: esplora_hash(&header.hash),
+            height: header.height,
+            version: header.version,
+            timestamp: header.timestamp,
+            tx_count: header.transactions_count,
+            size,
+            weight: 4 * size,
+            merkle_root: esplora_hash(&header.transactions_merkle_root),
+            previousblockhash: (header.height > 0)
+                .then(|| esplora_hash(&header.previous_block_header_hash)),
+            mediantime: recent[recent.len() / 2],
+            nonce: header.nonce,
+            bits: compact_target(header.difficulty),
+            difficulty: 16f64.powi(header.difficulty as i32),
+        }
+    }
+
+    /// Name of the file of the document at the path relative to the output
+    /// directory, whose parent directories are created.
+    fn document(output_dir: &Path, path: &str) -> Result<String, SimulatorError> {
+        let file_name = output_dir.join(path);
+        if let Some(parent) = file_name.parent() {
+            fs::create_dir_all(parent).map_err(|source| SimulatorError::Io {
+                file_name: parent.to_string_lossy().to_string(),
+                source,
+            })?;
+        }
+        Ok(file_name.to_string_lossy().to_string())
+    }
+
+    /// Writes the blocks and transactions of the blockchain as the static
+    /// responses of the Esplora REST API, so that an Esplora frontend can use
+    /// the output directory as its API. Paths that are also the prefix of
+    /// other paths, such as `block/<hash>` and `block/<hash>/txids`, hold
+    /// their response in an `index.json` file in their directory.
+    pub fn export_explorer(
+        args: ExportExplorerArgs,
+        context: &SimulatorContext,
+    ) -> Result<(), SimulatorError> {
+        info!("Loading the blockchain from {}", args.blockchain_state);
+        let blockchain = load_blockchain(&args.blockchain_state, context)?;
+        let tip = blockchain.tip_header()?;
+        let output_dir = Path::new(&args.output_dir);
+        // The documents are served as they are, so they are never compressed.
+        let output = OutputOptions {
+            compression: Compression::None,
+            ..context.output
+        };
+        info!(
+            "Writing the explorer documents of {} blocks to {}",
+            blockchain.len(),
+            args.output_dir
+        );
+
+        let mut timestamps = vec![];
+        let mut esplora_blocks = vec![];
+        for (index, block) in blockchain.iter().enumerate() {
+            timestamps.push(block.header.timestamp);
+            let esplora_block = esplora_block(block, &timestamps);
+            let path = format!("block/{}/index.json", esplora_block.id);
+            write_json(&document(output_dir, &path)?, output, &esplora_block)?;
+            let path = format!("block-height/{}", block.header.height);
+            write_text(&document(output_dir, &path)?, output, &esplora_block.id)?;
+            write_block(output_dir, output, &blockchain, index, block)?;
+            esplora_blocks.push(esplora_block);
+
+            let page: Vec<&EsploraBlock> =
+                esplora_blocks.iter().rev().take(BLOCKS_PER_PAGE).collect();
+            let path = format!("blocks/{}", block.header.height);
+            write_json(&document(output_dir, &path)?, output, &page)?;
+        }
+
+        let page: Vec<&EsploraBlock> = esplora_blocks.iter().rev().take(BLOCKS_PER_PAGE).collect();
+        let path = document(output_dir, "blocks/index.json")?;
+        write_json(&path, output, &page)?;
+        let path = document(output_dir, "blocks/tip/height")?;
+        write_text(&path, output, &tip.height.to_string())?;
+        let path = document(output_dir, "blocks/tip/hash")?;
+        write_text(&path, output, &esplora_hash(&tip.hash))
+    }
+
+    /// Writes the status and the transactions of the block at the index of
+    /// the blockchain.
+    fn write_block(
+        output_dir: &Path,
+        output: OutputOptions,
+        blockchain: &Blockchain,
+        index: usize,
+        block: &Block,
+    ) -> Result<(), SimulatorError> {
+        let header = &block.header;
+        let hash = esplora_hash(&header.hash);
+        let status = BlockStatus {
+            in_best_chain: true,
+            height: header.height,
+            next_best: blockchain
+                .blocks()
+                .get(index + 1)
+                .map(|next| esplora_hash(&next.header.hash)),
+        };
+        let path = format!("block/{}/status", hash);
+        write_json(&document(output_dir, &path)?, output, &status)?;
+
+        let transactions: Vec<EsploraTransaction> = block
+            .transactions
+            .iter()
+            .map(|transaction| esplora_transaction(transaction, header))
+            .collect::<Result<_, _>>()?;
+        let txids: Vec<&String> = transactions.iter().map(|t| &t.txid).collect();
+        let path = format!("block/{}/txids", hash);
+        write_json(&document(output_dir, &path)?, output, &txids)?;
+        // Blocks without transactions still have an empty first page.
+        let mut pages: Vec<&[EsploraTransaction]> =
+            transactions.chunks(TRANSACTIONS_PER_PAGE).collect();
+        if pages.is_empty() {
+            pages.push(&[]);
+        }
+        for (number, page) in pages.into_iter().enumerate() {
+            let path = format!("block/{}/txs/{}", hash, number * TRANSACTIONS_PER_PAGE);
+            write_json(&document(output_dir, &path)?, output, &page)?;
+            if number == 0 {
+                let path = format!("block/{}/txs/index.json", hash);
+                write_json(&document(output_dir, &path)?, output, &page)?;
+            }
+        }
+        for transaction in &transactions {
+            let path = format!("tx/{}/index.json", transaction.txid);
+            write_json(&document(output_dir, &path)?, output, transaction)?;
+            let path = format!("tx/{}/status", transaction.txid);
+            write_json(&document(output_dir, &path)?, output, &transaction.status)?;
+        }
+        Ok(())
+    }
+}
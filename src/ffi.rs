@@ -0,0 +1,95 @@
+// This module exposes the verification of proofs and headers and the hashing
+// of transactions through a C ABI
+pub mod ffi {
+    use std::{
+        ffi::{c_char, c_int, CStr},
+        ptr,
+    };
+
+    use serde::de::DeserializeOwned;
+
+    use crate::{
+        hashing::hashing::Hashable,
+        model::blockchain::{Header, HeaderFormat, InclusionProof, Transaction},
+    };
+
+    /// Returned when the check passes.
+    pub const BSIM_VALID: c_int = 1;
+    /// Returned when the check fails.
+    pub const BSIM_INVALID: c_int = 0;
+    /// Returned when the input is missing, not UTF-8 or not in the format of
+    /// the JSON files.
+    pub const BSIM_MALFORMED_INPUT: c_int = -1;
+    /// Returned when the output buffer can't hold the result and its
+    /// terminating NUL.
+    pub const BSIM_BUFFER_TOO_SMALL: c_int = -2;
+
+    /// Parses the NUL-terminated JSON string, or returns none if it is null
+    /// or malformed.
+    ///
+    /// # Safety
+    ///
+    /// `json` must be null or point to a NUL-terminated string.
+    unsafe fn parse<T: DeserializeOwned>(json: *const c_char) -> Option<T> {
+        if json.is_null() {
+            return None;
+        }
+        let json = CStr::from_ptr(json).to_str().ok()?;
+        serde_json::from_str(json).ok()
+    }
+
+    /// Checks that the inclusion proof, given as JSON in the format written
+    /// by `GenerateInclusionProof`, leads to its merkle root.
+    ///
+    /// # Safety
+    ///
+    /// `proof_json` must be null or point to a NUL-terminated string.
+    #[no_mangle]
+    pub unsafe extern "C" fn bsim_verify_inclusion_proof(proof_json: *const c_char) -> c_int {
+        match parse::<InclusionProof>(proof_json) {
+            Some(proof) if proof.verify().is_ok() => BSIM_VALID,
+            Some(_) => BSIM_INVALID,
+            None => BSIM_MALFORMED_INPUT,
+        }
+    }
+
+    /// Checks that the header, given as JSON, hashes to its stored hash in
+    /// the simulator header format and satisfies the proof of work required by its difficulty.
+    ///
+    /// # Safety
+    ///
+    /// `header_json` must be null or point to a NUL-terminated string.
+    #[no_mangle]
+    pub unsafe extern "C" fn bsim_verify_header_pow(header_json: *const c_char) -> c_int {
+        match parse::<Header>(header_json) {
+            Some(header) if header.validate(HeaderFormat::Simulator).is_ok() => BSIM_VALID,
+            Some(_) => BSIM_INVALID,
+            None => BSIM_MALFORMED_INPUT,
+        }
+    }
+
+    /// Writes the hash of the transaction, given as JSON, into the buffer as
+    /// a NUL-terminated string of 64 hex digits. Returns zero on success.
+    ///
+    /// # Safety
+    ///
+    /// `transaction_json` must be null or point to a NUL-terminated string,
+    /// and `output` must be valid for writes of `output_len` bytes.
+    #[no_mangle]
+    pub unsafe extern "C" fn bsim_transaction_hash(
+        transaction_json: *const c_char,
+        output: *mut c_char,
+        output_len: usize,
+    ) -> c_int {
+        let Some(transaction) = parse::<Transaction>(transaction_json) else {
+            return BSIM_MALFORMED_INPUT;
+        };
+        let hash = transaction.hash().to_string();
+        if output.is_null() || output_len <= hash.len() {
+            return BSIM_BUFFER_TOO_SMALL;
+        }
+        ptr::copy_nonoverlapping(hash.as_ptr().cast::<c_char>(), output, hash.len());
+        *output.add(hash.len()) = 0;
+        0
+    }
+}
@@ -0,0 +1,156 @@
+// This module generates synthetic mempools for the simulation
+pub mod generator {
+    use log::info;
+    use rand::{Rng, RngCore, SeedableRng};
+    use rand_chacha::ChaCha20Rng;
+    use rand_distr::{Distribution, Exp, LogNormal, Zipf};
+    use serde::Serialize;
+
+    use crate::{
+        args::args::GenerateTransactionsArgs,
+        config::config::SimulatorContext,
+        data_sourcing::data_provider::{write_json, write_transactions},
+        error::error::SimulatorError,
+        hashing::hashing::Hashable,
+        model::blockchain::{Address, Amount, Hash256, Transaction},
+        wallet::{
+            hd::{derive_accounts, parse_mnemonic, ExtendedKey},
+            wallet::Wallet,
+        },
+    };
+
+    /// Highest fee drawn when the fees are uniform, which is the upper end of
+    /// the fees found in the tutorial mempool.
+    const MAX_UNIFORM_FEE: u64 = 100;
+
+    #[derive(Serialize)]
+    struct Arrival {
+        transaction_hash: Hash256,
+        /// Seconds since the start of the generated traffic
+        arrival_time: f64,
+    }
+
+    /// Picks the accounts sending and receiving the transactions, either
+    /// uniformly or with the popularity of the accounts following Zipf's law,
+    /// so that the first accounts are involved in most of the transactions.
+    enum AccountPicker {
+        Uniform(usize),
+        Zipf(Zipf<f64>),
+    }
+
+    impl AccountPicker {
+        fn pick(&self, rng: &mut impl Rng) -> usize {
+            match self {
+                AccountPicker::Uniform(count) => rng.gen_range(0..*count),
+                AccountPicker::Zipf(zipf) => zipf.sample(rng) as usize - 1,
+            }
+        }
+
+        fn pick_pair(&self, rng: &mut impl Rng) -> (usize, usize) {
+            let sender = self.pick(rng);
+            let receiver = match self {
+                AccountPicker::Uniform(count) => {
+                    // Skip over the sender so that nobody sends coins to themselves.
+                    let receiver = rng.gen_range(0..count - 1);
+                    receiver + (receiver >= sender) as usize
+                }
+                AccountPicker::Zipf(_) => loop {
+                    let receiver = self.pick(rng);
+                    if receiver != sender {
+                        break receiver;
+                    }
+                },
+            };
+            (sender, receiver)
+        }
+    }
+
+    pub fn generate_transactions(
+        args: GenerateTransactionsArgs,
+        context: &SimulatorContext,
+    ) -> Result<(), SimulatorError> {
+        let mut rng = ChaCha20Rng::seed_from_u64(args.seed);
+
+        info!("Deriving the keys of {} accounts...", args.accounts);
+        let mut hd_seed = [0u8; 64];
+        rng.fill_bytes(&mut hd_seed);
+        if let Some(phrase) = &args.mnemonic {
+            hd_seed = parse_mnemonic(phrase)?.to_seed(args.passphrase.as_str());
+        }
+
+        let mut wallet = Wallet::default();
+        let accounts: Vec<(Address, ExtendedKey)> = derive_accounts(&hd_seed, args.accounts)?
+            .into_iter()
+            .map(|key| (wallet.insert(&key.signing_key), key))
+            .collect();
+
+        let picker = match args.zipf_exponent {
+            Some(exponent) => AccountPicker::Zipf(
+                Zipf::new(accounts.len() as u64, exponent).expect("exponent is positive"),
+            ),
+            None => AccountPicker::Uniform(accounts.len()),
+        };
+        let fees = args
+            .fee_sigma
+            .map(|sigma| LogNormal::new(args.fee_median.ln(), sigma).expect("sigma is positive"));
+        let arrivals = args
+            .arrival_rate
+            .map(|rate| Exp::new(rate).expect("rate is positive"));
+
+        info!("Generating {} transactions...", args.transactions);
+        let mut arrival_time = 0.0;
+        let mut arrival_times = vec![];
+        let transactions = (0..args.transactions)
+            .map(|_| {
+                let (sender, receiver) = picker.pick_pair(&mut rng);
+                let amount = Amount(rng.gen_range(1..=100_000_000));
+                let fee = Amount(match &fees {
+                    // The tail of the distribution is kept, a fee is only
+                    // raised to the smallest one that can be paid.
+                    Some(fees) => (fees.sample(&mut rng).round() as u64).max(1),
+                    None => rng.gen_range(1..=MAX_UNIFORM_FEE),
+                });
+                if let Some(arrivals) = &arrivals {
+                    arrival_time += arrivals.sample(&mut rng);
+                    arrival_times.push(arrival_time);
+                }
+                let (sender_address, sender_key) = &accounts[sender];
+                Transaction::builder()
+                    .amount(amount)
+                    .lock_time(args.lock_time)
+                    .receiver(accounts[receiver].0)
+                    .sender(sender_address)
+                    .transaction_fee(fee)
+                    .signed_with(&sender_key.signing_key)
+                    .build()
+            })
+            .collect::<Result<Vec<Transaction>, SimulatorError>>()?;
+
+        if let Some(arrivals_output) = &args.arrivals_output {
+            info!(
+                "Saving the arrival times of the transactions to {}",
+                arrivals_output
+            );
+            let arrivals: Vec<Arrival> = transactions
+                .iter()
+                .zip(arrival_times)
+                .map(|(transaction, arrival_time)| Arrival {
+                    transaction_hash: transaction.hash(),
+                    arrival_time,
+                })
+                .collect();
+            write_json(arrivals_output, context.output, &arrivals)?;
+        }
+
+        if let Some(wallet_output) = &args.wallet_output {
+            info!("Saving the keys of the accounts to {}", wallet_output);
+            wallet.save(wallet_output, context.output)?;
+        }
+        write_transactions(
+            &args.mempool_output,
+            context.output,
+            context.data_format,
+            &transactions,
+        )
+    }
+}
@@ -0,0 +1,288 @@
+// This module gossips the blocks and transactions of a node over libp2p and
+// synchronises its chain with a peer's through requests and responses
+pub mod gossip {
+    use std::{
+        io,
+        net::SocketAddr,
+        sync::{mpsc::channel, Arc},
+        thread,
+        time::Duration,
+    };
+
+    use libp2p::{
+        futures::StreamExt,
+        gossipsub::{self, IdentTopic, MessageAuthenticity},
+        mdns,
+        multiaddr::Protocol,
+        noise,
+        request_response::{self, ProtocolSupport},
+        swarm::{behaviour::toggle::Toggle, NetworkBehaviour, SwarmEvent},
+        tcp, yamux, Multiaddr, PeerId, StreamProtocol, Swarm,
+    };
+    use log::{debug, info, warn};
+    use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver};
+
+    use crate::{
+        error::error::SimulatorError,
+        p2p::p2p::{Message, Node, MAX_MESSAGE_SIZE},
+    };
+
+    const BLOCKS_TOPIC: &str = "blocks";
+    const TRANSACTIONS_TOPIC: &str = "transactions";
+    const SYNC_PROTOCOL: &str = "/blockchain-simulator/sync/1";
+
+    #[derive(NetworkBehaviour)]
+    struct GossipBehaviour {
+        gossipsub: gossipsub::Behaviour,
+        mdns: Toggle<mdns::tokio::Behaviour>,
+        /// Answers the requests for headers and blocks of a synchronising
+        /// peer with all the messages a TCP peer would send back
+        sync: request_response::json::Behaviour<Message, Vec<Message>>,
+    }
+
+    /// Starts gossiping on a background thread. The node publishes the
+    /// blocks and transactions it accepts on the gossipsub topics and
+    /// receives the ones published by its peers, which are found through
+    /// the bootstrap addresses and, if enabled, mDNS. When a gossiped block
+    /// doesn't extend the tip, the node synchronises with the peer that
+    /// relayed it by sending its requests directly to that peer.
+    pub fn start_gossip(
+        node: Arc<Node>,
+        listen: &str,
+        bootstrap: &[String],
+        mdns: bool,
+    ) -> Result<(), SimulatorError> {
+        let failed = |reason: String| SimulatorError::PeerConnection {
+            peer: listen.to_string(),
+            source: io::Error::other(reason),
+        };
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .map_err(|error| failed(error.to_string()))?;
+        // The transport needs the runtime when it is created and dialing.
+        let guard = runtime.enter();
+        let mut swarm = build_swarm(mdns).map_err(failed)?;
+        swarm
+            .listen_on(to_multiaddr(listen).map_err(failed)?)
+            .map_err(|error| failed(error.to_string()))?;
+        for peer in bootstrap {
+            match to_multiaddr(peer) {
+                Ok(address) => {
+                    if let Err(error) = swarm.dial(address) {
+                        warn!("Unable to dial the bootstrap peer {}: {}", peer, error);
+                    }
+                }
+                Err(error) => warn!("Skipping the bootstrap peer {}: {}", peer, error),
+            }
+        }
+        drop(guard);
+
+        // The node queues the messages on a blocking channel, which is
+        // forwarded to the asynchronous swarm loop.
+        let (outbox, inbox) = channel();
+        let (forward, published) = unbounded_channel();
+        let id = node.register(outbox);
+        thread::spawn(move || {
+            for message in inbox {
+                if forward.send(message).is_err() {
+                    break;
+                }
+            }
+        });
+        thread::spawn(move || runtime.block_on(run_swarm(swarm, node, id, published)));
+        Ok(())
+    }
+
+    fn build_swarm(mdns: bool) -> Result<Swarm<GossipBehaviour>, String> {
+        let swarm = libp2p::SwarmBuilder::with_new_identity()
+            .with_tokio()
+            .with_tcp(
+                tcp::Config::default(),
+                noise::Config::new,
+                yamux::Config::default,
+            )
+            .map_err(|error| error.to_string())?
+            .with_behaviour(|key| {
+                let config = gossipsub::ConfigBuilder::default()
+                    .max_transmit_size(MAX_MESSAGE_SIZE as usize)
+                    .build()
+                    .map_err(|error| error.to_string())?;
+                let mut gossipsub =
+                    gossipsub::Behaviour::new(MessageAuthenticity::Signed(key.clone()), config)?;
+                for topic in [BLOCKS_TOPIC, TRANSACTIONS_TOPIC] {
+                    gossipsub
+                        .subscribe(&IdentTopic::new(topic))
+                        .map_err(|error| error.to_string())?;
+                }
+                let mdns = match mdns {
+                    true => Some(mdns::tokio::Behaviour::new(
+                        mdns::Config::default(),
+                        key.public().to_peer_id(),
+                    )?),
+                    false => None,
+                };
+                let sync = request_response::json::Behaviour::new(
+                    [(StreamProtocol::new(SYNC_PROTOCOL), ProtocolSupport::Full)],
+                    request_response::Config::default(),
+                );
+                Ok(GossipBehaviour {
+                    gossipsub,
+                    mdns: Toggle::from(mdns),
+                    sync,
+                })
+            })
+            .map_err(|error| error.to_string())?
+            .with_swarm_config(|config| {
+                config.with_idle_connection_timeout(Duration::from_secs(60))
+            })
+            .build();
+        Ok(swarm)
+    }
+
+    /// Parses a multiaddress, or a socket address as the equivalent TCP
+    /// multiaddress.
+    fn to_multiaddr(address: &str) -> Result<Multiaddr, String> {
+        match address.parse::<SocketAddr>() {
+            Ok(socket) => Ok(Multiaddr::from(socket.ip()).with(Protocol::Tcp(socket.port()))),
+            Err(_) => address.parse().map_err(|error| format!("{}", error)),
+        }
+    }
+
+    async fn run_swarm(
+        mut swarm: Swarm<GossipBehaviour>,
+        node: Arc<Node>,
+        id: usize,
+        mut published: UnboundedReceiver<Arc<Message>>,
+    ) {
+        // Peer that relayed the last block, which is asked for the branch of
+        // the block if it doesn't extend the tip.
+        let mut sync_peer = None;
+        loop {
+            tokio::select! {
+                Some(message) = published.recv() => {
+                    publish(&mut swarm, &node, id, sync_peer, &message)
+                }
+                event = swarm.select_next_some() => {
+                    handle_event(&mut swarm, &node, id, &mut sync_peer, event)
+                }
+            }
+        }
+    }
+
+    fn publish(
+        swarm: &mut Swarm<GossipBehaviour>,
+        node: &Node,
+        id: usize,
+        sync_peer: Option<PeerId>,
+        message: &Message,
+    ) {
+        let topic = match message {
+            Message::Block(_) => BLOCKS_TOPIC,
+            Message::Transaction(_) => TRANSACTIONS_TOPIC,
+            Message::GetHeaders { .. } | Message::GetBlocks(_) => {
+                let peer = sync_peer.or_else(|| swarm.connected_peers().next().copied());
+                match peer {
+                    Some(peer) => {
+                        swarm
+                            .behaviour_mut()
+                            .sync
+                            .send_request(&peer, message.clone());
+                    }
+                    None => node.abandon_sync(id),
+                }
+                return;
+            }
+            // The tip reaches the peers as the blocks are gossiped.
+            _ => return,
+        };
+        let data = serde_json::to_vec(message).unwrap();
+        if let Err(error) = swarm
+            .behaviour_mut()
+            .gossipsub
+            .publish(IdentTopic::new(topic), data)
+        {
+            debug!("Unable to publish on the topic {}: {}", topic, error);
+        }
+    }
+
+    fn handle_event(
+        swarm: &mut Swarm<GossipBehaviour>,
+        node: &Node,
+        id: usize,
+        sync_peer: &mut Option<PeerId>,
+        event: SwarmEvent<GossipBehaviourEvent>,
+    ) {
+        match event {
+            SwarmEvent::NewListenAddr { address, .. } => info!(
+                "Listening for peers on {}/p2p/{}",
+                address,
+                swarm.local_peer_id()
+            ),
+            SwarmEvent::ConnectionEstablished { peer_id, .. } => {
+                info!("Connected to the peer {}", peer_id)
+            }
+            SwarmEvent::Behaviour(GossipBehaviourEvent::Mdns(mdns::Event::Discovered(peers))) => {
+                for (peer_id, address) in peers {
+                    info!("Discovered the peer {} at {}", peer_id, address);
+                    swarm.behaviour_mut().gossipsub.add_explicit_peer(&peer_id);
+                }
+            }
+            SwarmEvent::Behaviour(GossipBehaviourEvent::Mdns(mdns::Event::Expired(peers))) => {
+                for (peer_id, _) in peers {
+                    swarm
+                        .behaviour_mut()
+                        .gossipsub
+                        .remove_explicit_peer(&peer_id);
+                }
+            }
+            SwarmEvent::Behaviour(GossipBehaviourEvent::Gossipsub(gossipsub::Event::Message {
+                propagation_source,
+                message,
+                ..
+            })) => match serde_json::from_slice(&message.data) {
+                // Gossipsub relays the messages itself, so they are not
+                // published again.
+                Ok(Message::Block(block)) => {
+                    *sync_peer = Some(propagation_source);
+                    node.receive_block(block, Some(id))
+                }
+                Ok(Message::Transaction(transaction)) => node.receive_transaction(transaction, id),
+                Ok(_) => {}
+                Err(error) => warn!(
+                    "Ignoring a malformed message from {}: {}",
+                    propagation_source, error
+                ),
+            },
+            SwarmEvent::Behaviour(GossipBehaviourEvent::Sync(
+                request_response::Event::Message { peer, message },
+            )) => match message {
+                request_response::Message::Request {
+                    request, channel, ..
+                } => {
+                    let responses = node.answer(&request);
+                    if swarm
+                        .behaviour_mut()
+                        .sync
+                        .send_response(channel, responses)
+                        .is_err()
+                    {
+                        debug!("The peer {} closed the synchronisation request", peer);
+                    }
+                }
+                request_response::Message::Response { response, .. } => {
+                    for message in response {
+                        node.handle(id, message);
+                    }
+                }
+            },
+            SwarmEvent::Behaviour(GossipBehaviourEvent::Sync(
+                request_response::Event::OutboundFailure { peer, error, .. },
+            )) => {
+                warn!("Unable to synchronise with the peer {}: {}", peer, error);
+                node.abandon_sync(id);
+            }
+            _ => {}
+        }
+    }
+}
@@ -0,0 +1,278 @@
+// This module provides the plugin point for the hash function used when mining
+pub mod hasher {
+    use std::{
+        fmt::Display,
+        io::{BufRead, BufReader, Write},
+        process::{Child, ChildStdin, ChildStdout, Command, Stdio},
+        str::FromStr,
+        thread,
+        time::{Duration, Instant},
+    };
+
+    use libloading::{Library, Symbol};
+    use sha2::{Digest, Sha256};
+
+    use crate::error::error::SimulatorError;
+
+    /// SHA-256 implementation used in the proof of work loop, which is where
+    /// the simulator spends nearly all of its time. Implementations receive
+    /// the header preimage and return its SHA-256 digest.
+    pub trait PowHasher {
+        fn sha256(&mut self, data: &[u8]) -> Result<[u8; 32], SimulatorError>;
+
+        /// Whether this is the builtin SHA-256, which the search for the
+        /// nonce can resume from the midstate of the header instead of
+        /// hashing every preimage in full.
+        fn is_builtin(&self) -> bool {
+            false
+        }
+    }
+
+    /// Hasher selected with the `--hasher` flag: `builtin`, `process:<command>`
+    /// or `dylib:<path>`.
+    #[derive(Clone, Debug, PartialEq, Eq, Default)]
+    pub enum HasherSpec {
+        #[default]
+        Builtin,
+        /// Worker process reading one preimage per line on its standard input
+        /// and answering with the hex-encoded digest on its standard output.
+        Process(String),
+        /// Shared library exporting the C function
+        /// `void pow_sha256(const uint8_t *data, size_t len, uint8_t out[32])`.
+        Dylib(String),
+    }
+
+    impl FromStr for HasherSpec {
+        type Err = String;
+
+        fn from_str(value: &str) -> Result<Self, Self::Err> {
+            match value.split_once(':') {
+                None if value == "builtin" => Ok(HasherSpec::Builtin),
+                Some(("process", command)) if !command.is_empty() => {
+                    Ok(HasherSpec::Process(command.to_string()))
+                }
+                Some(("dylib", path)) if !path.is_empty() => {
+                    Ok(HasherSpec::Dylib(path.to_string()))
+                }
+                _ => Err("expected builtin, process:<command> or dylib:<path>".to_string()),
+            }
+        }
+    }
+
+    impl Display for HasherSpec {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            match self {
+                HasherSpec::Builtin => write!(f, "builtin"),
+                HasherSpec::Process(command) => write!(f, "process:{}", command),
+                HasherSpec::Dylib(path) => write!(f, "dylib:{}", path),
+            }
+        }
+    }
+
+    /// Instantiates the hasher, starting the worker process or loading the
+    /// library if needed.
+    pub fn create_pow_hasher(spec: &HasherSpec) -> Result<Box<dyn PowHasher>, SimulatorError> {
+        Ok(match spec {
+            HasherSpec::Builtin => Box::new(BuiltinHasher),
+            HasherSpec::Process(command) => Box::new(ProcessHasher::spawn(command)?),
+            HasherSpec::Dylib(path) => Box::new(DylibHasher::load(path)?),
+        })
+    }
+
+    pub struct BuiltinHasher;
+
+    impl PowHasher for BuiltinHasher {
+        fn sha256(&mut self, data: &[u8]) -> Result<[u8; 32], SimulatorError> {
+            Ok(Sha256::digest(data).into())
+        }
+
+        fn is_builtin(&self) -> bool {
+            true
+        }
+    }
+
+    pub struct ProcessHasher {
+        command: String,
+        child: Child,
+        stdin: ChildStdin,
+        stdout: BufReader<ChildStdout>,
+    }
+
+    impl ProcessHasher {
+        /// Starts the worker, the command is split on whitespace into the
+        /// program and its arguments.
+        pub fn spawn(command: &str) -> Result<ProcessHasher, SimulatorError> {
+            let failed = |reason: String| SimulatorError::HasherFailed {
+                hasher: command.to_string(),
+                reason,
+            };
+            let mut parts = command.split_whitespace();
+            let program = parts
+                .next()
+                .ok_or_else(|| failed("empty command".to_string()))?;
+            let mut child = Command::new(program)
+                .args(parts)
+                .stdin(Stdio::piped())
+                .stdout(Stdio::piped())
+                .spawn()
+                .map_err(|e| failed(e.to_string()))?;
+            let stdin = child.stdin.take().unwrap();
+            let stdout = BufReader::new(child.stdout.take().unwrap());
+            Ok(ProcessHasher {
+                command: command.to_string(),
+                child,
+                stdin,
+                stdout,
+            })
+        }
+    }
+
+    impl PowHasher for ProcessHasher {
+        fn sha256(&mut self, data: &[u8]) -> Result<[u8; 32], SimulatorError> {
+            let failed = |reason: String| SimulatorError::HasherFailed {
+                hasher: self.command.clone(),
+                reason,
+            };
+            self.stdin
+                .write_all(data)
+                .and_then(|_| self.stdin.write_all(b"\n"))
+                .and_then(|_| self.stdin.flush())
+                .map_err(|e| failed(e.to_string()))?;
+
+            let mut line = String::new();
+            self.stdout
+                .read_line(&mut line)
+                .map_err(|e| failed(e.to_string()))?;
+            let mut digest = [0u8; 32];
+            hex::decode_to_slice(line.trim().trim_start_matches("0x"), &mut digest)
+                .map_err(|_| failed(format!("invalid digest {:?}", line.trim())))?;
+            Ok(digest)
+        }
+    }
+
+    impl Drop for ProcessHasher {
+        fn drop(&mut self) {
+            let _ = self.child.kill();
+            let _ = self.child.wait();
+        }
+    }
+
+    /// Hasher computing at most the given number of hashes per second with
+    /// another hasher, sleeping until each hash is due.
+    pub struct ThrottledHasher {
+        hasher: Box<dyn PowHasher>,
+        interval: Duration,
+        next_hash: Instant,
+    }
+
+    impl ThrottledHasher {
+        /// Fails if the interval between the hashes can't be represented,
+        /// which is the case for rates that aren't positive or are so low
+        /// that the interval overflows a `Duration`.
+        pub fn new(
+            hasher: Box<dyn PowHasher>,
+            hashes_per_second: f64,
+        ) -> Result<Self, SimulatorError> {
+            let interval = Duration::try_from_secs_f64(1.0 / hashes_per_second).map_err(|e| {
+                SimulatorError::HasherFailed {
+                    hasher: "throttled".to_string(),
+                    reason: format!("the hashrate gives no interval between the hashes: {}", e),
+                }
+            })?;
+            Ok(ThrottledHasher {
+                hasher,
+                interval,
+                next_hash: Instant::now(),
+            })
+        }
+    }
+
+    impl PowHasher for ThrottledHasher {
+        fn sha256(&mut self, data: &[u8]) -> Result<[u8; 32], SimulatorError> {
+            let now = Instant::now();
+            if now < self.next_hash {
+                thread::sleep(self.next_hash - now);
+            }
+            // A hasher falling behind doesn't catch up with a burst.
+            self.next_hash = self.next_hash.max(now) + self.interval;
+            self.hasher.sha256(data)
+        }
+    }
+
+    type PowSha256Fn = unsafe extern "C" fn(data: *const u8, len: usize, out: *mut u8);
+
+    pub struct DylibHasher {
+        // The function pointer is only valid while the library stays loaded.
+        _library: Library,
+        pow_sha256: PowSha256Fn,
+    }
+
+    impl DylibHasher {
+        pub fn load(path: &str) -> Result<DylibHasher, SimulatorError> {
+            let failed = |reason: String| SimulatorError::HasherFailed {
+                hasher: path.to_string(),
+                reason,
+            };
+            // SAFETY: loading a library runs its initialisers, the user is
+            // trusted to provide a library implementing the documented ABI.
+            unsafe {
+                let library = Library::new(path).map_err(|e| failed(e.to_string()))?;
+                let symbol: Symbol<PowSha256Fn> = library
+                    .get(b"pow_sha256\0")
+                    .map_err(|e| failed(e.to_string()))?;
+                let pow_sha256 = *symbol;
+                Ok(DylibHasher {
+                    _library: library,
+                    pow_sha256,
+                })
+            }
+        }
+    }
+
+    impl PowHasher for DylibHasher {
+        fn sha256(&mut self, data: &[u8]) -> Result<[u8; 32], SimulatorError> {
+            let mut digest = [0u8; 32];
+            // SAFETY: the function reads len bytes from data and writes 32
+            // bytes to out, both buffers are valid for the whole call.
+            unsafe { (self.pow_sha256)(data.as_ptr(), data.len(), digest.as_mut_ptr()) };
+            Ok(digest)
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use sha2::{Digest, Sha256};
+
+        use crate::error::error::SimulatorError;
+
+        use super::{create_pow_hasher, HasherSpec, PowHasher, ThrottledHasher};
+
+        fn throttled(hashes_per_second: f64) -> Result<ThrottledHasher, SimulatorError> {
+            ThrottledHasher::new(
+                create_pow_hasher(&HasherSpec::Builtin).unwrap(),
+                hashes_per_second,
+            )
+        }
+
+        #[test]
+        fn throttled_hasher_hashes_with_the_inner_hasher() {
+            let mut hasher = throttled(1e9).unwrap();
+            let digest: [u8; 32] = Sha256::digest(b"header").into();
+            assert_eq!(hasher.sha256(b"header").unwrap(), digest);
+        }
+
+        #[test]
+        fn unrepresentable_hashrates_are_rejected() {
+            for hashes_per_second in [1e-300, f64::MIN_POSITIVE, 0.0, -1.0, f64::NAN] {
+                assert!(
+                    matches!(
+                        throttled(hashes_per_second),
+                        Err(SimulatorError::HasherFailed { .. })
+                    ),
+                    "{} hashes per second was accepted",
+                    hashes_per_second
+                );
+            }
+        }
+    }
+}
@@ -1,9 +1,45 @@
 pub mod hashing {
-    use sha256::digest;
+    use sha2::{Digest, Sha256};
+    use sha3::Keccak256;
 
-    use crate::model::blockchain::{Header, Transaction};
+    use crate::{
+        model::blockchain::{Hash256, Header, HeaderFormat, Transaction},
+        rlp::rlp::RlpEncodable,
+    };
     pub trait Hashable {
-        fn hash(&self) -> String;
+        fn hash(&self) -> Hash256;
+    }
+
+    /// Scheme of the hashes reported for headers and transactions. The hashes
+    /// stored in the chain always follow the legacy scheme.
+    #[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+    #[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+    pub enum HashScheme {
+        /// SHA-256 of the comma-separated string of the fields
+        #[default]
+        Legacy,
+        /// Keccak-256 of the RLP encoding, as computed by Ethereum tooling
+        Keccak,
+    }
+
+    impl HashScheme {
+        pub fn hash<T: Hashable + RlpEncodable>(self, value: &T) -> Hash256 {
+            match self {
+                HashScheme::Legacy => value.hash(),
+                HashScheme::Keccak => keccak_hash(value),
+            }
+        }
+    }
+
+    /// Keccak-256 of the RLP encoding of the value.
+    pub fn keccak_hash(value: &impl RlpEncodable) -> Hash256 {
+        Hash256(Keccak256::digest(value.rlp_bytes()).into())
+    }
+
+    /// Implemented by the data that is signed by its author, the signature
+    /// is computed over the signing hash.
+    pub trait Signable {
+        fn signing_hash(&self) -> [u8; 32];
     }
 
     impl Hashable for Header {
@@ -14,23 +50,120 @@ pub mod hashing {
         ///    0s. Hashes (previous block header hash, transactions merkle root) and
         ///    addresses (miner) should be hex-encoded and prepended by 0x.
         /// 3. Hash the string produced in step 2 using the SHA-256 hash function.
-        fn hash(&self) -> String {
-            let strings = format!(
-                "{},{},{},{},{},{},{},{},{}",
-                &self.difficulty.to_string().as_str(),
-                &self.hash.to_string().as_str(),
-                &self.height.to_string().as_str(),
-                &self.miner.as_str(),
-                &self.nonce.to_string().as_str(),
-                &self.previous_block_header_hash.as_str(),
-                &self.timestamp.to_string().as_str(),
-                &self.transactions_count.to_string().as_str(),
-                &self.transactions_merkle_root.to_string().as_str()
-            );
+        ///
+        /// The version comes last in the alphabetical order and is only
+        /// included for versioned blocks, so that legacy hashes don't change.
+        fn hash(&self) -> Hash256 {
+            Hash256(Sha256::digest(self.hash_preimage().as_bytes()).into())
+        }
+    }
+
+    impl Header {
+        /// The comma-separated string of the header fields that is hashed to
+        /// obtain the header hash. The hash field is left empty, as a header
+        /// can't contain its own hash when it is being hashed.
+        pub fn hash_preimage(&self) -> String {
+            let (before_nonce, after_nonce) = self.preimage_around_nonce();
+            format!("{}{}{}", before_nonce, self.nonce, after_nonce)
+        }
 
-            let hash: String = digest(strings);
+        /// The parts of the preimage before and after the nonce, which stay
+        /// the same while the nonce is searched.
+        pub fn preimage_around_nonce(&self) -> (String, String) {
+            let before_nonce = format!("{},,{},{},", self.difficulty, self.height, self.miner);
+            let after_nonce = format!(
+                ",{},{},{},{}",
+                self.previous_block_header_hash,
+                self.timestamp,
+                self.transactions_count,
+                self.transactions_merkle_root
+            ) + &match self.version {
+                0 => String::new(),
+                version => format!(",{}", version),
+            };
+            (before_nonce, after_nonce)
+        }
+
+        /// The preimage as specified by the tutorial the sample chains in
+        /// data/ were produced for, which leaves the hash field out instead
+        /// of leaving it empty.
+        pub fn specified_preimage(&self) -> String {
+            let (before_nonce, after_nonce) = self.specified_preimage_around_nonce();
+            format!("{}{}{}", before_nonce, self.nonce, after_nonce)
+        }
 
-            return "0x".to_string() + &hash;
+        /// Like `preimage_around_nonce()`, for the specified preimage.
+        pub fn specified_preimage_around_nonce(&self) -> (String, String) {
+            let (before_nonce, after_nonce) = self.preimage_around_nonce();
+            // The empty hash field follows the difficulty.
+            (before_nonce.replacen(",,", ",", 1), after_nonce)
+        }
+    }
+
+    /// SHA-256 state after absorbing the part of the preimage of a header
+    /// before its nonce. The hash of the header with any nonce resumes from
+    /// it and only hashes the nonce and the rest of the preimage, instead of
+    /// formatting and hashing the whole header again.
+    #[derive(Clone)]
+    pub struct HeaderMidstate {
+        midstate: Sha256,
+        after_nonce: Vec<u8>,
+        format: HeaderFormat,
+    }
+
+    impl HeaderMidstate {
+        pub fn new(header: &Header, format: HeaderFormat) -> Self {
+            let (before_nonce, after_nonce) = match format {
+                HeaderFormat::Simulator => {
+                    let (before_nonce, after_nonce) = header.preimage_around_nonce();
+                    (before_nonce.into_bytes(), after_nonce.into_bytes())
+                }
+                HeaderFormat::Tutorial => {
+                    let (before_nonce, after_nonce) = header.specified_preimage_around_nonce();
+                    (before_nonce.into_bytes(), after_nonce.into_bytes())
+                }
+                // The nonce is the last 4 bytes of the header.
+                HeaderFormat::Bitcoin => (header.bitcoin_bytes()[..76].to_vec(), vec![]),
+            };
+            HeaderMidstate {
+                midstate: Sha256::new_with_prefix(before_nonce),
+                after_nonce,
+                format,
+            }
+        }
+
+        /// Hash of the header with the nonce, equal to
+        /// `format.header_hash(header)` for the header with that nonce.
+        pub fn hash(&self, nonce: u32) -> Hash256 {
+            let mut sha256 = self.midstate.clone();
+            match self.format {
+                HeaderFormat::Simulator | HeaderFormat::Tutorial => {
+                    let mut digits = [0u8; 10];
+                    sha256.update(decimal_digits(nonce, &mut digits));
+                    sha256.update(&self.after_nonce);
+                    Hash256(sha256.finalize().into())
+                }
+                HeaderFormat::Bitcoin => {
+                    sha256.update(nonce.to_le_bytes());
+                    let mut digest: [u8; 32] = Sha256::digest(sha256.finalize()).into();
+                    digest.reverse();
+                    Hash256(digest)
+                }
+            }
+        }
+    }
+
+    /// The decimal digits of the number, written to the end of the buffer
+    /// without allocating.
+    fn decimal_digits(mut number: u32, buffer: &mut [u8; 10]) -> &[u8] {
+        let mut start = buffer.len();
+        loop {
+            start -= 1;
+            buffer[start] = b'0' + (number % 10) as u8;
+            number /= 10;
+            if number == 0 {
+                return &buffer[start..];
+            }
         }
     }
 
@@ -44,19 +177,75 @@ pub mod hashing {
         ///    (sender, receiver) should be hex-encoded.
         /// 3 Hash the string produced in step 2 using the SHA-256 hash function
         ///    (remember to ensure that the hex string starts with 0x).
-        fn hash(&self) -> String {
+        fn hash(&self) -> Hash256 {
             let strings = format!(
                 "{},{},{},{},{},{}",
                 &self.amount.to_string().as_str(),
                 &self.lock_time.to_string().as_str(),
-                &self.receiver.as_str(),
-                &self.sender.as_str(),
+                &self.receiver.to_string(),
+                &self.sender.to_string(),
                 &self.signature.as_str(),
                 &self.transaction_fee.to_string().as_str()
             );
-            let hash: String = digest(strings.to_string());
+            Hash256(Sha256::digest(strings.as_bytes()).into())
+        }
+    }
+
+    impl Signable for Transaction {
+        /// The signature covers all fields of the transaction except for the
+        /// signature itself, encoded in the same way as for the transaction
+        /// hash. The hash is signed directly (as a prehashed message) using
+        /// ECDSA with the NIST P-256 curve.
+        fn signing_hash(&self) -> [u8; 32] {
+            let strings = format!(
+                "{},{},{},{},{}",
+                &self.amount.to_string().as_str(),
+                &self.lock_time.to_string().as_str(),
+                &self.receiver.to_string(),
+                &self.sender.to_string(),
+                &self.transaction_fee.to_string().as_str()
+            );
+            Sha256::digest(strings.as_bytes()).into()
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use crate::model::blockchain::{Address, Hash256, Header, HeaderFormat};
+
+        use super::HeaderMidstate;
 
-            return hash;
+        #[test]
+        fn midstate_hash_matches_header_hash() {
+            let mut header = Header {
+                difficulty: 2,
+                height: 3,
+                miner: Address([7; 20]),
+                nonce: 0,
+                hash: Hash256([0; 32]),
+                previous_block_header_hash: Hash256([1; 32]),
+                timestamp: 1700000000,
+                transactions_count: 2,
+                transactions_merkle_root: Hash256([2; 32]),
+                version: 0,
+            };
+            for format in [
+                HeaderFormat::Simulator,
+                HeaderFormat::Bitcoin,
+                HeaderFormat::Tutorial,
+            ] {
+                let midstate = HeaderMidstate::new(&header, format);
+                for nonce in [0, 9, 10, 123_456, u32::MAX] {
+                    header.nonce = nonce;
+                    assert_eq!(
+                        midstate.hash(nonce),
+                        format.header_hash(&header),
+                        "nonce {} in the {:?} format",
+                        nonce,
+                        format
+                    );
+                }
+            }
         }
     }
 }
@@ -0,0 +1,194 @@
+// This module checks a chain file for tampering, running all the integrity
+// checks in one pass and reporting their outcome per block
+pub mod integrity {
+    use log::{info, warn};
+    use serde::Serialize;
+
+    use crate::{
+        args::args::IntegrityReportArgs,
+        config::config::SimulatorContext,
+        data_sourcing::data_provider::{load_blockchain, write_json},
+        error::error::SimulatorError,
+        model::blockchain::{
+            is_valid_block_header_hash, Block, Hash256, Header, HeaderFormat, NULL_HASH,
+        },
+        node::miner::{compute_transaction_hashes, merkle_root_in_format},
+        wallet::wallet::verify_signature,
+    };
+
+    /// Outcome of the checks of a single block.
+    #[derive(Serialize)]
+    struct BlockIntegrity {
+        height: u32,
+        hash: Hash256,
+        /// Whether the transactions lead to the merkle root of the header,
+        /// computed in the configured header format.
+        merkle_root: bool,
+        /// Whether the header hashes to its stored hash in the configured
+        /// header format.
+        header_hash: bool,
+        proof_of_work: bool,
+        /// Whether the header follows the previous one in height and links to
+        /// its hash, or to the null hash for the genesis block.
+        linked: bool,
+        /// Whether the timestamp isn't earlier than the previous one.
+        timestamp: bool,
+        /// Number of transactions with invalid signatures, missing if the
+        /// signatures weren't checked.
+        invalid_signatures: Option<usize>,
+        passed: bool,
+    }
+
+    /// Outcome of one check over the whole chain.
+    #[derive(Serialize)]
+    struct CheckSummary {
+        passed: bool,
+        /// Heights of the blocks failing the check.
+        failed_blocks: Vec<u32>,
+    }
+
+    impl CheckSummary {
+        fn of(blocks: &[BlockIntegrity], check: impl Fn(&BlockIntegrity) -> bool) -> Self {
+            let failed_blocks: Vec<u32> = blocks
+                .iter()
+                .filter(|block| !check(block))
+                .map(|block| block.height)
+                .collect();
+            CheckSummary {
+                passed: failed_blocks.is_empty(),
+                failed_blocks,
+            }
+        }
+    }
+
+    #[derive(Serialize)]
+    struct IntegrityReport {
+        blockchain_state: String,
+        blocks_count: usize,
+        /// Whether every block passed every check.
+        passed: bool,
+        merkle_roots: CheckSummary,
+        header_hashes: CheckSummary,
+        proof_of_work: CheckSummary,
+        linkage: CheckSummary,
+        timestamps: CheckSummary,
+        /// Missing if the signatures weren't checked.
+        signatures: Option<CheckSummary>,
+        blocks: Vec<BlockIntegrity>,
+    }
+
+    fn has_valid_merkle_root(block: &Block, format: HeaderFormat) -> bool {
+        let transaction_hashes = compute_transaction_hashes(&block.transactions);
+        merkle_root_in_format(format, transaction_hashes)
+            == Some(block.header.transactions_merkle_root)
+    }
+
+    fn check_block(
+        block: &Block,
+        previous: Option<&Header>,
+        format: HeaderFormat,
+        verify_signatures: bool,
+    ) -> BlockIntegrity {
+        let header = &block.header;
+        let merkle_root = has_valid_merkle_root(block, format);
+        let header_hash = header.has_valid_hash(format);
+        let proof_of_work = is_valid_block_header_hash(&header.hash, header.difficulty as usize);
+        let (linked, timestamp) = match previous {
+            Some(previous) => (
+                header.height == previous.height.wrapping_add(1)
+                    && header.previous_block_header_hash == previous.hash,
+                header.timestamp >= previous.timestamp,
+            ),
+            None => (
+                header.height != 0 || header.previous_block_header_hash == NULL_HASH,
+                true,
+            ),
+        };
+        let invalid_signatures = verify_signatures.then(|| {
+            block
+                .transactions
+                .iter()
+                .filter(|t| !verify_signature(t))
+                .count()
+        });
+        let passed = merkle_root
+            && header_hash
+            && proof_of_work
+            && linked
+            && timestamp
+            && invalid_signatures.unwrap_or(0) == 0;
+        BlockIntegrity {
+            height: header.height,
+            hash: header.hash,
+            merkle_root,
+            header_hash,
+            proof_of_work,
+            linked,
+            timestamp,
+            invalid_signatures,
+            passed,
+        }
+    }
+
+    /// Runs the merkle root, header hash, proof of work, linkage, timestamp
+    /// and optionally the signature checks over every block and writes their
+    /// outcome as a JSON report, so that tampered chains are detected and
+    /// located without stopping at the first problem. Fails after writing
+    /// the report if any block failed a check.
+    pub fn integrity_report(
+        args: IntegrityReportArgs,
+        context: &SimulatorContext,
+    ) -> Result<(), SimulatorError> {
+        info!("Loading the blockchain from {}", args.blockchain_state);
+        let blockchain = load_blockchain(&args.blockchain_state, context)?;
+
+        info!("Checking the integrity of {} blocks...", blockchain.len());
+        let mut previous = None;
+        let blocks: Vec<BlockIntegrity> = blockchain
+            .iter()
+            .map(|block| {
+                let integrity = check_block(
+                    block,
+                    previous,
+                    context.header_format,
+                    args.verify_signatures,
+                );
+                previous = Some(&block.header);
+                integrity
+            })
+            .collect();
+        for block in blocks.iter().filter(|block| !block.passed) {
+            warn!(
+                "Block {} with hash {} failed the integrity checks",
+                block.height, block.hash
+            );
+        }
+
+        let failed = blocks.iter().filter(|block| !block.passed).count();
+        let report = IntegrityReport {
+            blockchain_state: args.blockchain_state.clone(),
+            blocks_count: blocks.len(),
+            passed: failed == 0,
+            merkle_roots: CheckSummary::of(&blocks, |block| block.merkle_root),
+            header_hashes: CheckSummary::of(&blocks, |block| block.header_hash),
+            proof_of_work: CheckSummary::of(&blocks, |block| block.proof_of_work),
+            linkage: CheckSummary::of(&blocks, |block| block.linked),
+            timestamps: CheckSummary::of(&blocks, |block| block.timestamp),
+            signatures: args
+                .verify_signatures
+                .then(|| CheckSummary::of(&blocks, |block| block.invalid_signatures == Some(0))),
+            blocks,
+        };
+        info!(
+            "{} of {} blocks passed the integrity checks, {} failed",
+            report.blocks_count - failed,
+            report.blocks_count,
+            failed
+        );
+        write_json(&args.report_output, context.output, &report)?;
+        if failed > 0 {
+            return Err(SimulatorError::TamperedBlocks(failed));
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,80 @@
+#![allow(clippy::module_inception)]
+
+//! Blockchain Miner Simulator. The model of the blockchain, its hashing and
+//! the verification of headers and inclusion proofs don't access the
+//! filesystem and compile to WebAssembly without the `cli` feature, which
+//! provides the simulator commands.
+
+#[cfg(feature = "cli")]
+pub mod aggregate;
+#[cfg(feature = "cli")]
+pub mod args;
+#[cfg(feature = "cli")]
+pub mod config;
+#[cfg(feature = "cli")]
+pub mod dashboard;
+#[cfg(feature = "cli")]
+pub mod data_sourcing;
+#[cfg(feature = "cli")]
+pub mod engine;
+pub mod error;
+#[cfg(feature = "cli")]
+pub mod explorer;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+#[cfg(feature = "cli")]
+pub mod generator;
+#[cfg(feature = "libp2p")]
+pub mod gossip;
+#[cfg(feature = "cli")]
+pub mod hasher;
+pub mod hashing;
+#[cfg(feature = "cli")]
+pub mod integrity;
+#[cfg(feature = "cli")]
+pub mod logging;
+pub mod model;
+#[cfg(feature = "cli")]
+pub mod network;
+#[cfg(feature = "cli")]
+pub mod node;
+#[cfg(feature = "napi")]
+pub mod nodejs;
+#[cfg(feature = "cli")]
+pub mod orchestrator;
+#[cfg(feature = "cli")]
+pub mod p2p;
+#[cfg(feature = "cli")]
+pub mod plot;
+pub mod proto;
+#[cfg(feature = "python")]
+pub mod python;
+#[cfg(feature = "cli")]
+pub mod remote;
+#[cfg(feature = "cli")]
+pub mod report;
+pub mod rlp;
+#[cfg(feature = "cli")]
+pub mod scenario;
+#[cfg(feature = "cli")]
+pub mod schema;
+#[cfg(feature = "cli")]
+pub mod simulation;
+#[cfg(feature = "cli")]
+pub mod snapshot;
+#[cfg(feature = "cli")]
+pub mod state;
+#[cfg(feature = "cli")]
+pub mod strategy;
+#[cfg(feature = "cli")]
+pub mod sweep;
+#[cfg(feature = "cli")]
+pub mod sync;
+#[cfg(feature = "cli")]
+pub mod upgrades;
+pub mod validation;
+#[cfg(feature = "cli")]
+pub mod views;
+pub mod wallet;
+#[cfg(feature = "wasm")]
+pub mod wasm;
@@ -0,0 +1,149 @@
+// This module sets up the logger, writing either the human-readable lines of
+// env_logger or structured JSON lines
+pub mod logging {
+    use std::{
+        fs::{File, OpenOptions},
+        io::{self, Write},
+    };
+
+    use clap::ValueEnum;
+    use log::LevelFilter;
+    use serde_json::json;
+
+    use crate::error::error::SimulatorError;
+
+    /// Format of the log lines written to the standard error.
+    #[derive(ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+    pub enum LogFormat {
+        /// Coloured lines meant to be read by people
+        #[default]
+        Text,
+        /// One JSON object per line with the timestamp, the level, the
+        /// module logging the event and its message, for log pipelines
+        Json,
+    }
+
+    /// Logging options of the command line.
+    #[derive(Debug, Default, PartialEq, Eq)]
+    pub struct LogOptions {
+        pub format: LogFormat,
+        /// Number of `-v` flags minus the number of `-q` flags.
+        pub verbosity: i32,
+        pub file: Option<String>,
+    }
+
+    impl LogOptions {
+        /// Finds the logging options in the raw command line arguments. The
+        /// logger is set up before clap parses the arguments so that the
+        /// errors found while loading the configuration are logged as
+        /// requested, invalid values are left for clap to report.
+        pub fn find(args: &[String]) -> Self {
+            let mut options = LogOptions::default();
+            let mut args = args.iter().skip(1);
+            while let Some(arg) = args.next() {
+                let mut value = |name: &str| match arg.strip_prefix(name) {
+                    Some("") => args.next().cloned(),
+                    Some(value) => value.strip_prefix('=').map(str::to_string),
+                    None => None,
+                };
+                if let Some(format) = value("--log-format") {
+                    options.format = LogFormat::from_str(&format, true).unwrap_or_default();
+                } else if let Some(file) = value("--log-file") {
+                    options.file = Some(file);
+                } else if arg == "--verbose" {
+                    options.verbosity += 1;
+                } else if arg == "--quiet" {
+                    options.verbosity -= 1;
+                } else if let Some(flags) = arg.strip_prefix('-') {
+                    // Clusters of short flags such as -vv.
+                    if !flags.is_empty() && flags.chars().all(|flag| flag == 'v' || flag == 'q') {
+                        for flag in flags.chars() {
+                            options.verbosity += if flag == 'v' { 1 } else { -1 };
+                        }
+                    }
+                }
+            }
+            options
+        }
+
+        /// Level set by the verbosity flags, or none without any flags.
+        fn level(&self) -> Option<LevelFilter> {
+            match self.verbosity {
+                0 => None,
+                ..=-3 => Some(LevelFilter::Off),
+                -2 => Some(LevelFilter::Error),
+                -1 => Some(LevelFilter::Warn),
+                1 => Some(LevelFilter::Debug),
+                2.. => Some(LevelFilter::Trace),
+            }
+        }
+    }
+
+    /// Writes the log lines both to the standard error and to the log file.
+    struct Tee {
+        file: File,
+    }
+
+    impl Write for Tee {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            io::stderr().write_all(buf)?;
+            self.file.write_all(buf)?;
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            io::stderr().flush()?;
+            self.file.flush()
+        }
+    }
+
+    /// Sets up the logger with the options. Without verbosity flags the
+    /// level is read from `MY_LOG_LEVEL` and defaults to info, and text lines
+    /// are coloured unless `MY_LOG_STYLE` says otherwise or they are also
+    /// appended to a log file. If the log file can't be opened, the logger
+    /// is still set up to write to the standard error.
+    pub fn init_logger(options: &LogOptions) -> Result<(), SimulatorError> {
+        let env = env_logger::Env::default()
+            .filter_or("MY_LOG_LEVEL", "info")
+            .write_style_or("MY_LOG_STYLE", "always");
+        let mut builder = env_logger::Builder::from_env(env);
+        if let Some(level) = options.level() {
+            builder.filter_level(level);
+        }
+        if options.format == LogFormat::Json {
+            builder
+                .write_style(env_logger::WriteStyle::Never)
+                .format(|buf, record| {
+                    let line = json!({
+                        "timestamp": buf.timestamp_millis().to_string(),
+                        "level": record.level().as_str(),
+                        "target": record.target(),
+                        "message": record.args().to_string(),
+                    });
+                    writeln!(buf, "{}", line)
+                });
+        }
+        let file = options.file.as_ref().map(|file_name| {
+            OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(file_name)
+                .map_err(|source| SimulatorError::Io {
+                    file_name: file_name.clone(),
+                    source,
+                })
+        });
+        let result = match file {
+            Some(Ok(file)) => {
+                builder
+                    .write_style(env_logger::WriteStyle::Never)
+                    .target(env_logger::Target::Pipe(Box::new(Tee { file })));
+                Ok(())
+            }
+            Some(Err(error)) => Err(error),
+            None => Ok(()),
+        };
+        builder.init();
+        result
+    }
+}
@@ -1,33 +1,105 @@
-use args::args::Args;
+use blockchain_simulator::aggregate::aggregate::aggregate_runs;
+use blockchain_simulator::args::args::Args;
 
-use clap::Parser;
-use model::simulator::SimulatorMode;
-use node::{
-    miner::produce_blocks,
-    validator::{generate_inclusion_proof, verify_inclusion_proof},
+use blockchain_simulator::config::config::{find_config_file, load_config, SimulatorContext};
+use blockchain_simulator::error::error::SimulatorError;
+use blockchain_simulator::explorer::explorer::export_explorer;
+use blockchain_simulator::generator::generator::generate_transactions;
+use blockchain_simulator::integrity::integrity::integrity_report;
+use blockchain_simulator::logging::logging::{init_logger, LogOptions};
+use blockchain_simulator::model::simulator::SimulatorMode;
+use blockchain_simulator::network::network::{render_propagation, simulate_network};
+use blockchain_simulator::node::{
+    miner::{create_genesis, estimate_mining_time, produce_blocks, remine_from, repair_chain},
+    validator::{
+        audit_merkle_roots, audit_supply, compare_chains, generate_inclusion_proof,
+        generate_proof_index, import_blocks, spv_verify, submit_block, validate_chain,
+        verify_inclusion_proof, verify_proofs,
+    },
 };
-use views::views::show_transaction_hash;
-
-mod args;
-mod data_sourcing;
-mod hashing;
-mod model;
-mod node;
-mod views;
+use blockchain_simulator::orchestrator::orchestrator::{orchestrate, replay};
+use blockchain_simulator::p2p::p2p::run_node;
+use blockchain_simulator::plot::plot::plot;
+use blockchain_simulator::remote::remote::import_remote;
+use blockchain_simulator::report::report::generate_report;
+use blockchain_simulator::scenario::scenario::run_scenario;
+use blockchain_simulator::schema::schema::export_schema;
+use blockchain_simulator::simulation::{fleet::simulate_miners, timewarp::simulate_time_warp};
+use blockchain_simulator::sweep::sweep::sweep;
+use blockchain_simulator::sync::sync::sync_from;
+use blockchain_simulator::views::views::{
+    audit_headers, balance_history, difficulty_report, export_headers, fee_histogram, list_blocks,
+    mempool_stats, miner_revenue, mining_stats, show_confirmations, show_header, show_merkle_tree,
+    show_transaction_hash, supply_report, track_transactions,
+};
+use blockchain_simulator::wallet::wallet::{create_wallet, sign_transaction};
+use log::error;
 
 /// Blockchain Miner Simulator
 fn main() {
-    let env = env_logger::Env::default()
-        .filter_or("MY_LOG_LEVEL", "info")
-        .write_style_or("MY_LOG_STYLE", "always");
-    env_logger::init_from_env(env);
+    let raw_args: Vec<String> = std::env::args().collect();
+    let result = init_logger(&LogOptions::find(&raw_args)).and_then(|_| run(&raw_args));
+    if let Err(error) = result {
+        error!("{}", error);
+        std::process::exit(error.exit_code());
+    }
+}
 
-    let args = Args::parse();
+fn run(raw_args: &[String]) -> Result<(), SimulatorError> {
+    let config = load_config(find_config_file(raw_args).as_deref())?;
+    let args = Args::parse_with_config(&config);
+    let context = SimulatorContext::new(config, &args);
     match args.command {
-        SimulatorMode::ProduceBlocks => produce_blocks(args.into()),
-        SimulatorMode::GetTransactionHash => show_transaction_hash(args.into()),
-        SimulatorMode::GenerateInclusionProof => generate_inclusion_proof(args.into()),
-        SimulatorMode::VerifyInclusionProof => verify_inclusion_proof(args.into()),
-        SimulatorMode::GenerateTransactions => todo!(),
+        SimulatorMode::ProduceBlocks(args) => produce_blocks(args, &context),
+        SimulatorMode::GetTransactionHash(args) => show_transaction_hash(args, &context),
+        SimulatorMode::ShowMerkleTree(args) => show_merkle_tree(args, &context),
+        SimulatorMode::ShowHeader(args) => show_header(args, &context),
+        SimulatorMode::ListBlocks(args) => list_blocks(args, &context),
+        SimulatorMode::GenerateInclusionProof(args) => generate_inclusion_proof(args, &context),
+        SimulatorMode::GenerateProofIndex(args) => generate_proof_index(args, &context),
+        SimulatorMode::VerifyInclusionProof(args) => verify_inclusion_proof(args, &context),
+        SimulatorMode::SpvVerify(args) => spv_verify(args, &context),
+        SimulatorMode::VerifyProofs(args) => verify_proofs(args, &context),
+        SimulatorMode::ExportHeaders(args) => export_headers(args, &context),
+        SimulatorMode::ExportExplorer(args) => export_explorer(args, &context),
+        SimulatorMode::Schema(args) => export_schema(args, &context),
+        SimulatorMode::GenerateTransactions(args) => generate_transactions(args, &context),
+        SimulatorMode::ImportBlocks(args) => import_blocks(args, &context),
+        SimulatorMode::ImportRemote(args) => import_remote(args, &context),
+        SimulatorMode::SubmitBlock(args) => submit_block(args, &context),
+        SimulatorMode::CompareChains(args) => compare_chains(args, &context),
+        SimulatorMode::GetConfirmations(args) => show_confirmations(args, &context),
+        SimulatorMode::DifficultyReport(args) => difficulty_report(args, &context),
+        SimulatorMode::MiningStats(args) => mining_stats(args, &context),
+        SimulatorMode::MempoolStats(args) => mempool_stats(args, &context),
+        SimulatorMode::FeeHistogram(args) => fee_histogram(args, &context),
+        SimulatorMode::MinerRevenue(args) => miner_revenue(args, &context),
+        SimulatorMode::BalanceHistory(args) => balance_history(args, &context),
+        SimulatorMode::TrackTransactions(args) => track_transactions(args, &context),
+        SimulatorMode::SupplyReport(args) => supply_report(args, &context),
+        SimulatorMode::AuditSupply(args) => audit_supply(args, &context),
+        SimulatorMode::AuditMerkleRoots(args) => audit_merkle_roots(args, &context),
+        SimulatorMode::AuditHeaders(args) => audit_headers(args, &context),
+        SimulatorMode::IntegrityReport(args) => integrity_report(args, &context),
+        SimulatorMode::Repair(args) => repair_chain(args, &context),
+        SimulatorMode::RemineFrom(args) => remine_from(args, &context),
+        SimulatorMode::EstimateMiningTime(args) => estimate_mining_time(args, &context),
+        SimulatorMode::SimulateMiners(args) => simulate_miners(args, &context),
+        SimulatorMode::SimulateNetwork(args) => simulate_network(args, &context),
+        SimulatorMode::RunScenario(args) => run_scenario(args, &context),
+        SimulatorMode::RenderPropagation(args) => render_propagation(args, &context),
+        SimulatorMode::AggregateRuns(args) => aggregate_runs(args, &context),
+        SimulatorMode::Sweep(args) => sweep(args, &context),
+        SimulatorMode::Plot(args) => plot(args, &context),
+        SimulatorMode::Report(args) => generate_report(args, &context),
+        SimulatorMode::Orchestrate(args) => orchestrate(args, &context),
+        SimulatorMode::Replay(args) => replay(args, &context),
+        SimulatorMode::RunNode(args) => run_node(args, &context),
+        SimulatorMode::SyncFrom(args) => sync_from(args, &context),
+        SimulatorMode::TimeWarp(args) => simulate_time_warp(args, &context),
+        SimulatorMode::CreateGenesis(args) => create_genesis(args, &context),
+        SimulatorMode::CreateWallet(args) => create_wallet(args, &context),
+        SimulatorMode::SignTransaction(args) => sign_transaction(args, &context),
+        SimulatorMode::ValidateChain(args) => validate_chain(args, &context),
     }
 }
@@ -1,71 +1,1534 @@
 pub mod blockchain {
-    use std::fmt::Display;
+    use std::{collections::HashMap, fmt::Display, str::FromStr};
 
+    use chrono::{DateTime, SecondsFormat};
     use crypto_bigint::U256;
-    use serde::{Deserialize, Serialize};
-    use sha256::digest;
+    use log::debug;
+    use p256::ecdsa::SigningKey;
+    use schemars::{
+        gen::SchemaGenerator,
+        schema::{InstanceType, Schema, SchemaObject, StringValidation},
+        JsonSchema,
+    };
+    use serde::{
+        de::{self, Unexpected, Visitor},
+        ser::SerializeStruct,
+        Deserialize, Deserializer, Serialize, Serializer,
+    };
+    use sha2::Sha256;
+    use sha3::{Digest, Keccak256};
 
-    #[derive(Serialize, Deserialize, Debug, Clone)]
+    use crate::{error::error::SimulatorError, hashing::hashing::Hashable, wallet::wallet::sign};
+
+    /// Hash used in place of the previous block header hash in the genesis block,
+    /// as the Merkle root of a genesis block without any allocations and to pad
+    /// the levels of the Merkle tree with an odd number of nodes.
+    pub const NULL_HASH: Hash256 = Hash256([0; 32]);
+
+    /// Address used as the sender of the transactions pre-funding the accounts
+    /// in the genesis block, these transactions are not signed.
+    pub const NULL_ADDRESS: Address = Address([0; 20]);
+
+    /// Prefix of the preimage of the mock hashes of blocks produced without
+    /// proof of work.
+    const MOCK_POW_PREFIX: &str = "mock-pow,";
+
+    /// Number of base units in a coin, as there are satoshis in a bitcoin.
+    pub const BASE_UNITS_PER_COIN: u64 = 100_000_000;
+
+    /// A finalized block declared in the configuration file. No block at the
+    /// checkpoint's height with a different hash is ever accepted.
+    #[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+    #[serde(deny_unknown_fields)]
+    pub struct Checkpoint {
+        pub height: u32,
+        pub hash: Hash256,
+    }
+
+    /// Finds the checkpoint at the height of the header that the header
+    /// conflicts with, if any.
+    pub fn conflicting_checkpoint<'c>(
+        checkpoints: &'c [Checkpoint],
+        header: &Header,
+    ) -> Option<&'c Checkpoint> {
+        checkpoints
+            .iter()
+            .find(|checkpoint| checkpoint.height == header.height && checkpoint.hash != header.hash)
+    }
+
+    /// Difficulty of the blocks from one height up to another, both included,
+    /// or up to every later height if no end is given.
+    #[derive(Serialize, Deserialize, JsonSchema, Debug, Clone, PartialEq, Eq)]
+    #[serde(deny_unknown_fields)]
+    pub struct DifficultyStep {
+        pub from_height: u32,
+        pub to_height: Option<u32>,
+        pub difficulty: u32,
+    }
+
+    /// How a block was mined, written next to the chain by produce-blocks
+    /// rather than into the header, whose fields are all covered by the hash.
+    #[derive(Serialize, Deserialize, JsonSchema, Debug, Clone, PartialEq)]
+    pub struct MiningStats {
+        pub height: u32,
+        pub hash: Hash256,
+        pub nonces_tried: u64,
+        /// Wall-clock time of the search for the nonce.
+        pub seconds: f64,
+        /// Nonces tried per second.
+        pub hashrate: f64,
+        pub threads: u32,
+    }
+
+    /// Difficulties that the mined blocks take at the heights covered by the
+    /// steps of a difficulty schedule file, instead of carrying over the
+    /// difficulty of the previous block.
+    #[derive(Debug, Clone, Default, PartialEq, Eq)]
+    pub struct DifficultySchedule {
+        steps: Vec<DifficultyStep>,
+    }
+
+    impl DifficultySchedule {
+        /// Fails with the reason if a step ends before it starts, requires
+        /// more leading zeros than the 64 digits of a hash or overlaps another
+        /// step.
+        pub fn new(mut steps: Vec<DifficultyStep>) -> Result<Self, String> {
+            steps.sort_by_key(|step| step.from_height);
+            for step in &steps {
+                if step.to_height.is_some_and(|to| to < step.from_height) {
+                    return Err(format!(
+                        "the step from height {} ends before it starts",
+                        step.from_height
+                    ));
+                }
+                if step.difficulty > 64 {
+                    return Err(format!(
+                        "the difficulty {} from height {} can never be satisfied",
+                        step.difficulty, step.from_height
+                    ));
+                }
+            }
+            for pair in steps.windows(2) {
+                if pair[0].to_height.is_none_or(|to| to >= pair[1].from_height) {
+                    return Err(format!(
+                        "the steps from heights {} and {} overlap",
+                        pair[0].from_height, pair[1].from_height
+                    ));
+                }
+            }
+            Ok(DifficultySchedule { steps })
+        }
+
+        /// Difficulty of the step covering the height, if any.
+        pub fn difficulty_at(&self, height: u32) -> Option<u32> {
+            self.steps
+                .iter()
+                .find(|step| {
+                    step.from_height <= height && step.to_height.is_none_or(|to| height <= to)
+                })
+                .map(|step| step.difficulty)
+        }
+    }
+
+    /// Finds the checkpoint that the branch ending at the given height
+    /// conflicts with: its block at the height of the checkpoint, looked up
+    /// with `hash_at`, isn't the checkpoint, or the branch ends below a
+    /// checkpoint that `finalized` reports the current chain contains, so
+    /// adopting it would revert a finalized block.
+    pub fn conflicting_branch_checkpoint<'a, 'c>(
+        checkpoints: &'c [Checkpoint],
+        tip_height: u32,
+        hash_at: impl Fn(u32) -> Option<&'a Hash256>,
+        finalized: impl Fn(&Checkpoint) -> bool,
+    ) -> Option<&'c Checkpoint> {
+        checkpoints.iter().find(|checkpoint| {
+            if checkpoint.height <= tip_height {
+                hash_at(checkpoint.height) != Some(&checkpoint.hash)
+            } else {
+                finalized(checkpoint)
+            }
+        })
+    }
+
+    /// A 20-byte account address. Addresses are always serialized (and hashed)
+    /// as lowercase hex prefixed by 0x, while `checksummed` produces the
+    /// EIP-55 mixed-case form that protects against typos when the address
+    /// is copied by hand.
+    #[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+    pub struct Address(pub [u8; 20]);
+
+    impl Address {
+        /// Upper-cases each hex letter of the address whose corresponding nibble
+        /// in the Keccak-256 hash of the lowercase hex address is at least 8.
+        pub fn checksummed(&self) -> String {
+            let lowercase_hex = hex::encode(self.0);
+            let hash = Keccak256::digest(lowercase_hex.as_bytes());
+            let checksummed: String = lowercase_hex
+                .chars()
+                .enumerate()
+                .map(|(i, c)| {
+                    let nibble = (hash[i / 2] >> (4 * (1 - i % 2))) & 0x0f;
+                    if nibble >= 8 {
+                        c.to_ascii_uppercase()
+                    } else {
+                        c
+                    }
+                })
+                .collect();
+            "0x".to_string() + &checksummed
+        }
+    }
+
+    impl Display for Address {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            write!(f, "0x{}", hex::encode(self.0))
+        }
+    }
+
+    impl FromStr for Address {
+        type Err = SimulatorError;
+
+        /// Accepts 0x followed by 40 hex digits. Mixed-case addresses are
+        /// treated as EIP-55 checksummed and rejected if the checksum is wrong.
+        fn from_str(value: &str) -> Result<Self, Self::Err> {
+            let malformed = |reason: &str| SimulatorError::MalformedAddress {
+                address: value.to_string(),
+                reason: reason.to_string(),
+            };
+            let digits = value
+                .strip_prefix("0x")
+                .ok_or_else(|| malformed("expected the 0x prefix"))?;
+            if digits.len() != 40 {
+                return Err(malformed("expected 40 hex digits"));
+            }
+            let mut bytes = [0; 20];
+            hex::decode_to_slice(digits, &mut bytes)
+                .map_err(|_| malformed("expected only hex digits"))?;
+
+            let address = Address(bytes);
+            let is_mixed_case = digits.chars().any(|c| c.is_ascii_lowercase())
+                && digits.chars().any(|c| c.is_ascii_uppercase());
+            if is_mixed_case && address.checksummed() != value {
+                return Err(malformed("invalid EIP-55 checksum"));
+            }
+            Ok(address)
+        }
+    }
+
+    impl Serialize for Address {
+        fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            serializer.collect_str(self)
+        }
+    }
+
+    impl<'de> Deserialize<'de> for Address {
+        fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+            let value = String::deserialize(deserializer)?;
+            value.parse().map_err(serde::de::Error::custom)
+        }
+    }
+
+    impl JsonSchema for Address {
+        fn schema_name() -> String {
+            "Address".to_string()
+        }
+
+        fn json_schema(_: &mut SchemaGenerator) -> Schema {
+            SchemaObject {
+                instance_type: Some(InstanceType::String.into()),
+                string: Some(Box::new(StringValidation {
+                    pattern: Some("^0x[0-9a-fA-F]{40}$".to_string()),
+                    ..Default::default()
+                })),
+                ..Default::default()
+            }
+            .into()
+        }
+    }
+
+    /// A 32-byte hash of a header, a transaction or a node of a Merkle tree.
+    /// Hashes are parsed from hex with or without the 0x prefix, and always
+    /// displayed and serialized as lowercase hex prefixed by 0x.
+    #[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+    pub struct Hash256(pub [u8; 32]);
+
+    impl Hash256 {
+        /// The hex digits of the hash without the 0x prefix, as they are
+        /// concatenated when hashing the nodes of the Merkle trees.
+        pub fn hex(&self) -> String {
+            hex::encode(self.0)
+        }
+
+        /// Number of leading zero hex digits, which is the difficulty the
+        /// hash satisfies.
+        pub fn leading_zero_digits(&self) -> usize {
+            self.hex()
+                .bytes()
+                .take_while(|digit| *digit == b'0')
+                .count()
+        }
+    }
+
+    impl Display for Hash256 {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            write!(f, "0x{}", self.hex())
+        }
+    }
+
+    impl FromStr for Hash256 {
+        type Err = SimulatorError;
+
+        /// Accepts 64 hex digits, optionally prefixed by 0x.
+        fn from_str(value: &str) -> Result<Self, Self::Err> {
+            let malformed = |reason: String| SimulatorError::MalformedHash {
+                hash: value.to_string(),
+                reason,
+            };
+            let digits = value.strip_prefix("0x").unwrap_or(value);
+            if digits.len() != 64 {
+                return Err(malformed(format!(
+                    "expected 64 hex digits, found {}",
+                    digits.len()
+                )));
+            }
+            let mut bytes = [0; 32];
+            hex::decode_to_slice(digits, &mut bytes)
+                .map_err(|_| malformed("expected only hex digits".to_string()))?;
+            Ok(Hash256(bytes))
+        }
+    }
+
+    impl Serialize for Hash256 {
+        fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            serializer.collect_str(self)
+        }
+    }
+
+    impl<'de> Deserialize<'de> for Hash256 {
+        fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+            let value = String::deserialize(deserializer)?;
+            value.parse().map_err(serde::de::Error::custom)
+        }
+    }
+
+    impl JsonSchema for Hash256 {
+        fn schema_name() -> String {
+            "Hash256".to_string()
+        }
+
+        fn json_schema(_: &mut SchemaGenerator) -> Schema {
+            SchemaObject {
+                instance_type: Some(InstanceType::String.into()),
+                string: Some(Box::new(StringValidation {
+                    pattern: Some("^(0x)?[0-9a-fA-F]{64}$".to_string()),
+                    ..Default::default()
+                })),
+                ..Default::default()
+            }
+            .into()
+        }
+    }
+
+    #[derive(Serialize, Deserialize, JsonSchema, Debug, Clone)]
     pub struct Header {
         pub difficulty: u32,
         pub height: u32,
-        pub miner: String,
+        pub miner: Address,
         pub nonce: u32,
-        pub hash: String,
-        pub previous_block_header_hash: String,
-        pub timestamp: u32,
+        pub hash: Hash256,
+        pub previous_block_header_hash: Hash256,
+        /// Seconds since the UNIX epoch. Besides the number written by the
+        /// simulator, RFC 3339 dates such as `2023-10-16T00:00:00Z` are read.
+        #[serde(deserialize_with = "deserialize_timestamp")]
+        pub timestamp: u64,
         pub transactions_count: u32,
-        pub transactions_merkle_root: String,
+        pub transactions_merkle_root: Hash256,
+        /// Version of the block, which signals support for the scheduled
+        /// rule deployments. Legacy blocks have version 0, which is left out
+        /// of both the JSON files and the hash.
+        #[serde(default, skip_serializing_if = "is_legacy_version")]
+        pub version: u32,
+    }
+
+    fn is_legacy_version(version: &u32) -> bool {
+        *version == 0
+    }
+
+    struct TimestampVisitor;
+
+    impl Visitor<'_> for TimestampVisitor {
+        type Value = u64;
+
+        fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+            f.write_str("seconds since the UNIX epoch or an RFC 3339 date")
+        }
+
+        fn visit_u64<E: de::Error>(self, seconds: u64) -> Result<u64, E> {
+            Ok(seconds)
+        }
+
+        fn visit_str<E: de::Error>(self, date: &str) -> Result<u64, E> {
+            parse_date(date).ok_or_else(|| E::invalid_value(Unexpected::Str(date), &self))
+        }
+    }
+
+    fn deserialize_timestamp<'de, D: Deserializer<'de>>(deserializer: D) -> Result<u64, D::Error> {
+        deserializer.deserialize_any(TimestampVisitor)
+    }
+
+    /// Seconds since the UNIX epoch of an RFC 3339 date, None if it isn't one
+    /// or lies before the epoch.
+    pub fn parse_date(date: &str) -> Option<u64> {
+        DateTime::parse_from_rfc3339(date)
+            .ok()
+            .and_then(|date| u64::try_from(date.timestamp()).ok())
+    }
+
+    /// The timestamp as an RFC 3339 date in UTC, such as
+    /// `2023-10-16T00:00:00Z`, or as the number of seconds if it lies beyond
+    /// the dates that can be represented.
+    pub fn format_timestamp(timestamp: u64) -> String {
+        i64::try_from(timestamp)
+            .ok()
+            .and_then(|seconds| DateTime::from_timestamp(seconds, 0))
+            .map_or_else(
+                || timestamp.to_string(),
+                |date| date.to_rfc3339_opts(SecondsFormat::Secs, true),
+            )
+    }
+
+    impl Header {
+        /// Hash of the header computed the way it is during mining, that is
+        /// with the hash field left empty. For a valid header it is equal to
+        /// the stored hash.
+        pub fn mined_hash(&self) -> Hash256 {
+            self.hash()
+        }
+
+        /// Hash of the header as specified by the tutorial the sample chains
+        /// in data/ were produced for, which leaves the hash field out of the
+        /// preimage instead of hashing it empty.
+        pub fn specified_hash(&self) -> Hash256 {
+            Hash256(Sha256::digest(self.specified_preimage().as_bytes()).into())
+        }
+
+        /// The header serialized to the canonical 80-byte Bitcoin layout: the
+        /// version, previous block hash, merkle root, timestamp, compact
+        /// target and nonce. Hashes are stored in the reversed byte order
+        /// Bitcoin displays them in. The timestamp is truncated to its 32 bits
+        /// in the layout, wrapping around in 2106 as in Bitcoin. The height,
+        /// miner and transactions count have no place in the layout and
+        /// aren't covered by the hash.
+        pub fn bitcoin_bytes(&self) -> [u8; 80] {
+            let mut bytes = [0u8; 80];
+            bytes[..4].copy_from_slice(&self.version.to_le_bytes());
+            bytes[4..36].copy_from_slice(&internal_byte_order(&self.previous_block_header_hash));
+            bytes[36..68].copy_from_slice(&internal_byte_order(&self.transactions_merkle_root));
+            bytes[68..72].copy_from_slice(&(self.timestamp as u32).to_le_bytes());
+            bytes[72..76].copy_from_slice(&compact_target(self.difficulty).to_le_bytes());
+            bytes[76..].copy_from_slice(&self.nonce.to_le_bytes());
+            bytes
+        }
+
+        /// Double SHA-256 of the 80-byte Bitcoin serialization, displayed in
+        /// reversed byte order as Bitcoin does, so that a hash satisfying the
+        /// proof of work starts with zeros.
+        pub fn bitcoin_hash(&self) -> Hash256 {
+            bitcoin_header_hash(&self.bitcoin_bytes())
+        }
+
+        /// Whether the stored hash is the hash of the header in the format.
+        /// Hashes computed in any other format are rejected.
+        pub fn has_valid_hash(&self, format: HeaderFormat) -> bool {
+            self.hash == format.header_hash(self)
+        }
+
+        /// Hash stamped on the blocks produced with `--no-pow` instead of
+        /// mining them: the SHA-256 of the preimage prefixed by `mock-pow,`,
+        /// with as many leading zeros as the difficulty requires. The prefix
+        /// keeps it apart from the hashes of mined headers, which never
+        /// validate as it.
+        pub fn mock_pow_hash(&self) -> Hash256 {
+            let preimage = format!("{}{}", MOCK_POW_PREFIX, self.hash_preimage());
+            let mut hash: [u8; 32] = Sha256::digest(preimage.as_bytes()).into();
+            for digit in 0..(self.difficulty as usize).min(64) {
+                hash[digit / 2] &= if digit % 2 == 0 { 0x0f } else { 0xf0 };
+            }
+            Hash256(hash)
+        }
+
+        /// Whether the block was produced with `--no-pow`.
+        pub fn has_mock_pow(&self) -> bool {
+            self.hash == self.mock_pow_hash()
+        }
+
+        /// Checks that the header hashes to its stored hash in the format and
+        /// that the hash satisfies the proof of work required by its
+        /// difficulty.
+        pub fn validate(&self, format: HeaderFormat) -> Result<(), SimulatorError> {
+            let invalid = |reason: &str| SimulatorError::InvalidHeader {
+                height: self.height,
+                reason: reason.to_string(),
+            };
+            if self.has_mock_pow() {
+                return Err(invalid("the block was produced with --no-pow"));
+            }
+            if !self.has_valid_hash(format) {
+                return Err(invalid("the hash does not match the header"));
+            }
+            if !is_valid_block_header_hash(&self.hash, self.difficulty as usize) {
+                return Err(invalid("the hash doesn't satisfy the proof of work"));
+            }
+            Ok(())
+        }
+
+        /// Expected number of hashes needed to mine the block, each leading
+        /// zero of the hash makes finding it 16 times harder.
+        pub fn work(&self) -> U256 {
+            match self.difficulty {
+                0..=63 => U256::ONE.shl_vartime(4 * self.difficulty as usize),
+                _ => U256::MAX,
+            }
+        }
+    }
+
+    /// Layout in which the headers of the mined blocks are serialized for
+    /// hashing.
+    #[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+    #[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+    pub enum HeaderFormat {
+        /// Comma-separated string of the fields hashed with SHA-256
+        #[default]
+        Simulator,
+        /// Canonical 80-byte Bitcoin header hashed with double SHA-256, with
+        /// merkle trees padded by duplicating the last leaf
+        Bitcoin,
+        /// Legacy layout of the tutorial the sample chains in data/ were
+        /// produced for, which leaves the hash field out of the
+        /// comma-separated string and hashes the 0x-prefixed hashes of the
+        /// merkle tree in sorted pairs
+        Tutorial,
     }
 
-    #[derive(Serialize, Deserialize, Debug, Clone)]
+    impl HeaderFormat {
+        /// Hash of the header in this format, computed with the hash field
+        /// left empty as during mining.
+        pub fn header_hash(self, header: &Header) -> Hash256 {
+            match self {
+                HeaderFormat::Simulator => header.mined_hash(),
+                HeaderFormat::Bitcoin => header.bitcoin_hash(),
+                HeaderFormat::Tutorial => header.specified_hash(),
+            }
+        }
+    }
+
+    /// Double SHA-256 of a serialized 80-byte Bitcoin header, displayed in
+    /// reversed byte order.
+    pub fn bitcoin_header_hash(bytes: &[u8; 80]) -> Hash256 {
+        let mut hash: [u8; 32] = Sha256::digest(Sha256::digest(bytes)).into();
+        hash.reverse();
+        Hash256(hash)
+    }
+
+    /// Bytes of a hash in the order Bitcoin serializes them, which is the
+    /// reverse of the displayed order.
+    pub fn internal_byte_order(hash: &Hash256) -> [u8; 32] {
+        let mut bytes = hash.0;
+        bytes.reverse();
+        bytes
+    }
+
+    /// Target in the compact `bits` encoding of Bitcoin headers that hashes
+    /// with the given number of leading zero hex digits stay below. Having
+    /// 4 * difficulty leading zero bits means being at most 2^(256 - 4 *
+    /// difficulty), which is a single mantissa bit. The target of difficulty
+    /// 0 doesn't fit into 256 bits, so it is capped at the largest target of
+    /// the encoding.
+    pub fn compact_target(difficulty: u32) -> u32 {
+        let bits = 256 - 4 * difficulty.min(64);
+        if bits == 256 {
+            return 0x2100_ffff;
+        }
+        ((bits / 8 + 1) << 24) | (1 << (16 + bits % 8))
+    }
+
+    /// The hash should have n=difficulty leading zero hex digits to be
+    /// considered valid. Difficulties above the 64 digits of the hash are
+    /// never satisfied.
+    pub fn is_valid_block_header_hash(hash: &Hash256, difficulty: usize) -> bool {
+        difficulty <= 64 && hash.leading_zero_digits() >= difficulty
+    }
+
+    /// Unit in which amounts are shown to the user. The files always store
+    /// amounts as a whole number of base units.
+    #[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+    #[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+    pub enum Denomination {
+        /// Whole number of base units
+        #[default]
+        Base,
+        /// Coins of `BASE_UNITS_PER_COIN` base units, with up to eight
+        /// decimals
+        Coins,
+    }
+
+    /// An amount of base units transferred, paid as a fee or credited as a
+    /// subsidy. Sums of amounts are checked so that they fail instead of
+    /// silently wrapping around.
+    #[derive(
+        Serialize,
+        Deserialize,
+        JsonSchema,
+        Clone,
+        Copy,
+        Debug,
+        Default,
+        PartialEq,
+        Eq,
+        PartialOrd,
+        Ord,
+        Hash,
+    )]
+    #[serde(transparent)]
+    pub struct Amount(pub u64);
+
+    impl Amount {
+        pub const ZERO: Amount = Amount(0);
+
+        pub fn checked_add(self, other: Amount) -> Option<Amount> {
+            self.0.checked_add(other.0).map(Amount)
+        }
+
+        pub fn checked_sub(self, other: Amount) -> Option<Amount> {
+            self.0.checked_sub(other.0).map(Amount)
+        }
+
+        /// Sum of the amounts, or `None` if it doesn't fit in 64 bits.
+        pub fn checked_sum(amounts: impl IntoIterator<Item = Amount>) -> Option<Amount> {
+            amounts
+                .into_iter()
+                .try_fold(Amount::ZERO, |sum, amount| sum.checked_add(amount))
+        }
+
+        /// The amount in the denomination, such as `150000000` base units or
+        /// `1.5 coins`.
+        pub fn format(self, denomination: Denomination) -> String {
+            match denomination {
+                Denomination::Base => self.0.to_string(),
+                Denomination::Coins => {
+                    let whole = self.0 / BASE_UNITS_PER_COIN;
+                    let fraction = format!("{:08}", self.0 % BASE_UNITS_PER_COIN);
+                    match fraction.trim_end_matches('0') {
+                        "" => format!("{} coins", whole),
+                        fraction => format!("{}.{} coins", whole, fraction),
+                    }
+                }
+            }
+        }
+    }
+
+    /// Amounts are displayed as a whole number of base units, which is also
+    /// how they are hashed.
+    impl Display for Amount {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            write!(f, "{}", self.0)
+        }
+    }
+
+    impl FromStr for Amount {
+        type Err = SimulatorError;
+
+        /// Accepts a whole number of base units, or a number of coins with up
+        /// to eight decimals followed by `coins`, such as `1.5coins`.
+        fn from_str(value: &str) -> Result<Self, Self::Err> {
+            let malformed = || SimulatorError::MalformedAmount(value.to_string());
+            let Some(coins) = value.trim().strip_suffix("coins") else {
+                return value.trim().parse().map(Amount).map_err(|_| malformed());
+            };
+            let (whole, fraction) = coins.trim().split_once('.').unwrap_or((coins.trim(), ""));
+            if fraction.len() > 8 || !fraction.bytes().all(|digit| digit.is_ascii_digit()) {
+                return Err(malformed());
+            }
+            let whole: u64 = whole.parse().map_err(|_| malformed())?;
+            let fraction: u64 = format!("{:0<8}", fraction)
+                .parse()
+                .map_err(|_| malformed())?;
+            whole
+                .checked_mul(BASE_UNITS_PER_COIN)
+                .and_then(|base_units| base_units.checked_add(fraction))
+                .map(Amount)
+                .ok_or_else(malformed)
+        }
+    }
+
+    #[derive(Serialize, Deserialize, JsonSchema, Debug, Clone)]
     pub struct Transaction {
-        pub amount: u64,
+        pub amount: Amount,
         pub lock_time: u32,
-        pub receiver: String,
-        pub sender: String,
+        pub receiver: Address,
+        pub sender: Address,
+        /// Unsigned transactions may omit the signature altogether.
+        #[serde(default)]
         pub signature: String,
-        pub transaction_fee: u64,
+        pub transaction_fee: Amount,
+    }
+
+    impl Transaction {
+        pub fn builder() -> TransactionBuilder {
+            TransactionBuilder::default()
+        }
+
+        /// Number of bytes the transaction takes up in the compact JSON
+        /// serialization of a block.
+        pub fn serialized_size(&self) -> usize {
+            serde_json::to_vec(self)
+                .expect("transactions always serialize")
+                .len()
+        }
+
+        /// Fee paid per byte of the serialized transaction.
+        pub fn fee_per_byte(&self) -> f64 {
+            self.transaction_fee.0 as f64 / self.serialized_size() as f64
+        }
+    }
+
+    /// Validating builder of transactions. Addresses can be given either as
+    /// `Address` values or as strings, which are parsed when the transaction
+    /// is built. If a signing key is set, the built transaction is signed.
+    #[derive(Default)]
+    pub struct TransactionBuilder {
+        amount: Amount,
+        lock_time: u32,
+        receiver: Option<String>,
+        sender: Option<String>,
+        transaction_fee: Amount,
+        max_fee: Option<Amount>,
+        signing_key: Option<SigningKey>,
     }
 
-    #[derive(Serialize, Deserialize, Debug, Clone)]
+    impl TransactionBuilder {
+        pub fn amount(mut self, amount: Amount) -> Self {
+            self.amount = amount;
+            self
+        }
+
+        pub fn lock_time(mut self, lock_time: u32) -> Self {
+            self.lock_time = lock_time;
+            self
+        }
+
+        pub fn receiver(mut self, receiver: impl ToString) -> Self {
+            self.receiver = Some(receiver.to_string());
+            self
+        }
+
+        pub fn sender(mut self, sender: impl ToString) -> Self {
+            self.sender = Some(sender.to_string());
+            self
+        }
+
+        pub fn transaction_fee(mut self, transaction_fee: Amount) -> Self {
+            self.transaction_fee = transaction_fee;
+            self
+        }
+
+        /// Rejects transactions paying a higher fee than the given one. Any
+        /// fee is accepted if it isn't set.
+        pub fn max_fee(mut self, max_fee: Amount) -> Self {
+            self.max_fee = Some(max_fee);
+            self
+        }
+
+        /// Signs the transaction with the key when it is built, the key must
+        /// belong to the sender.
+        pub fn signed_with(mut self, signing_key: &SigningKey) -> Self {
+            self.signing_key = Some(signing_key.clone());
+            self
+        }
+
+        pub fn build(self) -> Result<Transaction, SimulatorError> {
+            let invalid = |reason: &str| SimulatorError::InvalidTransaction(reason.to_string());
+            let sender: Address = self
+                .sender
+                .ok_or_else(|| invalid("the sender is missing"))?
+                .parse()?;
+            let receiver: Address = self
+                .receiver
+                .ok_or_else(|| invalid("the receiver is missing"))?
+                .parse()?;
+            if sender == NULL_ADDRESS {
+                return Err(invalid(
+                    "only genesis allocations are sent by the null address",
+                ));
+            }
+            if self.amount == Amount::ZERO {
+                return Err(invalid("the amount must be greater than zero"));
+            }
+            if let Some(max_fee) = self
+                .max_fee
+                .filter(|max_fee| self.transaction_fee > *max_fee)
+            {
+                return Err(invalid(&format!(
+                    "the fee cannot be higher than {}",
+                    max_fee
+                )));
+            }
+            if self.amount.checked_add(self.transaction_fee).is_none() {
+                return Err(invalid("the amount and the fee add up to too many coins"));
+            }
+
+            let transaction = Transaction {
+                amount: self.amount,
+                lock_time: self.lock_time,
+                receiver,
+                sender,
+                signature: "".to_string(),
+                transaction_fee: self.transaction_fee,
+            };
+            match &self.signing_key {
+                Some(signing_key) => sign(transaction, signing_key),
+                None => Ok(transaction),
+            }
+        }
+    }
+
+    #[derive(Serialize, Deserialize, JsonSchema, Debug, Clone)]
     pub struct Block {
         pub header: Header,
         pub transactions: Vec<Transaction>,
     }
 
-    #[derive(Clone, Debug, Serialize)]
+    impl Block {
+        /// Sum of the fees of the transactions, which the miner collects.
+        pub fn total_fees(&self) -> Result<Amount, SimulatorError> {
+            Amount::checked_sum(self.transactions.iter().map(|t| t.transaction_fee)).ok_or_else(
+                || SimulatorError::AmountOverflow(format!("fees of block {}", self.header.height)),
+            )
+        }
+    }
+
+    /// Sequence of blocks indexed by their header hashes and heights. It is
+    /// stored in the JSON files as the plain list of blocks.
+    #[derive(Debug, Clone, Default)]
+    pub struct Blockchain {
+        /// Header of the block preceding the first one, for chain segments
+        /// continuing from a snapshot rather than from the genesis block.
+        base: Option<Header>,
+        blocks: Vec<Block>,
+        by_hash: HashMap<Hash256, usize>,
+        by_height: HashMap<u32, usize>,
+    }
+
+    impl Blockchain {
+        /// Empty chain segment whose first block is to be mined on top of the
+        /// given header.
+        pub fn continuing_from(base: Header) -> Self {
+            Blockchain {
+                base: Some(base),
+                ..Default::default()
+            }
+        }
+
+        /// Header of the most recently appended block, or the header the
+        /// segment continues from if no block has been appended yet.
+        pub fn tip_header(&self) -> Result<&Header, SimulatorError> {
+            self.blocks
+                .last()
+                .map(|block| &block.header)
+                .or(self.base.as_ref())
+                .ok_or(SimulatorError::EmptyBlockchain)
+        }
+
+        /// The most recently appended block.
+        pub fn tip(&self) -> Result<&Block, SimulatorError> {
+            self.blocks.last().ok_or(SimulatorError::EmptyBlockchain)
+        }
+
+        pub fn get_by_hash(&self, hash: &Hash256) -> Option<&Block> {
+            self.by_hash.get(hash).map(|&index| &self.blocks[index])
+        }
+
+        pub fn get_by_height(&self, height: u32) -> Option<&Block> {
+            self.by_height
+                .get(&height)
+                .map(|&index| &self.blocks[index])
+        }
+
+        /// Looks up a block by its position in the chain, block numbers start
+        /// from 1 for the genesis block.
+        pub fn get_by_number(&self, block_number: usize) -> Result<&Block, SimulatorError> {
+            block_number
+                .checked_sub(1)
+                .and_then(|index| self.blocks.get(index))
+                .ok_or(SimulatorError::BlockNotFound(block_number))
+        }
+
+        /// Appends the block after checking that it extends the tip of the
+        /// chain, that its hash matches its header in the format and that it
+        /// doesn't conflict with any of the finalized checkpoints.
+        pub fn append(
+            &mut self,
+            block: Block,
+            format: HeaderFormat,
+            checkpoints: &[Checkpoint],
+        ) -> Result<(), SimulatorError> {
+            let has_valid_hash = block.header.has_valid_hash(format);
+            self.append_checked(block, checkpoints, has_valid_hash)
+        }
+
+        /// Appends a block produced without proof of work after the same
+        /// checks as `append()`, except that its hash must be the mock one.
+        pub fn append_mock_pow(
+            &mut self,
+            block: Block,
+            checkpoints: &[Checkpoint],
+        ) -> Result<(), SimulatorError> {
+            let has_mock_pow = block.header.has_mock_pow();
+            self.append_checked(block, checkpoints, has_mock_pow)
+        }
+
+        fn append_checked(
+            &mut self,
+            block: Block,
+            checkpoints: &[Checkpoint],
+            has_valid_hash: bool,
+        ) -> Result<(), SimulatorError> {
+            let invalid = |reason: &str| SimulatorError::InvalidBlock {
+                height: block.header.height,
+                reason: reason.to_string(),
+            };
+            let (expected_height, expected_previous_hash) = match self.tip_header() {
+                Ok(tip) => (tip.height + 1, tip.hash),
+                Err(_) => (0, NULL_HASH),
+            };
+            if block.header.height != expected_height {
+                return Err(invalid(&format!("expected height {}", expected_height)));
+            }
+            if block.header.previous_block_header_hash != expected_previous_hash {
+                return Err(invalid("the previous block header hash is not the tip"));
+            }
+            if !has_valid_hash {
+                return Err(invalid("the hash does not match the header"));
+            }
+            if let Some(checkpoint) = conflicting_checkpoint(checkpoints, &block.header) {
+                return Err(invalid(&format!(
+                    "it conflicts with the checkpoint {}",
+                    checkpoint.hash
+                )));
+            }
+
+            self.insert(block);
+            Ok(())
+        }
+
+        pub fn len(&self) -> usize {
+            self.blocks.len()
+        }
+
+        pub fn is_empty(&self) -> bool {
+            self.blocks.is_empty()
+        }
+
+        pub fn iter(&self) -> std::slice::Iter<'_, Block> {
+            self.blocks.iter()
+        }
+
+        /// Removes the blocks above the given height and returns them in
+        /// order, as when reorganising onto a branch forking at that height.
+        pub fn truncate(&mut self, height: u32) -> Vec<Block> {
+            let kept = self
+                .blocks
+                .iter()
+                .take_while(|block| block.header.height <= height)
+                .count();
+            let removed = self.blocks.split_off(kept);
+            for block in &removed {
+                self.by_hash.remove(&block.header.hash);
+                self.by_height.remove(&block.header.height);
+            }
+            removed
+        }
+
+        pub fn blocks(&self) -> &[Block] {
+            &self.blocks
+        }
+
+        /// Sum of the work of all blocks in the chain.
+        pub fn total_work(&self) -> U256 {
+            self.blocks.iter().fold(U256::ZERO, |work, block| {
+                work.saturating_add(&block.header.work())
+            })
+        }
+
+        fn insert(&mut self, block: Block) {
+            let index = self.blocks.len();
+            self.by_hash.insert(block.header.hash, index);
+            self.by_height.insert(block.header.height, index);
+            self.blocks.push(block);
+        }
+    }
+
+    /// Blocks loaded from files are indexed without validation, as the blocks
+    /// of the tutorial were hashed differently than the ones mined here.
+    impl From<Vec<Block>> for Blockchain {
+        fn from(blocks: Vec<Block>) -> Self {
+            let mut blockchain = Blockchain::default();
+            for block in blocks {
+                blockchain.insert(block);
+            }
+            blockchain
+        }
+    }
+
+    impl IntoIterator for Blockchain {
+        type Item = Block;
+        type IntoIter = std::vec::IntoIter<Block>;
+
+        fn into_iter(self) -> Self::IntoIter {
+            self.blocks.into_iter()
+        }
+    }
+
+    impl<'a> IntoIterator for &'a Blockchain {
+        type Item = &'a Block;
+        type IntoIter = std::slice::Iter<'a, Block>;
+
+        fn into_iter(self) -> Self::IntoIter {
+            self.iter()
+        }
+    }
+
+    impl Serialize for Blockchain {
+        fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            self.blocks.serialize(serializer)
+        }
+    }
+
+    impl<'de> Deserialize<'de> for Blockchain {
+        fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+            Ok(Blockchain::from(Vec::<Block>::deserialize(deserializer)?))
+        }
+    }
+
+    /// Blocks of competing branches sharing a common genesis block. The fork
+    /// choice follows the branch with the most cumulative work rather than the
+    /// most blocks, with ties resolved in favour of the tip received first.
+    #[derive(Debug, Default)]
+    pub struct BlockTree {
+        /// Blocks by their hashes, with the cumulative work of the branch
+        /// ending at each of them.
+        blocks: HashMap<Hash256, (Block, U256)>,
+        /// Hashes of the blocks in the order they were received.
+        received: Vec<Hash256>,
+        best_tip: Option<Hash256>,
+        checkpoints: Vec<Checkpoint>,
+        format: HeaderFormat,
+    }
+
+    impl BlockTree {
+        /// Empty tree of blocks hashed in the format, whose branches have to
+        /// agree with the checkpoints.
+        pub fn new(checkpoints: &[Checkpoint], format: HeaderFormat) -> Self {
+            BlockTree {
+                checkpoints: checkpoints.to_vec(),
+                format,
+                ..BlockTree::default()
+            }
+        }
+
+        /// Adds the block to the tree after checking that it extends a known
+        /// block (or is a genesis block), that its hash matches its header and
+        /// that its branch neither conflicts with a finalized checkpoint nor
+        /// forks off below one. Known blocks are ignored.
+        pub fn insert(&mut self, block: Block) -> Result<(), SimulatorError> {
+            let header = &block.header;
+            if self.blocks.contains_key(&header.hash) {
+                return Ok(());
+            }
+            let invalid = |reason: &str| SimulatorError::InvalidBlock {
+                height: header.height,
+                reason: reason.to_string(),
+            };
+            let parent_work = if header.previous_block_header_hash == NULL_HASH {
+                if header.height != 0 {
+                    return Err(invalid("only the genesis block has no parent"));
+                }
+                U256::ZERO
+            } else {
+                let (parent, parent_work) = self
+                    .blocks
+                    .get(&header.previous_block_header_hash)
+                    .ok_or_else(|| invalid("the previous block is unknown"))?;
+                if header.height != parent.header.height + 1 {
+                    return Err(invalid(&format!(
+                        "expected height {}",
+                        parent.header.height + 1
+                    )));
+                }
+                *parent_work
+            };
+            if !header.has_valid_hash(self.format) {
+                return Err(invalid("the hash does not match the header"));
+            }
+            let hash_at = |height| match height == header.height {
+                true => Some(&header.hash),
+                false => self.ancestor_at(&header.previous_block_header_hash, height),
+            };
+            let finalized = |checkpoint: &Checkpoint| self.blocks.contains_key(&checkpoint.hash);
+            if let Some(checkpoint) =
+                conflicting_branch_checkpoint(&self.checkpoints, header.height, hash_at, finalized)
+            {
+                return Err(invalid(&format!(
+                    "its branch conflicts with the checkpoint {}",
+                    checkpoint.hash
+                )));
+            }
+
+            let work = parent_work.saturating_add(&header.work());
+            let hash = header.hash;
+            let is_checkpoint = self.checkpoints.iter().any(|c| c.hash == hash);
+            self.blocks.insert(hash, (block, work));
+            self.received.push(hash);
+            // A block that passed the checks above is final. Other branches
+            // only stop being final when a checkpoint arrives, as they may
+            // then end below it.
+            if is_checkpoint {
+                self.best_tip = self.heaviest_final_block();
+            } else if self.best_work().is_none_or(|best_work| work > best_work) {
+                self.best_tip = Some(hash);
+            }
+            Ok(())
+        }
+
+        pub fn best_tip(&self) -> Option<&Header> {
+            self.best_tip
+                .as_ref()
+                .map(|hash| &self.blocks[hash].0.header)
+        }
+
+        /// The block with the most cumulative work whose branch satisfies
+        /// every checkpoint, the one received first among equally heavy
+        /// blocks.
+        fn heaviest_final_block(&self) -> Option<Hash256> {
+            let mut candidates: Vec<(&Hash256, U256)> = self
+                .received
+                .iter()
+                .map(|hash| (hash, self.blocks[hash].1))
+                .collect();
+            // The sort is stable, so equally heavy blocks stay in the order
+            // they were received.
+            candidates.sort_by(|(_, work), (_, other_work)| other_work.cmp(work));
+            candidates
+                .into_iter()
+                .map(|(hash, _)| hash)
+                .find(|hash| self.is_final(&self.blocks[*hash].0.header))
+                .copied()
+        }
+
+        /// Whether the branch ending at the header contains all known
+        /// checkpoints up to its height and doesn't end below any of them.
+        fn is_final(&self, header: &Header) -> bool {
+            let hash_at = |height| self.ancestor_at(&header.hash, height);
+            let finalized = |checkpoint: &Checkpoint| self.blocks.contains_key(&checkpoint.hash);
+            conflicting_branch_checkpoint(&self.checkpoints, header.height, hash_at, finalized)
+                .is_none()
+        }
+
+        /// Hash of the ancestor at the height of the block with the hash, or of
+        /// the block itself.
+        fn ancestor_at(&self, hash: &Hash256, height: u32) -> Option<&Hash256> {
+            let mut block = &self.blocks.get(hash)?.0;
+            while block.header.height > height {
+                block = &self.blocks.get(&block.header.previous_block_header_hash)?.0;
+            }
+            (block.header.height == height).then_some(&block.header.hash)
+        }
+
+        /// Cumulative work of the branch ending at the block with the hash.
+        pub fn cumulative_work(&self, hash: &Hash256) -> Option<U256> {
+            self.blocks.get(hash).map(|(_, work)| *work)
+        }
+
+        fn best_work(&self) -> Option<U256> {
+            self.best_tip
+                .as_ref()
+                .and_then(|hash| self.cumulative_work(hash))
+        }
+
+        /// The branch ending at the best tip, from the genesis block.
+        pub fn best_chain(&self) -> Blockchain {
+            let mut blocks = vec![];
+            let mut next = self.best_tip.as_ref();
+            while let Some((block, _)) = next.and_then(|hash| self.blocks.get(hash)) {
+                blocks.push(block.clone());
+                next = Some(&block.header.previous_block_header_hash);
+            }
+            blocks.reverse();
+            Blockchain::from(blocks)
+        }
+    }
+
+    /// Headers of the blocks of a chain without their transactions, for the
+    /// commands that don't need the transaction bodies. It is stored in the
+    /// same format as the blockchain with the transaction lists left out, and
+    /// can be loaded from full blockchain files as well.
+    #[derive(Debug, Clone, Default)]
+    pub struct HeaderChain {
+        headers: Vec<Header>,
+    }
+
+    /// Block of a blockchain file as seen by the header chain, the
+    /// transactions are skipped without being deserialized.
+    #[derive(Deserialize)]
+    struct HeaderOnlyBlock {
+        header: Header,
+    }
+
+    #[derive(Serialize)]
+    struct HeaderOnlyBlockRef<'a> {
+        header: &'a Header,
+    }
+
+    impl HeaderChain {
+        pub fn tip(&self) -> Result<&Header, SimulatorError> {
+            self.headers.last().ok_or(SimulatorError::EmptyBlockchain)
+        }
+
+        /// Looks up a header by the position of its block in the chain, block
+        /// numbers start from 1 for the genesis block.
+        pub fn get_by_number(&self, block_number: usize) -> Result<&Header, SimulatorError> {
+            block_number
+                .checked_sub(1)
+                .and_then(|index| self.headers.get(index))
+                .ok_or(SimulatorError::BlockNotFound(block_number))
+        }
+
+        pub fn get_by_height(&self, height: u32) -> Option<&Header> {
+            self.headers.iter().find(|header| header.height == height)
+        }
+
+        pub fn len(&self) -> usize {
+            self.headers.len()
+        }
+
+        pub fn is_empty(&self) -> bool {
+            self.headers.is_empty()
+        }
+
+        pub fn iter(&self) -> std::slice::Iter<'_, Header> {
+            self.headers.iter()
+        }
+
+        /// Checks that each header is valid on its own in the format and links
+        /// to the header before it.
+        pub fn verify(&self, format: HeaderFormat) -> Result<(), SimulatorError> {
+            let mut previous_hash = &NULL_HASH;
+            for (expected_height, header) in (0..).zip(&self.headers) {
+                let invalid = |reason: &str| SimulatorError::InvalidHeader {
+                    height: header.height,
+                    reason: reason.to_string(),
+                };
+                if header.height != expected_height {
+                    return Err(invalid(&format!("expected height {}", expected_height)));
+                }
+                if header.previous_block_header_hash != *previous_hash {
+                    return Err(invalid("it doesn't link to the previous header"));
+                }
+                header.validate(format)?;
+                debug!("Header {} is valid", header.height);
+                previous_hash = &header.hash;
+            }
+            Ok(())
+        }
+    }
+
+    impl From<&Blockchain> for HeaderChain {
+        fn from(blockchain: &Blockchain) -> Self {
+            HeaderChain {
+                headers: blockchain
+                    .iter()
+                    .map(|block| block.header.clone())
+                    .collect(),
+            }
+        }
+    }
+
+    impl<'a> IntoIterator for &'a HeaderChain {
+        type Item = &'a Header;
+        type IntoIter = std::slice::Iter<'a, Header>;
+
+        fn into_iter(self) -> Self::IntoIter {
+            self.iter()
+        }
+    }
+
+    impl Serialize for HeaderChain {
+        fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            serializer.collect_seq(
+                self.headers
+                    .iter()
+                    .map(|header| HeaderOnlyBlockRef { header }),
+            )
+        }
+    }
+
+    impl<'de> Deserialize<'de> for HeaderChain {
+        fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+            let blocks = Vec::<HeaderOnlyBlock>::deserialize(deserializer)?;
+            Ok(HeaderChain {
+                headers: blocks.into_iter().map(|block| block.header).collect(),
+            })
+        }
+    }
+
+    /// Node of a `MerkleTree`, whose children are the indices of their nodes
+    /// in the arena of the tree.
+    #[derive(Clone, Debug)]
     pub struct MerkleTreeNode {
-        pub hash: String,
-        pub left: Option<Box<MerkleTreeNode>>,
-        pub right: Option<Box<MerkleTreeNode>>,
+        pub hash: Hash256,
+        pub left: Option<usize>,
+        pub right: Option<usize>,
     }
 
-    impl Display for MerkleTreeNode {
-        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-            self.recursive_fmt(f, 0)
+    /// Merkle tree whose nodes are stored in a flat arena rather than boxed
+    /// one by one, level by level from the leaves to the root, which is the
+    /// last node. Its nodes are traversed from the root with `MerkleNode`.
+    #[derive(Clone, Debug)]
+    pub struct MerkleTree {
+        nodes: Vec<MerkleTreeNode>,
+        /// Number of leaves, not counting the one padding their level.
+        leaves_count: usize,
+    }
+
+    impl MerkleTree {
+        /// Tree of the nodes, whose first nodes are the leaves, whose children
+        /// must precede them in the arena and whose last node is the root.
+        /// None if there are no leaves, as a tree without leaves has no root.
+        pub fn from_nodes(nodes: Vec<MerkleTreeNode>, leaves_count: usize) -> Option<Self> {
+            if leaves_count == 0 || nodes.is_empty() {
+                return None;
+            }
+            Some(MerkleTree {
+                nodes,
+                leaves_count,
+            })
+        }
+
+        pub fn leaves_count(&self) -> usize {
+            self.leaves_count
+        }
+
+        pub fn root(&self) -> MerkleNode<'_> {
+            self.node(self.nodes.len() - 1)
+        }
+
+        pub fn node(&self, index: usize) -> MerkleNode<'_> {
+            MerkleNode { tree: self, index }
+        }
+
+        /// Hashes of the sibling of the leaf at the index and of the siblings
+        /// of its ancestors, from the leaf up to the root, as listed by
+        /// inclusion proofs. The path to the leaf is followed from the root
+        /// by the bits of its index, so that the proofs of all leaves are
+        /// read off the same tree. None if there is no leaf at the index.
+        pub fn sibling_hashes(&self, leaf: usize) -> Option<Vec<Hash256>> {
+            if leaf >= self.leaves_count {
+                return None;
+            }
+            let depth = std::iter::successors(Some(self.root()), MerkleNode::left).count() - 1;
+            let mut node = self.root();
+            let mut hashes = vec![];
+            for level in (0..depth).rev() {
+                let (next, sibling) = match (leaf >> level) & 1 {
+                    0 => (node.left()?, node.right()?),
+                    _ => (node.right()?, node.left()?),
+                };
+                hashes.push(sibling.hash());
+                node = next;
+            }
+            hashes.reverse();
+            Some(hashes)
         }
     }
 
-    impl MerkleTreeNode {
+    /// A node of a `MerkleTree`, borrowed from the tree to reach its
+    /// children.
+    #[derive(Clone, Copy, Debug)]
+    pub struct MerkleNode<'a> {
+        tree: &'a MerkleTree,
+        index: usize,
+    }
+
+    impl<'a> MerkleNode<'a> {
+        pub fn index(&self) -> usize {
+            self.index
+        }
+
+        pub fn hash(&self) -> Hash256 {
+            self.tree.nodes[self.index].hash
+        }
+
+        pub fn left(&self) -> Option<MerkleNode<'a>> {
+            self.tree.nodes[self.index]
+                .left
+                .map(|index| self.tree.node(index))
+        }
+
+        pub fn right(&self) -> Option<MerkleNode<'a>> {
+            self.tree.nodes[self.index]
+                .right
+                .map(|index| self.tree.node(index))
+        }
+
         fn recursive_fmt(&self, f: &mut std::fmt::Formatter<'_>, depth: usize) -> std::fmt::Result {
             let indentation = "    ".repeat(depth);
-            match (&self.left, &self.right) {
-                (None, None) => writeln!(f, "{}Leaf: {}", indentation, self.hash),
-                (None, Some(node)) => {
-                    writeln!(f, "{}Node: {}", indentation, self.hash)?;
-                    node.recursive_fmt(f, depth + 1)
+            match (self.left(), self.right()) {
+                (None, None) => writeln!(f, "{}Leaf: {}", indentation, self.hash()),
+                (left, right) => {
+                    writeln!(f, "{}Node: {}", indentation, self.hash())?;
+                    for node in left.into_iter().chain(right) {
+                        node.recursive_fmt(f, depth + 1)?;
+                    }
+                    Ok(())
                 }
-                (Some(node), None) => {
-                    writeln!(f, "{}Node: {}", indentation, self.hash)?;
-                    node.recursive_fmt(f, depth + 1)
+            }
+        }
+    }
+
+    /// Nodes are serialized with their children nested in them, as the
+    /// boxed nodes the tree used to be made of were.
+    impl Serialize for MerkleNode<'_> {
+        fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            let mut node = serializer.serialize_struct("MerkleTreeNode", 3)?;
+            node.serialize_field("hash", &self.hash())?;
+            node.serialize_field("left", &self.left())?;
+            node.serialize_field("right", &self.right())?;
+            node.end()
+        }
+    }
+
+    impl Serialize for MerkleTree {
+        fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            self.root().serialize(serializer)
+        }
+    }
+
+    impl Display for MerkleNode<'_> {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            self.recursive_fmt(f, 0)
+        }
+    }
+
+    impl Display for MerkleTree {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            self.root().fmt(f)
+        }
+    }
+
+    /// Merkle root of the transactions of a block under construction, kept
+    /// up to date as transactions are added and removed. Every level of the
+    /// tree is stored, so a change only rehashes the path from the changed
+    /// leaf to the root, which takes O(log n) hashes instead of rebuilding
+    /// the tree. The root is the one `construct_merkle_tree` computes from
+    /// the leaves in the same order.
+    #[derive(Clone, Debug)]
+    pub struct MerkleAccumulator {
+        /// The levels from the leaves to the root, the last level holds a
+        /// single node unless there are no leaves.
+        levels: Vec<Vec<Hash256>>,
+    }
+
+    impl Default for MerkleAccumulator {
+        fn default() -> Self {
+            MerkleAccumulator {
+                levels: vec![vec![]],
+            }
+        }
+    }
+
+    impl MerkleAccumulator {
+        pub fn new() -> Self {
+            Self::default()
+        }
+
+        /// Hashes of the transactions, in the order of the leaves.
+        pub fn leaves(&self) -> &[Hash256] {
+            &self.levels[0]
+        }
+
+        pub fn len(&self) -> usize {
+            self.leaves().len()
+        }
+
+        pub fn is_empty(&self) -> bool {
+            self.leaves().is_empty()
+        }
+
+        /// Root of the tree, which is the null hash if there are no leaves
+        /// as for blocks without transactions.
+        pub fn root(&self) -> Hash256 {
+            self.levels
+                .last()
+                .and_then(|level| level.first())
+                .copied()
+                .unwrap_or(NULL_HASH)
+        }
+
+        /// Appends a leaf after the last one.
+        pub fn push(&mut self, hash: Hash256) {
+            self.levels[0].push(hash);
+            self.update_path(self.len() - 1);
+        }
+
+        /// Removes the last leaf.
+        pub fn pop(&mut self) -> Option<Hash256> {
+            let hash = self.levels[0].pop()?;
+            self.update_path(self.len().saturating_sub(1));
+            Some(hash)
+        }
+
+        /// Replaces the leaf at the index.
+        pub fn set(&mut self, index: usize, hash: Hash256) {
+            self.levels[0][index] = hash;
+            self.update_path(index);
+        }
+
+        /// Removes the leaf at the index, which is replaced by the last leaf
+        /// so that only two paths change, as `Vec::swap_remove` does.
+        pub fn swap_remove(&mut self, index: usize) -> Hash256 {
+            let removed = self.levels[0][index];
+            let last = self.pop().expect("the index is that of a leaf");
+            if index < self.len() {
+                self.set(index, last);
+            }
+            removed
+        }
+
+        /// Rehashes the ancestors of the leaf at the index, resizing the
+        /// levels above to the number of leaves. Levels with an odd number
+        /// of nodes are padded with the null hash as in the full tree.
+        fn update_path(&mut self, mut index: usize) {
+            let mut level = 0;
+            while self.levels[level].len() > 1 {
+                let nodes = &self.levels[level];
+                let parent_index = index / 2;
+                let right = nodes.get(2 * parent_index + 1).unwrap_or(&NULL_HASH);
+                let parent = merkle_parent(&nodes[2 * parent_index], right);
+                let parents_count = nodes.len().div_ceil(2);
+                if self.levels.len() == level + 1 {
+                    self.levels.push(vec![]);
                 }
-                (Some(node1), Some(node2)) => {
-                    writeln!(f, "{}Node: {}", indentation, self.hash)?;
-                    node1.recursive_fmt(f, depth + 1)?;
-                    node2.recursive_fmt(f, depth + 1)
+                let parents = &mut self.levels[level + 1];
+                parents.truncate(parents_count);
+                if parent_index == parents.len() {
+                    parents.push(parent);
+                } else {
+                    parents[parent_index] = parent;
                 }
+                index = parent_index;
+                level += 1;
             }
+            self.levels.truncate(level + 1);
         }
     }
 
@@ -81,54 +1544,459 @@ pub mod blockchain {
     /// above, then the next element in the list is the hash that needs to be hashed
     /// with whatever we got in the first step. We repeat the process until the
     /// end of the list and whatever we get should equal the merkle root.
-    #[derive(Serialize, Deserialize, Debug, Clone)]
+    #[derive(Serialize, Deserialize, JsonSchema, Debug, Clone)]
     pub struct InclusionProof {
-        pub transaction_hash: String,
-        pub merkle_root: String,
-        pub hashes: Vec<String>,
+        pub transaction_hash: Hash256,
+        pub merkle_root: Hash256,
+        pub hashes: Vec<Hash256>,
     }
 
     impl InclusionProof {
-        pub fn verify(&self) -> Result<InclusionProof, String> {
-            let hashes = &self.hashes;
-            let mut current_hash = self.transaction_hash.clone();
-            for i in 0..hashes.len() {
-                let hash_a = current_hash;
-                let hash_b = hashes[i].to_string();
-
-                let hash_a_value = U256::from_be_hex(hash_a.clone().trim_start_matches("0x"));
-                let hash_b_value =
-                    U256::from_be_hex(hash_b.clone().clone().trim_start_matches("0x"));
-
-                // The order of concatenation depends on the comparison of the
-                // strings
-                current_hash = if hash_a_value < hash_b_value {
-                    digest(hash_a + &hash_b)
-                } else {
-                    digest(hash_b + &hash_a)
-                };
-            }
-            // At this point current hash should be equal to the merkle root.
-            // we need to format the current_hash with 0x accordingly.
-            current_hash = "0x".to_string() + &current_hash;
-            if current_hash == self.merkle_root {
+        /// Fails with `InvalidProof` if the proof doesn't lead to the merkle
+        /// root.
+        pub fn verify(&self) -> Result<InclusionProof, SimulatorError> {
+            let root = self
+                .hashes
+                .iter()
+                .fold(self.transaction_hash, |current, hash| {
+                    merkle_parent(&current, hash)
+                });
+            if root == self.merkle_root {
                 Ok(self.clone())
             } else {
-                Err("Inclusion proof verification failed".to_string())
+                Err(SimulatorError::InvalidProof)
+            }
+        }
+    }
+
+    /// Inclusion proofs of all transactions of a block together with its
+    /// header, written by generate-proof-index so that the proofs can be
+    /// checked against the header without the blockchain.
+    #[derive(Serialize, Deserialize, JsonSchema, Debug, Clone)]
+    pub struct ProofIndex {
+        pub header: Header,
+        /// Proofs of the transactions in the order of the block.
+        pub proofs: Vec<InclusionProof>,
+    }
+
+    impl ProofIndex {
+        /// Fails if a proof doesn't lead to the merkle root of the header.
+        pub fn verify(&self) -> Result<(), SimulatorError> {
+            for proof in &self.proofs {
+                if proof.merkle_root != self.header.transactions_merkle_root {
+                    return Err(SimulatorError::MerkleRootMismatch);
+                }
+                proof.verify()?;
             }
+            Ok(())
+        }
+    }
+
+    /// Parent of two nodes of the Merkle tree, which is the SHA-256 of the hex
+    /// digits of the lower hash followed by those of the higher one. The null
+    /// hash padding the levels with an odd number of nodes is hashed with its
+    /// 0x prefix, as it always has been in the trees of the simulator.
+    pub fn merkle_parent(a: &Hash256, b: &Hash256) -> Hash256 {
+        let text = |hash: &Hash256| match *hash == NULL_HASH {
+            true => hash.to_string(),
+            false => hash.hex(),
+        };
+        let (lower, higher) = (a.min(b), a.max(b));
+        Hash256(Sha256::digest((text(lower) + &text(higher)).as_bytes()).into())
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use crate::error::error::SimulatorError;
+
+        use super::{
+            bitcoin_header_hash, compact_target, Address, Amount, Block, BlockTree, Checkpoint,
+            Hash256, Header, HeaderFormat, Transaction, TransactionBuilder, NULL_HASH,
+        };
+
+        /// The mainnet genesis block header, whose target 0x1d00ffff lies
+        /// between those of difficulties 7 and 8.
+        const GENESIS_HEADER: &str = "01000000\
+            0000000000000000000000000000000000000000000000000000000000000000\
+            3ba3edfd7a7b12b27ac72c3e67768f617fc81bc3888a51323a9fb8aa4b1e5e4a\
+            29ab5f49\
+            ffff001d\
+            1dac2b7c";
+        const GENESIS_HASH: &str =
+            "000000000019d6689c085ae165831e934ff763ae46a2a6c172b3f1b60a8ce26f";
+
+        fn transfer(transaction_fee: u64) -> TransactionBuilder {
+            Transaction::builder()
+                .sender(Address([1; 20]))
+                .receiver(Address([2; 20]))
+                .amount(Amount(1_000))
+                .transaction_fee(Amount(transaction_fee))
+        }
+
+        #[test]
+        fn any_fee_is_accepted_without_max_fee() {
+            let transaction = transfer(1_000_000).build().unwrap();
+            assert_eq!(transaction.transaction_fee, Amount(1_000_000));
+        }
+
+        #[test]
+        fn fee_above_max_fee_is_rejected() {
+            assert!(transfer(500).max_fee(Amount(500)).build().is_ok());
+            assert!(matches!(
+                transfer(501).max_fee(Amount(500)).build(),
+                Err(SimulatorError::InvalidTransaction(_))
+            ));
+        }
+
+        #[test]
+        fn overflowing_fee_is_rejected() {
+            assert!(matches!(
+                transfer(u64::MAX).build(),
+                Err(SimulatorError::InvalidTransaction(_))
+            ));
+        }
+
+        fn genesis_bytes() -> [u8; 80] {
+            let mut bytes = [0; 80];
+            hex::decode_to_slice(GENESIS_HEADER, &mut bytes).unwrap();
+            bytes
+        }
+
+        /// The mainnet genesis header in the fields of the simulator, without
+        /// its hash.
+        fn genesis_header() -> Header {
+            Header {
+                difficulty: 8,
+                height: 0,
+                miner: Address([0; 20]),
+                nonce: 2083236893,
+                hash: Hash256([0; 32]),
+                previous_block_header_hash: Hash256([0; 32]),
+                timestamp: 1231006505,
+                transactions_count: 1,
+                transactions_merkle_root:
+                    "4a5e1e4baab89f3a32518a88c31bc87f618f76673e2cc77ab2127b7afdeda33b"
+                        .parse()
+                        .unwrap(),
+                version: 1,
+            }
+        }
+
+        #[test]
+        fn genesis_header_is_serialized_to_the_bitcoin_layout() {
+            let header = genesis_header();
+            let bytes = header.bitcoin_bytes();
+            let genesis = genesis_bytes();
+            // Everything but the bits, which the difficulty can't express.
+            assert_eq!(bytes[..72], genesis[..72]);
+            assert_eq!(bytes[72..76], 0x1d01_0000u32.to_le_bytes());
+            assert_eq!(bytes[76..], genesis[76..]);
+            assert_eq!(header.bitcoin_hash(), bitcoin_header_hash(&bytes));
+        }
+
+        #[test]
+        fn genesis_header_has_its_bitcoin_hash() {
+            assert_eq!(bitcoin_header_hash(&genesis_bytes()).hex(), GENESIS_HASH);
+        }
+
+        #[test]
+        fn hash_is_only_valid_in_its_header_format() {
+            let formats = [
+                HeaderFormat::Simulator,
+                HeaderFormat::Bitcoin,
+                HeaderFormat::Tutorial,
+            ];
+            for format in formats {
+                let mut header = genesis_header();
+                header.hash = format.header_hash(&header);
+                for other in formats {
+                    assert_eq!(
+                        header.has_valid_hash(other),
+                        other == format,
+                        "a {:?} hash checked in the {:?} format",
+                        format,
+                        other
+                    );
+                }
+            }
+        }
+
+        #[test]
+        fn compact_target_encodes_the_powers_of_sixteen() {
+            // Values of GetCompact in Bitcoin Core for the targets 2^(256 - 4 * difficulty).
+            assert_eq!(compact_target(1), 0x2010_0000);
+            assert_eq!(compact_target(2), 0x2001_0000);
+            assert_eq!(compact_target(3), 0x1f10_0000);
+            assert_eq!(compact_target(8), 0x1d01_0000);
+            assert_eq!(compact_target(16), 0x1901_0000);
+            assert_eq!(compact_target(63), 0x0110_0000);
+            assert_eq!(compact_target(64), 0x0101_0000);
+            // Difficulty 0 is capped at the largest target and beyond 64 at
+            // that of 64.
+            assert_eq!(compact_target(0), 0x2100_ffff);
+            assert_eq!(compact_target(65), compact_target(64));
+        }
+
+        /// Block of a single header extending the parent, or a genesis block,
+        /// whose nonce tells apart the siblings of equal difficulty.
+        fn child(parent: Option<&Block>, difficulty: u32, nonce: u32) -> Block {
+            let mut header = Header {
+                difficulty,
+                height: parent.map_or(0, |parent| parent.header.height + 1),
+                nonce,
+                previous_block_header_hash: parent.map_or(NULL_HASH, |parent| parent.header.hash),
+                ..genesis_header()
+            };
+            header.hash = HeaderFormat::Simulator.header_hash(&header);
+            Block {
+                header,
+                transactions: vec![],
+            }
+        }
+
+        #[test]
+        fn late_checkpoint_moves_the_best_tip_to_its_lighter_branch() {
+            let genesis = child(None, 1, 0);
+            let a1 = child(Some(&genesis), 4, 1);
+            let a2 = child(Some(&a1), 4, 1);
+            let b1 = child(Some(&genesis), 1, 2);
+            let b2 = child(Some(&b1), 1, 2);
+            let b3 = child(Some(&b2), 1, 2);
+            let checkpoint = Checkpoint {
+                height: 3,
+                hash: b3.header.hash,
+            };
+            let mut tree = BlockTree::new(&[checkpoint], HeaderFormat::Simulator);
+            for block in [&genesis, &a1, &a2, &b1, &b2] {
+                tree.insert(block.clone()).unwrap();
+            }
+            assert_eq!(tree.best_tip().unwrap().hash, a2.header.hash);
+
+            // The heavier branch ends below the checkpoint, so it stops being
+            // final once the checkpoint block arrives.
+            tree.insert(b3.clone()).unwrap();
+            assert_eq!(tree.best_tip().unwrap().hash, b3.header.hash);
+
+            let a3 = child(Some(&a2), 4, 1);
+            assert!(matches!(
+                tree.insert(a3),
+                Err(SimulatorError::InvalidBlock { height: 3, .. })
+            ));
+            let b4 = child(Some(&b3), 1, 2);
+            tree.insert(b4.clone()).unwrap();
+            assert_eq!(tree.best_tip().unwrap().hash, b4.header.hash);
         }
     }
 }
 
+#[cfg(feature = "cli")]
 pub mod simulator {
     use clap::Subcommand;
 
-    #[derive(Debug, Subcommand, PartialEq, Eq)]
+    use crate::args::args::{
+        AggregateRunsArgs, AuditHeadersArgs, AuditMerkleRootsArgs, AuditSupplyArgs,
+        BalanceHistoryArgs, CompareChainsArgs, CreateGenesisArgs, CreateWalletArgs,
+        DifficultyReportArgs, EstimateMiningTimeArgs, ExportExplorerArgs, ExportHeadersArgs,
+        FeeHistogramArgs, GenerateInclusionProofArgs, GenerateProofIndexArgs,
+        GenerateTransactionsArgs, GetConfirmationsArgs, GetTransactionHashArgs, ImportBlocksArgs,
+        ImportRemoteArgs, IntegrityReportArgs, ListBlocksArgs, MempoolStatsArgs, MinerRevenueArgs,
+        MiningStatsArgs, OrchestrateArgs, PlotArgs, ProduceBlocksArgs, RemineFromArgs,
+        RenderPropagationArgs, RepairArgs, ReplayArgs, ReportArgs, RunNodeArgs, RunScenarioArgs,
+        SchemaArgs, ShowHeaderArgs, ShowMerkleTreeArgs, SignTransactionArgs, SimulateMinersArgs,
+        SimulateNetworkArgs, SpvVerifyArgs, SubmitBlockArgs, SupplyReportArgs, SweepArgs,
+        SyncFromArgs, TimeWarpArgs, TrackTransactionsArgs, ValidateChainArgs,
+        VerifyInclusionProofArgs, VerifyProofsArgs,
+    };
+    use crate::config::config::ContextArgs;
+
+    #[derive(Debug, Subcommand, PartialEq)]
     pub enum SimulatorMode {
-        ProduceBlocks,
-        GetTransactionHash,
-        GenerateInclusionProof,
-        VerifyInclusionProof,
-        GenerateTransactions,
+        /// Mine new blocks on top of the chain using transactions from the mempool
+        ProduceBlocks(ProduceBlocksArgs),
+        /// Show the hash of a transaction in a given block
+        GetTransactionHash(GetTransactionHashArgs),
+        /// Show the Merkle tree of a block as text, JSON or a graph,
+        /// highlighting the path to a transaction
+        ShowMerkleTree(ShowMerkleTreeArgs),
+        /// Show the header of a block together with its recomputed hash, its
+        /// proof of work, the time since the previous block and its fees
+        ShowHeader(ShowHeaderArgs),
+        /// List the blocks matching filters on their height, miner,
+        /// transactions and time with the selected columns
+        ListBlocks(ListBlocksArgs),
+        /// Generate a proof that a transaction is included in a given block
+        GenerateInclusionProof(GenerateInclusionProofArgs),
+        /// Generate the inclusion proofs of all transactions of a block in a
+        /// single file together with the header of the block
+        GenerateProofIndex(GenerateProofIndexArgs),
+        /// Verify a proof that a transaction is included in a given block
+        VerifyInclusionProof(VerifyInclusionProofArgs),
+        /// Verify an inclusion proof as a light client knowing only the headers
+        SpvVerify(SpvVerifyArgs),
+        /// Verify a directory or a JSONL file of inclusion proofs against the
+        /// headers of a chain in parallel
+        VerifyProofs(VerifyProofsArgs),
+        /// Write the block headers of the blockchain without the transactions
+        ExportHeaders(ExportHeadersArgs),
+        /// Write the blocks and transactions as the responses of the REST API
+        /// of the Esplora block explorer
+        ExportExplorer(ExportExplorerArgs),
+        /// Write the JSON Schema of the blockchain, mempool or inclusion proof
+        /// files
+        Schema(SchemaArgs),
+        /// Generate signed transactions between deterministically derived accounts
+        GenerateTransactions(GenerateTransactionsArgs),
+        /// Append the blocks from another chain file that extend the blockchain
+        ImportBlocks(ImportBlocksArgs),
+        /// Import a range of real blocks from the JSON-RPC endpoint of a
+        /// Bitcoin Core or Ethereum node
+        ImportRemote(ImportRemoteArgs),
+        /// Append a block sealed by an external miner to the blockchain
+        SubmitBlock(SubmitBlockArgs),
+        /// Select the chain with the most cumulative work out of competing chains
+        CompareChains(CompareChainsArgs),
+        /// Report how deeply a transaction is buried and how likely it is to
+        /// be reversed
+        GetConfirmations(GetConfirmationsArgs),
+        /// Report the difficulty, block interval and implied hashrate per block
+        DifficultyReport(DifficultyReportArgs),
+        /// Summarise the nonces tried, the time taken and the hashrate
+        /// achieved while mining the blocks of a mining statistics file
+        MiningStats(MiningStatsArgs),
+        /// Summarise the fees, lock times and senders of the transactions of a
+        /// mempool and the blocks it takes to clear
+        MempoolStats(MempoolStatsArgs),
+        /// Count the transactions of a mempool by their fee or fee per byte
+        /// and print them as a histogram
+        FeeHistogram(FeeHistogramArgs),
+        /// Report the blocks, subsidies and fees earned by every miner of the
+        /// chain and their share of the blocks
+        MinerRevenue(MinerRevenueArgs),
+        /// Report the balance of an address after every block of the chain
+        BalanceHistory(BalanceHistoryArgs),
+        /// Report where and after how many blocks every transaction of a
+        /// mempool was included in the chain, and which were never mined
+        TrackTransactions(TrackTransactionsArgs),
+        /// Report the coinbase subsidy and the issued supply over the chain
+        SupplyReport(SupplyReportArgs),
+        /// Replay the chain and verify that no coins were created or destroyed
+        AuditSupply(AuditSupplyArgs),
+        /// Recompute the merkle root of every block from its transactions and
+        /// report the blocks whose header doesn't match them
+        AuditMerkleRoots(AuditMerkleRootsArgs),
+        /// Recompute the hash of every header and check its proof of work and
+        /// its link to the previous header
+        AuditHeaders(AuditHeadersArgs),
+        /// Run all the integrity checks over the chain and report the blocks
+        /// failing each of them as JSON
+        IntegrityReport(IntegrityReportArgs),
+        /// Re-mine the chain from its first invalid block onward so that it
+        /// becomes valid again
+        Repair(RepairArgs),
+        /// Alter a transaction and measure the work of re-mining the chain
+        /// from its block up to the tip
+        RemineFrom(RemineFromArgs),
+        /// Estimate the expected and percentile times to find a block of a
+        /// difficulty at the set or measured hashrate
+        EstimateMiningTime(EstimateMiningTimeArgs),
+        /// Simulate which miners of a fleet win the blocks given their hashrates
+        SimulateMiners(SimulateMinersArgs),
+        /// Simulate the propagation of blocks between the nodes of the miners
+        SimulateNetwork(SimulateNetworkArgs),
+        /// Run the experiment described by a scenario file and store its results
+        RunScenario(RunScenarioArgs),
+        /// Render a propagation trace as a timeline or a graph
+        RenderPropagation(RenderPropagationArgs),
+        /// Summarise the reports of several simulation runs with the mean,
+        /// standard deviation and percentiles of their metrics
+        AggregateRuns(AggregateRunsArgs),
+        /// Run the network simulation with several seeds for every point of a
+        /// grid of difficulties, latencies and attacker hashrates
+        Sweep(SweepArgs),
+        /// Draw a chart of the block times, difficulty, mempool size or fees
+        /// of a chain as an SVG or PNG image
+        Plot(PlotArgs),
+        /// Summarise a simulated chain and its event log in a single HTML file
+        Report(ReportArgs),
+        /// Run the miners of a fleet as nodes mining real blocks in one process
+        /// for a number of virtual hours
+        Orchestrate(OrchestrateArgs),
+        /// Replay the event log of an orchestrated run and check that it
+        /// reproduces the recorded chains byte for byte
+        Replay(ReplayArgs),
+        /// Run a node exchanging blocks and transactions with peers over TCP
+        RunNode(RunNodeArgs),
+        /// Synchronise the chain from another chain file or a running node
+        SyncFrom(SyncFromArgs),
+        /// Simulate a timestamp manipulation attack on difficulty retargeting
+        TimeWarp(TimeWarpArgs),
+        /// Mine the genesis block of a new blockchain
+        CreateGenesis(CreateGenesisArgs),
+        /// Create a wallet from a new or existing mnemonic phrase
+        CreateWallet(CreateWalletArgs),
+        /// Sign a transaction with the sender's private key from a wallet
+        SignTransaction(SignTransactionArgs),
+        /// Check the validity of all blocks in the blockchain
+        ValidateChain(ValidateChainArgs),
+    }
+
+    impl SimulatorMode {
+        /// Arguments of the subcommand, which carry the flags of the
+        /// settings it reads.
+        pub fn context_args(&self) -> &dyn ContextArgs {
+            match self {
+                SimulatorMode::ProduceBlocks(args) => args,
+                SimulatorMode::GetTransactionHash(args) => args,
+                SimulatorMode::ShowMerkleTree(args) => args,
+                SimulatorMode::ShowHeader(args) => args,
+                SimulatorMode::ListBlocks(args) => args,
+                SimulatorMode::GenerateInclusionProof(args) => args,
+                SimulatorMode::GenerateProofIndex(args) => args,
+                SimulatorMode::VerifyInclusionProof(args) => args,
+                SimulatorMode::SpvVerify(args) => args,
+                SimulatorMode::VerifyProofs(args) => args,
+                SimulatorMode::ExportHeaders(args) => args,
+                SimulatorMode::ExportExplorer(args) => args,
+                SimulatorMode::Schema(args) => args,
+                SimulatorMode::GenerateTransactions(args) => args,
+                SimulatorMode::ImportBlocks(args) => args,
+                SimulatorMode::ImportRemote(args) => args,
+                SimulatorMode::SubmitBlock(args) => args,
+                SimulatorMode::CompareChains(args) => args,
+                SimulatorMode::GetConfirmations(args) => args,
+                SimulatorMode::DifficultyReport(args) => args,
+                SimulatorMode::MiningStats(args) => args,
+                SimulatorMode::MempoolStats(args) => args,
+                SimulatorMode::FeeHistogram(args) => args,
+                SimulatorMode::MinerRevenue(args) => args,
+                SimulatorMode::BalanceHistory(args) => args,
+                SimulatorMode::TrackTransactions(args) => args,
+                SimulatorMode::SupplyReport(args) => args,
+                SimulatorMode::AuditSupply(args) => args,
+                SimulatorMode::AuditMerkleRoots(args) => args,
+                SimulatorMode::AuditHeaders(args) => args,
+                SimulatorMode::IntegrityReport(args) => args,
+                SimulatorMode::Repair(args) => args,
+                SimulatorMode::RemineFrom(args) => args,
+                SimulatorMode::EstimateMiningTime(args) => args,
+                SimulatorMode::SimulateMiners(args) => args,
+                SimulatorMode::SimulateNetwork(args) => args,
+                SimulatorMode::RunScenario(args) => args,
+                SimulatorMode::RenderPropagation(args) => args,
+                SimulatorMode::AggregateRuns(args) => args,
+                SimulatorMode::Sweep(args) => args,
+                SimulatorMode::Plot(args) => args,
+                SimulatorMode::Report(args) => args,
+                SimulatorMode::Orchestrate(args) => args,
+                SimulatorMode::Replay(args) => args,
+                SimulatorMode::RunNode(args) => args,
+                SimulatorMode::SyncFrom(args) => args,
+                SimulatorMode::TimeWarp(args) => args,
+                SimulatorMode::CreateGenesis(args) => args,
+                SimulatorMode::CreateWallet(args) => args,
+                SimulatorMode::SignTransaction(args) => args,
+                SimulatorMode::ValidateChain(args) => args,
+            }
+        }
     }
 }
@@ -0,0 +1,1126 @@
+// This module simulates the propagation of blocks through a network of nodes
+pub mod network {
+    use std::{
+        cmp::Reverse,
+        collections::{BTreeMap, BTreeSet, HashMap, HashSet},
+        fmt::Write,
+    };
+
+    use clap::ValueEnum;
+    use log::{info, warn};
+    use rand::{Rng, SeedableRng};
+    use rand_chacha::ChaCha20Rng;
+    use serde::{Deserialize, Serialize};
+
+    use crate::{
+        args::args::{RenderPropagationArgs, SimulateNetworkArgs},
+        config::config::SimulatorContext,
+        data_sourcing::data_provider::{
+            load_json, load_toml, write_json, write_report, write_text, OutputOptions, ReportFormat,
+        },
+        engine::engine::EventQueue,
+        error::error::SimulatorError,
+        model::blockchain::Address,
+        simulation::fleet::{load_fleet, BlockRace, VirtualMiner},
+    };
+
+    /// Shape of the peer graph over which the nodes gossip blocks and
+    /// transactions.
+    #[derive(ValueEnum, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+    #[serde(rename_all = "kebab-case")]
+    pub enum Topology {
+        /// Every node is linked to every other node
+        FullMesh,
+        /// Every node is linked to its neighbours in the order of the fleet file
+        Ring,
+        /// Every node is linked to the same number of random peers
+        Regular,
+        /// Every node is linked to the first node of the fleet file
+        Star,
+    }
+
+    /// Links file listing the edges of the peer graph as `[[links]]` tables.
+    #[derive(Deserialize, Debug)]
+    #[serde(deny_unknown_fields)]
+    pub struct Links {
+        pub links: Vec<Link>,
+    }
+
+    /// Bidirectional link between two nodes of the fleet.
+    #[derive(Deserialize, Debug)]
+    #[serde(deny_unknown_fields)]
+    pub struct Link {
+        pub from: Address,
+        pub to: Address,
+        /// Seconds it takes a block to travel over the link, the default
+        /// latency if not given
+        pub latency: Option<f64>,
+    }
+
+    /// Block of the simulated network, identified by its index in the order
+    /// in which the blocks were mined. Block 0 is the genesis block.
+    #[derive(Debug, Clone)]
+    pub struct NetworkBlock {
+        pub parent: Option<u32>,
+        pub height: u32,
+        /// Index of the node that mined the block, none for the genesis block
+        pub miner: Option<usize>,
+        pub mined_at: f64,
+        /// Transactions of the block, numbered in the order in which they
+        /// were submitted
+        pub transactions: Vec<u32>,
+    }
+
+    /// Switch of a node to a branch that doesn't extend its previous tip.
+    #[derive(Serialize, Debug, Clone)]
+    pub struct Reorg {
+        pub node: Address,
+        /// Seconds from the start of the simulation
+        pub time: f64,
+        /// Number of blocks of the previous branch that were abandoned
+        pub reverted_blocks: u32,
+        /// Number of transactions confirmed by the previous branch that are
+        /// no longer confirmed by the new one
+        pub reverted_transactions: u64,
+    }
+
+    /// Moment a node first saw a block, either by mining it or by receiving
+    /// it from a peer.
+    #[derive(Serialize, Deserialize, Debug, Clone)]
+    pub struct PropagationEvent {
+        pub block: u32,
+        pub parent: Option<u32>,
+        pub height: u32,
+        pub miner: Address,
+        pub node: Address,
+        /// Seconds from the start of the simulation
+        pub time: f64,
+    }
+
+    /// Block or transaction in flight from one node to another.
+    enum Delivery {
+        Block {
+            node: usize,
+            block: u32,
+            from: usize,
+        },
+        /// Transaction sent to a node, by no other node if it is submitted to
+        /// the node
+        Transaction {
+            node: usize,
+            transaction: u32,
+            from: Option<usize>,
+        },
+    }
+
+    /// Blocks and transactions known to a node and the tip of its best chain.
+    struct NodeView {
+        seen: HashSet<u32>,
+        tip: u32,
+        /// Blocks received before their parents, by the parent they wait for.
+        waiting: HashMap<u32, Vec<u32>>,
+        known_transactions: HashSet<u32>,
+        /// Known transactions not confirmed by the chain of the node, which
+        /// are included oldest first
+        mempool: BTreeSet<u32>,
+        /// Transactions confirmed by the chain of the node
+        confirmed: HashSet<u32>,
+    }
+
+    /// Nodes gossiping blocks and transactions over the links of the peer
+    /// graph. Every node relays the blocks and transactions it accepts to all
+    /// of its peers, which receive them after the latency of the link, and
+    /// follows the longest chain it knows with ties resolved in favour of the
+    /// block seen first. Miners only include the transactions that reached
+    /// them.
+    pub struct Network<'a> {
+        nodes: &'a [VirtualMiner],
+        /// Peers of every node with the latency of the link to them
+        peers: Vec<Vec<(usize, f64)>>,
+        pub blocks: Vec<NetworkBlock>,
+        views: Vec<NodeView>,
+        deliveries: EventQueue<Delivery>,
+        pub trace: Vec<PropagationEvent>,
+        pub reorgs: Vec<Reorg>,
+        /// Whether the links between nodes of different partitions are cut
+        partitioned: bool,
+        /// Victim and attacker of an eclipse attack, the victim is only linked
+        /// to the attacker, which in turn is cut off from the honest nodes
+        eclipse: Option<(usize, usize)>,
+        /// Transactions arriving per second
+        transaction_rate: f64,
+        /// Maximum number of transactions per block
+        block_capacity: u64,
+        /// Number of transactions submitted so far
+        submitted: u32,
+        /// Picks the nodes the transactions are submitted to
+        transaction_rng: ChaCha20Rng,
+    }
+
+    impl<'a> Network<'a> {
+        /// Network of the nodes connected by the links, given as pairs of
+        /// node indices with the latency of the link between them.
+        pub fn from_links(nodes: &'a [VirtualMiner], links: &[(usize, usize, f64)]) -> Self {
+            let mut peers = vec![vec![]; nodes.len()];
+            for &(a, b, latency) in links {
+                peers[a].push((b, latency));
+                peers[b].push((a, latency));
+            }
+            Network::new(nodes, peers)
+        }
+
+        fn new(nodes: &'a [VirtualMiner], peers: Vec<Vec<(usize, f64)>>) -> Self {
+            let genesis = NetworkBlock {
+                parent: None,
+                height: 0,
+                miner: None,
+                mined_at: 0.0,
+                transactions: vec![],
+            };
+            Network {
+                nodes,
+                peers,
+                blocks: vec![genesis],
+                views: (0..nodes.len())
+                    .map(|_| NodeView {
+                        seen: HashSet::from([0]),
+                        tip: 0,
+                        waiting: HashMap::new(),
+                        known_transactions: HashSet::new(),
+                        mempool: BTreeSet::new(),
+                        confirmed: HashSet::new(),
+                    })
+                    .collect(),
+                deliveries: EventQueue::default(),
+                trace: vec![],
+                reorgs: vec![],
+                partitioned: false,
+                eclipse: None,
+                transaction_rate: 0.0,
+                block_capacity: 0,
+                submitted: 0,
+                transaction_rng: ChaCha20Rng::seed_from_u64(0),
+            }
+        }
+
+        /// Lets the transactions arrive at the given rate, each at a node
+        /// picked by the random number generator. The miners include the
+        /// oldest unconfirmed transactions they know up to the block capacity.
+        pub fn set_transaction_model(
+            &mut self,
+            transaction_rate: f64,
+            block_capacity: u64,
+            transaction_rng: ChaCha20Rng,
+        ) {
+            self.transaction_rate = transaction_rate;
+            self.block_capacity = block_capacity;
+            self.transaction_rng = transaction_rng;
+        }
+
+        /// Submits the transactions arriving until the given time to their
+        /// nodes, which gossip them once they are delivered.
+        pub fn submit_transactions(&mut self, time: f64) {
+            if self.transaction_rate <= 0.0 || self.nodes.is_empty() {
+                return;
+            }
+            loop {
+                let arrival = self.submitted_at(self.submitted);
+                if arrival > time {
+                    break;
+                }
+                let node = self.transaction_rng.gen_range(0..self.nodes.len());
+                let transaction = self.submitted;
+                self.deliveries.schedule(
+                    arrival,
+                    Delivery::Transaction {
+                        node,
+                        transaction,
+                        from: None,
+                    },
+                );
+                self.submitted += 1;
+            }
+        }
+
+        /// Seconds from the start of the simulation at which the transaction
+        /// was submitted, the transactions arrive at evenly spaced times.
+        pub fn submitted_at(&self, transaction: u32) -> f64 {
+            (transaction + 1) as f64 / self.transaction_rate
+        }
+
+        /// Cuts the links between the nodes of different partitions.
+        pub fn partition(&mut self) {
+            self.partitioned = true;
+        }
+
+        /// Restores the cut links, over which the nodes then exchange all the
+        /// blocks and transactions they know so that the partitions converge
+        /// to one chain.
+        pub fn heal(&mut self, time: f64) {
+            self.partitioned = false;
+            for node in 0..self.nodes.len() {
+                let mut known: Vec<u32> = self.views[node].seen.iter().copied().collect();
+                // Parents are always mined, and thus sent, before their children.
+                known.sort();
+                let mut transactions: Vec<u32> = self.views[node]
+                    .known_transactions
+                    .iter()
+                    .copied()
+                    .collect();
+                transactions.sort();
+                for i in 0..self.peers[node].len() {
+                    let (peer, latency) = self.peers[node][i];
+                    if self.nodes[peer].partition == self.nodes[node].partition
+                        || !self.is_linked(node, peer)
+                    {
+                        continue;
+                    }
+                    for &block in &known {
+                        self.send(node, peer, block, time + latency);
+                    }
+                    for &transaction in &transactions {
+                        self.send_transaction(node, peer, transaction, time + latency);
+                    }
+                }
+            }
+        }
+
+        /// Surrounds the victim with the attacker, so that the victim only
+        /// learns about the minority chain the two of them mine. The attacker
+        /// connects to the victim over a link with the given latency if the
+        /// two aren't peers already.
+        pub fn eclipse(&mut self, victim: usize, attacker: usize, latency: f64) {
+            if !self.peers[victim].iter().any(|&(peer, _)| peer == attacker) {
+                self.peers[victim].push((attacker, latency));
+                self.peers[attacker].push((victim, latency));
+            }
+            self.eclipse = Some((victim, attacker));
+        }
+
+        /// Whether blocks can currently travel over the link between the nodes.
+        fn is_linked(&self, node: usize, peer: usize) -> bool {
+            if self.partitioned && self.nodes[node].partition != self.nodes[peer].partition {
+                return false;
+            }
+            match self.eclipse {
+                Some((victim, attacker)) => {
+                    let eclipsed = |n| n == victim || n == attacker;
+                    eclipsed(node) == eclipsed(peer)
+                }
+                None => true,
+            }
+        }
+
+        fn send(&mut self, from: usize, node: usize, block: u32, time: f64) {
+            self.deliveries
+                .schedule(time, Delivery::Block { node, block, from });
+        }
+
+        fn send_transaction(&mut self, from: usize, node: usize, transaction: u32, time: f64) {
+            let delivery = Delivery::Transaction {
+                node,
+                transaction,
+                from: Some(from),
+            };
+            self.deliveries.schedule(time, delivery);
+        }
+
+        /// Delivers all blocks and transactions arriving until the given time.
+        pub fn run_until(&mut self, time: f64) {
+            while let Some((arrival, delivery)) = self.deliveries.pop_until(time) {
+                match delivery {
+                    Delivery::Block { node, block, from } => {
+                        self.receive(node, block, Some(from), arrival)
+                    }
+                    Delivery::Transaction {
+                        node,
+                        transaction,
+                        from,
+                    } => self.receive_transaction(node, transaction, from, arrival),
+                }
+            }
+        }
+
+        /// Adds the transaction to the node's mempool unless its chain
+        /// already confirms it and relays it to the other peers.
+        fn receive_transaction(
+            &mut self,
+            node: usize,
+            transaction: u32,
+            from: Option<usize>,
+            time: f64,
+        ) {
+            let view = &mut self.views[node];
+            if !view.known_transactions.insert(transaction) {
+                return;
+            }
+            if !view.confirmed.contains(&transaction) {
+                view.mempool.insert(transaction);
+            }
+            for i in 0..self.peers[node].len() {
+                let (peer, latency) = self.peers[node][i];
+                if Some(peer) != from && self.is_linked(node, peer) {
+                    self.send_transaction(node, peer, transaction, time + latency);
+                }
+            }
+        }
+
+        /// Mines a block on top of the node's tip and starts relaying it.
+        pub fn mine(&mut self, node: usize, time: f64) -> u32 {
+            let view = &self.views[node];
+            let parent = &self.blocks[view.tip as usize];
+            let transactions = view
+                .mempool
+                .iter()
+                .take(self.block_capacity as usize)
+                .copied()
+                .collect();
+            let block = NetworkBlock {
+                parent: Some(view.tip),
+                height: parent.height + 1,
+                miner: Some(node),
+                mined_at: time,
+                transactions,
+            };
+            let id = self.blocks.len() as u32;
+            self.blocks.push(block);
+            self.receive(node, id, None, time);
+            id
+        }
+
+        fn receive(&mut self, node: usize, block: u32, from: Option<usize>, time: f64) {
+            let view = &mut self.views[node];
+            if view.seen.contains(&block) {
+                return;
+            }
+            let NetworkBlock {
+                parent,
+                height,
+                miner,
+                ..
+            } = self.blocks[block as usize];
+            if let Some(parent) = parent.filter(|parent| !view.seen.contains(parent)) {
+                view.waiting.entry(parent).or_default().push(block);
+                return;
+            }
+
+            view.seen.insert(block);
+            if height > self.blocks[view.tip as usize].height {
+                if parent != Some(view.tip) {
+                    let (reverted_blocks, reverted_transactions) =
+                        reverted(&self.blocks, view.tip, block);
+                    if reverted_blocks > 0 {
+                        self.reorgs.push(Reorg {
+                            node: self.nodes[node].address,
+                            time,
+                            reverted_blocks,
+                            reverted_transactions,
+                        });
+                    }
+                }
+                switch_tip(&self.blocks, view, block);
+            }
+            let waiting = view.waiting.remove(&block).unwrap_or_default();
+            self.trace.push(PropagationEvent {
+                block,
+                parent,
+                height,
+                miner: self.nodes[miner.unwrap()].address,
+                node: self.nodes[node].address,
+                time,
+            });
+
+            for i in 0..self.peers[node].len() {
+                let (peer, latency) = self.peers[node][i];
+                if Some(peer) != from && self.is_linked(node, peer) {
+                    self.send(node, peer, block, time + latency);
+                }
+            }
+            for child in waiting {
+                self.receive(node, child, None, time);
+            }
+        }
+
+        pub fn tip(&self, node: usize) -> u32 {
+            self.views[node].tip
+        }
+
+        /// The highest tip of all nodes, the earliest mined one if several
+        /// tips have the same height.
+        pub fn best_tip(&self) -> u32 {
+            self.best_tip_of(0..self.nodes.len())
+        }
+
+        /// The highest tip of the given nodes, the earliest mined one if
+        /// several tips have the same height.
+        fn best_tip_of(&self, nodes: impl Iterator<Item = usize>) -> u32 {
+            nodes
+                .map(|node| self.tip(node))
+                .min_by_key(|&tip| (Reverse(self.blocks[tip as usize].height), tip))
+                .unwrap_or(0)
+        }
+
+        /// The blocks of the chain ending at the tip.
+        pub fn chain(&self, tip: u32) -> HashSet<u32> {
+            let mut chain = HashSet::new();
+            let mut next = Some(tip);
+            while let Some(block) = next {
+                chain.insert(block);
+                next = self.blocks[block as usize].parent;
+            }
+            chain
+        }
+    }
+
+    /// Blocks of the old branch and of the new one above the block where
+    /// the branches ending at the tips fork.
+    fn branches(blocks: &[NetworkBlock], old_tip: u32, new_tip: u32) -> (Vec<u32>, Vec<u32>) {
+        let (mut old, mut new) = (old_tip, new_tip);
+        let (mut reverted, mut appended) = (vec![], vec![]);
+        while old != new {
+            if blocks[old as usize].height >= blocks[new as usize].height {
+                reverted.push(old);
+                old = blocks[old as usize].parent.unwrap();
+            } else {
+                appended.push(new);
+                new = blocks[new as usize].parent.unwrap();
+            }
+        }
+        (reverted, appended)
+    }
+
+    /// Numbers of blocks and transactions reverted when switching from the
+    /// old tip to the new one.
+    fn reverted(blocks: &[NetworkBlock], old_tip: u32, new_tip: u32) -> (u32, u64) {
+        let (reverted, appended) = branches(blocks, old_tip, new_tip);
+        let transactions = |branch: &[u32]| -> HashSet<u32> {
+            branch
+                .iter()
+                .flat_map(|&block| blocks[block as usize].transactions.iter().copied())
+                .collect()
+        };
+        let reverted_transactions = transactions(&reverted)
+            .difference(&transactions(&appended))
+            .count();
+        (reverted.len() as u32, reverted_transactions as u64)
+    }
+
+    /// Moves the node to the new tip, returning the transactions of the
+    /// reverted blocks to its mempool and removing the ones the new branch
+    /// confirms.
+    fn switch_tip(blocks: &[NetworkBlock], view: &mut NodeView, new_tip: u32) {
+        let (reverted, appended) = branches(blocks, view.tip, new_tip);
+        for block in reverted {
+            for &transaction in &blocks[block as usize].transactions {
+                view.confirmed.remove(&transaction);
+                view.mempool.insert(transaction);
+            }
+        }
+        for block in appended {
+            for &transaction in &blocks[block as usize].transactions {
+                view.confirmed.insert(transaction);
+                view.known_transactions.insert(transaction);
+                view.mempool.remove(&transaction);
+            }
+        }
+        view.tip = new_tip;
+    }
+
+    /// View of the eclipsed node compared to the honest chain at some time.
+    #[derive(Serialize)]
+    pub struct DivergenceRow {
+        time: f64,
+        victim_height: u32,
+        honest_height: u32,
+        /// Blocks of the victim's chain that are not in the honest chain
+        diverged_blocks: u32,
+        /// Blocks of the honest chain the victim doesn't know about
+        missed_blocks: u32,
+    }
+
+    impl Network<'_> {
+        fn divergence(&self, victim: usize, attacker: usize, time: f64) -> DivergenceRow {
+            let victim_tip = self.tip(victim);
+            let honest_tip =
+                self.best_tip_of((0..self.nodes.len()).filter(|&n| n != victim && n != attacker));
+            let victim_height = self.blocks[victim_tip as usize].height;
+            let honest_height = self.blocks[honest_tip as usize].height;
+            let (diverged_blocks, _) = reverted(&self.blocks, victim_tip, honest_tip);
+            DivergenceRow {
+                time,
+                victim_height,
+                honest_height,
+                diverged_blocks,
+                missed_blocks: honest_height - (victim_height - diverged_blocks),
+            }
+        }
+    }
+
+    /// Row of the report of `SimulateNetwork`, which `AggregateRuns` reads
+    /// back to summarise several runs.
+    #[derive(Serialize, Deserialize)]
+    pub struct NetworkBlockRow {
+        pub block: u32,
+        pub height: u32,
+        pub miner: Address,
+        /// Seconds from the start of the simulation
+        pub mined_at: f64,
+        pub transactions: u64,
+        pub in_main_chain: bool,
+        /// Seconds until the last node saw the block, missing if some nodes
+        /// never saw it
+        pub propagation_time: Option<f64>,
+        /// Average number of seconds between the submission of the block's
+        /// transactions and the block being mined, missing for empty blocks
+        pub confirmation_time: Option<f64>,
+    }
+
+    /// Simulates the miners of the fleet as the nodes of a network linked by
+    /// the chosen topology or links file. The blocks are found as in
+    /// `SimulateMiners`, but each miner builds on its own view of the chain, so blocks found while another
+    /// block is still propagating end up as forks. If a partition is
+    /// scheduled, the nodes only communicate within their partitions until
+    /// it heals, after which the shorter branches are reorganised away. In an
+    /// eclipse attack, the victim only hears from the attacker and follows
+    /// the minority chain the two of them mine.
+    pub fn simulate_network(
+        args: SimulateNetworkArgs,
+        context: &SimulatorContext,
+    ) -> Result<(), SimulatorError> {
+        info!("Loading the fleet of miners from {}", args.fleet);
+        let fleet = load_fleet(&args.fleet)?;
+        let links = match &args.links {
+            Some(links_file) => {
+                info!("Loading the peer graph from {}", links_file);
+                let file: Links = load_toml(links_file)?;
+                Some(file.links)
+            }
+            None => None,
+        };
+        run_network(&args, &fleet.miners, links, context.output)
+    }
+
+    /// Runs the network simulation for the nodes, linked by the given links or
+    /// by the topology of the arguments if there are none. The fleet and links
+    /// files of the arguments are not read.
+    pub fn run_network(
+        args: &SimulateNetworkArgs,
+        nodes: &[VirtualMiner],
+        links: Option<Vec<Link>>,
+        output: OutputOptions,
+    ) -> Result<(), SimulatorError> {
+        match args.blocks_to_mine {
+            Some(blocks) => info!(
+                "Simulating {} blocks mined by {} nodes...",
+                blocks,
+                nodes.len()
+            ),
+            None => info!(
+                "Simulating {} seconds of mining by {} nodes...",
+                args.duration.unwrap_or_default(),
+                nodes.len()
+            ),
+        }
+        let (network, divergence) = simulate(args, nodes, links)?;
+
+        let reverted_blocks: u32 = network.reorgs.iter().map(|r| r.reverted_blocks).sum();
+        let reverted_transactions: u64 =
+            network.reorgs.iter().map(|r| r.reverted_transactions).sum();
+        info!(
+            "{} reorganisations reverted {} blocks and {} transactions, the deepest one reverted {} blocks",
+            network.reorgs.len(),
+            reverted_blocks,
+            reverted_transactions,
+            network.reorgs.iter().map(|r| r.reverted_blocks).max().unwrap_or(0)
+        );
+        if let Some(reorgs_output) = &args.reorgs_output {
+            write_report(reorgs_output, output, args.format, &network.reorgs)?;
+        }
+        if let Some(last) = divergence.last() {
+            info!(
+                "The eclipsed node ends {} blocks off the honest chain, missing {} honest blocks (at most {} diverged)",
+                last.diverged_blocks,
+                last.missed_blocks,
+                divergence.iter().map(|row| row.diverged_blocks).max().unwrap_or(0)
+            );
+        }
+        if let Some(divergence_output) = &args.divergence_output {
+            write_report(divergence_output, output, args.format, &divergence)?;
+        }
+
+        report_network(&network, &args.report_output, output, args.format)?;
+        if let Some(trace_output) = &args.trace_output {
+            info!("Writing the propagation trace to {}", trace_output);
+            write_json(trace_output, output, &network.trace)?;
+        }
+        Ok(())
+    }
+
+    /// Runs the network simulation for the nodes without writing any of its
+    /// outputs, returning the network once all blocks have been delivered
+    /// together with the divergence of the eclipsed node after every block.
+    pub fn simulate<'a>(
+        args: &SimulateNetworkArgs,
+        nodes: &'a [VirtualMiner],
+        links: Option<Vec<Link>>,
+    ) -> Result<(Network<'a>, Vec<DivergenceRow>), SimulatorError> {
+        let mut rng = ChaCha20Rng::seed_from_u64(args.seed);
+        let expected_attempts = 16f64.powi(args.difficulty as i32);
+        let mut race = BlockRace::new(nodes);
+        let links = peer_graph(args, nodes, links)?;
+        report_connectivity(nodes.len(), &links);
+        let mut network = Network::from_links(nodes, &links);
+        // Another stream, so that the transactions don't change the blocks.
+        let mut transaction_rng = ChaCha20Rng::seed_from_u64(args.seed);
+        transaction_rng.set_stream(2);
+        network.set_transaction_model(args.transaction_rate, args.block_capacity, transaction_rng);
+        let mut partition = args
+            .partition_at
+            .zip(args.partition_duration)
+            .map(|(start, duration)| (start, start + duration));
+        let eclipse = match (args.eclipse_victim, args.eclipse_attacker) {
+            (Some(victim), Some(attacker)) => {
+                let (victim, attacker) = (node_index(nodes, victim)?, node_index(nodes, attacker)?);
+                if victim == attacker {
+                    return Err(SimulatorError::InvalidTopology(format!(
+                        "the node {} can't eclipse itself",
+                        nodes[victim].address
+                    )));
+                }
+                network.eclipse(victim, attacker, args.latency);
+                Some((victim, attacker))
+            }
+            _ => None,
+        };
+        let mut divergence = vec![];
+
+        let mut mined = 0;
+        while args.blocks_to_mine.is_none_or(|blocks| mined < blocks) {
+            let winner = race.next_block(expected_attempts, &mut rng)?;
+            if args.duration.is_some_and(|duration| race.time > duration) {
+                break;
+            }
+            mined += 1;
+            network.submit_transactions(race.time);
+            update_partition(&mut network, &mut partition, race.time);
+            network.run_until(race.time);
+            network.mine(winner, race.time);
+            if let Some((victim, attacker)) = eclipse {
+                divergence.push(network.divergence(victim, attacker, race.time));
+            }
+        }
+        update_partition(&mut network, &mut partition, f64::INFINITY);
+        network.run_until(f64::INFINITY);
+        Ok((network, divergence))
+    }
+
+    /// Links of the peer graph given explicitly or generated for the
+    /// chosen topology, with the latencies spread around the default one.
+    fn peer_graph(
+        args: &SimulateNetworkArgs,
+        nodes: &[VirtualMiner],
+        links: Option<Vec<Link>>,
+    ) -> Result<Vec<(usize, usize, f64)>, SimulatorError> {
+        // A separate stream, so that the same seed produces the same blocks
+        // regardless of the topology.
+        let mut rng = ChaCha20Rng::seed_from_u64(args.seed);
+        rng.set_stream(1);
+        let latency = |rng: &mut ChaCha20Rng| {
+            args.latency * rng.gen_range(1.0 - args.latency_spread..=1.0 + args.latency_spread)
+        };
+
+        if let Some(links) = links {
+            return links
+                .into_iter()
+                .map(|link| {
+                    let (a, b) = (node_index(nodes, link.from)?, node_index(nodes, link.to)?);
+                    if a == b {
+                        return Err(SimulatorError::InvalidTopology(format!(
+                            "the node {} is linked to itself",
+                            link.from
+                        )));
+                    }
+                    Ok((a, b, link.latency.unwrap_or_else(|| latency(&mut rng))))
+                })
+                .collect();
+        }
+
+        let n = nodes.len();
+        let pairs: Vec<(usize, usize)> = match args.topology {
+            Topology::FullMesh => (0..n)
+                .flat_map(|a| (a + 1..n).map(move |b| (a, b)))
+                .collect(),
+            Topology::Ring if n < 3 => (1..n).map(|b| (0, b)).collect(),
+            Topology::Ring => (0..n).map(|a| (a, (a + 1) % n)).collect(),
+            Topology::Star => (1..n).map(|b| (0, b)).collect(),
+            Topology::Regular => random_regular_graph(n, args.degree, &mut rng)?,
+        };
+        Ok(pairs
+            .into_iter()
+            .map(|(a, b)| (a, b, latency(&mut rng)))
+            .collect())
+    }
+
+    /// Random graph in which every node has `degree` peers. The links are
+    /// paired up at random, starting over when the pairing gets stuck.
+    fn random_regular_graph(
+        nodes: usize,
+        degree: usize,
+        rng: &mut ChaCha20Rng,
+    ) -> Result<Vec<(usize, usize)>, SimulatorError> {
+        if degree >= nodes || (nodes * degree) % 2 == 1 {
+            return Err(SimulatorError::InvalidTopology(format!(
+                "no graph of {} nodes with {} peers each exists",
+                nodes, degree
+            )));
+        }
+        'attempt: for _ in 0..100 {
+            let mut stubs: Vec<usize> = (0..nodes)
+                .flat_map(|node| std::iter::repeat_n(node, degree))
+                .collect();
+            let mut links = HashSet::new();
+            while !stubs.is_empty() {
+                let pair = (0..100).find_map(|_| {
+                    let (i, j) = (rng.gen_range(0..stubs.len()), rng.gen_range(0..stubs.len()));
+                    let link = (stubs[i].min(stubs[j]), stubs[i].max(stubs[j]));
+                    (link.0 != link.1 && !links.contains(&link)).then_some((i, j, link))
+                });
+                let Some((i, j, link)) = pair else {
+                    continue 'attempt;
+                };
+                links.insert(link);
+                stubs.swap_remove(i.max(j));
+                stubs.swap_remove(i.min(j));
+            }
+            let mut links: Vec<(usize, usize)> = links.into_iter().collect();
+            links.sort();
+            return Ok(links);
+        }
+        Err(SimulatorError::InvalidTopology(format!(
+            "failed to link {} nodes with {} random peers each",
+            nodes, degree
+        )))
+    }
+
+    /// Warns about the nodes that can't reach the first node, as the blocks
+    /// they mine never propagate to the rest of the network.
+    fn report_connectivity(nodes: usize, links: &[(usize, usize, f64)]) {
+        let mut peers = vec![vec![]; nodes];
+        for &(a, b, _) in links {
+            peers[a].push(b);
+            peers[b].push(a);
+        }
+        let mut reached = HashSet::from([0]);
+        let mut queue = vec![0];
+        while let Some(node) = queue.pop() {
+            for &peer in &peers[node] {
+                if reached.insert(peer) {
+                    queue.push(peer);
+                }
+            }
+        }
+        if nodes > 0 && reached.len() < nodes {
+            warn!(
+                "The peer graph is not connected, {} of {} nodes can't reach the first node",
+                nodes - reached.len(),
+                nodes
+            );
+        }
+    }
+
+    fn node_index(nodes: &[VirtualMiner], address: Address) -> Result<usize, SimulatorError> {
+        nodes
+            .iter()
+            .position(|node| node.address == address)
+            .ok_or(SimulatorError::NodeNotFound(address))
+    }
+
+    /// Starts and heals the scheduled partition once the simulation reaches
+    /// the respective times, delivering the blocks sent before then first.
+    fn update_partition(network: &mut Network, partition: &mut Option<(f64, f64)>, time: f64) {
+        let Some((start, end)) = *partition else {
+            return;
+        };
+        if start <= time && !network.partitioned {
+            network.run_until(start);
+            info!("Partitioning the network at {:.2}s", start);
+            network.partition();
+        }
+        if end <= time {
+            network.run_until(end);
+            info!("Healing the partition at {:.2}s", end);
+            network.heal(end);
+            *partition = None;
+        }
+    }
+
+    /// Writes the fate of every mined block and logs the stale rate.
+    pub fn report_network(
+        network: &Network,
+        report_output: &str,
+        output: OutputOptions,
+        format: ReportFormat,
+    ) -> Result<(), SimulatorError> {
+        let rows = block_rows(network);
+        let stale = rows.iter().filter(|row| !row.in_main_chain).count();
+        info!(
+            "{} of {} blocks ended up stale ({:.2}%)",
+            stale,
+            rows.len(),
+            100.0 * stale as f64 / rows.len().max(1) as f64
+        );
+        write_report(report_output, output, format, &rows)
+    }
+
+    /// Rows of the report of the blocks mined in the network, in the order
+    /// in which they were mined.
+    pub fn block_rows(network: &Network) -> Vec<NetworkBlockRow> {
+        let main_chain = network.chain(network.best_tip());
+        let mut last_seen: HashMap<u32, (usize, f64)> = HashMap::new();
+        for event in &network.trace {
+            let (count, time) = last_seen.entry(event.block).or_default();
+            *count += 1;
+            *time = time.max(event.time);
+        }
+
+        (1..network.blocks.len() as u32)
+            .map(|block| {
+                let mined = &network.blocks[block as usize];
+                let (seen_by, last_seen) = last_seen[&block];
+                NetworkBlockRow {
+                    block,
+                    height: mined.height,
+                    miner: network.nodes[mined.miner.unwrap()].address,
+                    mined_at: mined.mined_at,
+                    transactions: mined.transactions.len() as u64,
+                    in_main_chain: main_chain.contains(&block),
+                    propagation_time: (seen_by == network.nodes.len())
+                        .then_some(last_seen - mined.mined_at),
+                    confirmation_time: (!mined.transactions.is_empty()).then(|| {
+                        mined
+                            .transactions
+                            .iter()
+                            .map(|&transaction| mined.mined_at - network.submitted_at(transaction))
+                            .sum::<f64>()
+                            / mined.transactions.len() as f64
+                    }),
+                }
+            })
+            .collect()
+    }
+
+    #[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+    pub enum PropagationStyle {
+        /// Table of the blocks in the order they were mined with the time it
+        /// took them to reach the nodes
+        Timeline,
+        /// Graphviz graph of the block tree
+        Dot,
+    }
+
+    /// Propagation of a block summarised from the trace.
+    pub struct TracedBlock {
+        pub parent: Option<u32>,
+        pub height: u32,
+        pub miner: Address,
+        pub mined_at: f64,
+        /// First time each node saw the block
+        pub seen: Vec<f64>,
+    }
+
+    /// Blocks of the trace by their number, together with the blocks of the
+    /// main chain, which ends at the highest block mined first.
+    pub fn traced_blocks(trace: &[PropagationEvent]) -> (BTreeMap<u32, TracedBlock>, HashSet<u32>) {
+        let mut blocks: BTreeMap<u32, TracedBlock> = BTreeMap::new();
+        for event in trace {
+            let block = blocks.entry(event.block).or_insert(TracedBlock {
+                parent: event.parent,
+                height: event.height,
+                miner: event.miner,
+                mined_at: event.time,
+                seen: vec![],
+            });
+            if event.node == event.miner {
+                block.mined_at = event.time;
+            }
+            block.seen.push(event.time);
+        }
+
+        let best_tip = blocks
+            .iter()
+            .min_by_key(|(&id, block)| (Reverse(block.height), id))
+            .map(|(&id, _)| id);
+        let mut main_chain = HashSet::new();
+        let mut next = best_tip;
+        while let Some(id) = next {
+            main_chain.insert(id);
+            next = blocks.get(&id).and_then(|block| block.parent);
+        }
+        (blocks, main_chain)
+    }
+
+    /// Renders a propagation trace written by `SimulateNetwork` as a
+    /// timeline or as a graph of the block tree, marking the stale blocks.
+    pub fn render_propagation(
+        args: RenderPropagationArgs,
+        context: &SimulatorContext,
+    ) -> Result<(), SimulatorError> {
+        info!("Loading the propagation trace from {}", args.trace);
+        let trace: Vec<PropagationEvent> = load_json(&args.trace)?;
+
+        let nodes: HashSet<Address> = trace.iter().map(|event| event.node).collect();
+        let (blocks, main_chain) = traced_blocks(&trace);
+
+        let mut rendered = String::new();
+        match args.style {
+            PropagationStyle::Timeline => {
+                writeln!(
+                    rendered,
+                    "{:>6} {:>6}  {:<13} {:>10} {:>10} {:>7}  chain",
+                    "block", "height", "miner", "mined at", "reached", "nodes"
+                )
+                .unwrap();
+                let mut timeline: Vec<(&u32, &TracedBlock)> = blocks.iter().collect();
+                timeline.sort_by(|(_, a), (_, b)| a.mined_at.total_cmp(&b.mined_at));
+                for (id, block) in timeline {
+                    let last_seen = block.seen.iter().copied().fold(block.mined_at, f64::max);
+                    writeln!(
+                        rendered,
+                        "{:>6} {:>6}  {:<13} {:>9.2}s {:>+9.2}s {:>7}  {}",
+                        id,
+                        block.height,
+                        short_address(&block.miner),
+                        block.mined_at,
+                        last_seen - block.mined_at,
+                        format!("{}/{}", block.seen.len(), nodes.len()),
+                        if main_chain.contains(id) {
+                            "main"
+                        } else {
+                            "stale"
+                        }
+                    )
+                    .unwrap();
+                }
+            }
+            PropagationStyle::Dot => {
+                writeln!(rendered, "digraph propagation {{").unwrap();
+                writeln!(rendered, "  rankdir=RL;").unwrap();
+                writeln!(rendered, "  b0 [label=\"genesis\"];").unwrap();
+                for (id, block) in &blocks {
+                    let last_seen = block.seen.iter().copied().fold(block.mined_at, f64::max);
+                    writeln!(
+                        rendered,
+                        "  b{} [label=\"#{} height {}\\n{}\\nmined at {:.2}s, spread in {:.2}s\"{}];",
+                        id,
+                        id,
+                        block.height,
+                        short_address(&block.miner),
+                        block.mined_at,
+                        last_seen - block.mined_at,
+                        if main_chain.contains(id) {
+                            ""
+                        } else {
+                            ", style=dashed, color=red"
+                        }
+                    )
+                    .unwrap();
+                    if let Some(parent) = block.parent {
+                        writeln!(rendered, "  b{} -> b{};", id, parent).unwrap();
+                    }
+                }
+                writeln!(rendered, "}}").unwrap();
+            }
+        }
+
+        write_text(&args.output, context.output, &rendered)
+    }
+
+    /// Address shortened to its first and last bytes for display.
+    pub fn short_address(address: &Address) -> String {
+        let address = address.to_string();
+        format!("{}…{}", &address[..6], &address[address.len() - 4..])
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use rand::SeedableRng;
+        use rand_chacha::ChaCha20Rng;
+
+        use crate::{
+            error::error::SimulatorError, model::blockchain::Address,
+            simulation::fleet::VirtualMiner,
+        };
+
+        use super::{random_regular_graph, Network};
+
+        fn miners(count: u8) -> Vec<VirtualMiner> {
+            (0..count)
+                .map(|address| VirtualMiner {
+                    address: Address([address; 20]),
+                    hashrate: 1.0,
+                    joins_at: 0.0,
+                    leaves_at: None,
+                    timestamp_skew: 0.0,
+                    byzantine: false,
+                    partition: 0,
+                })
+                .collect()
+        }
+
+        #[test]
+        fn random_regular_graph_gives_every_node_the_degree() {
+            let mut rng = ChaCha20Rng::seed_from_u64(0);
+            let links = random_regular_graph(10, 3, &mut rng).unwrap();
+            assert_eq!(links.len(), 15);
+            let mut degrees = [0; 10];
+            for &(a, b) in &links {
+                assert!(a < b);
+                degrees[a] += 1;
+                degrees[b] += 1;
+            }
+            assert_eq!(degrees, [3; 10]);
+        }
+
+        #[test]
+        fn random_regular_graph_with_odd_stubs_is_rejected() {
+            let mut rng = ChaCha20Rng::seed_from_u64(0);
+            assert!(matches!(
+                random_regular_graph(5, 3, &mut rng),
+                Err(SimulatorError::InvalidTopology(_))
+            ));
+        }
+
+        #[test]
+        fn blocks_propagate_after_the_latency_of_each_link() {
+            let nodes = miners(3);
+            let mut network = Network::from_links(&nodes, &[(0, 1, 1.0), (1, 2, 2.0)]);
+            let block = network.mine(0, 0.0);
+            network.run_until(2.5);
+            assert_eq!(network.tip(1), block);
+            assert_eq!(network.tip(2), 0);
+            network.run_until(3.0);
+            assert_eq!(network.tip(2), block);
+            let times: Vec<f64> = network.trace.iter().map(|event| event.time).collect();
+            assert_eq!(times, [0.0, 1.0, 3.0]);
+        }
+
+        #[test]
+        fn longer_branch_reverts_the_block_seen_first() {
+            let nodes = miners(2);
+            let mut network = Network::from_links(&nodes, &[(0, 1, 1.0)]);
+            let first = network.mine(0, 0.0);
+            let competing = network.mine(1, 0.5);
+            network.run_until(5.0);
+            assert_eq!((network.tip(0), network.tip(1)), (first, competing));
+            assert!(network.reorgs.is_empty());
+
+            let longer = network.mine(0, 5.0);
+            network.run_until(10.0);
+            assert_eq!(network.tip(1), longer);
+            assert_eq!(network.best_tip(), longer);
+            assert_eq!(network.reorgs.len(), 1);
+            assert_eq!(network.reorgs[0].node, nodes[1].address);
+            assert_eq!(network.reorgs[0].reverted_blocks, 1);
+        }
+    }
+}
@@ -1,246 +1,1629 @@
 pub mod miner {
-    use std::fs;
+    use std::{cell::Cell, num::NonZeroUsize, thread, time::Instant};
 
-    use crypto_bigint::U256;
-    use log::{debug, info};
-    use sha256::digest;
+    use clap::ValueEnum;
+    use log::{debug, info, warn};
+    use serde::Serialize;
+    use sha2::{Digest, Sha256};
 
     use crate::{
-        args::args::ProduceBlocksArgs,
-        data_sourcing::data_provider::{load_blockchain, load_transactions},
+        args::args::{
+            CreateGenesisArgs, EstimateMiningTimeArgs, ProduceBlocksArgs, RemineFromArgs,
+            RepairArgs,
+        },
+        config::config::SimulatorContext,
+        data_sourcing::data_provider::{
+            load_allocations, load_blockchain, load_difficulty_schedule, load_snapshot,
+            load_transactions, write_blockchain, write_json, write_transactions,
+        },
+        error::error::SimulatorError,
+        hasher::hasher::{create_pow_hasher, HasherSpec, ThrottledHasher},
         hashing::hashing::Hashable,
-        model::blockchain::{Block, Header, MerkleTreeNode, Transaction},
+        model::blockchain::{
+            format_timestamp, internal_byte_order, merkle_parent, Address, Amount, Block,
+            Blockchain, Denomination, DifficultySchedule, Hash256, Header, HeaderFormat,
+            MerkleTree, MerkleTreeNode, MiningStats, Transaction, NULL_ADDRESS, NULL_HASH,
+        },
+        node::validator::check_block,
+        snapshot::snapshot::{mempool_digest, Snapshot},
+        state::state::{apply_block, compute_balances, Balances},
+        strategy::strategy::{NonceFound, NonceSearch, CHECK_INTERRUPTION_EVERY_N_NONCES},
+        upgrades::upgrades::{Rule, RuleTracker},
+        wallet::wallet::verify_signature,
     };
 
-    pub fn produce_blocks(args: ProduceBlocksArgs) {
-        info!("Loading the blockchain from {}", args.blockchain_state);
-        let mut blockchain = load_blockchain(&args.blockchain_state).unwrap();
+    /// Criterion by which the miner prioritizes the transactions of the
+    /// mempool when filling a block.
+    #[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+    pub enum TransactionSelection {
+        /// Highest absolute fee first
+        Fee,
+        /// Highest fee per byte of the serialized transaction first
+        FeePerByte,
+    }
+
+    /// Mines the requested number of blocks. When resuming from a snapshot,
+    /// the blocks are mined on top of the snapshot's tip and only the newly
+    /// mined blocks are written to the output, without loading the chain.
+    pub fn produce_blocks(
+        args: ProduceBlocksArgs,
+        context: &SimulatorContext,
+    ) -> Result<(), SimulatorError> {
+        let (mut blockchain, mut balances, snapshot_digest) = match &args.from_snapshot {
+            Some(snapshot_file) => {
+                info!("Resuming from the snapshot {}", snapshot_file);
+                let snapshot = load_snapshot(snapshot_file)?;
+                let digest = snapshot.mempool_digest.clone();
+                (
+                    Blockchain::continuing_from(snapshot.tip),
+                    Some(snapshot.balances),
+                    Some(digest),
+                )
+            }
+            None => {
+                let blockchain_state = args.blockchain_state();
+                info!("Loading the blockchain from {}", blockchain_state);
+                let blockchain = load_blockchain(blockchain_state, context)?;
+                let balances = args
+                    .snapshot_output
+                    .as_ref()
+                    .map(|_| compute_balances(&blockchain, &context.subsidy_schedule))
+                    .transpose()?;
+                (blockchain, balances, None)
+            }
+        };
 
         info!("Loading the available mempool from {}", args.mempool);
-        let transactions = load_transactions(&args.mempool).unwrap();
+        let transactions = load_transactions(&args.mempool)?;
+        if snapshot_digest.is_some_and(|digest| digest != mempool_digest(&transactions)) {
+            warn!("The mempool is different from the one remaining at the snapshot");
+        }
 
-        let mut most_recent_block = blockchain
-            .iter()
-            .max_by(|b1: &&Block, b2: &&Block| b1.header.timestamp.cmp(&b2.header.timestamp))
-            .unwrap();
+        let mut executable_transactions = find_executable_transactions(
+            transactions,
+            blockchain.tip_header()?.timestamp + args.block_interval,
+            args.selection,
+            args.verify_signatures,
+        );
+
+        let difficulty_schedule = match &args.difficulty_schedule {
+            Some(file_name) => {
+                info!("Loading the difficulty schedule from {}", file_name);
+                load_difficulty_schedule(file_name)?
+            }
+            None => DifficultySchedule::default(),
+        };
+
+        // The deployments are only tracked from the first loaded block, which
+        // is the tip of the snapshot when resuming from one.
+        let mut tracker = RuleTracker::new(&context.deployments);
+        for block in &blockchain {
+            tracker.observe(&block.header);
+        }
+
+        let mut mining_stats = vec![];
+        for mined in 1..=args.blocks_to_mine {
+            if executable_transactions.is_empty() && !args.allow_empty_blocks {
+                warn!(
+                    "The mempool ran out of executable transactions after {} blocks, \
+                     use --allow-empty-blocks to keep mining",
+                    mined - 1
+                );
+                break;
+            }
+            let block_size = args.block_capacity.min(executable_transactions.len());
+            let mut new_block_transactions: Vec<Transaction> =
+                executable_transactions.drain(0..block_size).collect();
+            let previous_header = blockchain.tip_header()?;
+            if tracker.is_active(Rule::SignedTransactions, previous_header.height + 1) {
+                new_block_transactions.retain(verify_signature);
+            }
+            let difficulty = difficulty_schedule
+                .difficulty_at(previous_header.height + 1)
+                .or(args.difficulty);
+            let (block, stats) = mine_new_block(
+                new_block_transactions,
+                previous_header,
+                difficulty,
+                args.block_interval,
+                tracker.signaling_version(&args.signal),
+                args.no_pow,
+                context,
+            )?;
+            tracker.check_rules(&block, Some(previous_header))?;
+            tracker.observe(&block.header);
+            report_block_fees(&block, context.denomination)?;
+            if let Some(balances) = &mut balances {
+                apply_block(balances, &block, &context.subsidy_schedule)?;
+            }
+            if args.no_pow {
+                blockchain.append_mock_pow(block, &context.checkpoints)?;
+            } else {
+                blockchain.append(block, context.header_format, &context.checkpoints)?;
+            }
+            mining_stats.extend(stats);
+
+            if mined % args.snapshot_interval == 0 && mined != args.blocks_to_mine {
+                write_checkpoint(
+                    &args,
+                    context,
+                    &blockchain,
+                    &executable_transactions,
+                    &balances,
+                )?;
+            }
+        }
+
+        write_checkpoint(
+            &args,
+            context,
+            &blockchain,
+            &executable_transactions,
+            &balances,
+        )?;
+        match &args.mining_stats_output {
+            Some(output) => {
+                info!(
+                    "Writing the mining statistics of {} blocks to {}",
+                    mining_stats.len(),
+                    output
+                );
+                write_json(output, context.output, &mining_stats)
+            }
+            None => Ok(()),
+        }
+    }
 
-        let mut executable_transactions =
-            find_executable_transactions(transactions, most_recent_block.header.timestamp + 10);
+    fn report_block_fees(block: &Block, denomination: Denomination) -> Result<(), SimulatorError> {
+        let total_fees = block.total_fees()?;
+        let total_size: usize = block.transactions.iter().map(|t| t.serialized_size()).sum();
+        info!(
+            "Block {} ({}) collects {} in fees from {} bytes of transactions ({:.4} base units per byte)",
+            block.header.height,
+            format_timestamp(block.header.timestamp),
+            total_fees.format(denomination),
+            total_size,
+            total_fees.0 as f64 / total_size.max(1) as f64
+        );
+        Ok(())
+    }
 
-        for _ in 0..args.blocks_to_mine {
-            let new_block_transactions = executable_transactions.drain(0..100).collect();
-            let block = mine_new_block(new_block_transactions, most_recent_block);
-            blockchain.push(block);
-            most_recent_block = blockchain.last().unwrap();
+    /// Writes the blockchain and the mempool, followed by the snapshot if one
+    /// was requested, so that the snapshot never refers to unwritten blocks.
+    fn write_checkpoint(
+        args: &ProduceBlocksArgs,
+        context: &SimulatorContext,
+        blockchain: &Blockchain,
+        mempool: &[Transaction],
+        balances: &Option<Balances>,
+    ) -> Result<(), SimulatorError> {
+        let output = context.output;
+        write_blockchain(
+            args.blockchain_state_output(),
+            output,
+            context.data_format,
+            blockchain,
+        )?;
+        write_transactions(args.mempool_output(), output, context.data_format, mempool)?;
+        if let (Some(snapshot_output), Some(balances)) = (&args.snapshot_output, balances) {
+            let tip = blockchain.tip_header()?;
+            info!(
+                "Writing a snapshot at height {} to {}",
+                tip.height, snapshot_output
+            );
+            let snapshot = Snapshot::new(tip, balances, mempool);
+            write_json(snapshot_output, output, &snapshot)?;
         }
+        Ok(())
+    }
+
+    /// Highest difficulty there can be, as the hashes have 64 hex digits.
+    pub const MAX_POW_DIFFICULTY: u32 = 64;
+
+    pub fn create_genesis(
+        args: CreateGenesisArgs,
+        context: &SimulatorContext,
+    ) -> Result<(), SimulatorError> {
+        let transactions: Vec<Transaction> = match &args.allocations {
+            Some(allocations_file) => {
+                info!("Loading the genesis allocations from {}", allocations_file);
+                load_allocations(allocations_file)?
+                    .into_iter()
+                    .map(|(receiver, amount)| Transaction {
+                        amount,
+                        lock_time: 0,
+                        receiver,
+                        sender: NULL_ADDRESS,
+                        signature: "".to_string(),
+                        transaction_fee: Amount::ZERO,
+                    })
+                    .collect()
+            }
+            None => vec![],
+        };
+
+        info!(
+            "Producing the genesis block with {} allocations...",
+            transactions.len()
+        );
+
+        let template = BlockBuilder::genesis()
+            .difficulty(args.difficulty)
+            .miner(args.miner)
+            .timestamp(args.timestamp)
+            .transactions(transactions)
+            .format(context.header_format)
+            .build();
+
+        info!("Mining the genesis block...");
+        let genesis = template.mine(args.difficulty as usize, &context.nonce_search())?;
+
+        info!(
+            "Successfully mined the genesis block with header:\n{}",
+            serde_json::to_string_pretty(&genesis.header).unwrap()
+        );
 
-        fs::write(
+        let mut blockchain = Blockchain::default();
+        blockchain.append(genesis, context.header_format, &context.checkpoints)?;
+        write_blockchain(
             &args.blockchain_state_output,
-            serde_json::to_string_pretty(&blockchain).unwrap(),
+            context.output,
+            context.data_format,
+            &blockchain,
         )
-        .unwrap();
+    }
+
+    /// Why the block can't stay in the chain after the given header, or none
+    /// if it is valid in the format.
+    fn invalidity(
+        block: &Block,
+        previous: Option<&Header>,
+        format: HeaderFormat,
+    ) -> Option<String> {
+        let header = &block.header;
+        let linked = match previous {
+            Some(previous) => {
+                header.height == previous.height + 1
+                    && header.previous_block_header_hash == previous.hash
+            }
+            None => header.height != 0 || header.previous_block_header_hash == NULL_HASH,
+        };
+        if !linked {
+            return Some("it doesn't link to the previous block".to_string());
+        }
+        if !header.has_valid_hash(format) {
+            return Some("the hash does not match the header".to_string());
+        }
+        check_block(block, format)
+            .err()
+            .map(|error| error.to_string())
+    }
+
+    /// Mines the block again on top of the previous header, recomputing its
+    /// merkle root and transactions count and keeping the difficulty, miner,
+    /// timestamp and version of its header, together with how the search for
+    /// its nonce went.
+    fn remine_block(
+        block: Block,
+        previous: Option<&Header>,
+        context: &SimulatorContext,
+    ) -> Result<(Block, MiningStats), SimulatorError> {
+        let header = &block.header;
+        let builder = match previous {
+            Some(previous) => BlockBuilder::on_top_of(previous),
+            None if header.height == 0 => BlockBuilder::genesis(),
+            None => {
+                return Err(SimulatorError::InvalidBlock {
+                    height: header.height,
+                    reason: "the first block of a chain segment can't be re-mined \
+                             without its previous block"
+                        .to_string(),
+                })
+            }
+        };
+        let template = builder
+            .difficulty(header.difficulty)
+            .miner(header.miner)
+            .timestamp(header.timestamp)
+            .version(header.version)
+            .transactions(block.transactions)
+            .format(context.header_format)
+            .build();
+        info!("Re-mining block {}...", template.header.height);
+        let pow_difficulty = template.header.difficulty as usize;
+        template.mine_with_stats(pow_difficulty, &context.nonce_search())
+    }
+
+    /// Finds the first invalid block of the chain and re-mines it and every
+    /// block after it, since each of them commits to the hash of its
+    /// predecessor. The merkle roots and transactions counts are recomputed
+    /// from the transactions, while the difficulty, miner, timestamp and
+    /// version of the original headers are kept. The number of hashes it
+    /// took shows how much work rewriting history costs.
+    pub fn repair_chain(
+        args: RepairArgs,
+        context: &SimulatorContext,
+    ) -> Result<(), SimulatorError> {
+        info!("Loading the blockchain from {}", args.blockchain_state);
+        let blocks: Vec<Block> = load_blockchain(&args.blockchain_state, context)?
+            .into_iter()
+            .collect();
+
+        let mut repaired: Vec<Block> = Vec::with_capacity(blocks.len());
+        let (mut remined, mut hashes) = (0, 0u64);
+        for block in blocks {
+            let previous = repaired.last().map(|block| &block.header);
+            let header = &block.header;
+            // Once a block is re-mined, the blocks after it no longer link to
+            // it and are re-mined as well.
+            if remined == 0 {
+                match invalidity(&block, previous, context.header_format) {
+                    Some(reason) => warn!("Block {} is invalid: {}", header.height, reason),
+                    None => {
+                        repaired.push(block);
+                        continue;
+                    }
+                }
+            }
+            let (block, stats) = remine_block(block, previous, context)?;
+            hashes += stats.nonces_tried;
+            remined += 1;
+            repaired.push(block);
+        }
 
-        fs::write(
-            &args.mempool_output,
-            serde_json::to_string_pretty(&executable_transactions).unwrap(),
+        let blockchain = Blockchain::from(repaired);
+        if remined == 0 {
+            info!("All {} blocks are valid", blockchain.len());
+        } else {
+            info!(
+                "Re-mined {} of {} blocks, which took {} hashes",
+                remined,
+                blockchain.len(),
+                hashes
+            );
+        }
+        write_blockchain(
+            &args.blockchain_state_output,
+            context.output,
+            context.data_format,
+            &blockchain,
         )
-        .unwrap();
     }
 
-    fn find_executable_transactions(
+    /// Alters a transaction of the block at the given height, as an attacker
+    /// rewriting history would, and re-mines that block and every block up
+    /// to the tip. The hashes it took are compared with the work the
+    /// difficulties of the blocks require on average, which is what an
+    /// attacker needs to outpace the rest of the network.
+    pub fn remine_from(
+        args: RemineFromArgs,
+        context: &SimulatorContext,
+    ) -> Result<(), SimulatorError> {
+        info!("Loading the blockchain from {}", args.blockchain_state);
+        let mut blocks: Vec<Block> = load_blockchain(&args.blockchain_state, context)?
+            .into_iter()
+            .collect();
+        let start = blocks
+            .iter()
+            .position(|block| block.header.height == args.height)
+            .ok_or(SimulatorError::HeightNotFound(args.height))?;
+
+        let transactions = &mut blocks[start].transactions;
+        let transaction = args
+            .transaction_number_in_block
+            .checked_sub(1)
+            .and_then(|index| transactions.get_mut(index))
+            .ok_or(SimulatorError::TransactionNotFound {
+                block_number: args.height as usize,
+                transaction_number: args.transaction_number_in_block,
+            })?;
+        let original_amount = transaction.amount;
+        transaction.amount = match args.amount {
+            Some(amount) => amount,
+            // The largest amount is lowered instead of wrapping around.
+            None => original_amount
+                .0
+                .checked_add(1)
+                .or_else(|| original_amount.0.checked_sub(1))
+                .map(Amount)
+                .ok_or_else(|| {
+                    SimulatorError::AmountOverflow(format!(
+                        "altered amount of transaction {} in block {}",
+                        args.transaction_number_in_block, args.height
+                    ))
+                })?,
+        };
+        info!(
+            "Altered the amount of transaction {} in block {} from {} to {}",
+            args.transaction_number_in_block,
+            args.height,
+            original_amount.format(context.denomination),
+            transaction.amount.format(context.denomination)
+        );
+
+        let rebuilt = blocks.split_off(start);
+        let expected_hashes: f64 = rebuilt
+            .iter()
+            .map(|block| 16f64.powi(block.header.difficulty as i32))
+            .sum();
+        info!(
+            "Rebuilding the {} blocks from block {} up to the tip...",
+            rebuilt.len(),
+            args.height
+        );
+        let started = Instant::now();
+        let mut hashes = 0u64;
+        for block in rebuilt {
+            let previous = blocks.last().map(|block| &block.header);
+            let (block, stats) = remine_block(block, previous, context)?;
+            hashes += stats.nonces_tried;
+            blocks.push(block);
+        }
+        let elapsed = started.elapsed().as_secs_f64();
+
+        info!(
+            "Rebuilding the chain from block {} took {} hashes in {:.3} s ({:.0} hashes per \
+             second), the difficulties of the blocks require {:.0} hashes on average",
+            args.height,
+            hashes,
+            elapsed,
+            hashes as f64 / elapsed.max(f64::EPSILON),
+            expected_hashes
+        );
+        match &args.blockchain_state_output {
+            Some(output) => write_blockchain(
+                output,
+                context.output,
+                context.data_format,
+                &Blockchain::from(blocks),
+            ),
+            None => Ok(()),
+        }
+    }
+
+    #[derive(Serialize)]
+    struct MiningTimePercentile {
+        percentile: f64,
+        hashes: f64,
+        seconds: f64,
+    }
+
+    #[derive(Serialize)]
+    struct MiningTimeEstimate {
+        difficulty: u32,
+        /// Hashes of headers per second, in the format and with the hasher
+        /// and the strategy the blocks would be mined with.
+        hashrate: f64,
+        /// Whether the hashrate was measured rather than set with --hashrate.
+        measured: bool,
+        expected_hashes: f64,
+        expected_seconds: f64,
+        percentiles: Vec<MiningTimePercentile>,
+    }
+
+    /// Measures the hashrate of the miner by searching for a nonce that is
+    /// never found for the given number of seconds, with the hasher, the
+    /// header format and the strategy of the context.
+    fn measure_hashrate(seconds: f64, context: &SimulatorContext) -> Result<f64, SimulatorError> {
+        let template = BlockBuilder::genesis()
+            .difficulty(MAX_POW_DIFFICULTY)
+            .format(context.header_format)
+            .build();
+        let started = Instant::now();
+        // The interruption is checked after every batch of nonces.
+        let batches = Cell::new(0u64);
+        template.mine_until(MAX_POW_DIFFICULTY as usize, &context.nonce_search(), || {
+            batches.set(batches.get() + 1);
+            started.elapsed().as_secs_f64() >= seconds
+        })?;
+        let hashes = batches.get() * CHECK_INTERRUPTION_EVERY_N_NONCES;
+        Ok(hashes as f64 / started.elapsed().as_secs_f64())
+    }
+
+    /// Reports the expected and percentile times to find a block of the
+    /// difficulty at the hashrate set with --hashrate, or measured if none
+    /// is set. Every hash is valid with probability 16^-difficulty, so the
+    /// number of hashes needed follows a geometric distribution whose
+    /// percentiles are much longer than the mean for the unlucky miners.
+    pub fn estimate_mining_time(
+        args: EstimateMiningTimeArgs,
+        context: &SimulatorContext,
+    ) -> Result<(), SimulatorError> {
+        let (hashrate, measured) = match context.hashrate {
+            Some(hashrate) => (hashrate, false),
+            None => {
+                info!("Measuring the hashrate for {} s...", args.measure_seconds);
+                (measure_hashrate(args.measure_seconds, context)?, true)
+            }
+        };
+        info!(
+            "Estimating the mining time at {:.0} hashes per second",
+            hashrate
+        );
+
+        let probability = 16f64.powi(-(args.difficulty as i32));
+        let expected_hashes = 1.0 / probability;
+        let percentiles = args
+            .percentiles
+            .iter()
+            .map(|&percentile| {
+                // Smallest number of hashes finding a block with the
+                // probability of the percentile, ln_1p keeps the precision
+                // of the tiny probabilities of high difficulties.
+                let hashes = match args.difficulty {
+                    0 => 1.0,
+                    _ => ((-percentile / 100.0).ln_1p() / (-probability).ln_1p()).ceil(),
+                };
+                MiningTimePercentile {
+                    percentile,
+                    hashes,
+                    seconds: hashes / hashrate,
+                }
+            })
+            .collect();
+        let estimate = MiningTimeEstimate {
+            difficulty: args.difficulty,
+            hashrate,
+            measured,
+            expected_hashes,
+            expected_seconds: expected_hashes / hashrate,
+            percentiles,
+        };
+        info!(
+            "A block of difficulty {} takes {:.3} s on average",
+            args.difficulty, estimate.expected_seconds
+        );
+        write_json(&args.report_output, context.output, &estimate)
+    }
+
+    /// Transactions with invalid signatures are dropped from the mempool if
+    /// the signatures are to be verified.
+    pub fn find_executable_transactions(
         mut transactions: Vec<Transaction>,
-        new_block_timestamp: u32,
+        new_block_timestamp: u64,
+        selection: TransactionSelection,
+        verify_signatures: bool,
     ) -> Vec<Transaction> {
+        // Transactions whose lock time has passed are dropped before they are
+        // sorted and their signatures verified.
+        transactions.retain(|t| u64::from(t.lock_time) > new_block_timestamp);
+
         // Need to sort the transactions in the decreasing order of their fees.
-        transactions.sort_by(|t1: &Transaction, t2: &Transaction| {
-            t2.transaction_fee.cmp(&t1.transaction_fee)
-        });
+        match selection {
+            TransactionSelection::Fee => {
+                transactions.sort_by(|t1: &Transaction, t2: &Transaction| {
+                    t2.transaction_fee.cmp(&t1.transaction_fee)
+                })
+            }
+            TransactionSelection::FeePerByte => {
+                // The sizes are computed once, and the densities compared by
+                // cross-multiplying in u128 to avoid rounding and overflows.
+                let mut sized: Vec<(usize, Transaction)> = transactions
+                    .into_iter()
+                    .map(|t| (t.serialized_size(), t))
+                    .collect();
+                sized.sort_by(|(size1, t1), (size2, t2)| {
+                    let weighted_fee =
+                        |t: &Transaction, size: usize| t.transaction_fee.0 as u128 * size as u128;
+                    weighted_fee(t2, *size1).cmp(&weighted_fee(t1, *size2))
+                });
+                transactions = sized.into_iter().map(|(_, t)| t).collect();
+            }
+        }
+
+        if verify_signatures {
+            info!(
+                "Verifying the signatures of {} transactions...",
+                transactions.len()
+            );
+            let total_count = transactions.len();
+            transactions.retain(verify_signature);
+            info!(
+                "Rejected {} transactions with invalid signatures",
+                total_count - transactions.len()
+            );
+        }
 
         transactions
-            .into_iter()
-            .filter(|t| t.lock_time > new_block_timestamp)
-            .collect()
     }
 
-    pub fn compute_transaction_hashes(transactions: Vec<Transaction>) -> Vec<String> {
+    pub fn compute_transaction_hashes(transactions: &[Transaction]) -> Vec<Hash256> {
         transactions.iter().map(|t| t.hash()).collect()
     }
 
-    /// Here the intermediate hashes don't have 0x00 in front of them
-    pub fn construct_merkle_tree(transaction_hashes: Vec<String>) -> MerkleTreeNode {
+    /// Merkle root of the transactions as stored in the block header, which is
+    /// the null hash for blocks without transactions.
+    pub fn compute_merkle_root(transactions: &[Transaction]) -> Hash256 {
+        if transactions.is_empty() {
+            return NULL_HASH;
+        }
+        info!("Computing transaction hashes...");
+        let transaction_hashes = compute_transaction_hashes(transactions);
+        merkle_root_of_hashes(transaction_hashes)
+    }
+
+    /// Like `compute_merkle_root()`, for transactions that are already
+    /// hashed.
+    pub fn merkle_root_of_hashes(transaction_hashes: Vec<Hash256>) -> Hash256 {
+        if transaction_hashes.is_empty() {
+            return NULL_HASH;
+        }
+        info!("Assembling the Merkle tree...");
+        let merkle_tree =
+            construct_merkle_tree(transaction_hashes).expect("there is at least one leaf");
+        debug!("Assembled Merkle tree: \n{}", merkle_tree);
+        merkle_tree.root().hash()
+    }
+
+    /// Merkle root as specified by the tutorial the sample chains in data/
+    /// were produced for, where every hash of the tree is hashed with its 0x
+    /// prefix.
+    pub fn compute_specified_merkle_root(transactions: &[Transaction]) -> Hash256 {
+        specified_merkle_root_of_hashes(&compute_transaction_hashes(transactions))
+    }
+
+    /// Like `compute_specified_merkle_root()`, for transactions that are
+    /// already hashed.
+    pub fn specified_merkle_root_of_hashes(transaction_hashes: &[Hash256]) -> Hash256 {
+        let mut hashes = transaction_hashes.to_vec();
+        while hashes.len() > 1 {
+            if !hashes.len().is_multiple_of(2) {
+                hashes.push(NULL_HASH);
+            }
+            hashes = hashes
+                .chunks(2)
+                .map(|pair| {
+                    let (lower, higher) = (pair[0].min(pair[1]), pair[0].max(pair[1]));
+                    Hash256(Sha256::digest(format!("{}{}", lower, higher).as_bytes()).into())
+                })
+                .collect();
+        }
+        hashes.pop().unwrap_or(NULL_HASH)
+    }
+
+    /// Merkle root of the Bitcoin compatibility mode, where the parent of two
+    /// nodes is the double SHA-256 of their concatenated bytes and levels with
+    /// an odd number of nodes are padded by duplicating the last one. The
+    /// transaction hashes are the leaves, and like all hashes in the mode
+    /// they are displayed in reversed byte order.
+    pub fn compute_bitcoin_merkle_root(transactions: &[Transaction]) -> Hash256 {
+        bitcoin_merkle_root_of_hashes(&compute_transaction_hashes(transactions))
+    }
+
+    /// Like `compute_bitcoin_merkle_root()`, for transactions that are
+    /// already hashed.
+    pub fn bitcoin_merkle_root_of_hashes(transaction_hashes: &[Hash256]) -> Hash256 {
+        bitcoin_merkle_root_with_mutation(transaction_hashes).0
+    }
+
+    /// Bitcoin merkle root of the hashes, together with whether two
+    /// identical nodes are paired on any level before it is padded. The
+    /// padding makes the root of such a tree equal to that of the tree
+    /// without the duplicated nodes (CVE-2012-2459), so it proves nothing
+    /// about the transactions.
+    fn bitcoin_merkle_root_with_mutation(transaction_hashes: &[Hash256]) -> (Hash256, bool) {
+        if transaction_hashes.is_empty() {
+            return (NULL_HASH, false);
+        }
+        let mut hashes: Vec<[u8; 32]> =
+            transaction_hashes.iter().map(internal_byte_order).collect();
+        let mut mutated = false;
+        while hashes.len() > 1 {
+            mutated |= hashes.chunks_exact(2).any(|pair| pair[0] == pair[1]);
+            if !hashes.len().is_multiple_of(2) {
+                hashes.push(*hashes.last().unwrap());
+            }
+            hashes = hashes
+                .chunks(2)
+                .map(|pair| Sha256::digest(Sha256::digest([pair[0], pair[1]].concat())).into())
+                .collect();
+        }
+        let mut root = hashes[0];
+        root.reverse();
+        (Hash256(root), mutated)
+    }
+
+    /// Merkle root the already hashed transactions lead to in the header
+    /// format, or none in the Bitcoin format if the tree pairs duplicated
+    /// nodes, as the same root is then reached by other transactions.
+    pub fn merkle_root_in_format(
+        format: HeaderFormat,
+        transaction_hashes: Vec<Hash256>,
+    ) -> Option<Hash256> {
+        match format {
+            HeaderFormat::Simulator => Some(merkle_root_of_hashes(transaction_hashes)),
+            HeaderFormat::Bitcoin => match bitcoin_merkle_root_with_mutation(&transaction_hashes) {
+                (_, true) => None,
+                (root, false) => Some(root),
+            },
+            HeaderFormat::Tutorial => Some(specified_merkle_root_of_hashes(&transaction_hashes)),
+        }
+    }
+
+    /// Levels of the Merkle tree with fewer nodes are paired on the calling
+    /// thread, as starting the threads would take longer than hashing them.
+    const PARALLEL_LEVEL_SIZE: usize = 4096;
+
+    /// Hashes of the parents of the consecutive pairs of nodes of a level,
+    /// which has an even number of nodes. The pairs are independent of each
+    /// other, so large levels are split into contiguous runs of pairs hashed
+    /// on the given number of threads.
+    fn pair_level(level: &[MerkleTreeNode], threads: usize) -> Vec<Hash256> {
+        let parent = |pair: &[MerkleTreeNode]| merkle_parent(&pair[0].hash, &pair[1].hash);
+        if level.len() < PARALLEL_LEVEL_SIZE || threads <= 1 {
+            return level.chunks(2).map(parent).collect();
+        }
+        // Every run has an even number of nodes so that no pair is split.
+        let run_size = level.len().div_ceil(threads).next_multiple_of(2);
+        thread::scope(|scope| {
+            let workers: Vec<_> = level
+                .chunks(run_size)
+                .map(|run| scope.spawn(move || run.chunks(2).map(parent).collect::<Vec<_>>()))
+                .collect();
+            workers
+                .into_iter()
+                .flat_map(|worker| worker.join().unwrap())
+                .collect()
+        })
+    }
+
+    /// Here the intermediate hashes don't have 0x00 in front of them. None if
+    /// there are no transactions to build the tree of.
+    pub fn construct_merkle_tree(transaction_hashes: Vec<Hash256>) -> Option<MerkleTree> {
+        let threads = thread::available_parallelism().map_or(1, NonZeroUsize::get);
+        construct_merkle_tree_on(transaction_hashes, threads)
+    }
+
+    /// Like `construct_merkle_tree()`, hashing the large levels on the given
+    /// number of threads. The tree is the same for any number of threads.
+    pub fn construct_merkle_tree_on(
+        transaction_hashes: Vec<Hash256>,
+        threads: usize,
+    ) -> Option<MerkleTree> {
         // is the comparison operator used here the string or numerical comparison?
-        let null_string = "0x0000000000000000000000000000000000000000000000000000000000000000";
 
-        let mut nodes: Vec<MerkleTreeNode> = transaction_hashes
-            .iter()
-            .map(|t| MerkleTreeNode {
-                hash: t.to_string(),
-                left: None,
-                right: None,
-            })
-            .collect::<Vec<MerkleTreeNode>>();
+        // Every level holds about half as many nodes as the one below it.
+        let mut nodes: Vec<MerkleTreeNode> = Vec::with_capacity(2 * transaction_hashes.len() + 1);
+        nodes.extend(transaction_hashes.iter().map(|t| MerkleTreeNode {
+            hash: *t,
+            left: None,
+            right: None,
+        }));
 
-        while nodes.len() > 1 {
-            let mut next_level_nodes: Vec<MerkleTreeNode> = vec![];
-            if nodes.len() % 2 != 0 {
+        let mut level = 0..nodes.len();
+        while level.len() > 1 {
+            if !level.len().is_multiple_of(2) {
                 nodes.push(MerkleTreeNode {
-                    hash: null_string.to_owned(),
+                    hash: NULL_HASH,
                     left: None,
                     right: None,
                 });
+                level.end += 1;
             }
-            for i in 0..(nodes.len() / 2) {
-                let node_a: &MerkleTreeNode = nodes.get(2 * i).unwrap();
-                let node_b: &MerkleTreeNode = nodes.get(2 * i + 1).unwrap();
-                let hash_a = node_a.hash.clone();
-                let hash_b = node_b.hash.clone();
-
-                let hash_a_value = U256::from_be_hex(node_a
-                    .hash
-                    .clone()
-                    .trim_start_matches("0x"));
-                let hash_b_value = U256::from_be_hex(node_b
-                    .hash
-                    .clone()
-                    .trim_start_matches("0x"));
-
-                let new_hash: String = if hash_a_value < hash_b_value {
-                    digest(hash_a + &hash_b)
-                } else {
-                    digest(hash_b + &hash_a)
-                };
-                let new_node = MerkleTreeNode {
-                    hash: new_hash,
-                    left: Some(Box::new(node_a.clone())),
-                    right: Some(Box::new(node_b.clone())),
-                };
-                next_level_nodes.push(new_node)
+            let parents = pair_level(&nodes[level.clone()], threads);
+            let next_level_start = nodes.len();
+            nodes.extend(parents.into_iter().enumerate().map(|(pair, hash)| {
+                let left = level.start + 2 * pair;
+                MerkleTreeNode {
+                    hash,
+                    left: Some(left),
+                    right: Some(left + 1),
+                }
+            }));
+            level = next_level_start..nodes.len();
+        }
+
+        MerkleTree::from_nodes(nodes, transaction_hashes.len())
+    }
+
+    /// Difficulty used for the proof of work if none is configured.
+    const DEFAULT_POW_DIFFICULTY: usize = 5;
+
+    /// Assembles the header and the transactions of a new block without
+    /// mining it. The resulting `BlockTemplate` can be inspected before it is
+    /// turned into a block by either searching for a valid nonce with `mine()`
+    /// or by sealing it with a nonce found elsewhere using `seal()`.
+    pub struct BlockBuilder {
+        difficulty: u32,
+        height: u32,
+        miner: Address,
+        previous_block_header_hash: Hash256,
+        timestamp: u64,
+        transactions: Vec<Transaction>,
+        version: u32,
+        format: HeaderFormat,
+    }
+
+    impl BlockBuilder {
+        /// Builder of the first block of a new blockchain, which has no
+        /// previous block and is mined by the null address unless specified.
+        pub fn genesis() -> Self {
+            BlockBuilder {
+                difficulty: DEFAULT_POW_DIFFICULTY as u32,
+                height: 0,
+                miner: NULL_ADDRESS,
+                previous_block_header_hash: NULL_HASH,
+                timestamp: 0,
+                transactions: vec![],
+                version: 0,
+                format: HeaderFormat::Simulator,
+            }
+        }
+
+        /// Builder of the block following the one with the given header,
+        /// carrying over its difficulty, miner and timestamp.
+        pub fn on_top_of(previous_header: &Header) -> Self {
+            BlockBuilder {
+                difficulty: previous_header.difficulty,
+                height: previous_header.height + 1,
+                miner: previous_header.miner,
+                previous_block_header_hash: previous_header.hash,
+                timestamp: previous_header.timestamp,
+                transactions: vec![],
+                version: 0,
+                format: HeaderFormat::Simulator,
             }
-            nodes = next_level_nodes;
         }
 
-        return nodes.get(0).unwrap().clone();
+        pub fn difficulty(mut self, difficulty: u32) -> Self {
+            self.difficulty = difficulty;
+            self
+        }
+
+        pub fn miner(mut self, miner: Address) -> Self {
+            self.miner = miner;
+            self
+        }
+
+        pub fn timestamp(mut self, timestamp: u64) -> Self {
+            self.timestamp = timestamp;
+            self
+        }
+
+        pub fn transactions(mut self, transactions: Vec<Transaction>) -> Self {
+            self.transactions = transactions;
+            self
+        }
+
+        pub fn version(mut self, version: u32) -> Self {
+            self.version = version;
+            self
+        }
+
+        pub fn format(mut self, format: HeaderFormat) -> Self {
+            self.format = format;
+            self
+        }
+
+        /// Computes the Merkle root of the transactions and assembles the
+        /// header. Blocks without transactions have the null hash as the root.
+        pub fn build(self) -> BlockTemplate {
+            let transactions_merkle_root = match self.format {
+                HeaderFormat::Simulator => compute_merkle_root(&self.transactions),
+                HeaderFormat::Bitcoin => compute_bitcoin_merkle_root(&self.transactions),
+                HeaderFormat::Tutorial => compute_specified_merkle_root(&self.transactions),
+            };
+            info!("Merkle root: {}", transactions_merkle_root);
+
+            let header = Header {
+                difficulty: self.difficulty,
+                height: self.height,
+                miner: self.miner,
+                nonce: 0,
+                hash: NULL_HASH,
+                previous_block_header_hash: self.previous_block_header_hash,
+                timestamp: self.timestamp,
+                transactions_count: self.transactions.len().try_into().unwrap(),
+                transactions_merkle_root,
+                version: self.version,
+            };
+            debug!(
+                "Assembled the header of the new block: \n{}",
+                serde_json::to_string_pretty(&header).unwrap()
+            );
+
+            BlockTemplate {
+                header,
+                transactions: self.transactions,
+                format: self.format,
+            }
+        }
     }
 
-    pub fn mine_new_block(transactions: Vec<Transaction>, previous_block: &Block) -> Block {
-        info!(
-            "Producing a new block with {} transactions...",
-            transactions.len()
-        );
+    /// Block whose header has been assembled but doesn't have a nonce and a
+    /// hash yet.
+    #[derive(Debug, Clone)]
+    pub struct BlockTemplate {
+        pub header: Header,
+        pub transactions: Vec<Transaction>,
+        pub format: HeaderFormat,
+    }
 
-        info!("Computing transaction hashes...");
-        let transaction_hashes = compute_transaction_hashes(transactions.to_vec());
+    impl BlockTemplate {
+        /// Searches for the nonce giving the header hash the required number
+        /// of leading zeros.
+        pub fn mine(
+            self,
+            pow_difficulty: usize,
+            search: &NonceSearch,
+        ) -> Result<Block, SimulatorError> {
+            let (block, _) = self.mine_with_stats(pow_difficulty, search)?;
+            Ok(block)
+        }
 
-        info!("Assembling the Merkle tree...");
-        let merkle_root = construct_merkle_tree(transaction_hashes.clone());
-        debug!("Assembled Merkle tree: \n{}", merkle_root.clone());
-        info!("Merkle root: {}", merkle_root.hash);
-
-        let mut header = Header {
-            difficulty: previous_block.header.difficulty,
-            height: previous_block.header.height + 1,
-            miner: previous_block.header.miner.clone(),
-            nonce: 0,
-            hash: "".to_string(),
-            previous_block_header_hash: previous_block.header.hash.clone(),
-            timestamp: previous_block.header.timestamp + 10,
-            transactions_count: transaction_hashes.len().try_into().unwrap(),
-            transactions_merkle_root: "0x".to_string() + &merkle_root.hash,
-        };
+        /// Like `mine()`, but also returns how the search for the nonce went.
+        pub fn mine_with_stats(
+            self,
+            pow_difficulty: usize,
+            search: &NonceSearch,
+        ) -> Result<(Block, MiningStats), SimulatorError> {
+            let started = Instant::now();
+            let found = find_nonce(
+                self.header.clone(),
+                self.format,
+                pow_difficulty,
+                search,
+                &|| false,
+            )?
+            .expect("the search is never interrupted");
+            let seconds = started.elapsed().as_secs_f64();
+            let block = self.seal(found.nonce);
+            let stats = MiningStats {
+                height: block.header.height,
+                hash: block.header.hash,
+                nonces_tried: found.nonces_tried,
+                seconds,
+                hashrate: found.nonces_tried as f64 / seconds.max(f64::EPSILON),
+                // The nonces are searched on a single thread.
+                threads: 1,
+            };
+            Ok((block, stats))
+        }
 
-        debug!(
-            "Assembled the header of the new block: \n{}",
-            serde_json::to_string_pretty(&header).unwrap()
-        );
+        /// Like `mine()`, but gives up as soon as `interrupted` returns true,
+        /// which is checked every thousand nonces.
+        pub fn mine_until(
+            self,
+            pow_difficulty: usize,
+            search: &NonceSearch,
+            interrupted: impl Fn() -> bool,
+        ) -> Result<Option<Block>, SimulatorError> {
+            let nonce = find_nonce(
+                self.header.clone(),
+                self.format,
+                pow_difficulty,
+                search,
+                &interrupted,
+            )?;
+            Ok(nonce.map(|found| self.seal(found.nonce)))
+        }
 
-        let mut block_header_hash = header.hash();
+        /// Completes the block with the mock hash of its header instead of
+        /// searching for a nonce, so that the block is produced instantly but
+        /// is only accepted by `Blockchain::append_mock_pow()`.
+        pub fn stamp_mock_pow(mut self) -> Block {
+            self.header.hash = self.header.mock_pow_hash();
+            Block {
+                header: self.header,
+                transactions: self.transactions,
+            }
+        }
 
-        info!("Mining the new block...");
-        while !is_valid_block_header_hash(&block_header_hash, 5) {
-            header.nonce += 1;
-            let log_every_n_nonce = 100000;
-            if header.nonce % log_every_n_nonce == 0 {
-                info!("Tested nonce number: {}", header.nonce);
+        /// Completes the block with the given nonce, without checking that the
+        /// resulting hash satisfies the proof of work.
+        pub fn seal(mut self, nonce: u32) -> Block {
+            self.header.nonce = nonce;
+            self.header.hash = self.format.header_hash(&self.header);
+            Block {
+                header: self.header,
+                transactions: self.transactions,
             }
-            block_header_hash = header.hash();
         }
+    }
 
+    /// Assembles and mines the block following previous_header in the header
+    /// format, with the hasher and the mining strategy of the context, or
+    /// stamps it with a mock hash if `mock_pow` is set. If no difficulty is
+    /// given, the header carries over the difficulty of the previous block.
+    /// Returns the mining statistics of the block unless it was stamped.
+    pub fn mine_new_block(
+        transactions: Vec<Transaction>,
+        previous_header: &Header,
+        difficulty: Option<u32>,
+        block_interval: u64,
+        version: u32,
+        mock_pow: bool,
+        context: &SimulatorContext,
+    ) -> Result<(Block, Option<MiningStats>), SimulatorError> {
         info!(
-            "The nonce required to make the header hash valid is: {}",
-            header.nonce
+            "Producing a new block with {} transactions...",
+            transactions.len()
         );
 
-        header.hash = block_header_hash;
+        let mut builder = BlockBuilder::on_top_of(previous_header)
+            .timestamp(previous_header.timestamp + block_interval)
+            .transactions(transactions)
+            .version(version)
+            .format(context.header_format);
+        if let Some(difficulty) = difficulty {
+            builder = builder.difficulty(difficulty);
+        }
+        let template = builder.build();
+
+        let (block, stats) = if mock_pow {
+            info!("Stamping the new block with a mock proof of work...");
+            (template.stamp_mock_pow(), None)
+        } else {
+            info!("Mining the new block...");
+            let pow_difficulty = template.header.difficulty as usize;
+            let (block, stats) =
+                template.mine_with_stats(pow_difficulty, &context.nonce_search())?;
+            (block, Some(stats))
+        };
 
         info!(
             "Successfully mined the next block with header:\n{}",
-            serde_json::to_string_pretty(&header).unwrap()
+            serde_json::to_string_pretty(&block.header).unwrap()
         );
 
-        Block {
-            header,
-            transactions,
+        Ok((block, stats))
+    }
+
+    /// Searches for the nonce giving the header hash the required number of
+    /// leading zeros with the strategy of the search, and returns it. The
+    /// hashes are computed with the hasher selected by `--hasher`, throttled
+    /// to the hashrate if one is set, unless the strategy has its own
+    /// SHA-256. Returns none if the search is interrupted before a valid
+    /// nonce is found.
+    fn find_nonce(
+        header: Header,
+        format: HeaderFormat,
+        difficulty: usize,
+        search: &NonceSearch,
+        interrupted: &dyn Fn() -> bool,
+    ) -> Result<Option<NonceFound>, SimulatorError> {
+        let NonceSearch {
+            hasher,
+            strategy,
+            hashrate,
+        } = *search;
+        if difficulty > MAX_POW_DIFFICULTY as usize {
+            return Err(SimulatorError::InvalidHeader {
+                height: header.height,
+                reason: format!("the difficulty {} can never be satisfied", difficulty),
+            });
+        }
+        if let (HeaderFormat::Bitcoin, HasherSpec::Process(command)) = (format, hasher) {
+            return Err(SimulatorError::HasherFailed {
+                hasher: command.clone(),
+                reason: "worker processes hash lines of text and can't hash the binary \
+                         Bitcoin headers"
+                    .to_string(),
+            });
+        }
+        if strategy.uses_builtin_sha256() && (*hasher != HasherSpec::Builtin || hashrate.is_some())
+        {
+            return Err(SimulatorError::HasherFailed {
+                hasher: hasher.to_string(),
+                reason: "the mining strategy hashes with the builtin SHA-256, which can't be \
+                         replaced or throttled"
+                    .to_string(),
+            });
+        }
+        let mut hasher = create_pow_hasher(hasher)?;
+        if let Some(hashrate) = hashrate {
+            // Bitcoin headers are hashed twice.
+            let hashes_per_header = match format {
+                HeaderFormat::Simulator | HeaderFormat::Tutorial => 1.0,
+                HeaderFormat::Bitcoin => 2.0,
+            };
+            hasher = Box::new(ThrottledHasher::new(hasher, hashrate * hashes_per_header)?);
+        }
+        let found =
+            strategy.find_nonce(&header, format, difficulty, hasher.as_mut(), interrupted)?;
+
+        if let Some(found) = found {
+            info!(
+                "The nonce required to make the header hash valid is: {}",
+                found.nonce
+            );
         }
+        Ok(found)
     }
 
-    /// The hash string should have n=difficulty leading zeros to be considered
-    /// valid. It also needs to start with "0x".
-    pub fn is_valid_block_header_hash(hash: &str, difficulty: usize) -> bool {
-        hash[2..(2 + difficulty)] == "0".repeat(difficulty)
+    #[cfg(test)]
+    mod tests {
+        use serde::Serialize;
+
+        use crate::model::blockchain::{
+            merkle_parent, Hash256, MerkleAccumulator, MerkleTreeNode, NULL_HASH,
+        };
+
+        use super::{
+            construct_merkle_tree, construct_merkle_tree_on, pair_level, PARALLEL_LEVEL_SIZE,
+        };
+
+        /// Distinct leaves, none of which is the null hash padding a level.
+        fn leaf_hashes(count: usize) -> Vec<Hash256> {
+            (1..=count as u32)
+                .map(|leaf| {
+                    let mut hash = [0; 32];
+                    hash[..4].copy_from_slice(&leaf.to_be_bytes());
+                    Hash256(hash)
+                })
+                .collect()
+        }
+
+        /// Node of the Merkle tree as it was built before the arena, boxing
+        /// copies of its children.
+        #[derive(Clone, Serialize)]
+        struct BoxedNode {
+            hash: Hash256,
+            left: Option<Box<BoxedNode>>,
+            right: Option<Box<BoxedNode>>,
+        }
+
+        fn boxed_tree(transaction_hashes: &[Hash256]) -> BoxedNode {
+            let leaf = |hash| BoxedNode {
+                hash,
+                left: None,
+                right: None,
+            };
+            let mut nodes: Vec<BoxedNode> = transaction_hashes.iter().copied().map(leaf).collect();
+            while nodes.len() > 1 {
+                if !nodes.len().is_multiple_of(2) {
+                    nodes.push(leaf(NULL_HASH));
+                }
+                nodes = nodes
+                    .chunks(2)
+                    .map(|pair| BoxedNode {
+                        hash: merkle_parent(&pair[0].hash, &pair[1].hash),
+                        left: Some(Box::new(pair[0].clone())),
+                        right: Some(Box::new(pair[1].clone())),
+                    })
+                    .collect();
+            }
+            nodes.remove(0)
+        }
+
+        /// Hashes of the siblings on the path from the leaf with the hash up
+        /// to the root, found by searching the tree for the leaf.
+        fn boxed_sibling_hashes(node: &BoxedNode, leaf: &Hash256) -> Option<Vec<Hash256>> {
+            match (&node.left, &node.right) {
+                (Some(left), Some(right)) => {
+                    let (mut hashes, sibling) = match boxed_sibling_hashes(left, leaf) {
+                        Some(hashes) => (hashes, right.hash),
+                        None => (boxed_sibling_hashes(right, leaf)?, left.hash),
+                    };
+                    hashes.push(sibling);
+                    Some(hashes)
+                }
+                _ => (node.hash == *leaf).then(Vec::new),
+            }
+        }
+
+        #[test]
+        fn arena_tree_matches_boxed_tree() {
+            for count in 1..=17 {
+                let hashes = leaf_hashes(count);
+                let tree = construct_merkle_tree(hashes.clone()).unwrap();
+                let boxed = boxed_tree(&hashes);
+                assert_eq!(
+                    serde_json::to_value(&tree).unwrap(),
+                    serde_json::to_value(&boxed).unwrap(),
+                    "the serialized trees of {} leaves differ",
+                    count
+                );
+                for (leaf, hash) in hashes.iter().enumerate() {
+                    assert_eq!(
+                        tree.sibling_hashes(leaf),
+                        boxed_sibling_hashes(&boxed, hash),
+                        "the sibling hashes of leaf {} of {} differ",
+                        leaf,
+                        count
+                    );
+                }
+                assert_eq!(tree.sibling_hashes(count), None);
+            }
+        }
+
+        fn constructed_root(hashes: &[Hash256]) -> Hash256 {
+            construct_merkle_tree(hashes.to_vec())
+                .unwrap()
+                .root()
+                .hash()
+        }
+
+        #[test]
+        fn accumulator_root_matches_constructed_tree() {
+            let hashes = leaf_hashes(17);
+            let mut accumulator = MerkleAccumulator::new();
+            for count in 1..=hashes.len() {
+                accumulator.push(hashes[count - 1]);
+                assert_eq!(
+                    accumulator.root(),
+                    constructed_root(&hashes[..count]),
+                    "the roots of {} leaves differ",
+                    count
+                );
+            }
+        }
+
+        #[test]
+        fn accumulator_root_follows_removed_and_replaced_leaves() {
+            let mut hashes = leaf_hashes(17);
+            let mut accumulator = MerkleAccumulator::new();
+            hashes.iter().for_each(|hash| accumulator.push(*hash));
+
+            hashes[5] = Hash256([0xaa; 32]);
+            accumulator.set(5, hashes[5]);
+            assert_eq!(accumulator.root(), constructed_root(&hashes));
+
+            assert_eq!(accumulator.swap_remove(2), hashes.swap_remove(2));
+            assert_eq!(accumulator.root(), constructed_root(&hashes));
+
+            while hashes.len() > 1 {
+                assert_eq!(accumulator.pop(), hashes.pop());
+                assert_eq!(
+                    accumulator.root(),
+                    constructed_root(&hashes),
+                    "the roots of {} leaves differ",
+                    hashes.len()
+                );
+            }
+            accumulator.pop();
+            assert_eq!(accumulator.root(), NULL_HASH);
+        }
+
+        #[test]
+        fn split_level_is_paired_like_sequential_level() {
+            let level: Vec<MerkleTreeNode> = leaf_hashes(PARALLEL_LEVEL_SIZE + 6)
+                .into_iter()
+                .map(|hash| MerkleTreeNode {
+                    hash,
+                    left: None,
+                    right: None,
+                })
+                .collect();
+            assert_eq!(pair_level(&level, 4), pair_level(&level, 1));
+        }
+
+        #[test]
+        fn parallel_tree_has_sequential_root() {
+            let hashes = leaf_hashes(3 * PARALLEL_LEVEL_SIZE + 5);
+            let sequential = construct_merkle_tree_on(hashes.clone(), 1).unwrap();
+            let parallel = construct_merkle_tree_on(hashes, 3).unwrap();
+            assert_eq!(parallel.root().hash(), sequential.root().hash());
+            let last_leaf = sequential.leaves_count() - 1;
+            assert_eq!(
+                parallel.sibling_hashes(last_leaf),
+                sequential.sibling_hashes(last_leaf)
+            );
+        }
     }
 }
 
 pub mod validator {
-    use std::{cell::RefCell, fs, rc::Rc};
+    use std::{collections::HashMap, fs, num::NonZeroUsize, path::Path, thread};
 
-    use log::info;
-    use sha256::{digest, Sha256Digest};
+    use crypto_bigint::U256;
+    use log::{debug, info, warn};
+    use serde::Serialize;
 
     use crate::{
-        args::args::{GenerateInclusionProofArgs, VerifyInclusionProofArgs},
-        data_sourcing::data_provider::{load_blockchain, load_inclusion_proof},
-        model::blockchain::{InclusionProof, MerkleTreeNode},
-        node::miner::{compute_transaction_hashes, construct_merkle_tree},
+        args::args::{
+            AuditMerkleRootsArgs, AuditSupplyArgs, CompareChainsArgs, GenerateInclusionProofArgs,
+            GenerateProofIndexArgs, ImportBlocksArgs, SpvVerifyArgs, SubmitBlockArgs,
+            ValidateChainArgs, VerifyInclusionProofArgs, VerifyProofsArgs,
+        },
+        config::config::SimulatorContext,
+        data_sourcing::data_provider::{
+            load_blockchain, load_headers, load_inclusion_proof, load_json, load_transactions,
+            print_result, read_file_contents, write_blockchain, write_inclusion_proof, write_json,
+        },
+        error::error::SimulatorError,
+        model::blockchain::{
+            conflicting_checkpoint, is_valid_block_header_hash, Block, BlockTree, Hash256, Header,
+            HeaderChain, HeaderFormat, InclusionProof, MerkleNode, MerkleTree, ProofIndex,
+            Transaction,
+        },
+        node::miner::{compute_transaction_hashes, construct_merkle_tree, merkle_root_in_format},
+        state::state::{audit_block, expected_issuance, AuditBalances},
+        upgrades::upgrades::RuleTracker,
+        wallet::wallet::verify_signature,
     };
 
-    pub fn generate_inclusion_proof(args: GenerateInclusionProofArgs) {
+    /// Checks that the headers link up and carry valid proof of work, that
+    /// every block matches its header, that the chain contains the finalized
+    /// checkpoints and the signatures of the transactions in every block,
+    /// reporting the number of rejected transactions per block.
+    pub fn validate_chain(
+        args: ValidateChainArgs,
+        context: &SimulatorContext,
+    ) -> Result<(), SimulatorError> {
         info!("Loading the blockchain from {}", args.blockchain_state);
-        let blockchain = load_blockchain(&args.blockchain_state).unwrap();
+        let blockchain = load_blockchain(&args.blockchain_state, context)?;
 
-        let block = blockchain.get(args.block_number - 1).unwrap();
+        info!("Verifying the chain of {} headers...", blockchain.len());
+        HeaderChain::from(&blockchain).verify(context.header_format)?;
 
-        let transactions = &block.transactions;
+        info!("Verifying the merkle roots of the blocks...");
+        for block in &blockchain {
+            check_block(block, context.header_format)?;
+        }
 
-        info!("Computing transaction hashes...");
-        let transaction_hashes = compute_transaction_hashes(transactions.to_vec());
+        info!("Verifying {} checkpoints...", context.checkpoints.len());
+        for block in &blockchain {
+            if let Some(checkpoint) = conflicting_checkpoint(&context.checkpoints, &block.header) {
+                return Err(SimulatorError::CheckpointMismatch {
+                    height: block.header.height,
+                    hash: block.header.hash.to_string(),
+                    expected: checkpoint.hash.to_string(),
+                });
+            }
+        }
 
-        info!("Assembling the Merkle tree...");
-        let merkle_root = construct_merkle_tree(transaction_hashes.clone());
+        info!("Verifying the consensus rules active at each height...");
+        let mut tracker = RuleTracker::new(&context.deployments);
+        let mut previous_header = None;
+        for block in &blockchain {
+            tracker.check_rules(block, previous_header)?;
+            tracker.observe(&block.header);
+            previous_header = Some(&block.header);
+        }
+
+        info!("Verifying the transaction signatures...");
+        let mut total_rejected = 0;
+        for block in &blockchain {
+            let rejected = block
+                .transactions
+                .iter()
+                .filter(|t| !verify_signature(t))
+                .count();
+            if rejected > 0 {
+                warn!(
+                    "Block {}: {} of {} transactions have invalid signatures",
+                    block.header.height,
+                    rejected,
+                    block.transactions.len()
+                );
+            } else {
+                debug!(
+                    "Block {}: all {} signatures are valid",
+                    block.header.height,
+                    block.transactions.len()
+                );
+            }
+            total_rejected += rejected;
+        }
+
+        if total_rejected > 0 {
+            return Err(SimulatorError::InvalidSignatures(total_rejected));
+        }
+        info!("All {} blocks are valid!", blockchain.len());
+        let result = ValidityResult {
+            checked: blockchain.len(),
+        };
+        print_result(context.result_format, result.checked, &result)
+    }
+
+    /// Result of the commands checking many items, which is only printed if
+    /// all of them are valid.
+    #[derive(Serialize)]
+    struct ValidityResult {
+        /// Number of items checked.
+        checked: usize,
+    }
+
+    /// Replays the whole chain and checks that every block mints exactly the
+    /// coins the subsidy schedule allows and that no account spends more than
+    /// it holds. Fees move from the senders to the miners so no coins are
+    /// burned, while transactions sent from the null address outside of the
+    /// genesis block create coins from nothing.
+    pub fn audit_supply(
+        args: AuditSupplyArgs,
+        context: &SimulatorContext,
+    ) -> Result<(), SimulatorError> {
+        info!("Loading the blockchain from {}", args.blockchain_state);
+        let blockchain = load_blockchain(&args.blockchain_state, context)?;
+
+        info!("Replaying {} blocks...", blockchain.len());
+        let mut balances = AuditBalances::new();
+        let mut supply = 0;
+        let mut expected_supply = 0;
+        let mut flagged = 0;
+        for block in &blockchain {
+            let expected = expected_issuance(block, &context.subsidy_schedule);
+            expected_supply += expected;
+            let audit = audit_block(&mut balances, block, &context.subsidy_schedule);
+            supply += audit.minted;
+            if audit.minted != expected {
+                warn!(
+                    "Block {} minted {} coins instead of {}",
+                    block.header.height, audit.minted, expected
+                );
+            }
+            for problem in &audit.problems {
+                warn!("Block {} cannot apply {}", block.header.height, problem);
+            }
+            if audit.minted != expected || !audit.problems.is_empty() {
+                flagged += 1;
+            }
+        }
 
-        let transaction_hash_to_verify = &args.transaction_hash_to_verify;
+        info!(
+            "Total supply: {}, expected issuance: {}",
+            supply, expected_supply
+        );
+        if flagged > 0 {
+            return Err(SimulatorError::SupplyMismatch(flagged));
+        }
+        info!(
+            "The supply of all {} blocks is consistent",
+            blockchain.len()
+        );
+        let result = ValidityResult {
+            checked: blockchain.len(),
+        };
+        print_result(context.result_format, result.checked, &result)
+    }
+
+    /// Recomputes the merkle root of every block from its transactions in
+    /// the configured header format and compares it to the root in the
+    /// header, so that transactions added, removed, reordered, altered or
+    /// duplicated after the block was mined are detected.
+    pub fn audit_merkle_roots(
+        args: AuditMerkleRootsArgs,
+        context: &SimulatorContext,
+    ) -> Result<(), SimulatorError> {
+        info!("Loading the blockchain from {}", args.blockchain_state);
+        let blockchain = load_blockchain(&args.blockchain_state, context)?;
+
+        info!(
+            "Recomputing the merkle roots of {} blocks...",
+            blockchain.len()
+        );
+        let mut mismatched = 0;
+        for block in &blockchain {
+            let header = &block.header;
+            let root = header.transactions_merkle_root;
+            let transaction_hashes = compute_transaction_hashes(&block.transactions);
+            if merkle_root_in_format(context.header_format, transaction_hashes) != Some(root) {
+                warn!(
+                    "Block {} with hash {} has the merkle root {}, which its {} transactions don't lead to",
+                    header.height,
+                    header.hash,
+                    root,
+                    block.transactions.len()
+                );
+                mismatched += 1;
+            }
+        }
 
-        let Some(inclusion_proof) =
-            produce_inclusion_proof(merkle_root.clone(), transaction_hash_to_verify.to_string())
-        else {
-            info!("Transaction not found in block, no inclusion proof generated.");
-            return;
+        if mismatched > 0 {
+            return Err(SimulatorError::MerkleRootsMismatch(mismatched));
+        }
+        info!(
+            "The merkle roots of all {} blocks match their transactions",
+            blockchain.len()
+        );
+        let result = ValidityResult {
+            checked: blockchain.len(),
         };
+        print_result(context.result_format, result.checked, &result)
+    }
+
+    pub fn generate_inclusion_proof(
+        args: GenerateInclusionProofArgs,
+        context: &SimulatorContext,
+    ) -> Result<(), SimulatorError> {
+        info!("Loading the blockchain from {}", args.blockchain_state);
+        let blockchain = load_blockchain(&args.blockchain_state, context)?;
+
+        let block = blockchain.get_by_number(args.block_number)?;
+        let inclusion_proof =
+            inclusion_proof(&block.transactions, &args.transaction_hash_to_verify)?;
+
+        write_inclusion_proof(
+            &args.inclusion_proof,
+            context.output,
+            context.data_format,
+            &inclusion_proof,
+        )?;
+
+        info!(
+            "Generated Inclusion proof:\n{}",
+            serde_json::to_string_pretty(&inclusion_proof).unwrap()
+        );
+        Ok(())
+    }
+
+    /// Writes the inclusion proofs of all transactions of a block together
+    /// with its header, so that any of them can be verified without the
+    /// blockchain.
+    pub fn generate_proof_index(
+        args: GenerateProofIndexArgs,
+        context: &SimulatorContext,
+    ) -> Result<(), SimulatorError> {
+        info!("Loading the blockchain from {}", args.blockchain_state);
+        let blockchain = load_blockchain(&args.blockchain_state, context)?;
+
+        let block = blockchain.get_by_number(args.block_number)?;
+        let index = proof_index(block);
+        if index
+            .proofs
+            .first()
+            .is_some_and(|proof| proof.merkle_root != block.header.transactions_merkle_root)
+        {
+            warn!(
+                "The merkle root of the transactions of block {} is not the one in its header, \
+                 so the proofs can't be verified against the header",
+                block.header.height
+            );
+        }
+
+        info!(
+            "Writing the inclusion proofs of {} transactions to {}",
+            index.proofs.len(),
+            args.proof_index
+        );
+        write_json(&args.proof_index, context.output, &index)
+    }
+
+    /// Inclusion proofs of all transactions of the block, which are read off
+    /// a single Merkle tree of the block.
+    pub fn proof_index(block: &Block) -> ProofIndex {
+        let transaction_hashes = compute_transaction_hashes(&block.transactions);
+        if transaction_hashes.is_empty() {
+            return ProofIndex {
+                header: block.header.clone(),
+                proofs: vec![],
+            };
+        }
+        info!("Assembling the Merkle tree...");
+        let merkle_tree =
+            construct_merkle_tree(transaction_hashes.clone()).expect("there is at least one leaf");
+        let merkle_root = merkle_tree.root().hash();
+        let proofs = transaction_hashes
+            .into_iter()
+            .enumerate()
+            .map(|(leaf, transaction_hash)| InclusionProof {
+                transaction_hash,
+                merkle_root,
+                hashes: merkle_tree
+                    .sibling_hashes(leaf)
+                    .expect("every transaction is a leaf"),
+            })
+            .collect();
+        ProofIndex {
+            header: block.header.clone(),
+            proofs,
+        }
+    }
+
+    /// Proof that the transaction with the given hash is one of the
+    /// transactions of a block, which fails if it isn't, as is the case for
+    /// every transaction when the block has no transactions.
+    pub fn inclusion_proof(
+        transactions: &[Transaction],
+        transaction_hash: &Hash256,
+    ) -> Result<InclusionProof, SimulatorError> {
+        info!("Computing transaction hashes...");
+        let transaction_hashes = compute_transaction_hashes(transactions);
 
-        let proof = serde_json::to_string_pretty(&inclusion_proof).unwrap();
-        fs::write(&args.inclusion_proof, proof.clone()).unwrap();
+        info!("Assembling the Merkle tree...");
+        let merkle_tree = construct_merkle_tree(transaction_hashes);
 
-        info!("Generated Inclusion proof:\n{}", proof);
+        merkle_tree
+            .and_then(|merkle_tree| produce_inclusion_proof(&merkle_tree, *transaction_hash))
+            .ok_or_else(|| SimulatorError::TransactionNotInBlock(transaction_hash.to_string()))
     }
 
     fn produce_inclusion_proof(
-        merkle_root: MerkleTreeNode,
-        transaction_hash_to_verify: String,
+        merkle_tree: &MerkleTree,
+        transaction_hash_to_verify: Hash256,
     ) -> Option<InclusionProof> {
-        let path_to_transaction = find_path_to_transaction_in_merkle_tree(
-            &merkle_root,
-            &transaction_hash_to_verify,
-            vec![],
-        )?;
+        let path_to_transaction = path_to_transaction(merkle_tree, &transaction_hash_to_verify)?;
 
         // Path to transaction starts at the root node and then includes all
         // nodes that we have to traverse to get to that transaction
@@ -249,97 +1632,572 @@ pub mod validator {
         // inclusion proof, those are the siblings of all transactions that are included in
         // the path.
 
-        let mut proof: Vec<String> = vec![];
+        let mut proof: Vec<Hash256> = vec![];
 
-        print!(
-            "{}",
-            serde_json::to_string_pretty(&path_to_transaction)
-                .unwrap()
-                .clone()
+        debug!(
+            "Path to the transaction:\n{}",
+            serde_json::to_string_pretty(&path_to_transaction).unwrap()
         );
-        for i in 0..path_to_transaction.len() - 1 {
-            let current_parent = path_to_transaction.get(i).unwrap();
-            let current_node = path_to_transaction.get(i + 1).unwrap();
+        for pair in path_to_transaction.windows(2) {
+            let (current_parent, current_node) = (pair[0], pair[1]);
 
             // We always need to pick the node that is different from the current
             // node (the other sibling) and extract its hash to the vector of hashes.
 
-            if current_parent.left.as_ref().unwrap().hash == current_node.hash {
-                proof.push(current_parent.right.as_ref().unwrap().hash.clone());
+            let left = current_parent.left().unwrap();
+            let right = current_parent.right().unwrap();
+            if left.index() == current_node.index() {
+                proof.push(right.hash());
             } else {
-                proof.push(current_parent.left.as_ref().unwrap().hash.clone());
+                proof.push(left.hash());
             }
         }
 
         let hashes = proof.into_iter().rev().collect();
 
-        return Some(InclusionProof {
+        Some(InclusionProof {
             transaction_hash: transaction_hash_to_verify,
-            merkle_root: "0x".to_string() + &merkle_root.hash,
+            merkle_root: merkle_tree.root().hash(),
             hashes,
-        });
+        })
+    }
+
+    /// Nodes from the root of the tree to the first node with the hash in
+    /// depth-first order, None if no node has it.
+    pub fn path_to_transaction<'a>(
+        merkle_tree: &'a MerkleTree,
+        transaction_hash: &Hash256,
+    ) -> Option<Vec<MerkleNode<'a>>> {
+        let mut path = vec![];
+        find_path_to_transaction_in_merkle_tree(merkle_tree.root(), transaction_hash, &mut path)
+            .then_some(path)
     }
 
-    fn find_path_to_transaction_in_merkle_tree(
-        current_node: &MerkleTreeNode,
-        transaction_hash_to_verify: &str,
-        path_accumulator: Vec<MerkleTreeNode>,
-    ) -> Option<Vec<MerkleTreeNode>> {
-        let mut new_path_accumulator = path_accumulator.clone();
-        new_path_accumulator.push(current_node.clone());
-        if current_node.hash == transaction_hash_to_verify {
-            return Some(new_path_accumulator.to_vec());
+    /// Extends the path with the nodes from the current node to the first
+    /// node with the hash in depth-first order, and returns whether one was
+    /// found. The path is left as it was if none was.
+    fn find_path_to_transaction_in_merkle_tree<'a>(
+        current_node: MerkleNode<'a>,
+        transaction_hash_to_verify: &Hash256,
+        path_accumulator: &mut Vec<MerkleNode<'a>>,
+    ) -> bool {
+        path_accumulator.push(current_node);
+        if current_node.hash() == *transaction_hash_to_verify {
+            return true;
         }
 
-        if let Some(node) = &current_node.left {
-            let maybe_found = find_path_to_transaction_in_merkle_tree(
+        for node in current_node.left().into_iter().chain(current_node.right()) {
+            if find_path_to_transaction_in_merkle_tree(
                 node,
                 transaction_hash_to_verify,
-                new_path_accumulator.clone(),
-            );
-            if maybe_found.is_some() {
-                return maybe_found;
+                path_accumulator,
+            ) {
+                return true;
             }
         }
 
-        if let Some(node) = &current_node.right {
-            let maybe_found = find_path_to_transaction_in_merkle_tree(
-                node,
-                transaction_hash_to_verify,
-                new_path_accumulator.clone(),
+        path_accumulator.pop();
+        false
+    }
+
+    /// Result of the verification of an inclusion proof, which is only
+    /// printed if the proof is valid.
+    #[derive(Serialize)]
+    struct ProofResult {
+        transaction_hash: Hash256,
+        block_height: u32,
+        merkle_root: Hash256,
+    }
+
+    pub fn verify_inclusion_proof(
+        args: VerifyInclusionProofArgs,
+        context: &SimulatorContext,
+    ) -> Result<(), SimulatorError> {
+        info!("Loading the block headers from {}", args.blockchain_state);
+        let headers = load_headers(&args.blockchain_state, context)?;
+
+        info!("Loading the inclusion proof from {}", args.inclusion_proof);
+        let proof: InclusionProof = load_inclusion_proof(&args.inclusion_proof)?;
+
+        let header = headers.get_by_number(args.block_number)?;
+
+        info!("Checking of the merkle root in the inclusion proof matches the requested block");
+        if header.transactions_merkle_root != proof.merkle_root {
+            return Err(SimulatorError::MerkleRootMismatch);
+        };
+
+        info!("Verifying the proof...");
+        let proof = proof.verify()?;
+        info!("The proof is valid!");
+        info!("Proof:\n{}", serde_json::to_string_pretty(&proof).unwrap());
+        let result = ProofResult {
+            transaction_hash: proof.transaction_hash,
+            block_height: header.height,
+            merkle_root: proof.merkle_root,
+        };
+        print_result(context.result_format, proof.transaction_hash, &result)
+    }
+
+    /// Verifies an inclusion proof the way a light client would, trusting
+    /// only the chain of headers and not having any of the transactions. The
+    /// header chain is checked first as a proof is only meaningful against
+    /// headers that carry valid proof of work.
+    pub fn spv_verify(
+        args: SpvVerifyArgs,
+        context: &SimulatorContext,
+    ) -> Result<(), SimulatorError> {
+        info!("Loading the block headers from {}", args.headers);
+        let headers = load_headers(&args.headers, context)?;
+
+        info!("Loading the inclusion proof from {}", args.inclusion_proof);
+        let proof: InclusionProof = load_inclusion_proof(&args.inclusion_proof)?;
+
+        info!("Verifying the chain of {} headers...", headers.len());
+        headers.verify(context.header_format)?;
+
+        let header = headers
+            .get_by_height(args.block_height)
+            .ok_or(SimulatorError::HeightNotFound(args.block_height))?;
+
+        info!("Checking that the merkle root of the proof is in the header at the claimed height");
+        if header.transactions_merkle_root != proof.merkle_root {
+            return Err(SimulatorError::MerkleRootMismatch);
+        };
+
+        info!("Verifying the proof...");
+        proof.verify()?;
+        info!(
+            "The transaction {} is included in block {}!",
+            proof.transaction_hash, args.block_height
+        );
+        let result = ProofResult {
+            transaction_hash: proof.transaction_hash,
+            block_height: args.block_height,
+            merkle_root: proof.merkle_root,
+        };
+        print_result(context.result_format, proof.transaction_hash, &result)
+    }
+
+    /// Where an inclusion proof verified by `verify_proofs` is read from.
+    enum ProofSource {
+        File(String),
+        /// Line of a JSONL file, numbered from 1.
+        Line {
+            file_name: String,
+            number: usize,
+            text: String,
+        },
+    }
+
+    impl ProofSource {
+        fn name(&self) -> String {
+            match self {
+                ProofSource::File(file_name) => file_name.clone(),
+                ProofSource::Line {
+                    file_name, number, ..
+                } => format!("{}:{}", file_name, number),
+            }
+        }
+
+        fn load(&self) -> Result<InclusionProof, SimulatorError> {
+            match self {
+                ProofSource::File(file_name) => load_inclusion_proof(file_name),
+                ProofSource::Line { text, .. } => {
+                    serde_json::from_str(text).map_err(|source| SimulatorError::MalformedJson {
+                        file_name: self.name(),
+                        source,
+                    })
+                }
+            }
+        }
+    }
+
+    /// The files of the directory in the order of their names, or the
+    /// non-empty lines of the JSONL file.
+    fn proof_sources(path: &str) -> Result<Vec<ProofSource>, SimulatorError> {
+        let io_error = |source| SimulatorError::Io {
+            file_name: path.to_string(),
+            source,
+        };
+        if !Path::new(path).is_dir() {
+            let contents = read_file_contents(path)?;
+            return Ok(contents
+                .lines()
+                .enumerate()
+                .filter(|(_, line)| !line.trim().is_empty())
+                .map(|(index, line)| ProofSource::Line {
+                    file_name: path.to_string(),
+                    number: index + 1,
+                    text: line.to_string(),
+                })
+                .collect());
+        }
+        let mut file_names = vec![];
+        for entry in fs::read_dir(path).map_err(io_error)? {
+            let entry_path = entry.map_err(io_error)?.path();
+            if entry_path.is_file() {
+                file_names.push(entry_path.to_string_lossy().to_string());
+            }
+        }
+        file_names.sort();
+        Ok(file_names.into_iter().map(ProofSource::File).collect())
+    }
+
+    /// Verifies every inclusion proof of a directory or a JSONL file against
+    /// the chain of headers, which is checked first as in `spv_verify`. A
+    /// proof is valid if it leads to the merkle root of one of the headers.
+    /// The proofs are loaded and verified on several threads, and those that
+    /// fail are listed with the reason once all are verified.
+    pub fn verify_proofs(
+        args: VerifyProofsArgs,
+        context: &SimulatorContext,
+    ) -> Result<(), SimulatorError> {
+        info!("Loading the block headers from {}", args.headers);
+        let headers = load_headers(&args.headers, context)?;
+
+        info!("Verifying the chain of {} headers...", headers.len());
+        headers.verify(context.header_format)?;
+        let heights: HashMap<Hash256, u32> = headers
+            .iter()
+            .map(|header| (header.transactions_merkle_root, header.height))
+            .collect();
+
+        info!("Listing the inclusion proofs in {}", args.proofs);
+        let sources = proof_sources(&args.proofs)?;
+        let threads = args
+            .threads
+            .map(|threads| threads as usize)
+            .or_else(|| thread::available_parallelism().ok().map(NonZeroUsize::get))
+            .unwrap_or(1)
+            .clamp(1, sources.len().max(1));
+        info!(
+            "Verifying {} inclusion proofs on {} threads...",
+            sources.len(),
+            threads
+        );
+
+        let verify = |source: &ProofSource| -> Result<(Hash256, u32), SimulatorError> {
+            let proof = source.load()?;
+            let height = *heights
+                .get(&proof.merkle_root)
+                .ok_or_else(|| SimulatorError::UnknownMerkleRoot(proof.merkle_root.to_string()))?;
+            proof.verify()?;
+            Ok((proof.transaction_hash, height))
+        };
+        let verify = &verify;
+        let results: Vec<Result<(Hash256, u32), SimulatorError>> = thread::scope(|scope| {
+            let workers: Vec<_> = sources
+                .chunks(sources.len().div_ceil(threads).max(1))
+                .map(|chunk| scope.spawn(move || chunk.iter().map(verify).collect::<Vec<_>>()))
+                .collect();
+            workers
+                .into_iter()
+                .flat_map(|worker| worker.join().unwrap())
+                .collect()
+        });
+
+        let mut failed = 0;
+        for (source, result) in sources.iter().zip(results) {
+            match result {
+                Ok((transaction_hash, height)) => debug!(
+                    "{}: the transaction {} is included in block {}",
+                    source.name(),
+                    transaction_hash,
+                    height
+                ),
+                Err(error) => {
+                    warn!("{}: {}", source.name(), error);
+                    failed += 1;
+                }
+            }
+        }
+        info!(
+            "{} of {} inclusion proofs are valid, {} failed",
+            sources.len() - failed,
+            sources.len(),
+            failed
+        );
+        if failed > 0 {
+            return Err(SimulatorError::InvalidProofs(failed));
+        }
+        let result = ValidityResult {
+            checked: sources.len(),
+        };
+        print_result(context.result_format, result.checked, &result)
+    }
+
+    /// Checks the parts of a block that don't depend on the chain it extends:
+    /// the proof of work of its header and that the header commits to its
+    /// transactions, with the merkle root computed in the header format. The
+    /// linkage is checked when appending the block.
+    pub fn check_block(block: &Block, format: HeaderFormat) -> Result<(), SimulatorError> {
+        let header = &block.header;
+        let invalid = |reason: &str| SimulatorError::InvalidBlock {
+            height: header.height,
+            reason: reason.to_string(),
+        };
+        if !is_valid_block_header_hash(&header.hash, header.difficulty as usize) {
+            return Err(invalid("the hash doesn't satisfy the proof of work"));
+        }
+        if header.transactions_count as usize != block.transactions.len() {
+            return Err(invalid(
+                "the transactions count doesn't match the transactions",
+            ));
+        }
+        if block.total_fees().is_err() {
+            return Err(invalid("the fees of the transactions overflow"));
+        }
+        let transaction_hashes = compute_transaction_hashes(&block.transactions);
+        if merkle_root_in_format(format, transaction_hashes)
+            != Some(header.transactions_merkle_root)
+        {
+            return Err(invalid("the merkle root doesn't match the transactions"));
+        }
+        Ok(())
+    }
+
+    /// Appends the blocks of another chain file that extend the local chain.
+    /// Blocks that are already present are skipped and invalid blocks are
+    /// rejected, which also rejects any block built on top of them.
+    pub fn import_blocks(
+        args: ImportBlocksArgs,
+        context: &SimulatorContext,
+    ) -> Result<(), SimulatorError> {
+        info!("Loading the blockchain from {}", args.blockchain_state);
+        let mut blockchain = load_blockchain(&args.blockchain_state, context)?;
+
+        info!("Loading the blocks to import from {}", args.blocks);
+        let blocks = load_blockchain(&args.blocks, context)?;
+
+        let mut tracker = RuleTracker::new(&context.deployments);
+        for block in &blockchain {
+            tracker.observe(&block.header);
+        }
+
+        let (mut accepted, mut known, mut rejected) = (0, 0, 0);
+        for block in blocks {
+            if blockchain.get_by_hash(&block.header.hash).is_some() {
+                debug!("Block {} is already in the chain", block.header.height);
+                known += 1;
+                continue;
+            }
+            let header = block.header.clone();
+            let result = check_block(&block, context.header_format)
+                .and_then(|_| tracker.check_rules(&block, blockchain.tip_header().ok()))
+                .and_then(|_| {
+                    blockchain.append(block, context.header_format, &context.checkpoints)
+                });
+            match result {
+                Ok(()) => {
+                    tracker.observe(&header);
+                    debug!("Accepted block {}", header.height);
+                    accepted += 1;
+                }
+                Err(error) => {
+                    warn!("Rejected block {}: {}", header.hash, error);
+                    rejected += 1;
+                }
+            }
+        }
+
+        info!(
+            "Imported {} blocks, skipped {} known blocks and rejected {} blocks",
+            accepted, known, rejected
+        );
+        write_blockchain(
+            &args.blockchain_state_output,
+            context.output,
+            context.data_format,
+            &blockchain,
+        )
+    }
+
+    /// Builds the block tree out of the competing chains and reports the chain
+    /// with the most cumulative work, which the fork choice selects even if
+    /// another chain has more blocks.
+    pub fn compare_chains(
+        args: CompareChainsArgs,
+        context: &SimulatorContext,
+    ) -> Result<(), SimulatorError> {
+        let mut tree = BlockTree::new(&context.checkpoints, context.header_format);
+        for chain_file in &args.chains {
+            info!("Loading the blockchain from {}", chain_file);
+            let blockchain = load_blockchain(chain_file, context)?;
+            info!(
+                "{}: {} blocks with a total work of {}",
+                chain_file,
+                blockchain.len(),
+                format_work(blockchain.total_work())
             );
-            if maybe_found.is_some() {
-                return maybe_found;
+            for block in blockchain {
+                let hash = block.header.hash;
+                if let Err(error) =
+                    check_block(&block, context.header_format).and_then(|_| tree.insert(block))
+                {
+                    warn!("Rejected block {} of {}: {}", hash, chain_file, error);
+                    break;
+                }
             }
         }
 
-        return None;
+        let best_tip = tree.best_tip().ok_or(SimulatorError::EmptyBlockchain)?;
+        info!(
+            "The heaviest chain ends at block {} with hash {} and has a cumulative work of {}",
+            best_tip.height,
+            best_tip.hash,
+            format_work(tree.cumulative_work(&best_tip.hash).unwrap_or_default())
+        );
+        if let Some(output) = &args.blockchain_state_output {
+            write_blockchain(
+                output,
+                context.output,
+                context.data_format,
+                &tree.best_chain(),
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Work as a hex number without the padding zeros.
+    fn format_work(work: U256) -> String {
+        let digits = work.to_string();
+        let digits = digits.trim_start_matches('0');
+        format!("0x{}", if digits.is_empty() { "0" } else { digits })
     }
 
-    pub fn verify_inclusion_proof(args: VerifyInclusionProofArgs) {
+    /// Appends a block sealed outside of the simulator, e.g. by an external
+    /// miner working on a block template, after checking its proof of work,
+    /// its merkle root and that it extends the tip of the chain.
+    pub fn submit_block(
+        args: SubmitBlockArgs,
+        context: &SimulatorContext,
+    ) -> Result<(), SimulatorError> {
         info!("Loading the blockchain from {}", args.blockchain_state);
-        let blockchain = load_blockchain(&args.blockchain_state).unwrap();
+        let mut blockchain = load_blockchain(&args.blockchain_state, context)?;
 
-        info!("Loading the inclusion proof from {}", args.inclusion_proof);
-        let proof: InclusionProof = load_inclusion_proof(&args.inclusion_proof).unwrap();
+        info!("Loading the sealed header from {}", args.header);
+        let header: Header = load_json(&args.header)?;
+
+        info!(
+            "Loading the transactions of the block from {}",
+            args.transactions
+        );
+        let transactions = load_transactions(&args.transactions)?;
 
-        let Some(block) = blockchain.get(args.block_number - 1) else {
-            info!("Block not found in blockchain.");
-            return;
+        let block = Block {
+            header,
+            transactions,
         };
+        let mut tracker = RuleTracker::new(&context.deployments);
+        for block in &blockchain {
+            tracker.observe(&block.header);
+        }
+        check_block(&block, context.header_format)?;
+        tracker.check_rules(&block, blockchain.tip_header().ok())?;
+        blockchain.append(block, context.header_format, &context.checkpoints)?;
+        info!(
+            "Accepted block {} with hash {}",
+            blockchain.tip_header()?.height,
+            blockchain.tip_header()?.hash
+        );
 
-        info!("Checking of the merkle root in the inclusion proof matches the requested block");
-        if block.header.transactions_merkle_root != proof.merkle_root {
-            info!("Merkle root in the proof does not match the block merkle root.");
-            return;
+        write_blockchain(
+            &args.blockchain_state_output,
+            context.output,
+            context.data_format,
+            &blockchain,
+        )
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use std::fs;
+
+        use crate::{
+            args::args::{AuditMerkleRootsArgs, HeaderFormatArgs, LoadArgs},
+            config::config::SimulatorContext,
+            data_sourcing::data_provider::write_blockchain,
+            error::error::SimulatorError,
+            model::blockchain::{Address, Amount, Blockchain, Hash256, HeaderFormat, Transaction},
+            node::miner::{compute_bitcoin_merkle_root, construct_merkle_tree, BlockBuilder},
         };
 
-        info!("Verifying the proof...");
-        if let Ok(proof) = proof.verify() {
-            info!("The proof is valid!");
-            info!("Proof:\n{}", serde_json::to_string_pretty(&proof).unwrap());
-        } else {
-            info!("The proof is invalid!");
+        use super::{audit_merkle_roots, inclusion_proof};
+
+        #[test]
+        fn merkle_tree_without_leaves_is_none() {
+            assert!(construct_merkle_tree(vec![]).is_none());
+        }
+
+        #[test]
+        fn duplicated_last_transaction_fails_the_merkle_root_audit() {
+            let context = SimulatorContext {
+                header_format: HeaderFormat::Bitcoin,
+                ..Default::default()
+            };
+            let transactions = (1..=3)
+                .map(|amount| {
+                    Transaction::builder()
+                        .sender(Address([1; 20]))
+                        .receiver(Address([2; 20]))
+                        .amount(Amount(amount))
+                        .transaction_fee(Amount(1))
+                        .build()
+                        .unwrap()
+                })
+                .collect();
+            let mut block = BlockBuilder::genesis()
+                .difficulty(0)
+                .transactions(transactions)
+                .format(HeaderFormat::Bitcoin)
+                .build()
+                .mine(0, &context.nonce_search())
+                .unwrap();
+            let last = block.transactions.last().unwrap().clone();
+            block.transactions.push(last);
+            block.header.transactions_count += 1;
+            // The duplicate pairs up with the padding of the odd level, which
+            // leaves the Bitcoin root unchanged.
+            assert_eq!(
+                compute_bitcoin_merkle_root(&block.transactions),
+                block.header.transactions_merkle_root
+            );
+
+            let file = std::env::temp_dir().join(format!(
+                "duplicated-last-transaction-{}.json",
+                std::process::id()
+            ));
+            let blockchain_state = file.to_str().unwrap().to_string();
+            write_blockchain(
+                &blockchain_state,
+                context.output,
+                context.data_format,
+                &Blockchain::from(vec![block]),
+            )
+            .unwrap();
+            let args = AuditMerkleRootsArgs {
+                blockchain_state,
+                header_format: HeaderFormatArgs {
+                    header_format: context.header_format,
+                },
+                load: LoadArgs::default(),
+            };
+            let result = audit_merkle_roots(args, &context);
+            fs::remove_file(&file).unwrap();
+            assert!(matches!(
+                result,
+                Err(SimulatorError::MerkleRootsMismatch(1))
+            ));
+        }
+
+        #[test]
+        fn inclusion_proof_in_empty_block_is_not_found() {
+            let transaction_hash = Hash256([1; 32]);
+            let result = inclusion_proof(&[], &transaction_hash);
+            assert!(matches!(
+                result,
+                Err(SimulatorError::TransactionNotInBlock(hash)) if hash == transaction_hash.to_string()
+            ));
         }
     }
 }
@@ -0,0 +1,84 @@
+// This module exposes the hashing of transactions, Merkle roots and inclusion
+// proofs to Node.js through napi-rs
+pub mod nodejs {
+    use napi::{Error, Result};
+    use napi_derive::napi;
+    use serde::{de::DeserializeOwned, Serialize};
+    use serde_json::Value;
+
+    use crate::{
+        error::error::SimulatorError,
+        hashing::hashing::Hashable,
+        model::blockchain::{InclusionProof, Transaction},
+        node::{miner::compute_merkle_root, validator::inclusion_proof},
+    };
+
+    /// Turns the whole numbers of the value back into integers, as numbers
+    /// outside of the 32-bit range cross from JavaScript as floating point.
+    fn integral(value: Value) -> Value {
+        match value {
+            Value::Number(number) => match number.as_f64() {
+                Some(float) if number.is_f64() && float.fract() == 0.0 && float >= 0.0 => {
+                    Value::from(float as u64)
+                }
+                Some(float) if number.is_f64() && float.fract() == 0.0 => Value::from(float as i64),
+                _ => Value::Number(number),
+            },
+            Value::Array(values) => Value::Array(values.into_iter().map(integral).collect()),
+            Value::Object(fields) => Value::Object(
+                fields
+                    .into_iter()
+                    .map(|(name, value)| (name, integral(value)))
+                    .collect(),
+            ),
+            other => other,
+        }
+    }
+
+    fn parse<T: DeserializeOwned>(value: Value) -> Result<T> {
+        serde_json::from_value(integral(value))
+            .map_err(|error| Error::from_reason(error.to_string()))
+    }
+
+    fn to_value<T: Serialize>(value: &T) -> Result<Value> {
+        serde_json::to_value(value).map_err(|error| Error::from_reason(error.to_string()))
+    }
+
+    fn to_js_error(error: SimulatorError) -> Error {
+        Error::from_reason(error.to_string())
+    }
+
+    /// Hash of a transaction given as an object in the format of the mempool
+    /// files.
+    #[napi(js_name = "transactionHash")]
+    pub fn transaction_hash(transaction: Value) -> Result<String> {
+        Ok(parse::<Transaction>(transaction)?.hash().to_string())
+    }
+
+    /// Merkle root of the transactions as stored in the block header.
+    #[napi(js_name = "merkleRoot")]
+    pub fn merkle_root(transactions: Value) -> Result<String> {
+        let transactions: Vec<Transaction> = parse(transactions)?;
+        Ok(compute_merkle_root(&transactions).to_string())
+    }
+
+    /// Proof that the transaction with the given hash is one of the
+    /// transactions of a block, in the format written by
+    /// `GenerateInclusionProof`.
+    #[napi(js_name = "generateInclusionProof")]
+    pub fn generate_inclusion_proof(
+        transactions: Value,
+        transaction_hash: String,
+    ) -> Result<Value> {
+        let transactions: Vec<Transaction> = parse(transactions)?;
+        let transaction_hash = transaction_hash.parse().map_err(to_js_error)?;
+        let proof = inclusion_proof(&transactions, &transaction_hash).map_err(to_js_error)?;
+        to_value(&proof)
+    }
+
+    /// Whether the inclusion proof leads to its merkle root.
+    #[napi(js_name = "verifyInclusionProof")]
+    pub fn verify_inclusion_proof(proof: Value) -> Result<bool> {
+        Ok(parse::<InclusionProof>(proof)?.verify().is_ok())
+    }
+}
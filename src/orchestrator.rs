@@ -0,0 +1,1154 @@
+// This module runs several simulated nodes in one process, each mining and
+// validating real blocks under a shared virtual clock
+pub mod orchestrator {
+    use std::{
+        collections::{BTreeMap, HashMap, HashSet},
+        fs,
+        io::Write,
+        path::Path,
+        rc::Rc,
+    };
+
+    use log::{info, warn};
+    use rand::{Rng, SeedableRng};
+    use rand_chacha::ChaCha20Rng;
+    use serde::{Deserialize, Serialize};
+
+    use crate::{
+        args::args::{OrchestrateArgs, ReplayArgs},
+        config::config::SimulatorContext,
+        data_sourcing::data_provider::{
+            load_blockchain, load_transactions, open_output, read_file_contents, write_blockchain,
+            write_json, write_json_line, DataFormat,
+        },
+        engine::engine::EventQueue,
+        error::error::SimulatorError,
+        hashing::hashing::Hashable,
+        model::blockchain::{
+            Address, Block, Blockchain, Checkpoint, Hash256, HeaderFormat, Transaction,
+            NULL_ADDRESS,
+        },
+        node::{
+            miner::{find_executable_transactions, BlockBuilder, TransactionSelection},
+            validator::check_block,
+        },
+        simulation::fleet::{load_fleet, BlockRace, VirtualMiner},
+        sync::sync::{sync_with, ChainSync, SyncOutcome},
+        upgrades::upgrades::{Deployment, RuleTracker},
+    };
+
+    /// Events of the orchestrated simulation.
+    enum Event {
+        /// The miner of the node found the next block
+        BlockFound(usize),
+        BlockArrives {
+            node: usize,
+            block: Rc<Block>,
+        },
+        /// A client submits the next transaction of the mempool file to the
+        /// node
+        TransactionSubmitted {
+            node: usize,
+            transaction: Transaction,
+        },
+        /// Transaction relayed by another node
+        TransactionArrives {
+            node: usize,
+            transaction: Rc<Transaction>,
+        },
+        /// The node crashes, losing its mempool and the blocks in flight to it
+        NodeCrashes(usize),
+        /// The node comes back and asks a peer for its chain and mempool
+        NodeRestarts(usize),
+        /// The restarted node receives the chain and the mempool of the peer
+        NodeSynced {
+            node: usize,
+            peer: usize,
+        },
+    }
+
+    /// Significant event of the orchestrated simulation, logged as one line
+    /// of the event log. The times are virtual seconds from the start.
+    #[derive(Serialize, Deserialize)]
+    #[serde(tag = "event", rename_all = "snake_case")]
+    pub enum TraceEvent {
+        /// Arguments of the run, logged first so that it can be replayed
+        RunStarted {
+            args: OrchestrateArgs,
+        },
+        BlockMined {
+            time: f64,
+            node: Address,
+            block: Block,
+        },
+        /// The node validated the block and added it to its chain or, if the
+        /// block doesn't extend the tip, kept it aside
+        BlockAccepted {
+            time: f64,
+            node: Address,
+            hash: Hash256,
+            height: u32,
+        },
+        Reorg {
+            time: f64,
+            node: Address,
+            fork_height: u32,
+            reverted: Vec<Hash256>,
+            appended: Vec<Hash256>,
+        },
+        TransactionIncluded {
+            time: f64,
+            node: Address,
+            transaction: Hash256,
+            block: Hash256,
+        },
+        NodeCrashed {
+            time: f64,
+            node: Address,
+            lost_chain: bool,
+        },
+        NodeRestarted {
+            time: f64,
+            node: Address,
+        },
+        /// The restarted node synchronised with the chain of the peer
+        NodeSynced {
+            time: f64,
+            node: Address,
+            peer: Address,
+        },
+    }
+
+    /// Switch of a node to another branch, waiting to be logged.
+    struct BranchSwitch {
+        fork_height: u32,
+        reverted: Vec<Hash256>,
+        appended: Vec<Hash256>,
+    }
+
+    /// Chain, mempool and the blocks off the chain of one simulated node.
+    struct SimulatedNode {
+        blockchain: Blockchain,
+        tracker: RuleTracker,
+        checkpoints: Vec<Checkpoint>,
+        /// Format the headers of the accepted blocks have to be hashed in
+        format: HeaderFormat,
+        /// Unconfirmed transactions by their hashes, so that the confirmed ones
+        /// are removed without rehashing the whole mempool
+        mempool: BTreeMap<Hash256, Transaction>,
+        /// Hashes of the transactions the node has seen, in its mempool or in
+        /// a block
+        known_transactions: HashSet<Hash256>,
+        /// Hashes of all blocks the node accepted, on its chain or not
+        seen: HashSet<Hash256>,
+        /// Valid blocks of branches the node doesn't follow, by their hashes
+        side_blocks: HashMap<Hash256, Rc<Block>>,
+        /// Blocks received before their parents, by the parent they wait for
+        waiting: HashMap<Hash256, Vec<Rc<Block>>>,
+        /// Whether the node is running, crashed nodes neither mine nor receive
+        /// anything
+        online: bool,
+        blocks_mined: u32,
+        reorgs: u32,
+        reverted_blocks: u32,
+        crashes: u32,
+        /// Branch switches since the events of the node were last logged
+        switches: Vec<BranchSwitch>,
+    }
+
+    impl SimulatedNode {
+        fn new(
+            blockchain: Blockchain,
+            mempool: Vec<Transaction>,
+            checkpoints: &[Checkpoint],
+            deployments: &[Deployment],
+            format: HeaderFormat,
+        ) -> Self {
+            let mut tracker = RuleTracker::new(deployments);
+            for block in &blockchain {
+                tracker.observe(&block.header);
+            }
+            let mut node = SimulatedNode {
+                seen: blockchain.iter().map(|b| b.header.hash).collect(),
+                mempool: mempool.into_iter().map(|t| (t.hash(), t)).collect(),
+                known_transactions: HashSet::new(),
+                blockchain,
+                tracker,
+                checkpoints: checkpoints.to_vec(),
+                format,
+                side_blocks: HashMap::new(),
+                waiting: HashMap::new(),
+                blocks_mined: 0,
+                online: true,
+                reorgs: 0,
+                reverted_blocks: 0,
+                crashes: 0,
+                switches: vec![],
+            };
+            node.known_transactions = node.mempool.keys().cloned().collect();
+            node
+        }
+
+        /// Adds the transaction to the mempool and returns whether it is new,
+        /// in which case the node relays it to its peers.
+        fn submit(&mut self, transaction: Transaction) -> bool {
+            let hash = transaction.hash();
+            if !self.known_transactions.insert(hash) {
+                return false;
+            }
+            self.mempool.insert(hash, transaction);
+            true
+        }
+
+        /// Accepts the block and the blocks that were waiting for it, and
+        /// returns the accepted blocks, which the node relays to its peers.
+        fn receive(&mut self, block: Rc<Block>) -> Vec<Rc<Block>> {
+            let mut accepted = vec![];
+            let mut pending = vec![block];
+            while let Some(block) = pending.pop() {
+                let header = &block.header;
+                if self.seen.contains(&header.hash) {
+                    continue;
+                }
+                if !self.seen.contains(&header.previous_block_header_hash) {
+                    self.waiting
+                        .entry(header.previous_block_header_hash)
+                        .or_default()
+                        .push(block);
+                    continue;
+                }
+                match self.accept(Rc::clone(&block)) {
+                    Ok(()) => {
+                        self.seen.insert(block.header.hash);
+                        if let Some(children) = self.waiting.remove(&block.header.hash) {
+                            pending.extend(children);
+                        }
+                        accepted.push(block);
+                    }
+                    Err(error) => warn!("Rejected block {}: {}", block.header.hash, error),
+                }
+            }
+            accepted
+        }
+
+        /// Appends the block if it extends the tip, otherwise keeps it aside
+        /// and switches to its branch if the branch has more work.
+        fn accept(&mut self, block: Rc<Block>) -> Result<(), SimulatorError> {
+            let tip = self.blockchain.tip_header()?;
+            if block.header.previous_block_header_hash == tip.hash {
+                check_block(&block, self.format)?;
+                self.tracker.check_rules(&block, Some(tip))?;
+                self.blockchain
+                    .append(Block::clone(&block), self.format, &self.checkpoints)?;
+                self.tracker.observe(&block.header);
+                self.confirm(&block);
+                return Ok(());
+            }
+
+            let mut branch = vec![Rc::clone(&block)];
+            while let Some(parent) = self
+                .side_blocks
+                .get(&branch.last().unwrap().header.previous_block_header_hash)
+            {
+                branch.push(Rc::clone(parent));
+            }
+            branch.reverse();
+
+            let mut sync = ChainSync::new(&self.checkpoints, self.format);
+            let headers = branch.iter().map(|b| b.header.clone()).collect();
+            sync.add_headers(&self.blockchain, headers)?;
+            if sync.wanted_blocks(&self.blockchain).is_empty() {
+                // The block is only validated once its branch has more work.
+                self.side_blocks
+                    .insert(block.header.hash, Rc::clone(&block));
+                return Ok(());
+            }
+            let blocks = branch.iter().map(|b| Block::clone(b)).collect();
+            sync.add_blocks(&self.blockchain, blocks);
+            let outcome = sync.apply(&mut self.blockchain, &mut self.tracker)?;
+            self.adopt(outcome);
+            Ok(())
+        }
+
+        /// Updates the mempool and the blocks off the chain after switching to
+        /// another branch.
+        fn adopt(&mut self, outcome: SyncOutcome) {
+            // The transactions of the reverted blocks become unconfirmed again
+            // unless the new branch contains them too.
+            let returned = outcome
+                .reverted
+                .iter()
+                .flat_map(|block| &block.transactions)
+                .filter(|t| t.sender != NULL_ADDRESS);
+            for transaction in returned {
+                self.mempool.insert(transaction.hash(), transaction.clone());
+            }
+            for appended in &outcome.appended {
+                self.confirm(appended);
+                self.side_blocks.remove(&appended.header.hash);
+                self.seen.insert(appended.header.hash);
+            }
+            if !outcome.reverted.is_empty() {
+                self.reorgs += 1;
+                self.reverted_blocks += outcome.reverted.len() as u32;
+                let hashes = |blocks: &[Block]| blocks.iter().map(|b| b.header.hash).collect();
+                self.switches.push(BranchSwitch {
+                    fork_height: outcome.fork_height,
+                    reverted: hashes(&outcome.reverted),
+                    appended: hashes(&outcome.appended),
+                });
+            }
+            for reverted in outcome.reverted {
+                self.side_blocks
+                    .insert(reverted.header.hash, Rc::new(reverted));
+            }
+        }
+
+        /// Stops the node, which loses everything it kept in memory: the
+        /// mempool, the blocks off its chain and the blocks waiting for their
+        /// parents. The chain survives the crash unless the initial chain to
+        /// restart from is given.
+        fn crash(&mut self, initial_chain: Option<&Blockchain>) {
+            let blockchain = match initial_chain {
+                Some(initial_chain) => initial_chain.clone(),
+                None => std::mem::take(&mut self.blockchain),
+            };
+            let mut restarted = SimulatedNode::new(
+                blockchain,
+                vec![],
+                &self.checkpoints,
+                self.tracker.deployments(),
+                self.format,
+            );
+            restarted.known_transactions = restarted
+                .blockchain
+                .iter()
+                .flat_map(|block| &block.transactions)
+                .map(|t| t.hash())
+                .collect();
+            *self = SimulatedNode {
+                online: false,
+                blocks_mined: self.blocks_mined,
+                reorgs: self.reorgs,
+                reverted_blocks: self.reverted_blocks,
+                crashes: self.crashes + 1,
+                switches: std::mem::take(&mut self.switches),
+                ..restarted
+            };
+        }
+
+        /// Synchronises the restarted node with the chain of a peer and takes
+        /// over the peer's mempool.
+        fn sync_from(
+            &mut self,
+            mut chain: Blockchain,
+            mempool: Vec<Transaction>,
+        ) -> Result<(), SimulatorError> {
+            let outcome = sync_with(
+                &mut self.blockchain,
+                &mut self.tracker,
+                &mut chain,
+                &self.checkpoints,
+                self.format,
+            )?;
+            if let Some(outcome) = outcome {
+                self.adopt(outcome);
+            }
+            for transaction in mempool {
+                self.submit(transaction);
+            }
+            Ok(())
+        }
+
+        fn confirm(&mut self, block: &Block) {
+            for transaction in &block.transactions {
+                let hash = transaction.hash();
+                self.mempool.remove(&hash);
+                self.known_transactions.insert(hash);
+            }
+        }
+    }
+
+    #[derive(Serialize)]
+    struct NodeReportRow {
+        node: Address,
+        hashrate: f64,
+        height: u32,
+        tip: Hash256,
+        blocks_mined: u32,
+        /// Blocks mined by the node that are part of the best final chain
+        blocks_in_best_chain: u32,
+        reorgs: u32,
+        reverted_blocks: u32,
+        crashes: u32,
+        mempool_transactions: usize,
+    }
+
+    #[derive(Serialize)]
+    struct OrchestrationReport {
+        virtual_hours: f64,
+        blocks_mined: u32,
+        best_height: u32,
+        /// Mined blocks that are not part of the best final chain
+        stale_blocks: u32,
+        stale_rate: f64,
+        /// Average number of seconds between the blocks of the best chain
+        mean_block_interval: f64,
+        /// Transactions other than the coinbase ones in the new blocks of the
+        /// best chain
+        confirmed_transactions: usize,
+        /// Number of nodes whose tip is the tip of the best chain
+        nodes_on_best_tip: usize,
+        nodes: Vec<NodeReportRow>,
+    }
+
+    /// Runs the miners of the fleet as nodes that each keep their own chain
+    /// and mempool, for the given number of virtual hours. The race for the
+    /// blocks is decided as in `SimulateNetwork`, but the winner actually
+    /// builds and seals a block on top of its own tip, which the other nodes
+    /// receive after the latency and validate before appending it or, if it
+    /// belongs to a heavier branch, reorganising to it. With a crash rate, the
+    /// nodes randomly crash, losing their mempool, and after the downtime
+    /// restart by synchronising with a peer.
+    pub fn orchestrate(
+        args: OrchestrateArgs,
+        context: &SimulatorContext,
+    ) -> Result<(), SimulatorError> {
+        let mut event_log = args.event_log.as_deref().map(open_output).transpose()?;
+        let outcome = match (&mut event_log, &args.event_log) {
+            (Some(writer), Some(file_name)) => {
+                let run_started = TraceEvent::RunStarted { args: args.clone() };
+                let canonical = context.output.canonical;
+                write_json_line(writer, file_name, canonical, &run_started)?;
+                let event_log = EventLog {
+                    writer,
+                    file_name,
+                    canonical,
+                };
+                run_orchestration(&args, context, Some(event_log))?
+            }
+            _ => run_orchestration(&args, context, None)?,
+        };
+        if let Some(event_log) = event_log {
+            info!(
+                "Wrote the event log to {}",
+                args.event_log.as_ref().unwrap()
+            );
+            event_log.commit()?;
+        }
+        let RunOutcome {
+            miners,
+            start_height,
+            nodes,
+        } = outcome;
+
+        let report = report(&args, &miners, &nodes, start_height)?;
+        info!(
+            "Mined {} blocks of which {} are stale, {} of {} nodes end on the best tip at height {}",
+            report.blocks_mined,
+            report.stale_blocks,
+            report.nodes_on_best_tip,
+            nodes.len(),
+            report.best_height
+        );
+
+        if let Some(chains_output_dir) = &args.chains_output_dir {
+            info!("Writing the chains of the nodes to {}", chains_output_dir);
+            fs::create_dir_all(chains_output_dir).map_err(|source| SimulatorError::Io {
+                file_name: chains_output_dir.clone(),
+                source,
+            })?;
+            for (miner, node) in miners.iter().zip(&nodes) {
+                let extension = match context.data_format {
+                    DataFormat::Json => "json",
+                    DataFormat::Proto => "pb",
+                };
+                let file_name =
+                    Path::new(chains_output_dir).join(format!("{}.{}", miner.address, extension));
+                let file_name = file_name.to_string_lossy();
+                write_blockchain(
+                    &file_name,
+                    context.output,
+                    context.data_format,
+                    &node.blockchain,
+                )?;
+            }
+        }
+        write_json(&args.report_output, context.output, &report)
+    }
+
+    /// Writer the events of a run are logged to, one JSON object per line.
+    struct EventLog<'a> {
+        writer: &'a mut dyn Write,
+        /// Name of the log file, used to report errors
+        file_name: &'a str,
+        /// Whether the events are written as canonical JSON
+        canonical: bool,
+    }
+
+    /// Final state of an orchestrated run.
+    struct RunOutcome {
+        miners: Vec<VirtualMiner>,
+        /// Height of the chain the nodes started from
+        start_height: u32,
+        nodes: Vec<SimulatedNode>,
+    }
+
+    /// Loads the fleet, the chain and the mempool of the arguments and runs
+    /// the orchestration, logging its events if an event log is given.
+    fn run_orchestration(
+        args: &OrchestrateArgs,
+        context: &SimulatorContext,
+        event_log: Option<EventLog>,
+    ) -> Result<RunOutcome, SimulatorError> {
+        info!("Loading the fleet of miners from {}", args.fleet);
+        let fleet = load_fleet(&args.fleet)?;
+        let miners = fleet.miners;
+        info!("Loading the blockchain from {}", args.blockchain_state);
+        let blockchain = load_blockchain(&args.blockchain_state, context)?;
+        let mempool = match &args.mempool {
+            Some(mempool) => {
+                info!("Loading the mempool from {}", mempool);
+                load_transactions(mempool)?
+            }
+            None => vec![],
+        };
+        let start = blockchain.tip_header()?.clone();
+        // Without a transaction rate, every node starts with the whole mempool.
+        let (initial_mempool, submissions) = match args.transaction_rate {
+            Some(_) => (vec![], mempool),
+            None => (mempool, vec![]),
+        };
+        let nodes: Vec<SimulatedNode> = miners
+            .iter()
+            .map(|_| {
+                SimulatedNode::new(
+                    blockchain.clone(),
+                    initial_mempool.clone(),
+                    &context.checkpoints,
+                    &context.deployments,
+                    context.header_format,
+                )
+            })
+            .collect();
+
+        let mut submission_rng = ChaCha20Rng::seed_from_u64(args.seed);
+        submission_rng.set_stream(2);
+        let mut crash_rng = ChaCha20Rng::seed_from_u64(args.seed);
+        crash_rng.set_stream(3);
+        let mut simulation = Orchestration {
+            args,
+            context,
+            miners: &miners,
+            nodes,
+            events: EventQueue::default(),
+            race: BlockRace::new(&miners),
+            rng: ChaCha20Rng::seed_from_u64(args.seed),
+            submission_rng,
+            crash_rng,
+            submissions: submissions.into_iter(),
+            initial_chain: blockchain,
+            event_log,
+            start_timestamp: start.timestamp,
+            end: args.hours * 3600.0,
+        };
+        info!(
+            "Simulating {} hours of mining by {} nodes...",
+            args.hours,
+            simulation.nodes.len()
+        );
+        simulation.run()?;
+        let nodes = simulation.nodes;
+        Ok(RunOutcome {
+            miners,
+            start_height: start.height,
+            nodes,
+        })
+    }
+
+    /// Reads all events of an event log written by `Orchestrate`.
+    pub fn load_event_log(file_name: &str) -> Result<Vec<TraceEvent>, SimulatorError> {
+        let contents = read_file_contents(file_name)?;
+        contents
+            .lines()
+            .enumerate()
+            .filter(|(_, line)| !line.trim().is_empty())
+            .map(|(index, line)| {
+                serde_json::from_str(line).map_err(|source| SimulatorError::MalformedJson {
+                    file_name: format!("{}:{}", file_name, index + 1),
+                    source,
+                })
+            })
+            .collect()
+    }
+
+    /// Re-runs an orchestrated run with the arguments and the seed recorded
+    /// at the start of its event log, and checks that the run logs exactly
+    /// the recorded events and, if the recorded chains are given, that the
+    /// final chains match them byte for byte. Any difference means that the
+    /// simulation is not deterministic.
+    pub fn replay(args: ReplayArgs, context: &SimulatorContext) -> Result<(), SimulatorError> {
+        info!("Loading the event log from {}", args.event_log);
+        let contents = read_file_contents(&args.event_log)?;
+        let mut recorded = contents.lines().filter(|line| !line.trim().is_empty());
+        let run_started = recorded.next().map(serde_json::from_str::<TraceEvent>);
+        let recorded_args = match run_started {
+            Some(Ok(TraceEvent::RunStarted { args })) => args,
+            Some(Err(source)) => {
+                return Err(SimulatorError::MalformedJson {
+                    file_name: format!("{}:1", args.event_log),
+                    source,
+                })
+            }
+            _ => {
+                return Err(SimulatorError::ReplayMismatch(format!(
+                    "{} does not start with the arguments of the run",
+                    args.event_log
+                )))
+            }
+        };
+        let run_args = OrchestrateArgs {
+            fleet: args.fleet.unwrap_or(recorded_args.fleet),
+            blockchain_state: args
+                .blockchain_state
+                .unwrap_or(recorded_args.blockchain_state),
+            mempool: args.mempool.or(recorded_args.mempool),
+            chains_output_dir: None,
+            event_log: None,
+            ..recorded_args
+        };
+
+        info!(
+            "Re-running the orchestration with the seed {}",
+            run_args.seed
+        );
+        let mut replayed = vec![];
+        let event_log = EventLog {
+            writer: &mut replayed,
+            file_name: &args.event_log,
+            canonical: context.output.canonical,
+        };
+        let outcome = run_orchestration(&run_args, context, Some(event_log))?;
+        let replayed = String::from_utf8(replayed).expect("the events are valid UTF-8");
+
+        let mut replayed = replayed.lines();
+        let mut events = 0;
+        // The first line holds the arguments of the run.
+        for (line, recorded) in (2..).zip(recorded) {
+            match replayed.next() {
+                Some(replayed) if replayed == recorded => events += 1,
+                Some(replayed) => {
+                    let (recorded, replayed) = (describe(recorded), describe(replayed));
+                    return Err(SimulatorError::ReplayMismatch(if recorded == replayed {
+                        format!(
+                            "line {} of {} differs from the replayed {}",
+                            line, args.event_log, replayed
+                        )
+                    } else {
+                        format!(
+                            "line {} of {} records {} but the replay logs {}",
+                            line, args.event_log, recorded, replayed
+                        )
+                    }));
+                }
+                None => {
+                    return Err(SimulatorError::ReplayMismatch(format!(
+                        "the replay ends before line {} of {}",
+                        line, args.event_log
+                    )))
+                }
+            }
+        }
+        if let Some(replayed) = replayed.next() {
+            return Err(SimulatorError::ReplayMismatch(format!(
+                "the replay logs {} after the last event of {}",
+                describe(replayed),
+                args.event_log
+            )));
+        }
+        info!("The replay logs all {} recorded events", events);
+
+        if let Some(chains_dir) = &args.chains_dir {
+            for (miner, node) in outcome.miners.iter().zip(&outcome.nodes) {
+                let file_name = Path::new(chains_dir).join(format!("{}.json", miner.address));
+                let file_name = file_name.to_string_lossy();
+                // The chains are compared as JSON values since they may have
+                // been written either pretty-printed or in canonical form.
+                let recorded: serde_json::Value =
+                    serde_json::from_str(&read_file_contents(&file_name)?).map_err(|source| {
+                        SimulatorError::MalformedJson {
+                            file_name: file_name.to_string(),
+                            source,
+                        }
+                    })?;
+                let replayed = serde_json::to_value(&node.blockchain).map_err(|source| {
+                    SimulatorError::SerializationFailed {
+                        file_name: file_name.to_string(),
+                        source,
+                    }
+                })?;
+                if replayed != recorded {
+                    return Err(SimulatorError::ReplayMismatch(format!(
+                        "the chain of node {} differs from {}",
+                        miner.address, file_name
+                    )));
+                }
+            }
+            info!(
+                "The replayed chains of all {} nodes match the recorded ones",
+                outcome.nodes.len()
+            );
+        }
+        Ok(())
+    }
+
+    /// Kind and time of a logged event, pointing out where a replay diverges.
+    fn describe(line: &str) -> String {
+        let event: serde_json::Value = serde_json::from_str(line).unwrap_or_default();
+        format!(
+            "a {} event at {}s",
+            event["event"].as_str().unwrap_or("malformed"),
+            event["time"]
+        )
+    }
+
+    /// State of a running orchestration, which processes the events in
+    /// the order of their virtual time.
+    struct Orchestration<'a, 'w> {
+        args: &'a OrchestrateArgs,
+        context: &'a SimulatorContext,
+        miners: &'a [VirtualMiner],
+        nodes: Vec<SimulatedNode>,
+        events: EventQueue<Event>,
+        race: BlockRace<'a>,
+        rng: ChaCha20Rng,
+        /// Separate stream for the submissions, so that the same seed
+        /// produces the same block winners regardless of the transactions
+        submission_rng: ChaCha20Rng,
+        /// Separate stream for the crashes and the peers the restarted nodes
+        /// synchronise with
+        crash_rng: ChaCha20Rng,
+        /// Transactions of the mempool file yet to be submitted
+        submissions: std::vec::IntoIter<Transaction>,
+        /// Chain the nodes start from, and restart from if they lose their
+        /// chain when crashing
+        initial_chain: Blockchain,
+        event_log: Option<EventLog<'w>>,
+        start_timestamp: u64,
+        /// Seconds after which no more blocks are mined and no more
+        /// transactions submitted
+        end: f64,
+    }
+
+    impl Orchestration<'_, '_> {
+        /// Processes the events until the end of the simulated time, and then
+        /// the blocks and transactions still in flight.
+        fn run(&mut self) -> Result<(), SimulatorError> {
+            self.schedule_block()?;
+            self.schedule_submission();
+            for node in 0..self.nodes.len() {
+                self.schedule_crash(node, 0.0);
+            }
+            while let Some((time, event)) = self.events.pop() {
+                match event {
+                    Event::BlockFound(_)
+                    | Event::TransactionSubmitted { .. }
+                    | Event::NodeCrashes(_)
+                        if time > self.end => {}
+                    Event::BlockFound(winner) => {
+                        // The work of a crashed miner is lost.
+                        if self.nodes[winner].online {
+                            self.mine(winner, time)?;
+                        }
+                        self.schedule_block()?;
+                    }
+                    Event::BlockArrives { node, block } => {
+                        if self.nodes[node].online {
+                            let accepted = self.nodes[node].receive(block);
+                            self.log_accepted(node, &accepted, time)?;
+                            self.relay_blocks(node, accepted, time);
+                        }
+                    }
+                    Event::TransactionSubmitted { node, transaction } => {
+                        if self.nodes[node].online {
+                            self.receive_transaction(node, transaction, time);
+                        }
+                        self.schedule_submission();
+                    }
+                    Event::TransactionArrives { node, transaction } => {
+                        if self.nodes[node].online {
+                            self.receive_transaction(node, Transaction::clone(&transaction), time);
+                        }
+                    }
+                    Event::NodeCrashes(node) => {
+                        info!("Node {} crashes at {:.2}s", self.miners[node].address, time);
+                        let lost_chain = self.args.lose_chain_on_crash;
+                        self.nodes[node].crash(lost_chain.then_some(&self.initial_chain));
+                        self.log(TraceEvent::NodeCrashed {
+                            time,
+                            node: self.miners[node].address,
+                            lost_chain,
+                        })?;
+                        self.events
+                            .schedule(time + self.args.downtime, Event::NodeRestarts(node));
+                    }
+                    Event::NodeRestarts(node) => {
+                        self.log(TraceEvent::NodeRestarted {
+                            time,
+                            node: self.miners[node].address,
+                        })?;
+                        self.restart(node, time);
+                    }
+                    Event::NodeSynced { node, peer } => {
+                        if self.nodes[node].online && self.nodes[peer].online {
+                            let chain = self.nodes[peer].blockchain.clone();
+                            let mempool = self.nodes[peer].mempool.values().cloned().collect();
+                            self.nodes[node].sync_from(chain, mempool)?;
+                            self.log(TraceEvent::NodeSynced {
+                                time,
+                                node: self.miners[node].address,
+                                peer: self.miners[peer].address,
+                            })?;
+                            self.log_switches(node, time)?;
+                        }
+                        self.schedule_crash(node, time);
+                    }
+                }
+            }
+            Ok(())
+        }
+
+        /// Schedules the next crash of the node, the crashes of every node
+        /// happening as a Poisson process.
+        fn schedule_crash(&mut self, node: usize, time: f64) {
+            let Some(crash_rate) = self.args.crash_rate else {
+                return;
+            };
+            let waiting_time = -(1.0 - self.crash_rng.gen::<f64>()).ln() / (crash_rate / 3600.0);
+            self.events
+                .schedule(time + waiting_time, Event::NodeCrashes(node));
+        }
+
+        /// Brings the node back and asks a random running peer for its chain
+        /// and mempool, which arrive after a round trip.
+        fn restart(&mut self, node: usize, time: f64) {
+            info!(
+                "Node {} restarts at {:.2}s",
+                self.miners[node].address, time
+            );
+            self.nodes[node].online = true;
+            let peers: Vec<usize> = self
+                .peers(node)
+                .filter(|&peer| self.nodes[peer].online)
+                .collect();
+            if peers.is_empty() {
+                self.schedule_crash(node, time);
+                return;
+            }
+            let peer = peers[self.crash_rng.gen_range(0..peers.len())];
+            self.events.schedule(
+                time + 2.0 * self.args.latency,
+                Event::NodeSynced { node, peer },
+            );
+        }
+
+        /// Schedules the block found next by the miners of the fleet.
+        fn schedule_block(&mut self) -> Result<(), SimulatorError> {
+            let expected_attempts = 16f64.powi(self.args.difficulty as i32);
+            let winner = self.race.next_block(expected_attempts, &mut self.rng)?;
+            self.events
+                .schedule(self.race.time, Event::BlockFound(winner));
+            Ok(())
+        }
+
+        /// Schedules the submission of the next transaction to a random node,
+        /// the submissions arriving as a Poisson process.
+        fn schedule_submission(&mut self) {
+            let (Some(rate), Some(transaction)) =
+                (self.args.transaction_rate, self.submissions.next())
+            else {
+                return;
+            };
+            let waiting_time = -(1.0 - self.submission_rng.gen::<f64>()).ln() / rate;
+            let node = self.submission_rng.gen_range(0..self.nodes.len());
+            self.events.schedule(
+                self.events.now() + waiting_time,
+                Event::TransactionSubmitted { node, transaction },
+            );
+        }
+
+        /// Builds and seals a block on top of the node's tip.
+        fn mine(&mut self, winner: usize, time: f64) -> Result<(), SimulatorError> {
+            let node = &mut self.nodes[winner];
+            let previous_header = node.blockchain.tip_header()?;
+            let timestamp = (self.start_timestamp + time as u64).max(previous_header.timestamp + 1);
+            // Transactions whose lock time has passed can never be included,
+            // so only the others are copied.
+            let candidates = node
+                .mempool
+                .values()
+                .filter(|t| u64::from(t.lock_time) > timestamp);
+            let mut transactions = find_executable_transactions(
+                candidates.cloned().collect(),
+                timestamp,
+                TransactionSelection::Fee,
+                false,
+            );
+            transactions.truncate(self.args.block_capacity);
+            let block = BlockBuilder::on_top_of(previous_header)
+                .miner(self.miners[winner].address)
+                .timestamp(timestamp)
+                .difficulty(self.args.difficulty)
+                .transactions(transactions)
+                .format(self.context.header_format)
+                .build()
+                .mine(self.args.difficulty as usize, &self.context.nonce_search())?;
+            node.blocks_mined += 1;
+            let block = Rc::new(block);
+            let accepted = node.receive(Rc::clone(&block));
+            if self.event_log.is_some() {
+                let miner = self.miners[winner].address;
+                self.log(TraceEvent::BlockMined {
+                    time,
+                    node: miner,
+                    block: Block::clone(&block),
+                })?;
+                for transaction in block
+                    .transactions
+                    .iter()
+                    .filter(|t| t.sender != NULL_ADDRESS)
+                {
+                    self.log(TraceEvent::TransactionIncluded {
+                        time,
+                        node: miner,
+                        transaction: transaction.hash(),
+                        block: block.header.hash,
+                    })?;
+                }
+            }
+            self.log_accepted(winner, &accepted, time)?;
+            self.relay_blocks(winner, accepted, time);
+            Ok(())
+        }
+
+        /// Appends the event to the event log, if one is written.
+        fn log(&mut self, event: TraceEvent) -> Result<(), SimulatorError> {
+            match &mut self.event_log {
+                Some(log) => {
+                    write_json_line(&mut *log.writer, log.file_name, log.canonical, &event)
+                }
+                None => Ok(()),
+            }
+        }
+
+        /// Logs the blocks accepted by the node and the reorgs they caused.
+        fn log_accepted(
+            &mut self,
+            node: usize,
+            accepted: &[Rc<Block>],
+            time: f64,
+        ) -> Result<(), SimulatorError> {
+            for block in accepted {
+                self.log(TraceEvent::BlockAccepted {
+                    time,
+                    node: self.miners[node].address,
+                    hash: block.header.hash,
+                    height: block.header.height,
+                })?;
+            }
+            self.log_switches(node, time)
+        }
+
+        fn log_switches(&mut self, node: usize, time: f64) -> Result<(), SimulatorError> {
+            for switch in std::mem::take(&mut self.nodes[node].switches) {
+                self.log(TraceEvent::Reorg {
+                    time,
+                    node: self.miners[node].address,
+                    fork_height: switch.fork_height,
+                    reverted: switch.reverted,
+                    appended: switch.appended,
+                })?;
+            }
+            Ok(())
+        }
+
+        fn receive_transaction(&mut self, node: usize, transaction: Transaction, time: f64) {
+            if !self.nodes[node].submit(transaction.clone()) {
+                return;
+            }
+            let transaction = Rc::new(transaction);
+            for peer in self.peers(node) {
+                self.events.schedule(
+                    time + self.args.latency,
+                    Event::TransactionArrives {
+                        node: peer,
+                        transaction: Rc::clone(&transaction),
+                    },
+                );
+            }
+        }
+
+        fn relay_blocks(&mut self, from: usize, blocks: Vec<Rc<Block>>, time: f64) {
+            for block in blocks {
+                for peer in self.peers(from) {
+                    self.events.schedule(
+                        time + self.args.latency,
+                        Event::BlockArrives {
+                            node: peer,
+                            block: Rc::clone(&block),
+                        },
+                    );
+                }
+            }
+        }
+
+        /// The nodes are fully connected.
+        fn peers(&self, node: usize) -> impl Iterator<Item = usize> {
+            (0..self.nodes.len()).filter(move |&peer| peer != node)
+        }
+    }
+
+    fn report(
+        args: &OrchestrateArgs,
+        miners: &[VirtualMiner],
+        nodes: &[SimulatedNode],
+        start_height: u32,
+    ) -> Result<OrchestrationReport, SimulatorError> {
+        // The best chain is the one with the most work, the chain of the
+        // first such node if several have the same work.
+        let best = nodes
+            .iter()
+            .rev()
+            .max_by_key(|node| node.blockchain.total_work())
+            .unwrap();
+        let best_tip = best.blockchain.tip_header()?;
+        let new_blocks: Vec<&Block> = best
+            .blockchain
+            .iter()
+            .filter(|block| block.header.height > start_height)
+            .collect();
+        let blocks_mined: u32 = nodes.iter().map(|node| node.blocks_mined).sum();
+        let mut rows = vec![];
+        for (miner, node) in miners.iter().zip(nodes) {
+            let tip = node.blockchain.tip_header()?;
+            rows.push(NodeReportRow {
+                node: miner.address,
+                hashrate: miner.hashrate,
+                height: tip.height,
+                tip: tip.hash,
+                blocks_mined: node.blocks_mined,
+                blocks_in_best_chain: new_blocks
+                    .iter()
+                    .filter(|block| block.header.miner == miner.address)
+                    .count() as u32,
+                reorgs: node.reorgs,
+                reverted_blocks: node.reverted_blocks,
+                crashes: node.crashes,
+                mempool_transactions: node.mempool.len(),
+            });
+        }
+        let mean_block_interval = match (new_blocks.first(), new_blocks.last()) {
+            (Some(first), Some(last)) if new_blocks.len() > 1 => {
+                (last.header.timestamp - first.header.timestamp) as f64
+                    / (new_blocks.len() - 1) as f64
+            }
+            _ => 0.0,
+        };
+        let stale_blocks = blocks_mined - new_blocks.len() as u32;
+        Ok(OrchestrationReport {
+            virtual_hours: args.hours,
+            blocks_mined,
+            best_height: best_tip.height,
+            stale_blocks,
+            stale_rate: if blocks_mined > 0 {
+                stale_blocks as f64 / blocks_mined as f64
+            } else {
+                0.0
+            },
+            mean_block_interval,
+            confirmed_transactions: new_blocks
+                .iter()
+                .flat_map(|block| &block.transactions)
+                .filter(|t| t.sender != NULL_ADDRESS)
+                .count(),
+            nodes_on_best_tip: rows.iter().filter(|row| row.tip == best_tip.hash).count(),
+            nodes: rows,
+        })
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use std::rc::Rc;
+
+        use crate::{
+            config::config::SimulatorContext,
+            hashing::hashing::Hashable,
+            model::blockchain::{Address, Amount, Block, Blockchain, Header, Transaction},
+            node::miner::BlockBuilder,
+        };
+
+        use super::{describe, SimulatedNode};
+
+        /// Block on top of the header mined by the miner at difficulty 0.
+        fn mine_on(previous: &Header, miner: u8, transactions: Vec<Transaction>) -> Rc<Block> {
+            let block = BlockBuilder::on_top_of(previous)
+                .difficulty(0)
+                .miner(Address([miner; 20]))
+                .timestamp(previous.timestamp + 1)
+                .transactions(transactions)
+                .build()
+                .mine(0, &SimulatorContext::default().nonce_search())
+                .unwrap();
+            Rc::new(block)
+        }
+
+        #[test]
+        fn node_reorganises_to_the_heavier_branch() {
+            let context = SimulatorContext::default();
+            let genesis = BlockBuilder::genesis()
+                .difficulty(0)
+                .build()
+                .mine(0, &context.nonce_search())
+                .unwrap();
+            let transaction = Transaction::builder()
+                .sender(Address([1; 20]))
+                .receiver(Address([2; 20]))
+                .amount(Amount(1))
+                .build()
+                .unwrap();
+            let first = mine_on(&genesis.header, 1, vec![transaction.clone()]);
+            let heavier_parent = mine_on(&genesis.header, 2, vec![]);
+            let heavier = mine_on(&heavier_parent.header, 2, vec![]);
+            let mut node = SimulatedNode::new(
+                Blockchain::from(vec![genesis]),
+                vec![transaction.clone()],
+                &[],
+                &[],
+                context.header_format,
+            );
+
+            assert_eq!(node.receive(Rc::clone(&first)).len(), 1);
+            assert!(node.mempool.is_empty());
+            // The tip of the heavier branch arrives before its parent.
+            assert!(node.receive(Rc::clone(&heavier)).is_empty());
+            assert_eq!(node.receive(heavier_parent).len(), 2);
+
+            assert_eq!(
+                node.blockchain.tip_header().unwrap().hash,
+                heavier.header.hash
+            );
+            assert_eq!((node.reorgs, node.reverted_blocks), (1, 1));
+            assert!(node.side_blocks.contains_key(&first.header.hash));
+            assert!(node.mempool.contains_key(&transaction.hash()));
+        }
+
+        #[test]
+        fn divergence_names_the_event_and_its_time() {
+            assert_eq!(
+                describe(r#"{"event":"block_mined","time":1.5}"#),
+                "a block_mined event at 1.5s"
+            );
+            assert!(describe("{").starts_with("a malformed event"));
+        }
+    }
+}
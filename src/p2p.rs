@@ -0,0 +1,921 @@
+// This module runs a node exchanging blocks and transactions with other
+// simulator processes over TCP
+pub mod p2p {
+    use std::{
+        collections::{HashMap, HashSet, VecDeque},
+        io::{ErrorKind, Read, Write},
+        net::{TcpListener, TcpStream},
+        sync::{
+            atomic::{AtomicU64, Ordering},
+            mpsc::{channel, Sender},
+            Arc, Mutex,
+        },
+        thread,
+        time::{Duration, Instant},
+    };
+
+    use clap::ValueEnum;
+    use log::{debug, info, warn};
+    use serde::{Deserialize, Serialize};
+
+    use crate::{
+        args::args::RunNodeArgs,
+        config::config::SimulatorContext,
+        dashboard::dashboard::serve_dashboard,
+        data_sourcing::data_provider::{load_blockchain, load_transactions, write_blockchain},
+        error::error::SimulatorError,
+        hashing::hashing::Hashable,
+        model::blockchain::{Block, Blockchain, Hash256, Header, Transaction},
+        node::{
+            miner::{find_executable_transactions, BlockBuilder, TransactionSelection},
+            validator::check_block,
+        },
+        sync::sync::{
+            blocks_by_hash, headers_after, locator, update_mempool, BlockSource, ChainSync,
+            SyncOutcome, MAX_BLOCKS,
+        },
+        upgrades::upgrades::RuleTracker,
+    };
+
+    #[cfg(feature = "libp2p")]
+    use crate::gossip::gossip::start_gossip;
+
+    /// Transport over which the node exchanges messages with its peers.
+    #[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+    pub enum Transport {
+        /// Length-prefixed messages over TCP connections to the given peers
+        Tcp,
+        /// Gossipsub topics over libp2p, with the given peers used to
+        /// bootstrap and optionally mDNS to discover peers on the local network
+        #[cfg(feature = "libp2p")]
+        Libp2p,
+    }
+
+    /// Messages larger than this are treated as a protocol violation.
+    pub const MAX_MESSAGE_SIZE: u32 = 32 * 1024 * 1024;
+
+    /// How long `SyncFrom` waits for a response before giving up on the peer.
+    const RESPONSE_TIMEOUT: Duration = Duration::from_secs(30);
+
+    /// Message of the wire protocol. Every message is sent as its JSON
+    /// encoding prefixed with the length of the encoding as a big-endian u32.
+    #[derive(Serialize, Deserialize, Clone, Debug)]
+    #[serde(tag = "type", content = "payload", rename_all = "snake_case")]
+    pub enum Message {
+        Block(Block),
+        Transaction(Transaction),
+        /// Header of the tip of the sender's chain, announced when connecting
+        Tip(Header),
+        /// Request for the headers following the first block of the locator
+        /// the receiver knows
+        GetHeaders {
+            locator: Vec<Hash256>,
+        },
+        Headers(Vec<Header>),
+        /// Request for the blocks with the given hashes, answered with
+        /// messages of at most `MAX_BLOCKS` blocks and ending with one of
+        /// fewer blocks, which is empty if necessary
+        GetBlocks(Vec<Hash256>),
+        Blocks(Vec<Block>),
+    }
+
+    pub fn write_message(stream: &mut TcpStream, message: &Message) -> std::io::Result<()> {
+        let encoded = serde_json::to_vec(message)?;
+        stream.write_all(&(encoded.len() as u32).to_be_bytes())?;
+        stream.write_all(&encoded)
+    }
+
+    /// Reads the next message, or none if the peer closed the connection.
+    pub fn read_message(stream: &mut TcpStream) -> std::io::Result<Option<Message>> {
+        let mut length = [0u8; 4];
+        match stream.read_exact(&mut length) {
+            Err(error) if error.kind() == ErrorKind::UnexpectedEof => return Ok(None),
+            result => result?,
+        }
+        let length = u32::from_be_bytes(length);
+        if length > MAX_MESSAGE_SIZE {
+            return Err(std::io::Error::new(
+                ErrorKind::InvalidData,
+                format!("the message of {} bytes is too large", length),
+            ));
+        }
+        let mut encoded = vec![0u8; length as usize];
+        stream.read_exact(&mut encoded)?;
+        Ok(Some(serde_json::from_slice(&encoded)?))
+    }
+
+    /// Chain and mempool of the node.
+    struct NodeState {
+        blockchain: Blockchain,
+        tracker: RuleTracker,
+        mempool: Vec<Transaction>,
+        /// Hashes of the transactions the node has seen, so that each one is
+        /// only relayed once.
+        known_transactions: HashSet<Hash256>,
+    }
+
+    /// Connected peer, whose messages are queued for the thread writing to
+    /// the connection so that relaying never blocks on a slow peer.
+    struct Peer {
+        id: usize,
+        outbox: Sender<Arc<Message>>,
+    }
+
+    /// Node shared by the miner and the threads serving the connections.
+    pub struct Node {
+        state: Mutex<NodeState>,
+        peers: Mutex<Vec<Peer>>,
+        /// Synchronisations in progress, by the peer they are with
+        syncs: Mutex<HashMap<usize, ChainSync>>,
+        next_peer_id: AtomicU64,
+        /// Incremented whenever the tip changes, so that the miner abandons
+        /// the block it is working on.
+        tip_changes: AtomicU64,
+        blockchain_state_output: String,
+        context: SimulatorContext,
+        /// Time at which the node started
+        started: Instant,
+        /// Arrival times of the recent tips with the expected number of
+        /// hashes it took to mine them, from which the hashrate is estimated
+        recent_tips: Mutex<VecDeque<(Instant, f64)>>,
+    }
+
+    /// Window over which the hashrate of the network is estimated.
+    const HASHRATE_WINDOW: Duration = Duration::from_secs(60);
+
+    /// Summary of a block shown by the dashboard.
+    #[derive(Serialize, Debug, Clone)]
+    pub struct BlockSummary {
+        pub height: u32,
+        pub hash: String,
+        pub miner: String,
+        pub timestamp: u64,
+        pub transactions: usize,
+    }
+
+    /// State of the node streamed to the dashboard.
+    #[derive(Serialize, Debug, Clone)]
+    pub struct NodeStatus {
+        pub height: u32,
+        pub tip: String,
+        /// The most recent blocks, newest first
+        pub recent_blocks: Vec<BlockSummary>,
+        pub mempool: usize,
+        pub peers: usize,
+        /// Hashes per second needed to mine the tips accepted over the last
+        /// minute, a valid hash takes 16^difficulty attempts on average
+        pub hashrate: f64,
+    }
+
+    impl Node {
+        /// Registers the connection and serves it on new threads reading and
+        /// writing the messages.
+        fn connect(self: &Arc<Self>, stream: TcpStream, address: String) {
+            let mut writer = match stream.try_clone() {
+                Ok(writer) => writer,
+                Err(error) => {
+                    warn!("Unable to use the connection to {}: {}", address, error);
+                    return;
+                }
+            };
+            let (outbox, inbox) = channel::<Arc<Message>>();
+            let id = self.register(outbox);
+            info!("Connected to the peer {}", address);
+
+            let peer = address.clone();
+            thread::spawn(move || {
+                for message in inbox {
+                    if let Err(error) = write_message(&mut writer, &message) {
+                        warn!("Unable to send to the peer {}: {}", peer, error);
+                        break;
+                    }
+                }
+            });
+            let node = Arc::clone(self);
+            thread::spawn(move || node.serve(id, stream, address));
+        }
+
+        /// Adds a peer receiving the messages queued in the outbox and returns
+        /// its id. The peer is first announced the tip and the mempool, so
+        /// that a peer joining late can synchronise. The peer is registered
+        /// under the same lock, so it also receives every block accepted
+        /// afterwards.
+        pub fn register(&self, outbox: Sender<Arc<Message>>) -> usize {
+            let id = self.next_peer_id.fetch_add(1, Ordering::SeqCst) as usize;
+            let state = self.state.lock().unwrap();
+            let tip = state
+                .blockchain
+                .tip_header()
+                .ok()
+                .cloned()
+                .map(Message::Tip);
+            let transactions = state.mempool.iter().cloned().map(Message::Transaction);
+            for message in tip.into_iter().chain(transactions) {
+                let _ = outbox.send(Arc::new(message));
+            }
+            self.peers.lock().unwrap().push(Peer { id, outbox });
+            id
+        }
+
+        fn send_to(&self, id: usize, message: Message) {
+            let peers = self.peers.lock().unwrap();
+            if let Some(peer) = peers.iter().find(|peer| peer.id == id) {
+                let _ = peer.outbox.send(Arc::new(message));
+            }
+        }
+
+        pub fn handle(&self, id: usize, message: Message) {
+            match message {
+                Message::Block(block) => self.receive_block(block, Some(id)),
+                Message::Transaction(transaction) => self.receive_transaction(transaction, id),
+                Message::Tip(header) => {
+                    let known = {
+                        let state = self.state.lock().unwrap();
+                        state.blockchain.get_by_hash(&header.hash).is_some()
+                    };
+                    if !known {
+                        self.start_sync(id);
+                    }
+                }
+                Message::GetHeaders { .. } | Message::GetBlocks(_) => {
+                    for response in self.answer(&message) {
+                        self.send_to(id, response);
+                    }
+                }
+                Message::Headers(headers) => self.receive_headers(id, headers),
+                Message::Blocks(blocks) => self.receive_blocks(id, blocks),
+            }
+        }
+
+        /// Returns the messages answering a request for headers or blocks,
+        /// or none if the message isn't a request.
+        pub fn answer(&self, request: &Message) -> Vec<Message> {
+            let state = self.state.lock().unwrap();
+            match request {
+                Message::GetHeaders { locator } => {
+                    vec![Message::Headers(headers_after(&state.blockchain, locator))]
+                }
+                Message::GetBlocks(hashes) => {
+                    let blocks = blocks_by_hash(&state.blockchain, hashes);
+                    let mut responses: Vec<Message> = blocks
+                        .chunks(MAX_BLOCKS)
+                        .map(|blocks| Message::Blocks(blocks.to_vec()))
+                        .collect();
+                    if blocks.len().is_multiple_of(MAX_BLOCKS) {
+                        responses.push(Message::Blocks(vec![]));
+                    }
+                    responses
+                }
+                _ => vec![],
+            }
+        }
+
+        /// Asks the peer for the headers of its chain, unless a
+        /// synchronisation with it is already in progress.
+        fn start_sync(&self, id: usize) {
+            let mut syncs = self.syncs.lock().unwrap();
+            if syncs.contains_key(&id) {
+                return;
+            }
+            let locator = locator(&self.state.lock().unwrap().blockchain);
+            syncs.insert(
+                id,
+                ChainSync::new(&self.context.checkpoints, self.context.header_format),
+            );
+            drop(syncs);
+            debug!("Synchronising with the peer {}", id);
+            self.send_to(id, Message::GetHeaders { locator });
+        }
+
+        /// Continues the synchronisation by asking for more headers or, once
+        /// all are received, for the blocks of a heavier branch.
+        fn receive_headers(&self, id: usize, headers: Vec<Header>) {
+            let mut syncs = self.syncs.lock().unwrap();
+            let Some(sync) = syncs.get_mut(&id) else {
+                return;
+            };
+            let state = self.state.lock().unwrap();
+            let request = match sync.add_headers(&state.blockchain, headers) {
+                Ok(true) => Message::GetHeaders {
+                    locator: sync.next_locator(&state.blockchain),
+                },
+                Ok(false) => {
+                    let wanted = sync.wanted_blocks(&state.blockchain);
+                    if wanted.is_empty() {
+                        syncs.remove(&id);
+                        return;
+                    }
+                    Message::GetBlocks(wanted)
+                }
+                Err(error) => {
+                    warn!(
+                        "Stopping the synchronisation with the peer {}: {}",
+                        id, error
+                    );
+                    syncs.remove(&id);
+                    return;
+                }
+            };
+            drop(state);
+            drop(syncs);
+            self.send_to(id, request);
+        }
+
+        /// Switches to the downloaded branch once all its blocks arrived. If
+        /// the reply ends before that, the peer switched to another branch
+        /// since sending its headers, so the synchronisation starts over.
+        fn receive_blocks(&self, id: usize, blocks: Vec<Block>) {
+            let mut syncs = self.syncs.lock().unwrap();
+            let Some(sync) = syncs.get_mut(&id) else {
+                return;
+            };
+            let last = blocks.len() < MAX_BLOCKS;
+            let mut state = self.state.lock().unwrap();
+            if !sync.add_blocks(&state.blockchain, blocks) {
+                if last {
+                    syncs.remove(&id);
+                    drop(state);
+                    drop(syncs);
+                    warn!(
+                        "The peer {} no longer has its whole branch, synchronising again",
+                        id
+                    );
+                    self.start_sync(id);
+                }
+                return;
+            }
+            let sync = syncs.remove(&id).unwrap();
+            drop(syncs);
+            let current = &mut *state;
+            let outcome = match sync.apply(&mut current.blockchain, &mut current.tracker) {
+                Ok(outcome) => outcome,
+                Err(error) => {
+                    warn!("Rejected the branch of the peer {}: {}", id, error);
+                    return;
+                }
+            };
+            info!(
+                "Synchronised with the peer {}, reverting {} blocks and appending {} blocks",
+                id,
+                outcome.reverted.len(),
+                outcome.appended.len()
+            );
+            self.adopt(current, &outcome);
+            let tip = outcome.appended.last().cloned();
+            drop(state);
+            if let Some(tip) = tip {
+                self.broadcast(Message::Block(tip), Some(id));
+            }
+        }
+
+        /// Updates the mempool after the chain switched to another branch and
+        /// signals the miner.
+        fn adopt(&self, state: &mut NodeState, outcome: &SyncOutcome) {
+            let included = update_mempool(&mut state.mempool, outcome);
+            state.known_transactions.extend(included);
+            self.tip_changes.fetch_add(1, Ordering::SeqCst);
+            for block in &outcome.appended {
+                self.record_tip(&block.header);
+            }
+            if let Ok(tip) = state.blockchain.tip_header() {
+                info!("New tip at height {} with hash {}", tip.height, tip.hash);
+            }
+            self.store(&state.blockchain);
+        }
+
+        /// Records the arrival of the new tip for the hashrate estimate.
+        fn record_tip(&self, header: &Header) {
+            let now = Instant::now();
+            let mut recent_tips = self.recent_tips.lock().unwrap();
+            recent_tips.push_back((now, 16f64.powi(header.difficulty as i32)));
+            while recent_tips
+                .front()
+                .is_some_and(|&(arrival, _)| now - arrival > HASHRATE_WINDOW)
+            {
+                recent_tips.pop_front();
+            }
+        }
+
+        /// Current state of the node with the given number of recent blocks.
+        pub fn status(&self, recent_blocks: usize) -> NodeStatus {
+            let state = self.state.lock().unwrap();
+            let tip = state.blockchain.tip_header().ok();
+            let now = Instant::now();
+            let window = (now - self.started).min(HASHRATE_WINDOW).as_secs_f64();
+            let hashes: f64 = self
+                .recent_tips
+                .lock()
+                .unwrap()
+                .iter()
+                .filter(|&&(arrival, _)| now - arrival <= HASHRATE_WINDOW)
+                .map(|&(_, hashes)| hashes)
+                .sum();
+            NodeStatus {
+                height: tip.map_or(0, |tip| tip.height),
+                tip: tip.map_or(String::new(), |tip| tip.hash.to_string()),
+                recent_blocks: state
+                    .blockchain
+                    .blocks()
+                    .iter()
+                    .rev()
+                    .take(recent_blocks)
+                    .map(|block| BlockSummary {
+                        height: block.header.height,
+                        hash: block.header.hash.to_string(),
+                        miner: block.header.miner.to_string(),
+                        timestamp: block.header.timestamp,
+                        transactions: block.transactions.len(),
+                    })
+                    .collect(),
+                mempool: state.mempool.len(),
+                peers: self.peers.lock().unwrap().len(),
+                hashrate: if window > 0.0 { hashes / window } else { 0.0 },
+            }
+        }
+
+        fn store(&self, blockchain: &Blockchain) {
+            let output = &self.blockchain_state_output;
+            if let Err(error) = write_blockchain(
+                output,
+                self.context.output,
+                self.context.data_format,
+                blockchain,
+            ) {
+                warn!("Unable to store the blockchain: {}", error);
+            }
+        }
+
+        /// Drops the synchronisation with the peer, for example because it
+        /// disconnected or its request could not be delivered.
+        pub fn abandon_sync(&self, id: usize) {
+            if self.syncs.lock().unwrap().remove(&id).is_some() {
+                debug!("Abandoned the synchronisation with the peer {}", id);
+            }
+        }
+
+        fn serve(&self, id: usize, mut stream: TcpStream, address: String) {
+            loop {
+                match read_message(&mut stream) {
+                    Ok(Some(message)) => self.handle(id, message),
+                    Ok(None) => break,
+                    Err(error) => {
+                        warn!("Dropping the peer {}: {}", address, error);
+                        break;
+                    }
+                }
+            }
+            info!("Disconnected from the peer {}", address);
+            self.peers.lock().unwrap().retain(|peer| peer.id != id);
+            self.abandon_sync(id);
+        }
+
+        /// Appends the block if it extends the tip and relays it to the other
+        /// peers. A block that doesn't extend the tip may belong to a heavier
+        /// branch, so the node synchronises with the peer that sent it.
+        pub fn receive_block(&self, block: Block, from: Option<usize>) {
+            {
+                let mut state = self.state.lock().unwrap();
+                if state.blockchain.get_by_hash(&block.header.hash).is_some() {
+                    return;
+                }
+                let tip = state.blockchain.tip_header().ok().cloned();
+                if tip
+                    .as_ref()
+                    .is_some_and(|tip| block.header.previous_block_header_hash != tip.hash)
+                {
+                    debug!(
+                        "Block {} doesn't extend the tip, synchronising",
+                        block.header.hash
+                    );
+                    drop(state);
+                    if let Some(from) = from {
+                        self.start_sync(from);
+                    }
+                    return;
+                }
+                let result = check_block(&block, self.context.header_format)
+                    .and_then(|_| state.tracker.check_rules(&block, tip.as_ref()))
+                    .and_then(|_| {
+                        let context = &self.context;
+                        state.blockchain.append(
+                            block.clone(),
+                            context.header_format,
+                            &context.checkpoints,
+                        )
+                    });
+                if let Err(error) = result {
+                    warn!("Rejected block {}: {}", block.header.hash, error);
+                    return;
+                }
+                state.tracker.observe(&block.header);
+                let included: HashSet<Hash256> =
+                    block.transactions.iter().map(|t| t.hash()).collect();
+                state.mempool.retain(|t| !included.contains(&t.hash()));
+                state.known_transactions.extend(included);
+                self.tip_changes.fetch_add(1, Ordering::SeqCst);
+                self.record_tip(&block.header);
+                info!(
+                    "New tip at height {} with hash {}",
+                    block.header.height, block.header.hash
+                );
+                self.store(&state.blockchain);
+            }
+            self.broadcast(Message::Block(block), from);
+        }
+
+        pub fn receive_transaction(&self, transaction: Transaction, from: usize) {
+            {
+                let mut state = self.state.lock().unwrap();
+                if !state.known_transactions.insert(transaction.hash()) {
+                    return;
+                }
+                debug!("Received the transaction {}", transaction.hash());
+                state.mempool.push(transaction.clone());
+            }
+            self.broadcast(Message::Transaction(transaction), Some(from));
+        }
+
+        /// Queues the message for all peers except the one it came from,
+        /// dropping the peers whose connections are closed.
+        fn broadcast(&self, message: Message, from: Option<usize>) {
+            let message = Arc::new(message);
+            self.peers.lock().unwrap().retain(|peer| {
+                Some(peer.id) == from || peer.outbox.send(Arc::clone(&message)).is_ok()
+            });
+        }
+    }
+
+    /// Runs a node that listens for peers on a TCP port, connects to the
+    /// configured peers and relays the blocks and transactions it learns
+    /// about, or gossips them over libp2p if that transport is selected. If
+    /// a miner address is given, the node also mines on top of its tip,
+    /// restarting whenever a peer's block extends the tip first.
+    pub fn run_node(args: RunNodeArgs, context: &SimulatorContext) -> Result<(), SimulatorError> {
+        info!("Loading the blockchain from {}", args.blockchain_state);
+        let blockchain = load_blockchain(&args.blockchain_state, context)?;
+        let mempool = match &args.mempool {
+            Some(mempool) => {
+                info!("Loading the mempool from {}", mempool);
+                load_transactions(mempool)?
+            }
+            None => vec![],
+        };
+        let mut tracker = RuleTracker::new(&context.deployments);
+        for block in &blockchain {
+            tracker.observe(&block.header);
+        }
+        let node = Arc::new(Node {
+            state: Mutex::new(NodeState {
+                known_transactions: mempool.iter().map(|t| t.hash()).collect(),
+                blockchain,
+                tracker,
+                mempool,
+            }),
+            peers: Mutex::new(vec![]),
+            syncs: Mutex::new(HashMap::new()),
+            next_peer_id: AtomicU64::new(0),
+            tip_changes: AtomicU64::new(0),
+            blockchain_state_output: args.blockchain_state_output.clone(),
+            context: context.clone(),
+            started: Instant::now(),
+            recent_tips: Mutex::new(VecDeque::new()),
+        });
+        if let Some(dashboard) = &args.dashboard {
+            serve_dashboard(Arc::clone(&node), dashboard)?;
+        }
+
+        match args.transport {
+            Transport::Tcp => start_tcp(&node, &args)?,
+            #[cfg(feature = "libp2p")]
+            Transport::Libp2p => {
+                start_gossip(Arc::clone(&node), &args.listen, &args.peers, args.mdns)?
+            }
+        }
+
+        let deadline = args
+            .duration
+            .map(|duration| Instant::now() + Duration::from_secs_f64(duration));
+        let expired = || deadline.is_some_and(|deadline| Instant::now() >= deadline);
+        let mut mined = 0;
+        while !expired() && args.blocks_to_mine.is_none_or(|blocks| mined < blocks) {
+            let Some(miner) = args.miner else {
+                thread::sleep(Duration::from_millis(100));
+                continue;
+            };
+            let tip_changes = node.tip_changes.load(Ordering::SeqCst);
+            let template = {
+                let state = node.state.lock().unwrap();
+                let previous_header = state.blockchain.tip_header()?;
+                let timestamp = previous_header.timestamp + args.block_interval;
+                // Only the transactions whose lock time hasn't passed are
+                // copied out of the mempool.
+                let candidates = state
+                    .mempool
+                    .iter()
+                    .filter(|t| u64::from(t.lock_time) > timestamp);
+                let mut transactions = find_executable_transactions(
+                    candidates.cloned().collect(),
+                    timestamp,
+                    TransactionSelection::Fee,
+                    false,
+                );
+                transactions.truncate(args.block_capacity);
+                let mut builder = BlockBuilder::on_top_of(previous_header)
+                    .miner(miner)
+                    .timestamp(timestamp)
+                    .transactions(transactions)
+                    .format(context.header_format);
+                if let Some(difficulty) = args.difficulty {
+                    builder = builder.difficulty(difficulty);
+                }
+                builder.build()
+            };
+            let pow_difficulty = template.header.difficulty as usize;
+            let search = context.nonce_search();
+            let block = template.mine_until(pow_difficulty, &search, || {
+                node.tip_changes.load(Ordering::SeqCst) != tip_changes || expired()
+            })?;
+            if let Some(block) = block {
+                info!("Mined block {}", block.header.height);
+                node.receive_block(block, None);
+                mined += 1;
+            }
+        }
+
+        let state = node.state.lock().unwrap();
+        info!(
+            "Stopping the node at height {}",
+            state.blockchain.tip_header()?.height
+        );
+        write_blockchain(
+            &args.blockchain_state_output,
+            context.output,
+            context.data_format,
+            &state.blockchain,
+        )
+    }
+
+    /// Accepts the connections of peers and dials the configured peers.
+    fn start_tcp(node: &Arc<Node>, args: &RunNodeArgs) -> Result<(), SimulatorError> {
+        let listener =
+            TcpListener::bind(&args.listen).map_err(|source| SimulatorError::PeerConnection {
+                peer: args.listen.clone(),
+                source,
+            })?;
+        info!("Listening for peers on {}", args.listen);
+        let acceptor = Arc::clone(node);
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                match stream {
+                    Ok(stream) => {
+                        let address = stream
+                            .peer_addr()
+                            .map_or("unknown".to_string(), |a| a.to_string());
+                        acceptor.connect(stream, address);
+                    }
+                    Err(error) => warn!("Unable to accept a peer: {}", error),
+                }
+            }
+        });
+        for peer in &args.peers {
+            let node = Arc::clone(node);
+            let peer = peer.clone();
+            thread::spawn(move || dial(node, peer));
+        }
+        Ok(())
+    }
+
+    /// Running node the chain is synchronised from by `SyncFrom`, which
+    /// requests the headers and blocks over a TCP connection.
+    pub struct RemotePeer {
+        address: String,
+        stream: TcpStream,
+    }
+
+    impl RemotePeer {
+        pub fn connect(address: &str) -> Result<Self, SimulatorError> {
+            let failed = |source| SimulatorError::PeerConnection {
+                peer: address.to_string(),
+                source,
+            };
+            let stream = TcpStream::connect(address).map_err(failed)?;
+            stream
+                .set_read_timeout(Some(RESPONSE_TIMEOUT))
+                .map_err(failed)?;
+            Ok(RemotePeer {
+                address: address.to_string(),
+                stream,
+            })
+        }
+
+        /// Sends the request and waits for the first response it accepts,
+        /// skipping the announcements the node sends in the meantime.
+        fn request<T>(
+            &mut self,
+            request: Message,
+            mut response: impl FnMut(Message) -> Option<T>,
+        ) -> Result<T, SimulatorError> {
+            let failed = |source| SimulatorError::PeerConnection {
+                peer: self.address.clone(),
+                source,
+            };
+            write_message(&mut self.stream, &request).map_err(failed)?;
+            loop {
+                match read_message(&mut self.stream).map_err(failed)? {
+                    Some(message) => {
+                        if let Some(result) = response(message) {
+                            return Ok(result);
+                        }
+                    }
+                    None => return Err(failed(ErrorKind::UnexpectedEof.into())),
+                }
+            }
+        }
+    }
+
+    impl BlockSource for RemotePeer {
+        fn headers(&mut self, locator: &[Hash256]) -> Result<Vec<Header>, SimulatorError> {
+            let request = Message::GetHeaders {
+                locator: locator.to_vec(),
+            };
+            self.request(request, |message| match message {
+                Message::Headers(headers) => Some(headers),
+                _ => None,
+            })
+        }
+
+        fn blocks(&mut self, hashes: &[Hash256]) -> Result<Vec<Block>, SimulatorError> {
+            // The blocks may arrive split over several messages, the last of
+            // which holds fewer than MAX_BLOCKS blocks.
+            let mut blocks = vec![];
+            self.request(
+                Message::GetBlocks(hashes.to_vec()),
+                |message| match message {
+                    Message::Blocks(received) => {
+                        let last = received.len() < MAX_BLOCKS;
+                        blocks.extend(received);
+                        last.then_some(())
+                    }
+                    _ => None,
+                },
+            )?;
+            if blocks.len() < hashes.len() {
+                return Err(SimulatorError::PeerConnection {
+                    peer: self.address.clone(),
+                    source: std::io::Error::new(
+                        ErrorKind::InvalidData,
+                        format!(
+                            "only {} of the {} requested blocks were sent",
+                            blocks.len(),
+                            hashes.len()
+                        ),
+                    ),
+                });
+            }
+            Ok(blocks)
+        }
+    }
+
+    /// Connects to the peer, retrying for a while as the peer may not have
+    /// started listening yet.
+    fn dial(node: Arc<Node>, peer: String) {
+        for _ in 0..30 {
+            match TcpStream::connect(&peer) {
+                Ok(stream) => return node.connect(stream, peer),
+                Err(error) => debug!("Unable to reach the peer {}: {}", peer, error),
+            }
+            thread::sleep(Duration::from_secs(1));
+        }
+        warn!("Giving up on connecting to the peer {}", peer);
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use std::{
+            collections::{HashMap, HashSet, VecDeque},
+            env, fs, process,
+            sync::{
+                atomic::AtomicU64,
+                mpsc::{channel, Receiver},
+                Arc, Mutex,
+            },
+            time::Instant,
+        };
+
+        use crate::{
+            config::config::SimulatorContext,
+            model::blockchain::{Address, Block, Blockchain},
+            node::miner::BlockBuilder,
+            sync::sync::{blocks_by_hash, headers_after, MAX_BLOCKS},
+            upgrades::upgrades::RuleTracker,
+        };
+
+        use super::{Message, Node, NodeState};
+
+        /// The blocks followed by empty blocks mined by the miner at
+        /// difficulty 0.
+        fn extend(blocks: &[Block], count: usize, miner: u8) -> Vec<Block> {
+            let context = SimulatorContext::default();
+            let mut blocks = blocks.to_vec();
+            for _ in 0..count {
+                let builder = match blocks.last() {
+                    Some(previous) => BlockBuilder::on_top_of(&previous.header),
+                    None => BlockBuilder::genesis(),
+                };
+                let block = builder
+                    .difficulty(0)
+                    .miner(Address([miner; 20]))
+                    .build()
+                    .mine(0, &context.nonce_search())
+                    .unwrap();
+                blocks.push(block);
+            }
+            blocks
+        }
+
+        /// Node without a miner storing its chain to the file.
+        fn node(blockchain: Blockchain, blockchain_state_output: &str) -> Node {
+            Node {
+                state: Mutex::new(NodeState {
+                    blockchain,
+                    tracker: RuleTracker::new(&[]),
+                    mempool: vec![],
+                    known_transactions: HashSet::new(),
+                }),
+                peers: Mutex::new(vec![]),
+                syncs: Mutex::new(HashMap::new()),
+                next_peer_id: AtomicU64::new(0),
+                tip_changes: AtomicU64::new(0),
+                blockchain_state_output: blockchain_state_output.to_string(),
+                context: SimulatorContext::default(),
+                started: Instant::now(),
+                recent_tips: Mutex::new(VecDeque::new()),
+            }
+        }
+
+        fn next_message(inbox: &Receiver<Arc<Message>>) -> Message {
+            Message::clone(&inbox.try_recv().expect("a message was sent to the peer"))
+        }
+
+        #[test]
+        fn blocks_reply_ends_with_a_short_message() {
+            let chain = Blockchain::from(extend(&[], MAX_BLOCKS, 1));
+            let hashes: Vec<_> = chain.iter().map(|block| block.header.hash).collect();
+            let node = node(chain, "unused.json");
+            let sizes: Vec<usize> = node
+                .answer(&Message::GetBlocks(hashes))
+                .iter()
+                .map(|message| match message {
+                    Message::Blocks(blocks) => blocks.len(),
+                    other => panic!("unexpected {:?}", other),
+                })
+                .collect();
+            assert_eq!(sizes, [MAX_BLOCKS, 0]);
+        }
+
+        #[test]
+        fn short_blocks_reply_restarts_the_synchronisation() {
+            let genesis = extend(&[], 1, 0);
+            let peer_chain = Blockchain::from(extend(&genesis, 3, 2));
+            let output = env::temp_dir()
+                .join(format!("{}-p2p-sync.json", process::id()))
+                .to_string_lossy()
+                .to_string();
+            let node = node(Blockchain::from(genesis), &output);
+            let (outbox, inbox) = channel();
+            let id = node.register(outbox);
+            assert!(matches!(next_message(&inbox), Message::Tip(_)));
+
+            node.handle(id, Message::Tip(peer_chain.tip_header().unwrap().clone()));
+            let Message::GetHeaders { locator } = next_message(&inbox) else {
+                panic!("expected a headers request");
+            };
+            node.handle(id, Message::Headers(headers_after(&peer_chain, &locator)));
+            let Message::GetBlocks(wanted) = next_message(&inbox) else {
+                panic!("expected a blocks request");
+            };
+            assert_eq!(wanted.len(), 3);
+
+            // A reply of fewer than MAX_BLOCKS blocks is the last one, so the
+            // peer no longer has the whole branch.
+            node.handle(
+                id,
+                Message::Blocks(blocks_by_hash(&peer_chain, &wanted[..2])),
+            );
+            let Message::GetHeaders { locator } = next_message(&inbox) else {
+                panic!("expected the synchronisation to start over");
+            };
+            assert_eq!(node.status(0).height, 0);
+
+            node.handle(id, Message::Headers(headers_after(&peer_chain, &locator)));
+            let Message::GetBlocks(wanted) = next_message(&inbox) else {
+                panic!("expected a blocks request");
+            };
+            node.handle(id, Message::Blocks(blocks_by_hash(&peer_chain, &wanted)));
+            fs::remove_file(&output).unwrap();
+            let tip = peer_chain.tip_header().unwrap();
+            assert_eq!(node.status(0).height, tip.height);
+            assert_eq!(node.status(0).tip, tip.hash.to_string());
+        }
+    }
+}
@@ -0,0 +1,316 @@
+// This module renders charts of the metrics of a chain or of a simulation
+pub mod plot {
+    use std::{collections::HashSet, io::Cursor, path::Path};
+
+    use clap::ValueEnum;
+    use image::{ImageOutputFormat, RgbImage};
+    use log::info;
+    use plotters::{coord::Shift, prelude::*};
+
+    use crate::{
+        args::args::PlotArgs,
+        config::config::SimulatorContext,
+        data_sourcing::data_provider::{
+            load_blockchain, load_json, load_transactions, write_bytes, write_text,
+        },
+        error::error::SimulatorError,
+        hashing::hashing::Hashable,
+        model::blockchain::{Blockchain, Hash256},
+        network::network::{traced_blocks, PropagationEvent},
+    };
+
+    #[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+    pub enum Chart {
+        /// Histogram of the number of seconds between consecutive blocks
+        BlockTimes,
+        /// Difficulty of the blocks by height
+        Difficulty,
+        /// Number of transactions of the mempool file not yet included by
+        /// the seconds since the first block
+        Mempool,
+        /// Transaction fees collected by the miner of every block by height
+        Fees,
+    }
+
+    #[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+    pub enum ImageFormat {
+        Svg,
+        Png,
+    }
+
+    /// Data of a chart, independent of the backend it is drawn with.
+    pub enum ChartData {
+        /// Counts of the values falling into equally wide bins, given as the
+        /// lower and upper bounds of the bins
+        Histogram {
+            bins: Vec<(f64, f64, u32)>,
+        },
+        Line {
+            points: Vec<(f64, f64)>,
+        },
+    }
+
+    /// Chart with its title and the descriptions of its axes.
+    pub struct LabelledChart {
+        pub title: &'static str,
+        pub x_description: &'static str,
+        pub y_description: &'static str,
+        pub data: ChartData,
+    }
+
+    /// Counts of the values in the given number of bins spanning from zero
+    /// to the largest value.
+    fn histogram(values: &[f64], bins: u32) -> Vec<(f64, f64, u32)> {
+        let largest = values.iter().copied().fold(0.0, f64::max).max(1.0);
+        let width = largest / bins as f64;
+        let mut counts = vec![0u32; bins as usize];
+        for &value in values {
+            let bin = ((value / width) as usize).min(bins as usize - 1);
+            counts[bin] += 1;
+        }
+        counts
+            .into_iter()
+            .enumerate()
+            .map(|(bin, count)| (bin as f64 * width, (bin + 1) as f64 * width, count))
+            .collect()
+    }
+
+    /// Chart of the chain. The mempool chart needs the transactions of the
+    /// mempool file.
+    pub fn chain_chart(
+        chart: Chart,
+        blockchain: &Blockchain,
+        mempool: Option<&str>,
+        bins: u32,
+    ) -> Result<LabelledChart, SimulatorError> {
+        let blocks = blockchain.blocks();
+        let start = blocks.first().map_or(0, |block| block.header.timestamp);
+        let chart = match chart {
+            Chart::BlockTimes => {
+                let intervals: Vec<f64> = blocks
+                    .windows(2)
+                    .map(|pair| pair[1].header.timestamp as f64 - pair[0].header.timestamp as f64)
+                    .collect();
+                LabelledChart {
+                    title: "Block times",
+                    x_description: "Seconds since the previous block",
+                    y_description: "Blocks",
+                    data: ChartData::Histogram {
+                        bins: histogram(&intervals, bins),
+                    },
+                }
+            }
+            Chart::Difficulty => LabelledChart {
+                title: "Difficulty",
+                x_description: "Height",
+                y_description: "Difficulty",
+                data: ChartData::Line {
+                    points: blocks
+                        .iter()
+                        .map(|block| (block.header.height as f64, block.header.difficulty as f64))
+                        .collect(),
+                },
+            },
+            Chart::Mempool => {
+                let mempool = mempool.ok_or_else(|| {
+                    SimulatorError::InvalidChart(
+                        "the mempool chart requires the mempool file".to_string(),
+                    )
+                })?;
+                info!("Loading the mempool from {}", mempool);
+                let mut pending: HashSet<Hash256> = load_transactions(mempool)?
+                    .iter()
+                    .map(|transaction| transaction.hash())
+                    .collect();
+                let mut points = vec![];
+                for block in blocks {
+                    for transaction in &block.transactions {
+                        pending.remove(&transaction.hash());
+                    }
+                    points.push((
+                        block.header.timestamp as f64 - start as f64,
+                        pending.len() as f64,
+                    ));
+                }
+                LabelledChart {
+                    title: "Mempool size",
+                    x_description: "Seconds since the first block",
+                    y_description: "Pending transactions",
+                    data: ChartData::Line { points },
+                }
+            }
+            Chart::Fees => LabelledChart {
+                title: "Fee revenue",
+                x_description: "Height",
+                y_description: "Fees",
+                data: ChartData::Line {
+                    points: blocks
+                        .iter()
+                        .map(|block| {
+                            let fees = block.total_fees()?;
+                            Ok((block.header.height as f64, fees.0 as f64))
+                        })
+                        .collect::<Result<_, SimulatorError>>()?,
+                },
+            },
+        };
+        Ok(chart)
+    }
+
+    /// Chart of the main chain of a propagation trace, which only records
+    /// when the blocks were mined.
+    fn trace_chart(
+        chart: Chart,
+        trace: &[PropagationEvent],
+        bins: u32,
+    ) -> Result<LabelledChart, SimulatorError> {
+        if chart != Chart::BlockTimes {
+            return Err(SimulatorError::InvalidChart(
+                "only the block times can be plotted from a propagation trace".to_string(),
+            ));
+        }
+        let (blocks, main_chain) = traced_blocks(trace);
+        let mut mined_at: Vec<(u32, f64)> = blocks
+            .iter()
+            .filter(|(id, _)| main_chain.contains(id))
+            .map(|(_, block)| (block.height, block.mined_at))
+            .collect();
+        mined_at.sort_by_key(|&(height, _)| height);
+        // The genesis block is not part of the trace and is mined at zero.
+        let mut previous = 0.0;
+        let intervals: Vec<f64> = mined_at
+            .into_iter()
+            .map(|(_, time)| {
+                let interval = time - previous;
+                previous = time;
+                interval
+            })
+            .collect();
+        Ok(LabelledChart {
+            title: "Block times",
+            x_description: "Seconds since the previous block",
+            y_description: "Blocks",
+            data: ChartData::Histogram {
+                bins: histogram(&intervals, bins),
+            },
+        })
+    }
+
+    /// Range from zero, or from the smallest value if it is negative, to a
+    /// bit above the largest value.
+    fn axis_range(values: impl Iterator<Item = f64>) -> std::ops::Range<f64> {
+        let (low, high) = values.fold((0.0f64, 0.0f64), |(low, high), value| {
+            (low.min(value), high.max(value))
+        });
+        low..(high + (high - low) * 0.05).max(low + 1.0)
+    }
+
+    fn draw<DB: DrawingBackend>(
+        root: &DrawingArea<DB, Shift>,
+        chart: &LabelledChart,
+    ) -> Result<(), SimulatorError>
+    where
+        DB::ErrorType: 'static,
+    {
+        let plot_error = |error: DrawingAreaErrorKind<DB::ErrorType>| {
+            SimulatorError::PlotFailed(error.to_string())
+        };
+        root.fill(&WHITE).map_err(plot_error)?;
+        let (x_range, y_range) = match &chart.data {
+            ChartData::Histogram { bins } => (
+                axis_range(bins.iter().flat_map(|&(low, high, _)| [low, high])),
+                axis_range(bins.iter().map(|&(_, _, count)| count as f64)),
+            ),
+            ChartData::Line { points } => (
+                axis_range(points.iter().map(|&(x, _)| x)),
+                axis_range(points.iter().map(|&(_, y)| y)),
+            ),
+        };
+        let mut context = ChartBuilder::on(root)
+            .caption(chart.title, ("sans-serif", 24))
+            .margin(20)
+            .x_label_area_size(50)
+            .y_label_area_size(70)
+            .build_cartesian_2d(x_range, y_range)
+            .map_err(plot_error)?;
+        context
+            .configure_mesh()
+            .x_desc(chart.x_description)
+            .y_desc(chart.y_description)
+            .draw()
+            .map_err(plot_error)?;
+        match &chart.data {
+            ChartData::Histogram { bins } => context
+                .draw_series(bins.iter().map(|&(low, high, count)| {
+                    Rectangle::new([(low, 0.0), (high, count as f64)], BLUE.mix(0.6).filled())
+                }))
+                .map_err(plot_error)?,
+            ChartData::Line { points } => context
+                .draw_series(LineSeries::new(points.iter().copied(), &BLUE))
+                .map_err(plot_error)?,
+        };
+        root.present().map_err(plot_error)
+    }
+
+    /// Renders the chart as an SVG document of the given size in pixels.
+    pub fn render_svg(chart: &LabelledChart, size: (u32, u32)) -> Result<String, SimulatorError> {
+        let mut svg = String::new();
+        draw(
+            &SVGBackend::with_string(&mut svg, size).into_drawing_area(),
+            chart,
+        )?;
+        Ok(svg)
+    }
+
+    /// Renders a chart of the chain, or the block times of the main chain of
+    /// a propagation trace written by `SimulateNetwork`, as an SVG or PNG
+    /// image.
+    pub fn plot(args: PlotArgs, context: &SimulatorContext) -> Result<(), SimulatorError> {
+        let chart = match (&args.blockchain_state, &args.trace) {
+            (Some(blockchain_state), _) => {
+                info!("Loading the blockchain from {}", blockchain_state);
+                let blockchain = load_blockchain(blockchain_state, context)?;
+                chain_chart(args.chart, &blockchain, args.mempool.as_deref(), args.bins)?
+            }
+            // clap requires either the blockchain or the trace.
+            (None, Some(trace)) => {
+                info!("Loading the propagation trace from {}", trace);
+                let trace: Vec<PropagationEvent> = load_json(trace)?;
+                trace_chart(args.chart, &trace, args.bins)?
+            }
+            (None, None) => unreachable!(),
+        };
+
+        let format = args.image_format.unwrap_or_else(|| {
+            match Path::new(&args.output).extension().and_then(|e| e.to_str()) {
+                Some("png") => ImageFormat::Png,
+                _ => ImageFormat::Svg,
+            }
+        });
+        let size = (args.width, args.height);
+        info!("Writing the chart to {}", args.output);
+        match format {
+            ImageFormat::Svg => {
+                let mut svg = String::new();
+                draw(
+                    &SVGBackend::with_string(&mut svg, size).into_drawing_area(),
+                    &chart,
+                )?;
+                write_text(&args.output, context.output, &svg)
+            }
+            ImageFormat::Png => {
+                let mut pixels = vec![0u8; args.width as usize * args.height as usize * 3];
+                draw(
+                    &BitMapBackend::with_buffer(&mut pixels, size).into_drawing_area(),
+                    &chart,
+                )?;
+                let mut png = Cursor::new(vec![]);
+                RgbImage::from_raw(args.width, args.height, pixels)
+                    .expect("the buffer holds every pixel")
+                    .write_to(&mut png, ImageOutputFormat::Png)
+                    .map_err(|error| SimulatorError::PlotFailed(error.to_string()))?;
+                write_bytes(&args.output, context.output, png.get_ref())
+            }
+        }
+    }
+}
@@ -0,0 +1,305 @@
+// This module provides the protobuf messages of the data files, as defined in
+// proto/blockchain.proto, and their conversions to and from the model
+pub mod proto {
+    use prost::Message;
+
+    use crate::model::blockchain::{self as model, Address, Amount, Hash256};
+
+    #[derive(Clone, PartialEq, Message)]
+    pub struct Header {
+        #[prost(uint32, tag = "1")]
+        pub difficulty: u32,
+        #[prost(uint32, tag = "2")]
+        pub height: u32,
+        #[prost(bytes = "vec", tag = "3")]
+        pub miner: Vec<u8>,
+        #[prost(uint32, tag = "4")]
+        pub nonce: u32,
+        #[prost(string, tag = "5")]
+        pub hash: String,
+        #[prost(string, tag = "6")]
+        pub previous_block_header_hash: String,
+        #[prost(uint64, tag = "7")]
+        pub timestamp: u64,
+        #[prost(uint32, tag = "8")]
+        pub transactions_count: u32,
+        #[prost(string, tag = "9")]
+        pub transactions_merkle_root: String,
+        #[prost(uint32, tag = "10")]
+        pub version: u32,
+    }
+
+    #[derive(Clone, PartialEq, Message)]
+    pub struct Transaction {
+        #[prost(uint64, tag = "1")]
+        pub amount: u64,
+        #[prost(uint32, tag = "2")]
+        pub lock_time: u32,
+        #[prost(bytes = "vec", tag = "3")]
+        pub receiver: Vec<u8>,
+        #[prost(bytes = "vec", tag = "4")]
+        pub sender: Vec<u8>,
+        #[prost(string, tag = "5")]
+        pub signature: String,
+        #[prost(uint64, tag = "6")]
+        pub transaction_fee: u64,
+    }
+
+    #[derive(Clone, PartialEq, Message)]
+    pub struct Block {
+        #[prost(message, optional, tag = "1")]
+        pub header: Option<Header>,
+        #[prost(message, repeated, tag = "2")]
+        pub transactions: Vec<Transaction>,
+    }
+
+    /// A blockchain file, or a headers file whose blocks have no
+    /// transactions.
+    #[derive(Clone, PartialEq, Message)]
+    pub struct Blockchain {
+        #[prost(message, repeated, tag = "1")]
+        pub blocks: Vec<Block>,
+    }
+
+    #[derive(Clone, PartialEq, Message)]
+    pub struct Mempool {
+        #[prost(message, repeated, tag = "1")]
+        pub transactions: Vec<Transaction>,
+    }
+
+    #[derive(Clone, PartialEq, Message)]
+    pub struct InclusionProof {
+        #[prost(string, tag = "1")]
+        pub transaction_hash: String,
+        #[prost(string, tag = "2")]
+        pub merkle_root: String,
+        #[prost(string, repeated, tag = "3")]
+        pub hashes: Vec<String>,
+    }
+
+    fn address(bytes: &[u8]) -> Result<Address, String> {
+        bytes
+            .try_into()
+            .map(Address)
+            .map_err(|_| format!("an address has {} bytes instead of 20", bytes.len()))
+    }
+
+    fn hash(value: &str) -> Result<Hash256, String> {
+        value.parse::<Hash256>().map_err(|error| error.to_string())
+    }
+
+    impl From<&model::Header> for Header {
+        fn from(header: &model::Header) -> Self {
+            Header {
+                difficulty: header.difficulty,
+                height: header.height,
+                miner: header.miner.0.to_vec(),
+                nonce: header.nonce,
+                hash: header.hash.to_string(),
+                previous_block_header_hash: header.previous_block_header_hash.to_string(),
+                timestamp: header.timestamp,
+                transactions_count: header.transactions_count,
+                transactions_merkle_root: header.transactions_merkle_root.to_string(),
+                version: header.version,
+            }
+        }
+    }
+
+    impl TryFrom<Header> for model::Header {
+        type Error = String;
+
+        fn try_from(header: Header) -> Result<Self, Self::Error> {
+            Ok(model::Header {
+                difficulty: header.difficulty,
+                height: header.height,
+                miner: address(&header.miner)?,
+                nonce: header.nonce,
+                hash: hash(&header.hash)?,
+                previous_block_header_hash: hash(&header.previous_block_header_hash)?,
+                timestamp: header.timestamp,
+                transactions_count: header.transactions_count,
+                transactions_merkle_root: hash(&header.transactions_merkle_root)?,
+                version: header.version,
+            })
+        }
+    }
+
+    impl From<&model::Transaction> for Transaction {
+        fn from(transaction: &model::Transaction) -> Self {
+            Transaction {
+                amount: transaction.amount.0,
+                lock_time: transaction.lock_time,
+                receiver: transaction.receiver.0.to_vec(),
+                sender: transaction.sender.0.to_vec(),
+                signature: transaction.signature.clone(),
+                transaction_fee: transaction.transaction_fee.0,
+            }
+        }
+    }
+
+    impl TryFrom<Transaction> for model::Transaction {
+        type Error = String;
+
+        fn try_from(transaction: Transaction) -> Result<Self, Self::Error> {
+            Ok(model::Transaction {
+                amount: Amount(transaction.amount),
+                lock_time: transaction.lock_time,
+                receiver: address(&transaction.receiver)?,
+                sender: address(&transaction.sender)?,
+                signature: transaction.signature,
+                transaction_fee: Amount(transaction.transaction_fee),
+            })
+        }
+    }
+
+    impl From<&model::Block> for Block {
+        fn from(block: &model::Block) -> Self {
+            Block {
+                header: Some(Header::from(&block.header)),
+                transactions: block.transactions.iter().map(Transaction::from).collect(),
+            }
+        }
+    }
+
+    impl TryFrom<Block> for model::Block {
+        type Error = String;
+
+        fn try_from(block: Block) -> Result<Self, Self::Error> {
+            let header = block.header.ok_or("a block has no header")?;
+            Ok(model::Block {
+                header: header.try_into()?,
+                transactions: transactions(block.transactions)?,
+            })
+        }
+    }
+
+    /// Transactions of the model, failing on the first malformed one.
+    pub fn transactions(transactions: Vec<Transaction>) -> Result<Vec<model::Transaction>, String> {
+        transactions.into_iter().map(TryInto::try_into).collect()
+    }
+
+    impl From<&model::Blockchain> for Blockchain {
+        fn from(blockchain: &model::Blockchain) -> Self {
+            Blockchain {
+                blocks: blockchain.iter().map(Block::from).collect(),
+            }
+        }
+    }
+
+    impl From<&model::HeaderChain> for Blockchain {
+        fn from(headers: &model::HeaderChain) -> Self {
+            Blockchain {
+                blocks: headers
+                    .iter()
+                    .map(|header| Block {
+                        header: Some(Header::from(header)),
+                        transactions: vec![],
+                    })
+                    .collect(),
+            }
+        }
+    }
+
+    impl TryFrom<Blockchain> for model::Blockchain {
+        type Error = String;
+
+        fn try_from(blockchain: Blockchain) -> Result<Self, Self::Error> {
+            let blocks = blockchain
+                .blocks
+                .into_iter()
+                .map(TryInto::try_into)
+                .collect::<Result<Vec<model::Block>, String>>()?;
+            Ok(model::Blockchain::from(blocks))
+        }
+    }
+
+    impl From<&model::InclusionProof> for InclusionProof {
+        fn from(proof: &model::InclusionProof) -> Self {
+            InclusionProof {
+                transaction_hash: proof.transaction_hash.to_string(),
+                merkle_root: proof.merkle_root.to_string(),
+                hashes: proof.hashes.iter().map(Hash256::to_string).collect(),
+            }
+        }
+    }
+
+    impl TryFrom<InclusionProof> for model::InclusionProof {
+        type Error = String;
+
+        fn try_from(proof: InclusionProof) -> Result<Self, Self::Error> {
+            Ok(model::InclusionProof {
+                transaction_hash: hash(&proof.transaction_hash)?,
+                merkle_root: hash(&proof.merkle_root)?,
+                hashes: proof
+                    .hashes
+                    .iter()
+                    .map(|value| hash(value))
+                    .collect::<Result<_, _>>()?,
+            })
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use prost::Message;
+
+        use crate::model::blockchain::{self as model, Address, Amount, Hash256};
+
+        use super::{Block, Transaction};
+
+        fn block() -> model::Block {
+            let transaction = model::Transaction::builder()
+                .sender(Address([1; 20]))
+                .receiver(Address([2; 20]))
+                .amount(Amount(7))
+                .transaction_fee(Amount(1))
+                .build()
+                .unwrap();
+            model::Block {
+                header: model::Header {
+                    difficulty: 2,
+                    height: 5,
+                    miner: Address([3; 20]),
+                    nonce: 42,
+                    hash: Hash256([4; 32]),
+                    previous_block_header_hash: Hash256([5; 32]),
+                    timestamp: 1_700_000_000,
+                    transactions_count: 1,
+                    transactions_merkle_root: Hash256([6; 32]),
+                    version: 1,
+                },
+                transactions: vec![transaction],
+            }
+        }
+
+        #[test]
+        fn block_round_trips_through_the_encoded_message() {
+            let block = block();
+            let encoded = Block::from(&block).encode_to_vec();
+            let decoded: model::Block = Block::decode(encoded.as_slice())
+                .unwrap()
+                .try_into()
+                .unwrap();
+            assert_eq!(
+                serde_json::to_value(&decoded).unwrap(),
+                serde_json::to_value(&block).unwrap()
+            );
+        }
+
+        #[test]
+        fn block_without_header_is_rejected() {
+            let mut message = Block::from(&block());
+            message.header = None;
+            let error = model::Block::try_from(message).unwrap_err();
+            assert_eq!(error, "a block has no header");
+        }
+
+        #[test]
+        fn truncated_address_is_rejected() {
+            let mut message = Transaction::from(&block().transactions[0]);
+            message.sender.pop();
+            let error = model::Transaction::try_from(message).unwrap_err();
+            assert_eq!(error, "an address has 19 bytes instead of 20");
+        }
+    }
+}
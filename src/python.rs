@@ -0,0 +1,169 @@
+// This module exposes the blockchain, mining and inclusion proofs as a Python
+// extension module
+pub mod python {
+    use pyo3::{exceptions::PyValueError, prelude::*};
+    use pythonize::{depythonize, pythonize};
+
+    use crate::{
+        config::config::SimulatorContext,
+        data_sourcing::data_provider::{load_blockchain, write_json, OutputOptions},
+        error::error::SimulatorError,
+        hashing::hashing::Hashable,
+        model::blockchain::{Blockchain, HeaderFormat, InclusionProof, Transaction},
+        node::{
+            miner::{
+                compute_merkle_root, compute_transaction_hashes, construct_merkle_tree,
+                mine_new_block,
+            },
+            validator::inclusion_proof,
+        },
+    };
+
+    fn to_py_error(error: SimulatorError) -> PyErr {
+        PyValueError::new_err(error.to_string())
+    }
+
+    /// Chain of blocks, whose blocks, headers and transactions are handed to
+    /// Python as dictionaries in the same format as the JSON files.
+    #[pyclass(name = "Blockchain")]
+    pub struct PyBlockchain {
+        blockchain: Blockchain,
+    }
+
+    #[pymethods]
+    impl PyBlockchain {
+        /// Loads the chain from a blockchain file, which may be compressed.
+        /// A strict load also checks the heights, links and timestamps of
+        /// the blocks.
+        #[staticmethod]
+        #[pyo3(signature = (file_name, strict = false))]
+        fn load(file_name: &str, strict: bool) -> PyResult<Self> {
+            let context = SimulatorContext {
+                strict_load: strict,
+                ..Default::default()
+            };
+            Ok(PyBlockchain {
+                blockchain: load_blockchain(file_name, &context).map_err(to_py_error)?,
+            })
+        }
+
+        /// Builds the chain from a list of blocks.
+        #[staticmethod]
+        fn from_blocks(blocks: &Bound<'_, PyAny>) -> PyResult<Self> {
+            Ok(PyBlockchain {
+                blockchain: depythonize(blocks)?,
+            })
+        }
+
+        fn save(&self, file_name: &str) -> PyResult<()> {
+            write_json(file_name, OutputOptions::default(), &self.blockchain).map_err(to_py_error)
+        }
+
+        fn blocks<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyAny>> {
+            Ok(pythonize(py, self.blockchain.blocks())?)
+        }
+
+        /// Header of the most recent block.
+        fn tip<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyAny>> {
+            let tip = self.blockchain.tip_header().map_err(to_py_error)?;
+            Ok(pythonize(py, tip)?)
+        }
+
+        fn __len__(&self) -> usize {
+            self.blockchain.len()
+        }
+
+        /// Mines a block with the transactions on top of the tip, appends it
+        /// to the chain and returns it. The block carries over the difficulty
+        /// of the tip unless one is given.
+        #[pyo3(signature = (transactions, difficulty = None, block_interval = 10))]
+        fn mine_block<'py>(
+            &mut self,
+            py: Python<'py>,
+            transactions: &Bound<'py, PyAny>,
+            difficulty: Option<u32>,
+            block_interval: u64,
+        ) -> PyResult<Bound<'py, PyAny>> {
+            let transactions: Vec<Transaction> = depythonize(transactions)?;
+            let tip = self.blockchain.tip_header().map_err(to_py_error)?;
+            let (block, _) = mine_new_block(
+                transactions,
+                tip,
+                difficulty,
+                block_interval,
+                0,
+                false,
+                &SimulatorContext::default(),
+            )
+            .map_err(to_py_error)?;
+            let mined = pythonize(py, &block)?;
+            self.blockchain
+                .append(block, HeaderFormat::Simulator, &[])
+                .map_err(to_py_error)?;
+            Ok(mined)
+        }
+
+        /// Proof that the transaction with the given hash is included in the
+        /// block with the given number, numbered from 1 for the genesis block.
+        fn inclusion_proof<'py>(
+            &self,
+            py: Python<'py>,
+            block_number: usize,
+            transaction_hash: &str,
+        ) -> PyResult<Bound<'py, PyAny>> {
+            let block = self
+                .blockchain
+                .get_by_number(block_number)
+                .map_err(to_py_error)?;
+            let transaction_hash = transaction_hash.parse().map_err(to_py_error)?;
+            let proof =
+                inclusion_proof(&block.transactions, &transaction_hash).map_err(to_py_error)?;
+            Ok(pythonize(py, &proof)?)
+        }
+    }
+
+    #[pyfunction]
+    fn transaction_hash(transaction: &Bound<'_, PyAny>) -> PyResult<String> {
+        Ok(depythonize::<Transaction>(transaction)?.hash().to_string())
+    }
+
+    /// Merkle root of the transactions as stored in the block header.
+    #[pyfunction]
+    fn merkle_root(transactions: &Bound<'_, PyAny>) -> PyResult<String> {
+        let transactions: Vec<Transaction> = depythonize(transactions)?;
+        Ok(compute_merkle_root(&transactions).to_string())
+    }
+
+    /// Merkle tree of the transactions as nested dictionaries of the hash and
+    /// the left and right children of every node.
+    #[pyfunction]
+    fn merkle_tree<'py>(
+        py: Python<'py>,
+        transactions: &Bound<'py, PyAny>,
+    ) -> PyResult<Bound<'py, PyAny>> {
+        let transactions: Vec<Transaction> = depythonize(transactions)?;
+        let tree =
+            construct_merkle_tree(compute_transaction_hashes(&transactions)).ok_or_else(|| {
+                to_py_error(SimulatorError::InvalidTransaction(
+                    "a Merkle tree needs at least one transaction".to_string(),
+                ))
+            })?;
+        Ok(pythonize(py, &tree)?)
+    }
+
+    /// Whether the inclusion proof leads to its merkle root.
+    #[pyfunction]
+    fn verify_inclusion_proof(proof: &Bound<'_, PyAny>) -> PyResult<bool> {
+        Ok(depythonize::<InclusionProof>(proof)?.verify().is_ok())
+    }
+
+    #[pymodule]
+    fn blockchain_simulator(module: &Bound<'_, PyModule>) -> PyResult<()> {
+        module.add_class::<PyBlockchain>()?;
+        module.add_function(wrap_pyfunction!(transaction_hash, module)?)?;
+        module.add_function(wrap_pyfunction!(merkle_root, module)?)?;
+        module.add_function(wrap_pyfunction!(merkle_tree, module)?)?;
+        module.add_function(wrap_pyfunction!(verify_inclusion_proof, module)?)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,383 @@
+// This module imports blocks from the JSON-RPC interface of a Bitcoin Core or
+// Ethereum node
+pub mod remote {
+    use std::error::Error;
+
+    use clap::ValueEnum;
+    use log::info;
+    use serde_json::{json, Value};
+
+    use crate::{
+        args::args::ImportRemoteArgs,
+        config::config::SimulatorContext,
+        data_sourcing::data_provider::write_blockchain,
+        error::error::SimulatorError,
+        model::blockchain::{
+            Address, Amount, Block, Blockchain, Hash256, Header, Transaction, NULL_ADDRESS,
+            NULL_HASH,
+        },
+        node::miner::compute_merkle_root,
+    };
+
+    /// Kind of node serving the JSON-RPC endpoint.
+    #[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+    pub enum RemoteChain {
+        /// Bitcoin Core, using getblockhash and getblock
+        Bitcoin,
+        /// Any Ethereum execution client, using eth_getBlockByNumber
+        Ethereum,
+    }
+
+    /// Number of satoshis in a bitcoin, the amounts of Bitcoin Core are
+    /// given in bitcoins.
+    const SATOSHIS_PER_BITCOIN: f64 = 100_000_000.0;
+
+    /// Number of wei in a gwei, Ethereum amounts are imported in gwei as
+    /// amounts in wei quickly overflow 64 bits.
+    const WEI_PER_GWEI: u128 = 1_000_000_000;
+
+    /// Pulls the blocks in the range of heights from the node and writes them
+    /// as a blockchain file. The conversion is best-effort: the headers keep
+    /// the hashes and links of the remote chain, which the simulator can't
+    /// recompute, while every remote transaction becomes a single transfer
+    /// and the merkle roots are computed over the converted transactions so
+    /// that inclusion proofs can be generated and verified.
+    pub fn import_remote(
+        args: ImportRemoteArgs,
+        context: &SimulatorContext,
+    ) -> Result<(), SimulatorError> {
+        if args.from_height > args.to_height {
+            return Err(SimulatorError::EmptyRange {
+                from: args.from_height,
+                to: args.to_height,
+            });
+        }
+        let mut client = RpcClient::new(&args.rpc_url);
+        let mut blocks = vec![];
+        for height in args.from_height..=args.to_height {
+            info!(
+                "Importing the block at height {} from {}",
+                height, client.endpoint
+            );
+            let block = match args.chain {
+                RemoteChain::Bitcoin => bitcoin_block(&mut client, height)?,
+                RemoteChain::Ethereum => ethereum_block(&mut client, height)?,
+            };
+            blocks.push(block);
+        }
+        info!(
+            "Imported {} blocks, writing them to {}",
+            blocks.len(),
+            args.blockchain_state_output
+        );
+        write_blockchain(
+            &args.blockchain_state_output,
+            context.output,
+            context.data_format,
+            &Blockchain::from(blocks),
+        )
+    }
+
+    struct RpcClient {
+        url: String,
+        /// URL without the credentials, shown in the logs and errors.
+        endpoint: String,
+        next_id: u64,
+    }
+
+    impl RpcClient {
+        fn new(url: &str) -> Self {
+            RpcClient {
+                url: url.to_string(),
+                endpoint: without_credentials(url),
+                next_id: 0,
+            }
+        }
+
+        fn failed(&self, reason: impl ToString) -> SimulatorError {
+            SimulatorError::RemoteRpc {
+                endpoint: self.endpoint.clone(),
+                reason: reason.to_string(),
+            }
+        }
+
+        /// Calls the method and returns its result, failing if the node
+        /// answers with an error.
+        fn call(&mut self, method: &str, params: Value) -> Result<Value, SimulatorError> {
+            self.next_id += 1;
+            let request = json!({
+                "jsonrpc": "2.0",
+                "id": self.next_id,
+                "method": method,
+                "params": params,
+            });
+            // Bitcoin Core answers failed calls with an error status and the
+            // error in the body, so the body is read either way. Transport
+            // errors are reported without their URL, which may hold the
+            // credentials.
+            let (status, response) = match ureq::post(&self.url).send_json(request) {
+                Ok(response) => (response.status(), response),
+                Err(ureq::Error::Status(status, response)) => (status, response),
+                Err(ureq::Error::Transport(transport)) => {
+                    let mut reason = transport.kind().to_string();
+                    for detail in [
+                        transport.message().map(str::to_string),
+                        transport.source().map(ToString::to_string),
+                    ]
+                    .into_iter()
+                    .flatten()
+                    {
+                        reason += &format!(": {}", detail);
+                    }
+                    return Err(self.failed(reason));
+                }
+            };
+            let mut response: Value = response.into_json().map_err(|error| match status {
+                200 => self.failed(format!("{} returned malformed JSON: {}", method, error)),
+                status => self.failed(format!("{} failed with the HTTP status {}", method, status)),
+            })?;
+            match response.get("error") {
+                Some(error) if !error.is_null() => {
+                    Err(self.failed(format!("{} failed: {}", method, error)))
+                }
+                _ => Ok(response["result"].take()),
+            }
+        }
+    }
+
+    /// Removes the user and password from the authority of the URL.
+    fn without_credentials(url: &str) -> String {
+        let Some((scheme, rest)) = url.split_once("://") else {
+            return url.to_string();
+        };
+        let authority = &rest[..rest.find('/').unwrap_or(rest.len())];
+        match authority.rfind('@') {
+            Some(at) => format!("{}://{}", scheme, &rest[at + 1..]),
+            None => url.to_string(),
+        }
+    }
+
+    fn field<'a>(value: &'a Value, name: &str, height: u32) -> Result<&'a Value, SimulatorError> {
+        value
+            .get(name)
+            .filter(|field| !field.is_null())
+            .ok_or_else(|| SimulatorError::InvalidBlock {
+                height,
+                reason: format!("the remote block has no field {}", name),
+            })
+    }
+
+    fn invalid(height: u32, name: &str) -> SimulatorError {
+        SimulatorError::InvalidBlock {
+            height,
+            reason: format!("the field {} of the remote block is malformed", name),
+        }
+    }
+
+    fn number(value: &Value, name: &str, height: u32) -> Result<u64, SimulatorError> {
+        field(value, name, height)?
+            .as_u64()
+            .ok_or_else(|| invalid(height, name))
+    }
+
+    fn string<'a>(value: &'a Value, name: &str, height: u32) -> Result<&'a str, SimulatorError> {
+        field(value, name, height)?
+            .as_str()
+            .ok_or_else(|| invalid(height, name))
+    }
+
+    /// Hash of the remote block, with or without the 0x prefix.
+    fn hash_field(value: &Value, name: &str, height: u32) -> Result<Hash256, SimulatorError> {
+        string(value, name, height)?
+            .parse()
+            .map_err(|_| invalid(height, name))
+    }
+
+    /// Number of leading zero hex digits of the 256-bit hashes that stay
+    /// below a target with the given number of significant hex digits.
+    fn leading_zeros(significant_digits: usize) -> u32 {
+        64usize.saturating_sub(significant_digits) as u32
+    }
+
+    fn significant_digits(hex: &str) -> usize {
+        hex.trim_start_matches("0x").trim_start_matches('0').len()
+    }
+
+    /// Difficulty of a Bitcoin block from the target in the compact `bits`
+    /// encoding, the mantissa is shifted by the exponent minus three bytes.
+    fn bitcoin_difficulty(bits: &str, height: u32) -> Result<u32, SimulatorError> {
+        let bits = u32::from_str_radix(bits, 16).map_err(|_| invalid(height, "bits"))?;
+        let exponent = (bits >> 24) as usize;
+        let mantissa = format!("{:x}", bits & 0x00ff_ffff);
+        let digits = significant_digits(&mantissa) + 2 * exponent.saturating_sub(3);
+        Ok(leading_zeros(digits))
+    }
+
+    /// Address in a standard output script paying to a 20-byte hash, that is
+    /// a P2PKH, P2SH or P2WPKH script.
+    fn script_address(script: &str) -> Option<Address> {
+        let hash = script
+            .strip_prefix("76a914")
+            .and_then(|rest| rest.strip_suffix("88ac"))
+            .or_else(|| {
+                script
+                    .strip_prefix("a914")
+                    .and_then(|rest| rest.strip_suffix("87"))
+            })
+            .or_else(|| script.strip_prefix("0014"))?;
+        let mut address = [0u8; 20];
+        hex::decode_to_slice(hash, &mut address).ok()?;
+        Some(Address(address))
+    }
+
+    fn output_address(output: &Value) -> Option<Address> {
+        script_address(output["scriptPubKey"]["hex"].as_str()?)
+    }
+
+    fn satoshis(bitcoins: &Value) -> Amount {
+        Amount((bitcoins.as_f64().unwrap_or(0.0) * SATOSHIS_PER_BITCOIN).round() as u64)
+    }
+
+    fn bitcoin_block(client: &mut RpcClient, height: u32) -> Result<Block, SimulatorError> {
+        let hash = client.call("getblockhash", json!([height]))?;
+        let block = client.call("getblock", json!([hash, 2]))?;
+        let transactions = field(&block, "tx", height)?
+            .as_array()
+            .ok_or_else(|| invalid(height, "tx"))?
+            .iter()
+            .map(|transaction| bitcoin_transaction(transaction, height))
+            .collect::<Result<Vec<Transaction>, SimulatorError>>()?;
+        // The coinbase transaction pays the miner in its first output.
+        let miner = output_address(&block["tx"][0]["vout"][0]).unwrap_or(NULL_ADDRESS);
+        let header = Header {
+            difficulty: bitcoin_difficulty(string(&block, "bits", height)?, height)?,
+            height,
+            miner,
+            nonce: number(&block, "nonce", height)? as u32,
+            hash: hash_field(&block, "hash", height)?,
+            // The genesis block has no previous block.
+            previous_block_header_hash: match block["previousblockhash"].is_null() {
+                true => NULL_HASH,
+                false => hash_field(&block, "previousblockhash", height)?,
+            },
+            timestamp: number(&block, "time", height)?,
+            transactions_count: transactions.len() as u32,
+            transactions_merkle_root: compute_merkle_root(&transactions),
+            version: block["version"].as_i64().unwrap_or(0) as u32,
+        };
+        Ok(Block {
+            header,
+            transactions,
+        })
+    }
+
+    /// Transfer of the total value of the outputs to the first output with
+    /// an address. Inputs only refer to earlier outputs, so the sender is
+    /// unknown and left as the null address, and the coinbase transaction
+    /// has no fee.
+    fn bitcoin_transaction(
+        transaction: &Value,
+        height: u32,
+    ) -> Result<Transaction, SimulatorError> {
+        let outputs = transaction["vout"]
+            .as_array()
+            .map(Vec::as_slice)
+            .unwrap_or_default();
+        Ok(Transaction {
+            amount: Amount::checked_sum(outputs.iter().map(|output| satoshis(&output["value"])))
+                .ok_or_else(|| invalid(height, "vout"))?,
+            lock_time: transaction["locktime"].as_u64().unwrap_or(0) as u32,
+            receiver: outputs
+                .iter()
+                .find_map(output_address)
+                .unwrap_or(NULL_ADDRESS),
+            sender: NULL_ADDRESS,
+            signature: "".to_string(),
+            transaction_fee: satoshis(&transaction["fee"]),
+        })
+    }
+
+    /// Amount of gwei in the given number of wei, or `None` if it doesn't
+    /// fit in an amount.
+    fn gwei(wei: u128) -> Option<Amount> {
+        u64::try_from(wei / WEI_PER_GWEI).ok().map(Amount)
+    }
+
+    /// Ethereum quantity, a 0x-prefixed hex number.
+    fn quantity(value: &Value, name: &str, height: u32) -> Result<u128, SimulatorError> {
+        let hex = string(value, name, height)?;
+        u128::from_str_radix(hex.trim_start_matches("0x"), 16).map_err(|_| invalid(height, name))
+    }
+
+    fn ethereum_address(value: &Value) -> Address {
+        value
+            .as_str()
+            .and_then(|address| address.parse().ok())
+            .unwrap_or(NULL_ADDRESS)
+    }
+
+    fn ethereum_block(client: &mut RpcClient, height: u32) -> Result<Block, SimulatorError> {
+        let block = client.call(
+            "eth_getBlockByNumber",
+            json!([format!("{:#x}", height), true]),
+        )?;
+        if block.is_null() {
+            return Err(SimulatorError::HeightNotFound(height));
+        }
+        let transactions = field(&block, "transactions", height)?
+            .as_array()
+            .ok_or_else(|| invalid(height, "transactions"))?
+            .iter()
+            .map(|transaction| ethereum_transaction(transaction, height))
+            .collect::<Result<Vec<Transaction>, SimulatorError>>()?;
+        // A block of difficulty D is valid if its hash is at most 2^256 / D,
+        // blocks after the merge have difficulty 0 and no proof of work.
+        let difficulty = string(&block, "difficulty", height)?;
+        let header = Header {
+            difficulty: match significant_digits(difficulty) {
+                0 => 0,
+                digits => (digits - 1) as u32,
+            },
+            height,
+            miner: ethereum_address(&block["miner"]),
+            // Ethereum nonces have 64 bits, of which the lower 32 are kept.
+            nonce: block["nonce"]
+                .as_str()
+                .and_then(|nonce| u64::from_str_radix(nonce.trim_start_matches("0x"), 16).ok())
+                .unwrap_or(0) as u32,
+            hash: hash_field(&block, "hash", height)?,
+            previous_block_header_hash: hash_field(&block, "parentHash", height)?,
+            timestamp: quantity(&block, "timestamp", height)?
+                .try_into()
+                .map_err(|_| invalid(height, "timestamp"))?,
+            transactions_count: transactions.len() as u32,
+            transactions_merkle_root: compute_merkle_root(&transactions),
+            version: 0,
+        };
+        Ok(Block {
+            header,
+            transactions,
+        })
+    }
+
+    /// Transfer of the value from the sender to the recipient, which is the
+    /// null address for contract creations. The fee is the most the sender
+    /// can pay, as the gas used is only known from the receipt.
+    fn ethereum_transaction(
+        transaction: &Value,
+        height: u32,
+    ) -> Result<Transaction, SimulatorError> {
+        let gas = quantity(transaction, "gas", height)?;
+        let gas_price = quantity(transaction, "gasPrice", height).unwrap_or(0);
+        Ok(Transaction {
+            amount: gwei(quantity(transaction, "value", height)?)
+                .ok_or_else(|| invalid(height, "value"))?,
+            lock_time: 0,
+            receiver: ethereum_address(&transaction["to"]),
+            sender: ethereum_address(&transaction["from"]),
+            signature: "".to_string(),
+            transaction_fee: gwei(gas.saturating_mul(gas_price))
+                .ok_or_else(|| invalid(height, "gasPrice"))?,
+        })
+    }
+}
@@ -0,0 +1,311 @@
+// This module summarises a simulation in a self-contained HTML page
+pub mod report {
+    use std::{
+        collections::{BTreeMap, HashSet},
+        fmt::Write,
+    };
+
+    use log::info;
+
+    use crate::{
+        args::args::ReportArgs,
+        config::config::SimulatorContext,
+        data_sourcing::data_provider::{load_blockchain, write_text},
+        error::error::SimulatorError,
+        model::blockchain::{
+            format_timestamp, Address, Amount, Block, Blockchain, Denomination, Hash256,
+            NULL_ADDRESS,
+        },
+        orchestrator::orchestrator::{load_event_log, TraceEvent},
+        plot::plot::{chain_chart, render_svg, Chart},
+    };
+
+    /// Size in pixels of the charts embedded in the report.
+    const CHART_SIZE: (u32, u32) = (800, 450);
+
+    const STYLE: &str = "body { font-family: sans-serif; max-width: 960px; margin: 2em auto; \
+                         color: #222; } \
+                         table { border-collapse: collapse; margin-bottom: 1.5em; } \
+                         th, td { border: 1px solid #ccc; padding: 4px 10px; text-align: left; } \
+                         th { background: #f2f2f2; } \
+                         td.number { text-align: right; font-variant-numeric: tabular-nums; }";
+
+    /// Escapes the characters with a special meaning in HTML.
+    fn escape(text: &str) -> String {
+        text.replace('&', "&amp;")
+            .replace('<', "&lt;")
+            .replace('>', "&gt;")
+            .replace('"', "&quot;")
+    }
+
+    /// Blocks and fees of a miner of the chain.
+    #[derive(Default)]
+    struct MinerStats {
+        blocks: u32,
+        fees: Amount,
+    }
+
+    /// Writes a table with a header row and the rows of cells, which are
+    /// right-aligned if they are numbers. The cells are escaped.
+    fn write_table(html: &mut String, header: &[&str], rows: &[Vec<String>]) {
+        html.push_str("<table>\n<tr>");
+        for column in header {
+            write!(html, "<th>{}</th>", escape(column)).unwrap();
+        }
+        html.push_str("</tr>\n");
+        for row in rows {
+            html.push_str("<tr>");
+            for cell in row {
+                let class = match cell.parse::<f64>() {
+                    Ok(_) => " class=\"number\"",
+                    Err(_) => "",
+                };
+                write!(html, "<td{}>{}</td>", class, escape(cell)).unwrap();
+            }
+            html.push_str("</tr>\n");
+        }
+        html.push_str("</table>\n");
+    }
+
+    fn chain_statistics(
+        blockchain: &Blockchain,
+        denomination: Denomination,
+    ) -> Result<Vec<Vec<String>>, SimulatorError> {
+        let blocks = blockchain.blocks();
+        let first = &blocks
+            .first()
+            .ok_or(SimulatorError::EmptyBlockchain)?
+            .header;
+        let tip = blockchain.tip_header()?;
+        let transactions: usize = blocks
+            .iter()
+            .flat_map(|block| &block.transactions)
+            .filter(|transaction| transaction.sender != NULL_ADDRESS)
+            .count();
+        let block_fees = blocks
+            .iter()
+            .map(Block::total_fees)
+            .collect::<Result<Vec<Amount>, SimulatorError>>()?;
+        let fees = Amount::checked_sum(block_fees)
+            .ok_or_else(|| SimulatorError::AmountOverflow("fees of the chain".to_string()))?;
+        let span = tip.timestamp.saturating_sub(first.timestamp);
+        let difficulties = blocks.iter().map(|block| block.header.difficulty);
+        let row = |name: &str, value: String| vec![name.to_string(), value];
+        Ok(vec![
+            row("Blocks", blocks.len().to_string()),
+            row("Heights", format!("{} to {}", first.height, tip.height)),
+            row("Tip", tip.hash.to_string()),
+            row(
+                "Times",
+                format!(
+                    "{} to {}",
+                    format_timestamp(first.timestamp),
+                    format_timestamp(tip.timestamp)
+                ),
+            ),
+            row("Seconds from the first to the last block", span.to_string()),
+            row(
+                "Mean block interval (s)",
+                format!("{:.2}", span as f64 / (blocks.len() - 1).max(1) as f64),
+            ),
+            row(
+                "Difficulty",
+                format!(
+                    "{} to {}",
+                    difficulties.clone().min().unwrap_or(0),
+                    difficulties.max().unwrap_or(0)
+                ),
+            ),
+            row(
+                "Transactions other than the coinbase",
+                transactions.to_string(),
+            ),
+            row("Total fees", fees.format(denomination)),
+        ])
+    }
+
+    fn top_miners(
+        blockchain: &Blockchain,
+        count: usize,
+        denomination: Denomination,
+    ) -> Result<Vec<Vec<String>>, SimulatorError> {
+        let mut miners: BTreeMap<Address, MinerStats> = BTreeMap::new();
+        for block in blockchain.iter() {
+            let stats = miners.entry(block.header.miner).or_default();
+            stats.blocks += 1;
+            stats.fees = stats.fees.checked_add(block.total_fees()?).ok_or_else(|| {
+                SimulatorError::AmountOverflow(format!("fees of the miner {}", block.header.miner))
+            })?;
+        }
+        let mut miners: Vec<(Address, MinerStats)> = miners.into_iter().collect();
+        miners.sort_by_key(|(_, stats)| std::cmp::Reverse(stats.blocks));
+        Ok(miners
+            .into_iter()
+            .take(count)
+            .map(|(miner, stats)| {
+                vec![
+                    miner.to_string(),
+                    stats.blocks.to_string(),
+                    format!("{:.4}", stats.blocks as f64 / blockchain.len() as f64),
+                    stats.fees.format(denomination),
+                ]
+            })
+            .collect())
+    }
+
+    /// Writes a single HTML file with the statistics, charts and top miners
+    /// of the chain. With the event log of the orchestrated run that
+    /// produced the chain, the report also lists the reorganisations of the
+    /// nodes, the blocks that ended up stale and the arguments of the run.
+    pub fn generate_report(
+        args: ReportArgs,
+        context: &SimulatorContext,
+    ) -> Result<(), SimulatorError> {
+        info!("Loading the blockchain from {}", args.blockchain_state);
+        let blockchain = load_blockchain(&args.blockchain_state, context)?;
+        let events = match &args.event_log {
+            Some(event_log) => {
+                info!("Loading the event log from {}", event_log);
+                load_event_log(event_log)?
+            }
+            None => vec![],
+        };
+
+        let mut html = String::new();
+        let title = escape(&args.title);
+        writeln!(
+            html,
+            "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n\
+             <title>{}</title>\n<style>{}</style>\n</head>\n<body>\n<h1>{}</h1>",
+            title, STYLE, title
+        )
+        .unwrap();
+
+        html.push_str("<h2>Chain</h2>\n");
+        write_table(
+            &mut html,
+            &["Statistic", "Value"],
+            &chain_statistics(&blockchain, context.denomination)?,
+        );
+
+        html.push_str("<h2>Charts</h2>\n");
+        for chart in [Chart::BlockTimes, Chart::Difficulty, Chart::Fees] {
+            let chart = chain_chart(chart, &blockchain, None, 30)?;
+            writeln!(
+                html,
+                "<figure>\n{}</figure>",
+                render_svg(&chart, CHART_SIZE)?
+            )
+            .unwrap();
+        }
+
+        html.push_str("<h2>Top miners</h2>\n");
+        write_table(
+            &mut html,
+            &["Miner", "Blocks", "Share", "Fees"],
+            &top_miners(&blockchain, args.top_miners, context.denomination)?,
+        );
+
+        if args.event_log.is_some() {
+            let chain: HashSet<&Hash256> =
+                blockchain.iter().map(|block| &block.header.hash).collect();
+            let mut stale = vec![];
+            let mut forks = vec![];
+            for event in &events {
+                match event {
+                    TraceEvent::BlockMined { time, node, block }
+                        if !chain.contains(&block.header.hash) =>
+                    {
+                        stale.push(vec![
+                            format!("{:.2}", time),
+                            node.to_string(),
+                            block.header.height.to_string(),
+                            block.header.hash.to_string(),
+                        ])
+                    }
+                    TraceEvent::Reorg {
+                        time,
+                        node,
+                        fork_height,
+                        reverted,
+                        appended,
+                    } => forks.push(vec![
+                        format!("{:.2}", time),
+                        node.to_string(),
+                        fork_height.to_string(),
+                        reverted.len().to_string(),
+                        appended.len().to_string(),
+                    ]),
+                    _ => {}
+                }
+            }
+
+            writeln!(
+                html,
+                "<h2>Fork events</h2>\n<p>{} reorganisations</p>",
+                forks.len()
+            )
+            .unwrap();
+            write_table(
+                &mut html,
+                &[
+                    "Time (s)",
+                    "Node",
+                    "Fork height",
+                    "Reverted blocks",
+                    "Appended blocks",
+                ],
+                &forks,
+            );
+            writeln!(
+                html,
+                "<h2>Stale blocks</h2>\n<p>{} mined blocks are not part of the chain</p>",
+                stale.len()
+            )
+            .unwrap();
+            write_table(&mut html, &["Time (s)", "Miner", "Height", "Hash"], &stale);
+        }
+
+        html.push_str("<h2>Configuration</h2>\n");
+        let mut configuration = vec![
+            vec![
+                "blockchain_state".to_string(),
+                args.blockchain_state.clone(),
+            ],
+            vec![
+                "initial_subsidy".to_string(),
+                context.subsidy_schedule.initial_subsidy.to_string(),
+            ],
+            vec![
+                "halving_interval".to_string(),
+                context.subsidy_schedule.halving_interval.to_string(),
+            ],
+        ];
+        let run_args = events.iter().find_map(|event| match event {
+            TraceEvent::RunStarted { args } => Some(args),
+            _ => None,
+        });
+        if let Some(run_args) = run_args {
+            let recorded = serde_json::to_value(run_args).map_err(|source| {
+                SimulatorError::SerializationFailed {
+                    file_name: args.output.clone(),
+                    source,
+                }
+            })?;
+            if let serde_json::Value::Object(fields) = recorded {
+                for (name, value) in fields {
+                    let value = match value {
+                        serde_json::Value::String(text) => text,
+                        other => other.to_string(),
+                    };
+                    configuration.push(vec![format!("run.{}", name), value]);
+                }
+            }
+        }
+        write_table(&mut html, &["Setting", "Value"], &configuration);
+        html.push_str("</body>\n</html>\n");
+
+        info!("Writing the report to {}", args.output);
+        write_text(&args.output, context.output, &html)
+    }
+}
@@ -0,0 +1,191 @@
+// This module provides the Recursive Length Prefix encoding of headers and
+// transactions used by Ethereum
+pub mod rlp {
+    use crate::model::blockchain::{Header, Transaction};
+
+    /// Implemented by the data that can be encoded as RLP, hashing the
+    /// encoding with Keccak-256 gives the hash Ethereum tooling computes.
+    pub trait RlpEncodable {
+        fn rlp_bytes(&self) -> Vec<u8>;
+    }
+
+    /// Encodes a byte string: single bytes below 0x80 are their own
+    /// encoding, other strings are prefixed by their length.
+    pub fn encode_bytes(bytes: &[u8]) -> Vec<u8> {
+        match bytes {
+            [byte] if *byte < 0x80 => vec![*byte],
+            _ => [length_prefix(bytes.len(), 0x80), bytes.to_vec()].concat(),
+        }
+    }
+
+    /// Encodes an integer as the byte string of its big-endian bytes without
+    /// leading zeros, so zero is the empty string.
+    pub fn encode_uint(value: u64) -> Vec<u8> {
+        encode_bytes(&minimal_be_bytes(value))
+    }
+
+    /// Encodes a list of already encoded items.
+    pub fn encode_list(items: &[Vec<u8>]) -> Vec<u8> {
+        let payload = items.concat();
+        [length_prefix(payload.len(), 0xc0), payload].concat()
+    }
+
+    fn minimal_be_bytes(value: u64) -> Vec<u8> {
+        let bytes = value.to_be_bytes();
+        let leading_zeros = bytes.iter().take_while(|byte| **byte == 0).count();
+        bytes[leading_zeros..].to_vec()
+    }
+
+    fn length_prefix(length: usize, offset: u8) -> Vec<u8> {
+        if length < 56 {
+            return vec![offset + length as u8];
+        }
+        let length_bytes = minimal_be_bytes(length as u64);
+        [vec![offset + 55 + length_bytes.len() as u8], length_bytes].concat()
+    }
+
+    /// Bytes of a hex-encoded key. Strings that are not valid hex
+    /// are encoded as they are.
+    fn hex_bytes(value: &str) -> Vec<u8> {
+        let digits = value.trim_start_matches("0x");
+        hex::decode(digits).unwrap_or_else(|_| value.as_bytes().to_vec())
+    }
+
+    impl RlpEncodable for Header {
+        /// The list of the parent hash, miner, transactions merkle root,
+        /// difficulty, height, timestamp, transactions count and nonce, in
+        /// the order of the Ethereum header fields they correspond to. The
+        /// hash of the header itself is left out, and the version is appended
+        /// only for versioned blocks as in the legacy hash.
+        fn rlp_bytes(&self) -> Vec<u8> {
+            let mut fields = vec![
+                encode_bytes(&self.previous_block_header_hash.0),
+                encode_bytes(&self.miner.0),
+                encode_bytes(&self.transactions_merkle_root.0),
+                encode_uint(self.difficulty.into()),
+                encode_uint(self.height.into()),
+                encode_uint(self.timestamp),
+                encode_uint(self.transactions_count.into()),
+                encode_uint(self.nonce.into()),
+            ];
+            if self.version != 0 {
+                fields.push(encode_uint(self.version.into()));
+            }
+            encode_list(&fields)
+        }
+    }
+
+    impl RlpEncodable for Transaction {
+        /// The list of the fields in alphabetical order by their key, as in
+        /// the legacy hash: amount, lock time, receiver, sender, signature and
+        /// transaction fee. The signature is the list of its comma-separated
+        /// parts, the public key and the signature itself, which is empty for
+        /// unsigned transactions.
+        fn rlp_bytes(&self) -> Vec<u8> {
+            let signature: Vec<Vec<u8>> = self
+                .signature
+                .split(',')
+                .filter(|part| !part.is_empty())
+                .map(|part| encode_bytes(&hex_bytes(part)))
+                .collect();
+            encode_list(&[
+                encode_uint(self.amount.0),
+                encode_uint(self.lock_time.into()),
+                encode_bytes(&self.receiver.0),
+                encode_bytes(&self.sender.0),
+                encode_list(&signature),
+                encode_uint(self.transaction_fee.0),
+            ])
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use sha3::{Digest, Keccak256};
+
+        use super::{encode_bytes, encode_list, encode_uint};
+
+        const LOREM: &[u8] = b"Lorem ipsum dolor sit amet, consectetur adipisicing elit";
+
+        #[test]
+        fn strings_are_encoded() {
+            assert_eq!(encode_bytes(b"dog"), [0x83, b'd', b'o', b'g']);
+            assert_eq!(encode_bytes(b""), [0x80]);
+            assert_eq!(encode_bytes(&[0x00]), [0x00]);
+            assert_eq!(encode_bytes(&[0x7f]), [0x7f]);
+            assert_eq!(encode_bytes(&[0x80]), [0x81, 0x80]);
+        }
+
+        #[test]
+        fn long_strings_have_their_length_prefixed() {
+            assert_eq!(LOREM.len(), 56);
+            assert_eq!(encode_bytes(LOREM), [&[0xb8, 0x38], LOREM].concat());
+            assert_eq!(
+                encode_bytes(&[0xaa; 1024]),
+                [&[0xb9, 0x04, 0x00][..], &[0xaa; 1024]].concat()
+            );
+        }
+
+        #[test]
+        fn integers_are_encoded() {
+            assert_eq!(encode_uint(0), [0x80]);
+            assert_eq!(encode_uint(15), [0x0f]);
+            assert_eq!(encode_uint(1024), [0x82, 0x04, 0x00]);
+            assert_eq!(
+                encode_uint(u64::MAX),
+                [0x88, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff]
+            );
+        }
+
+        #[test]
+        fn lists_are_encoded() {
+            assert_eq!(encode_list(&[]), [0xc0]);
+            assert_eq!(
+                encode_list(&[encode_bytes(b"cat"), encode_bytes(b"dog")]),
+                [0xc8, 0x83, b'c', b'a', b't', 0x83, b'd', b'o', b'g']
+            );
+            // The set theoretical representation of three, [ [], [[]], [ [], [[]] ] ]
+            let empty = encode_list(&[]);
+            let one = encode_list(std::slice::from_ref(&empty));
+            let two = encode_list(&[empty.clone(), one.clone()]);
+            assert_eq!(
+                encode_list(&[empty, one, two]),
+                [0xc7, 0xc0, 0xc1, 0xc0, 0xc3, 0xc0, 0xc1, 0xc0]
+            );
+        }
+
+        #[test]
+        fn long_lists_have_their_length_prefixed() {
+            assert_eq!(
+                encode_list(&[encode_bytes(LOREM)]),
+                [&[0xf8, 0x3a, 0xb8, 0x38], LOREM].concat()
+            );
+        }
+
+        /// The transaction signed in the example of EIP-155, whose signing
+        /// hash is given there.
+        #[test]
+        fn keccak_of_eip155_transaction_is_its_signing_hash() {
+            let transaction = encode_list(&[
+                encode_uint(9),
+                encode_uint(20_000_000_000),
+                encode_uint(21_000),
+                encode_bytes(&[0x35; 20]),
+                encode_uint(1_000_000_000_000_000_000),
+                encode_bytes(b""),
+                encode_uint(1),
+                encode_uint(0),
+                encode_uint(0),
+            ]);
+            assert_eq!(
+                hex::encode(&transaction),
+                "ec098504a817c800825208943535353535353535353535353535353535353535\
+                 880de0b6b3a764000080018080"
+            );
+            assert_eq!(
+                hex::encode(Keccak256::digest(&transaction)),
+                "daf5a779ae972f972197303d7b574746c7ef83eadac0f2791ad23db92e4c8e53"
+            );
+        }
+    }
+}
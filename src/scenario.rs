@@ -0,0 +1,254 @@
+// This module runs experiments described declaratively in scenario files
+pub mod scenario {
+    use std::{fs, path::Path};
+
+    use log::info;
+    use serde::Deserialize;
+
+    use crate::{
+        args::args::{RunScenarioArgs, SimulateNetworkArgs},
+        config::config::SimulatorContext,
+        data_sourcing::data_provider::{
+            parse_toml, parse_yaml, read_file_contents, ReportFormat, STDIO_FILE_NAME,
+        },
+        error::error::SimulatorError,
+        model::blockchain::Address,
+        network::network::{run_network, Link, Topology},
+        node::miner::MAX_POW_DIFFICULTY,
+        simulation::fleet::VirtualMiner,
+    };
+
+    /// Experiment on the simulated network, stored as a TOML file or, with a
+    /// .yaml or .yml extension, as a YAML file.
+    #[derive(Deserialize, Debug)]
+    #[serde(deny_unknown_fields)]
+    pub struct Scenario {
+        /// Name of the experiment, shown in the logs
+        pub name: Option<String>,
+        /// Seed of the random number generator deciding the block winners
+        pub seed: u64,
+        /// Difficulty (number of leading zeros) of the simulated blocks
+        pub difficulty: u32,
+        /// Number of blocks to simulate
+        pub blocks: Option<u32>,
+        /// Number of seconds after which the miners stop
+        pub duration: Option<f64>,
+        #[serde(default)]
+        pub network: NetworkSettings,
+        pub miners: Vec<VirtualMiner>,
+        /// Links of the peer graph, which replace the topology if given
+        #[serde(default)]
+        pub links: Vec<Link>,
+        #[serde(default)]
+        pub attacks: Vec<Attack>,
+    }
+
+    /// Settings of the network the miners are linked by.
+    #[derive(Deserialize, Debug)]
+    #[serde(deny_unknown_fields, default)]
+    pub struct NetworkSettings {
+        /// Number of seconds it takes a block to travel between two nodes
+        pub latency: f64,
+        /// Fraction by which the latency of every link randomly deviates from
+        /// the default latency
+        pub latency_spread: f64,
+        pub topology: Topology,
+        /// Number of peers of every node in the regular topology
+        pub degree: usize,
+        /// Number of transactions arriving per second
+        pub transaction_rate: f64,
+        /// Maximum number of transactions included in a single block
+        pub block_capacity: u64,
+    }
+
+    impl Default for NetworkSettings {
+        fn default() -> Self {
+            NetworkSettings {
+                latency: 1.0,
+                latency_spread: 0.0,
+                topology: Topology::FullMesh,
+                degree: 4,
+                transaction_rate: 10.0,
+                block_capacity: 100,
+            }
+        }
+    }
+
+    /// Attack scheduled during the experiment, given as `[[attacks]]` tables
+    /// with a `type` field.
+    #[derive(Deserialize, Debug)]
+    #[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+    pub enum Attack {
+        /// The network is split into the partitions of the miners for the
+        /// given number of seconds
+        Partition { at: f64, duration: f64 },
+        /// The victim only hears from the attacker for the whole experiment
+        Eclipse { victim: Address, attacker: Address },
+    }
+
+    impl Scenario {
+        /// Checks the values the command line would reject for the
+        /// equivalent options of `SimulateNetwork`.
+        fn validate(&self) -> Result<(), SimulatorError> {
+            let invalid = |reason: String| Err(SimulatorError::InvalidScenario(reason));
+            let positive = |value: f64| value > 0.0 && value.is_finite();
+            if self.blocks.is_none() && self.duration.is_none() {
+                return invalid("either the number of blocks or the duration is required".into());
+            }
+            if self.difficulty > MAX_POW_DIFFICULTY {
+                return invalid(format!(
+                    "the difficulty {} is larger than {}",
+                    self.difficulty, MAX_POW_DIFFICULTY
+                ));
+            }
+            if let Some(duration) = self.duration.filter(|&duration| !positive(duration)) {
+                return invalid(format!("the duration {} is not positive", duration));
+            }
+            let network = &self.network;
+            if !positive(network.latency) {
+                return invalid(format!("the latency {} is not positive", network.latency));
+            }
+            if !(0.0..=1.0).contains(&network.latency_spread) {
+                return invalid(format!(
+                    "the latency spread {} is not between 0 and 1",
+                    network.latency_spread
+                ));
+            }
+            if !positive(network.transaction_rate) {
+                return invalid(format!(
+                    "the transaction rate {} is not positive",
+                    network.transaction_rate
+                ));
+            }
+            for miner in &self.miners {
+                miner.validate()?;
+            }
+            for link in &self.links {
+                if let Some(latency) = link.latency.filter(|&latency| !positive(latency)) {
+                    return invalid(format!(
+                        "the latency {} of the link from {} to {} is not positive",
+                        latency, link.from, link.to
+                    ));
+                }
+            }
+            for attack in &self.attacks {
+                if let Attack::Partition { at, duration } = *attack {
+                    if !positive(at) || !positive(duration) {
+                        return invalid(format!(
+                            "the partition at {} for {} seconds is not positive",
+                            at, duration
+                        ));
+                    }
+                }
+            }
+            Ok(())
+        }
+    }
+
+    /// Reads and validates the scenario, returning it together with the
+    /// text it was parsed from.
+    fn load_scenario(file_name: &str) -> Result<(Scenario, String), SimulatorError> {
+        let contents = read_file_contents(file_name)?;
+        let scenario: Scenario = match Path::new(file_name).extension().and_then(|e| e.to_str()) {
+            Some("yaml" | "yml") => parse_yaml(file_name, &contents)?,
+            _ => parse_toml(file_name, &contents)?,
+        };
+        scenario.validate()?;
+        Ok((scenario, contents.to_string()))
+    }
+
+    /// Runs the experiment described by the scenario file and writes all of
+    /// its outputs, together with a copy of the scenario, to the results
+    /// directory, so that the results can be shared with the experiment that
+    /// produced them.
+    pub fn run_scenario(
+        args: RunScenarioArgs,
+        context: &SimulatorContext,
+    ) -> Result<(), SimulatorError> {
+        info!("Loading the scenario from {}", args.scenario);
+        let (scenario, contents) = load_scenario(&args.scenario)?;
+        if let Some(name) = &scenario.name {
+            info!("Running the scenario {}", name);
+        }
+
+        let io_error = |file_name: &Path| {
+            let file_name = file_name.to_string_lossy().to_string();
+            move |source| SimulatorError::Io { file_name, source }
+        };
+        let results_dir = Path::new(&args.results_dir);
+        fs::create_dir_all(results_dir).map_err(io_error(results_dir))?;
+        // The scenario read from the standard input is parsed as TOML.
+        let scenario_copy = match args.scenario.as_str() {
+            STDIO_FILE_NAME => results_dir.join("scenario.toml"),
+            file_name => results_dir.join(
+                Path::new(file_name)
+                    .file_name()
+                    .unwrap_or("scenario".as_ref()),
+            ),
+        };
+        fs::write(&scenario_copy, contents).map_err(io_error(&scenario_copy))?;
+
+        let extension = match args.format {
+            ReportFormat::Json => "json",
+            ReportFormat::Csv => "csv",
+        };
+        let output = |name: &str, extension: &str| {
+            results_dir
+                .join(format!("{}.{}", name, extension))
+                .to_string_lossy()
+                .to_string()
+        };
+        let mut network_args = SimulateNetworkArgs {
+            fleet: args.scenario.clone(),
+            blocks_to_mine: scenario.blocks,
+            duration: scenario.duration,
+            difficulty: scenario.difficulty,
+            seed: scenario.seed,
+            latency: scenario.network.latency,
+            latency_spread: scenario.network.latency_spread,
+            topology: scenario.network.topology,
+            degree: scenario.network.degree,
+            links: None,
+            transaction_rate: scenario.network.transaction_rate,
+            block_capacity: scenario.network.block_capacity,
+            partition_at: None,
+            partition_duration: None,
+            eclipse_victim: None,
+            eclipse_attacker: None,
+            divergence_output: None,
+            reorgs_output: Some(output("reorgs", extension)),
+            trace_output: Some(output("trace", "json")),
+            report_output: output("report", extension),
+            format: args.format,
+            output_options: args.output_options,
+        };
+        for attack in &scenario.attacks {
+            match *attack {
+                Attack::Partition { .. } if network_args.partition_at.is_some() => {
+                    return Err(SimulatorError::InvalidScenario(
+                        "only one partition can be scheduled".to_string(),
+                    ))
+                }
+                Attack::Partition { at, duration } => {
+                    network_args.partition_at = Some(at);
+                    network_args.partition_duration = Some(duration);
+                }
+                Attack::Eclipse { .. } if network_args.eclipse_victim.is_some() => {
+                    return Err(SimulatorError::InvalidScenario(
+                        "only one eclipse attack can be scheduled".to_string(),
+                    ))
+                }
+                Attack::Eclipse { victim, attacker } => {
+                    network_args.eclipse_victim = Some(victim);
+                    network_args.eclipse_attacker = Some(attacker);
+                    network_args.divergence_output = Some(output("divergence", extension));
+                }
+            }
+        }
+
+        let links = (!scenario.links.is_empty()).then_some(scenario.links);
+        run_network(&network_args, &scenario.miners, links, context.output)?;
+        info!("Wrote the results to {}", args.results_dir);
+        Ok(())
+    }
+}
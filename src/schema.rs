@@ -0,0 +1,53 @@
+// This module describes the formats of the data files as JSON Schemas
+pub mod schema {
+    use clap::ValueEnum;
+    use log::info;
+    use schemars::schema_for;
+
+    use crate::{
+        args::args::SchemaArgs,
+        config::config::SimulatorContext,
+        data_sourcing::data_provider::write_json,
+        error::error::SimulatorError,
+        model::blockchain::{
+            Block, DifficultyStep, InclusionProof, MiningStats, ProofIndex, Transaction,
+        },
+    };
+
+    #[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+    pub enum DataFile {
+        /// The list of blocks of a blockchain file
+        Blockchain,
+        /// The list of transactions of a mempool file
+        Mempool,
+        /// An inclusion proof written by generate-inclusion-proof
+        InclusionProof,
+        /// The inclusion proofs of a block written by generate-proof-index
+        ProofIndex,
+        /// The difficulty schedule read by produce-blocks
+        DifficultySchedule,
+        /// The mining statistics written by produce-blocks
+        MiningStats,
+    }
+
+    /// Writes the JSON Schema of the data file, generated from the types the
+    /// simulator reads the file into.
+    pub fn export_schema(
+        args: SchemaArgs,
+        context: &SimulatorContext,
+    ) -> Result<(), SimulatorError> {
+        let schema = match args.file {
+            DataFile::Blockchain => schema_for!(Vec<Block>),
+            DataFile::Mempool => schema_for!(Vec<Transaction>),
+            DataFile::InclusionProof => schema_for!(InclusionProof),
+            DataFile::ProofIndex => schema_for!(ProofIndex),
+            DataFile::DifficultySchedule => schema_for!(Vec<DifficultyStep>),
+            DataFile::MiningStats => schema_for!(Vec<MiningStats>),
+        };
+        info!(
+            "Writing the schema of the {:?} file to {}",
+            args.file, args.output
+        );
+        write_json(&args.output, context.output, &schema)
+    }
+}
@@ -0,0 +1,582 @@
+// This module simulates the behaviour of the network without mining the blocks
+pub mod fleet {
+    use clap::ValueEnum;
+    use log::info;
+    use rand::{Rng, SeedableRng};
+    use rand_chacha::ChaCha20Rng;
+    use serde::{Deserialize, Serialize};
+
+    use crate::{
+        args::args::SimulateMinersArgs,
+        config::config::SimulatorContext,
+        data_sourcing::data_provider::{load_toml, write_report},
+        error::error::SimulatorError,
+        model::blockchain::Address,
+    };
+
+    use super::bft::simulate_bft;
+
+    #[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+    pub enum Consensus {
+        /// Proof of work, the miners race for each block
+        Pow,
+        /// Rounds of proposals and votes among the miners acting as validators
+        Bft,
+    }
+
+    /// Fleet file listing the miners as `[[miners]]` tables.
+    #[derive(Deserialize, Debug)]
+    #[serde(deny_unknown_fields)]
+    pub struct Fleet {
+        pub miners: Vec<VirtualMiner>,
+    }
+
+    #[derive(Deserialize, Debug, Clone)]
+    #[serde(deny_unknown_fields)]
+    pub struct VirtualMiner {
+        pub address: Address,
+        /// Hashes per second
+        pub hashrate: f64,
+        /// Seconds from the start of the simulation at which the miner starts
+        /// mining
+        #[serde(default)]
+        pub joins_at: f64,
+        /// Seconds from the start of the simulation at which the miner stops
+        /// mining, the miner stays until the end if not set
+        pub leaves_at: Option<f64>,
+        /// Seconds added to the actual time in the timestamps of the blocks
+        /// mined by this miner, used by the time-warp scenario
+        #[serde(default)]
+        pub timestamp_skew: f64,
+        /// Whether the miner, acting as a BFT validator, withholds its
+        /// proposals and votes
+        #[serde(default)]
+        pub byzantine: bool,
+        /// Group of nodes the miner stays connected to while the network is
+        /// partitioned
+        #[serde(default)]
+        pub partition: u32,
+    }
+
+    impl VirtualMiner {
+        pub fn is_active(&self, time: f64) -> bool {
+            self.joins_at <= time && self.leaves_at.is_none_or(|leaves_at| time < leaves_at)
+        }
+
+        /// Checks that the hashrate is not negative and that the miner leaves
+        /// after it joins.
+        pub fn validate(&self) -> Result<(), SimulatorError> {
+            let invalid = |reason: String| {
+                Err(SimulatorError::InvalidMiner {
+                    address: self.address,
+                    reason,
+                })
+            };
+            if !(self.hashrate >= 0.0 && self.hashrate.is_finite()) {
+                return invalid(format!("the hashrate {} is negative", self.hashrate));
+            }
+            if !self.joins_at.is_finite() {
+                return invalid(format!("the joining time {} is not finite", self.joins_at));
+            }
+            if let Some(leaves_at) = self
+                .leaves_at
+                .filter(|&leaves_at| leaves_at <= self.joins_at)
+            {
+                return invalid(format!(
+                    "it leaves at {} but only joins at {}",
+                    leaves_at, self.joins_at
+                ));
+            }
+            Ok(())
+        }
+    }
+
+    /// Loads the fleet file and validates its miners.
+    pub fn load_fleet(file_name: &str) -> Result<Fleet, SimulatorError> {
+        let fleet: Fleet = load_toml(file_name)?;
+        for miner in &fleet.miners {
+            miner.validate()?;
+        }
+        Ok(fleet)
+    }
+
+    #[derive(Serialize)]
+    pub struct SimulatedBlock {
+        pub height: u32,
+        /// Seconds from the start of the simulation
+        pub time: f64,
+        pub miner: Address,
+    }
+
+    #[derive(Serialize)]
+    struct MinerReportRow {
+        miner: Address,
+        hashrate: f64,
+        blocks_won: u32,
+        /// Fraction of the blocks won by the miner
+        revenue_share: f64,
+        /// Fraction of all hashes computed during the simulation that were
+        /// computed by the miner, which is its expected share of the blocks
+        hashrate_share: f64,
+    }
+
+    /// Race between the miners of the fleet for the successive blocks. Finding
+    /// a block is a Poisson process whose rate is the total active hashrate
+    /// divided by the expected number of attempts. As the process is
+    /// memoryless, the race simply restarts whenever a miner joins or leaves.
+    pub struct BlockRace<'a> {
+        miners: &'a [VirtualMiner],
+        /// Seconds from the start of the simulation
+        pub time: f64,
+        /// Number of hashes computed by each miner so far
+        pub hashes: Vec<f64>,
+    }
+
+    impl<'a> BlockRace<'a> {
+        pub fn new(miners: &'a [VirtualMiner]) -> Self {
+            BlockRace {
+                miners,
+                time: 0.0,
+                hashes: vec![0.0; miners.len()],
+            }
+        }
+
+        /// Advances the time until the next block is found and returns the
+        /// index of the miner who found it.
+        pub fn next_block(
+            &mut self,
+            expected_attempts: f64,
+            rng: &mut impl Rng,
+        ) -> Result<usize, SimulatorError> {
+            let miners = self.miners;
+            loop {
+                let time = self.time;
+                let active: Vec<usize> = (0..miners.len())
+                    .filter(|&i| miners[i].is_active(time))
+                    .collect();
+                let next_change = miners
+                    .iter()
+                    .flat_map(|miner| [Some(miner.joins_at), miner.leaves_at])
+                    .flatten()
+                    .filter(|&change| change > time)
+                    .fold(f64::INFINITY, f64::min);
+                let total_hashrate: f64 = active.iter().map(|&i| miners[i].hashrate).sum();
+                if total_hashrate <= 0.0 {
+                    if next_change.is_infinite() {
+                        return Err(SimulatorError::NoActiveMiners(time));
+                    }
+                    self.time = next_change;
+                    continue;
+                }
+
+                let rate = total_hashrate / expected_attempts;
+                let waiting_time = -(1.0 - rng.gen::<f64>()).ln() / rate;
+                let elapsed = waiting_time.min(next_change - time);
+                for &i in &active {
+                    self.hashes[i] += elapsed * miners[i].hashrate;
+                }
+                self.time += elapsed;
+                if waiting_time > elapsed {
+                    continue;
+                }
+
+                // The winner is picked with probability proportional to its
+                // share of the active hashrate.
+                let mut target = rng.gen::<f64>() * total_hashrate;
+                let winner = *active
+                    .iter()
+                    .find(|&&i| {
+                        target -= miners[i].hashrate;
+                        target < 0.0
+                    })
+                    .unwrap_or(active.last().unwrap());
+                return Ok(winner);
+            }
+        }
+    }
+
+    /// Simulates which miners win the blocks at a fixed difficulty, where
+    /// each block takes 16^difficulty attempts on average, or which validators
+    /// propose the blocks committed by BFT consensus.
+    pub fn simulate_miners(
+        args: SimulateMinersArgs,
+        context: &SimulatorContext,
+    ) -> Result<(), SimulatorError> {
+        info!("Loading the fleet of miners from {}", args.fleet);
+        let fleet = load_fleet(&args.fleet)?;
+        let miners = fleet.miners;
+        let difficulty = match (args.consensus.unwrap_or(Consensus::Pow), args.difficulty) {
+            (Consensus::Pow, Some(difficulty)) => difficulty,
+            // clap requires the difficulty for proof of work.
+            _ => return simulate_bft(&args, &miners, context.output),
+        };
+
+        let mut rng = ChaCha20Rng::seed_from_u64(args.seed);
+        let expected_attempts = 16f64.powi(difficulty as i32);
+        let mut blocks_won = vec![0u32; miners.len()];
+        let mut blocks = vec![];
+
+        info!(
+            "Simulating {} blocks mined by {} miners...",
+            args.blocks_to_mine,
+            miners.len()
+        );
+        let mut race = BlockRace::new(&miners);
+        for height in 1..=args.blocks_to_mine {
+            let winner = race.next_block(expected_attempts, &mut rng)?;
+            blocks_won[winner] += 1;
+            blocks.push(SimulatedBlock {
+                height,
+                time: race.time,
+                miner: miners[winner].address,
+            });
+        }
+
+        info!(
+            "Simulated {} blocks in {:.0} seconds",
+            args.blocks_to_mine, race.time
+        );
+        let hashes = race.hashes;
+        let total_hashes: f64 = hashes.iter().sum();
+        let rows: Vec<MinerReportRow> = miners
+            .iter()
+            .enumerate()
+            .map(|(i, miner)| MinerReportRow {
+                miner: miner.address,
+                hashrate: miner.hashrate,
+                blocks_won: blocks_won[i],
+                revenue_share: blocks_won[i] as f64 / args.blocks_to_mine.max(1) as f64,
+                hashrate_share: if total_hashes > 0.0 {
+                    hashes[i] / total_hashes
+                } else {
+                    0.0
+                },
+            })
+            .collect();
+
+        if let Some(blocks_output) = &args.blocks_output {
+            write_report(blocks_output, context.output, args.format, &blocks)?;
+        }
+        write_report(&args.report_output, context.output, args.format, &rows)
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use rand::SeedableRng;
+        use rand_chacha::ChaCha20Rng;
+
+        use crate::{error::error::SimulatorError, model::blockchain::Address};
+
+        use super::{BlockRace, VirtualMiner};
+
+        fn miner(address: u8, joins_at: f64, leaves_at: Option<f64>) -> VirtualMiner {
+            VirtualMiner {
+                address: Address([address; 20]),
+                hashrate: 1.0,
+                joins_at,
+                leaves_at,
+                timestamp_skew: 0.0,
+                byzantine: false,
+                partition: 0,
+            }
+        }
+
+        #[test]
+        fn miner_is_active_from_joining_until_leaving() {
+            let miner = miner(1, 10.0, Some(20.0));
+            assert!(!miner.is_active(9.0));
+            assert!(miner.is_active(10.0));
+            assert!(miner.is_active(19.0));
+            assert!(!miner.is_active(20.0));
+        }
+
+        #[test]
+        fn miner_leaving_before_joining_is_invalid() {
+            assert!(miner(1, 10.0, Some(20.0)).validate().is_ok());
+            assert!(matches!(
+                miner(1, 10.0, Some(10.0)).validate(),
+                Err(SimulatorError::InvalidMiner { .. })
+            ));
+            let mut negative = miner(1, 0.0, None);
+            negative.hashrate = -1.0;
+            assert!(negative.validate().is_err());
+        }
+
+        #[test]
+        fn race_waits_for_the_first_miner_to_join() {
+            let miners = [miner(1, 0.0, Some(5.0)), miner(2, 100.0, None)];
+            let mut race = BlockRace::new(&miners);
+            race.time = 5.0;
+            let mut rng = ChaCha20Rng::seed_from_u64(0);
+            assert_eq!(race.next_block(1.0, &mut rng).unwrap(), 1);
+            assert!(race.time >= 100.0);
+            assert_eq!(race.hashes[0], 0.0);
+        }
+
+        #[test]
+        fn race_without_miners_fails() {
+            let miners = [miner(1, 0.0, Some(5.0))];
+            let mut race = BlockRace::new(&miners);
+            race.time = 5.0;
+            let mut rng = ChaCha20Rng::seed_from_u64(0);
+            assert!(matches!(
+                race.next_block(1.0, &mut rng),
+                Err(SimulatorError::NoActiveMiners(_))
+            ));
+        }
+    }
+}
+
+pub mod bft {
+    use log::{info, warn};
+    use rand::SeedableRng;
+    use rand_chacha::ChaCha20Rng;
+    use rand_distr::{Distribution, Exp};
+    use serde::Serialize;
+
+    use crate::{
+        args::args::SimulateMinersArgs,
+        data_sourcing::data_provider::{write_report, OutputOptions},
+        error::error::SimulatorError,
+        model::blockchain::Address,
+    };
+
+    use super::fleet::{SimulatedBlock, VirtualMiner};
+
+    #[derive(Serialize)]
+    struct HeightReportRow {
+        height: u32,
+        /// Number of rounds it took to commit the block
+        rounds: u32,
+        proposer: Address,
+        /// Seconds from the start of the simulation at which the block was
+        /// committed
+        time: f64,
+    }
+
+    /// Simulates Tendermint-style consensus among the miners of the fleet
+    /// acting as validators with equal voting power. In every round the
+    /// proposer (rotating with the height and the round) broadcasts a block,
+    /// the validators that receive it in time prevote for it and, once more
+    /// than two thirds of the prevotes arrive, precommit it. The block is
+    /// committed when more than two thirds of the precommits arrive. A round
+    /// fails if the proposer is byzantine or inactive or if any step times
+    /// out, in which case the next round waits longer. Byzantine validators
+    /// never vote.
+    pub fn simulate_bft(
+        args: &SimulateMinersArgs,
+        validators: &[VirtualMiner],
+        output: OutputOptions,
+    ) -> Result<(), SimulatorError> {
+        if validators.is_empty() {
+            return Err(SimulatorError::NoActiveMiners(0.0));
+        }
+        let mut rng = ChaCha20Rng::seed_from_u64(args.seed);
+        let delay = Exp::new(1.0 / args.message_delay).expect("the delay is positive");
+        let quorum = validators.len() * 2 / 3 + 1;
+
+        info!(
+            "Simulating {} heights of BFT consensus among {} validators ({} byzantine)...",
+            args.blocks_to_mine,
+            validators.len(),
+            validators.iter().filter(|v| v.byzantine).count()
+        );
+        let mut time = 0.0;
+        let mut rows = vec![];
+        let mut blocks = vec![];
+        'heights: for height in 1..=args.blocks_to_mine {
+            for round in 0..args.max_rounds {
+                let timeout = args.round_timeout * (round + 1) as f64;
+                let proposer = &validators[(height + round) as usize % validators.len()];
+                let honest: Vec<&VirtualMiner> = validators
+                    .iter()
+                    .filter(|v| !v.byzantine && v.is_active(time))
+                    .collect();
+
+                // Arrival times of the votes, relative to the start of the round.
+                let mut prevotes: Vec<f64> = honest
+                    .iter()
+                    .filter_map(|_| {
+                        let received = delay.sample(&mut rng);
+                        (received <= timeout).then(|| received + delay.sample(&mut rng))
+                    })
+                    .collect();
+                prevotes.sort_by(f64::total_cmp);
+                let prevote_quorum = prevotes.get(quorum - 1).copied();
+
+                let commit = prevote_quorum.and_then(|prevote_quorum| {
+                    let mut precommits: Vec<f64> = prevotes
+                        .iter()
+                        .map(|_| prevote_quorum + delay.sample(&mut rng))
+                        .collect();
+                    precommits.sort_by(f64::total_cmp);
+                    precommits.get(quorum - 1).copied()
+                });
+
+                let proposer_honest = !proposer.byzantine && proposer.is_active(time);
+                match commit {
+                    Some(commit)
+                        if proposer_honest
+                            && prevote_quorum.is_some_and(|t| t <= 2.0 * timeout)
+                            && commit <= 3.0 * timeout =>
+                    {
+                        time += commit;
+                        rows.push(HeightReportRow {
+                            height,
+                            rounds: round + 1,
+                            proposer: proposer.address,
+                            time,
+                        });
+                        blocks.push(SimulatedBlock {
+                            height,
+                            time,
+                            miner: proposer.address,
+                        });
+                        continue 'heights;
+                    }
+                    _ => time += 3.0 * timeout,
+                }
+            }
+            warn!(
+                "No block was committed at height {} within {} rounds, the network lost liveness",
+                height, args.max_rounds
+            );
+            break;
+        }
+
+        let total_rounds: u32 = rows.iter().map(|row| row.rounds).sum();
+        info!(
+            "Committed {} blocks in {:.0} seconds with {:.2} rounds per block on average",
+            rows.len(),
+            time,
+            total_rounds as f64 / rows.len().max(1) as f64
+        );
+
+        if let Some(blocks_output) = &args.blocks_output {
+            write_report(blocks_output, output, args.format, &blocks)?;
+        }
+        write_report(&args.report_output, output, args.format, &rows)
+    }
+}
+
+pub mod timewarp {
+    use log::info;
+    use rand::SeedableRng;
+    use rand_chacha::ChaCha20Rng;
+    use serde::Serialize;
+
+    use crate::{
+        args::args::TimeWarpArgs, config::config::SimulatorContext,
+        data_sourcing::data_provider::write_report, error::error::SimulatorError,
+        model::blockchain::Address,
+    };
+
+    use super::fleet::{load_fleet, BlockRace};
+
+    /// Bounds of the adjustment of the difficulty at each retarget, as a
+    /// factor of the previous difficulty.
+    const MAX_RETARGET_FACTOR: f64 = 4.0;
+
+    #[derive(Serialize)]
+    struct TimeWarpRow {
+        rules_enforced: bool,
+        height: u32,
+        /// Seconds from the start of the simulation at which the block was
+        /// found
+        time: f64,
+        /// Timestamp reported in the block header
+        timestamp: f64,
+        miner: Address,
+        /// Difficulty the block was mined at, fractional as the retargeting
+        /// adjusts the expected number of attempts continuously
+        difficulty: f64,
+    }
+
+    /// Simulates a fleet of miners, some of which may skew the timestamps of
+    /// their blocks, while the difficulty is retargeted every
+    /// `retarget_interval` blocks so that blocks are found every
+    /// `block_interval` seconds according to the timestamps. The scenario is
+    /// run twice with the same seed: once with the timestamps clamped by the
+    /// median-time-past and future drift rules and once accepting them as
+    /// reported.
+    pub fn simulate_time_warp(
+        args: TimeWarpArgs,
+        context: &SimulatorContext,
+    ) -> Result<(), SimulatorError> {
+        info!("Loading the fleet of miners from {}", args.fleet);
+        let fleet = load_fleet(&args.fleet)?;
+
+        let mut rows = vec![];
+        for rules_enforced in [true, false] {
+            info!(
+                "Simulating {} blocks with the timestamp rules {}...",
+                args.blocks_to_mine,
+                if rules_enforced {
+                    "enforced"
+                } else {
+                    "ignored"
+                }
+            );
+            let mut rng = ChaCha20Rng::seed_from_u64(args.seed);
+            let mut race = BlockRace::new(&fleet.miners);
+            let mut expected_attempts = 16f64.powi(args.difficulty as i32);
+            let mut timestamps: Vec<f64> = vec![0.0];
+
+            for height in 1..=args.blocks_to_mine {
+                let winner = race.next_block(expected_attempts, &mut rng)?;
+                let miner = &fleet.miners[winner];
+                let mut timestamp = race.time + miner.timestamp_skew;
+                if rules_enforced {
+                    let earliest =
+                        median_time_past(&timestamps, args.median_time_span as usize) + 1.0;
+                    let latest = race.time + args.max_future_drift as f64;
+                    timestamp = timestamp.min(latest).max(earliest);
+                }
+                timestamps.push(timestamp);
+                rows.push(TimeWarpRow {
+                    rules_enforced,
+                    height,
+                    time: race.time,
+                    timestamp,
+                    miner: miner.address,
+                    difficulty: expected_attempts.log(16.0),
+                });
+
+                if height % args.retarget_interval == 0 {
+                    let window =
+                        &timestamps[timestamps.len() - 1 - args.retarget_interval as usize..];
+                    let target_timespan = (args.block_interval * args.retarget_interval) as f64;
+                    let actual_timespan = (window[window.len() - 1] - window[0]).clamp(
+                        target_timespan / MAX_RETARGET_FACTOR,
+                        target_timespan * MAX_RETARGET_FACTOR,
+                    );
+                    expected_attempts *= target_timespan / actual_timespan;
+                }
+            }
+            info!("Final difficulty: {:.2}", expected_attempts.log(16.0));
+        }
+
+        write_report(&args.report_output, context.output, args.format, &rows)
+    }
+
+    /// Median of the timestamps of the last `span` blocks, which the timestamp
+    /// of the next block has to exceed.
+    fn median_time_past(timestamps: &[f64], span: usize) -> f64 {
+        let mut recent = timestamps[timestamps.len().saturating_sub(span)..].to_vec();
+        recent.sort_by(f64::total_cmp);
+        recent[recent.len() / 2]
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::median_time_past;
+
+        #[test]
+        fn median_time_past_only_considers_the_recent_blocks() {
+            let timestamps = [100.0, 1.0, 5.0, 3.0, 4.0];
+            assert_eq!(median_time_past(&timestamps, 3), 4.0);
+            assert_eq!(median_time_past(&timestamps, 11), 4.0);
+            assert_eq!(median_time_past(&timestamps[..2], 11), 100.0);
+        }
+    }
+}
@@ -0,0 +1,38 @@
+// This module provides checkpoints of the simulation for fast restarts
+pub mod snapshot {
+    use serde::{Deserialize, Serialize};
+    use sha256::digest;
+
+    use crate::{
+        model::blockchain::{Header, Transaction},
+        state::state::Balances,
+    };
+
+    /// Compact checkpoint of the simulation holding everything needed to
+    /// continue mining: the header of the tip of the chain, the balances after
+    /// applying all blocks up to it and a digest identifying the mempool
+    /// remaining at that point.
+    #[derive(Serialize, Deserialize, Debug, Clone)]
+    pub struct Snapshot {
+        pub tip: Header,
+        pub balances: Balances,
+        pub mempool_digest: String,
+        pub mempool_size: usize,
+    }
+
+    impl Snapshot {
+        pub fn new(tip: &Header, balances: &Balances, mempool: &[Transaction]) -> Snapshot {
+            Snapshot {
+                tip: tip.clone(),
+                balances: balances.clone(),
+                mempool_digest: mempool_digest(mempool),
+                mempool_size: mempool.len(),
+            }
+        }
+    }
+
+    /// SHA-256 hash of the compact JSON encoding of the mempool.
+    pub fn mempool_digest(mempool: &[Transaction]) -> String {
+        "0x".to_string() + &digest(serde_json::to_string(mempool).unwrap())
+    }
+}
@@ -0,0 +1,202 @@
+// This module tracks the account balances resulting from the blockchain
+pub mod state {
+    use std::collections::BTreeMap;
+
+    use crate::{
+        error::error::SimulatorError,
+        hashing::hashing::Hashable,
+        model::blockchain::{Address, Amount, Block, Blockchain, NULL_ADDRESS},
+    };
+
+    /// Coinbase subsidy credited to the miner of every block, which is halved
+    /// every `halving_interval` blocks until it reaches zero.
+    #[derive(Clone, Copy, Debug, PartialEq, Eq)]
+    pub struct SubsidySchedule {
+        pub initial_subsidy: Amount,
+        pub halving_interval: u32,
+    }
+
+    impl Default for SubsidySchedule {
+        fn default() -> Self {
+            SubsidySchedule {
+                initial_subsidy: Amount(5_000_000_000),
+                halving_interval: 210_000,
+            }
+        }
+    }
+
+    impl SubsidySchedule {
+        pub fn subsidy_at(&self, height: u32) -> Amount {
+            let halvings = height / self.halving_interval;
+            Amount(self.initial_subsidy.0.checked_shr(halvings).unwrap_or(0))
+        }
+    }
+
+    /// Balance of every account that took part in a transaction or mined a
+    /// block. Balances are signed as the senders of the tutorial transactions
+    /// were never funded and would otherwise underflow.
+    pub type Balances = BTreeMap<Address, i64>;
+
+    /// Moves the amounts of the block's transactions from their senders to
+    /// their receivers and credits the subsidy and the fees to the miner. The
+    /// null address funding the genesis allocations is never debited. Fails if
+    /// a balance no longer fits.
+    pub fn apply_block(
+        balances: &mut Balances,
+        block: &Block,
+        schedule: &SubsidySchedule,
+    ) -> Result<(), SimulatorError> {
+        let height = block.header.height;
+        let mut adjust = |address: Address, change: i128| {
+            let balance = balances.entry(address).or_default();
+            *balance = i64::try_from(*balance as i128 + change).map_err(|_| {
+                SimulatorError::InvalidBlock {
+                    height,
+                    reason: format!("the balance of {} overflows", address),
+                }
+            })?;
+            Ok::<(), SimulatorError>(())
+        };
+        let subsidy = schedule.subsidy_at(height);
+        adjust(block.header.miner, subsidy.0 as i128)?;
+        for transaction in &block.transactions {
+            let (amount, fee) = (
+                transaction.amount.0 as i128,
+                transaction.transaction_fee.0 as i128,
+            );
+            if transaction.sender != NULL_ADDRESS {
+                adjust(transaction.sender, -(amount + fee))?;
+            }
+            adjust(transaction.receiver, amount)?;
+            adjust(block.header.miner, fee)?;
+        }
+        Ok(())
+    }
+
+    /// Balances replayed by the supply audit, which may never go negative.
+    pub type AuditBalances = BTreeMap<Address, Amount>;
+
+    /// Outcome of replaying a block in the supply audit.
+    pub struct BlockAudit {
+        /// Coins created by the block. As blocks carry no coinbase
+        /// transaction, the coinbase consists of the subsidy credited to the
+        /// miner and any amounts sent from the null address.
+        pub minted: u128,
+        /// Transactions that spend more than the sender holds or overflow a
+        /// balance, which are left out of the balances
+        pub problems: Vec<String>,
+    }
+
+    /// Applies the block to the audited balances, skipping the transactions
+    /// that can't be applied so that one overspend doesn't taint the
+    /// following blocks.
+    pub fn audit_block(
+        balances: &mut AuditBalances,
+        block: &Block,
+        schedule: &SubsidySchedule,
+    ) -> BlockAudit {
+        let subsidy = schedule.subsidy_at(block.header.height);
+        let mut audit = BlockAudit {
+            minted: subsidy.0 as u128,
+            problems: vec![],
+        };
+        if let Err(problem) = transfer(balances, None, block.header.miner, subsidy, Amount::ZERO) {
+            audit.problems.push(problem);
+        }
+        for transaction in &block.transactions {
+            let sender = (transaction.sender != NULL_ADDRESS).then_some(transaction.sender);
+            let result = transfer(
+                balances,
+                sender,
+                transaction.receiver,
+                transaction.amount,
+                transaction.transaction_fee,
+            )
+            .and_then(|_| {
+                transfer(
+                    balances,
+                    None,
+                    block.header.miner,
+                    transaction.transaction_fee,
+                    Amount::ZERO,
+                )
+            });
+            match result {
+                Ok(()) if sender.is_none() => audit.minted += transaction.amount.0 as u128,
+                Ok(()) => {}
+                Err(problem) => audit.problems.push(format!(
+                    "the transaction {}: {}",
+                    transaction.hash(),
+                    problem
+                )),
+            }
+        }
+        audit
+    }
+
+    /// Moves the amount to the receiver, debiting the amount and the fee from
+    /// the sender, or minting the amount if there is no sender. The balances
+    /// are left unchanged on failure.
+    fn transfer(
+        balances: &mut AuditBalances,
+        sender: Option<Address>,
+        receiver: Address,
+        amount: Amount,
+        fee: Amount,
+    ) -> Result<(), String> {
+        let balance = |address| balances.get(&address).copied().unwrap_or_default();
+        let debit = match sender {
+            Some(sender) => {
+                let held = balance(sender);
+                let left = amount
+                    .checked_add(fee)
+                    .and_then(|spent| held.checked_sub(spent))
+                    .ok_or_else(|| {
+                        format!("{} spends more than the {} coins it holds", sender, held)
+                    })?;
+                Some((sender, left))
+            }
+            None => None,
+        };
+        let before = match debit {
+            Some((sender, left)) if sender == receiver => left,
+            _ => balance(receiver),
+        };
+        let received = before
+            .checked_add(amount)
+            .ok_or_else(|| format!("the balance of {} overflows", receiver))?;
+        if let Some((sender, left)) = debit {
+            balances.insert(sender, left);
+        }
+        balances.insert(receiver, received);
+        Ok(())
+    }
+
+    /// Coins the block is allowed to mint: the scheduled coinbase subsidy,
+    /// and for the genesis block also the allocations sent from the null
+    /// address.
+    pub fn expected_issuance(block: &Block, schedule: &SubsidySchedule) -> u128 {
+        let subsidy = schedule.subsidy_at(block.header.height).0 as u128;
+        if block.header.height != 0 {
+            return subsidy;
+        }
+        let allocations: u128 = block
+            .transactions
+            .iter()
+            .filter(|t| t.sender == NULL_ADDRESS)
+            .map(|t| t.amount.0 as u128)
+            .sum();
+        subsidy + allocations
+    }
+
+    pub fn compute_balances(
+        blockchain: &Blockchain,
+        schedule: &SubsidySchedule,
+    ) -> Result<Balances, SimulatorError> {
+        let mut balances = Balances::new();
+        for block in blockchain {
+            apply_block(&mut balances, block, schedule)?;
+        }
+        Ok(balances)
+    }
+}
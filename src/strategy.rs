@@ -0,0 +1,271 @@
+// This module provides the plugin point for the order in which the nonces
+// are searched when mining
+pub mod strategy {
+    use clap::ValueEnum;
+    use log::info;
+    use rand::{Rng, SeedableRng};
+    use rand_chacha::ChaCha8Rng;
+
+    use crate::{
+        error::error::SimulatorError,
+        hasher::hasher::{HasherSpec, PowHasher},
+        hashing::hashing::HeaderMidstate,
+        model::blockchain::{is_valid_block_header_hash, Hash256, Header, HeaderFormat},
+    };
+
+    /// Number of nonces tested between two progress messages.
+    const LOG_EVERY_N_NONCES: u64 = 100000;
+
+    /// Number of nonces tested between two checks of whether the search is
+    /// interrupted.
+    pub const CHECK_INTERRUPTION_EVERY_N_NONCES: u64 = 1000;
+
+    /// Search for the nonce of a header, implemented by the strategies
+    /// selected with `--mining-strategy` and by any other search that mines
+    /// blocks through `BlockTemplate::mine()`.
+    pub trait MiningStrategy: Sync {
+        /// Searches for a nonce giving the header a hash with the required
+        /// number of leading zeros in the format, computing the hashes with
+        /// the hasher. Returns none if `interrupted` returns true, which is
+        /// checked every thousand nonces.
+        fn find_nonce(
+            &self,
+            header: &Header,
+            format: HeaderFormat,
+            difficulty: usize,
+            hasher: &mut dyn PowHasher,
+            interrupted: &dyn Fn() -> bool,
+        ) -> Result<Option<NonceFound>, SimulatorError>;
+
+        /// Whether the strategy hashes with its own SHA-256 rather than with
+        /// the hasher selected with `--hasher`.
+        fn uses_builtin_sha256(&self) -> bool {
+            false
+        }
+    }
+
+    /// Strategy selected with the `--mining-strategy` flag.
+    #[derive(ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+    pub enum MiningStrategyKind {
+        /// Try the nonces in order starting from zero
+        #[default]
+        Sequential,
+        /// Try nonces drawn at random, seeded by the header so that the same
+        /// header always gets the same nonce
+        Random,
+        /// Try the nonces in order, hashing the part of the header before the
+        /// nonce only once. Requires the builtin hasher
+        Midstate,
+    }
+
+    impl MiningStrategyKind {
+        pub fn strategy(self) -> &'static dyn MiningStrategy {
+            match self {
+                MiningStrategyKind::Sequential => &SequentialNonces,
+                MiningStrategyKind::Random => &RandomNonces,
+                MiningStrategyKind::Midstate => &Midstate,
+            }
+        }
+    }
+
+    /// Nonce found by a strategy together with the number of nonces it tried.
+    #[derive(Clone, Copy, Debug, PartialEq, Eq)]
+    pub struct NonceFound {
+        pub nonce: u32,
+        pub nonces_tried: u64,
+    }
+
+    /// How the nonces of the mined blocks are searched for: the hasher, the
+    /// strategy and the number of headers hashed per second at most.
+    #[derive(Clone, Copy)]
+    pub struct NonceSearch<'a> {
+        pub hasher: &'a HasherSpec,
+        pub strategy: &'a dyn MiningStrategy,
+        pub hashrate: Option<f64>,
+    }
+
+    /// Hash of the header in the format computed with the hasher, which is
+    /// applied twice to the 80-byte serialization of Bitcoin headers.
+    pub fn hash_header(
+        hasher: &mut dyn PowHasher,
+        header: &Header,
+        format: HeaderFormat,
+    ) -> Result<Hash256, SimulatorError> {
+        let digest = match format {
+            HeaderFormat::Simulator => hasher.sha256(header.hash_preimage().as_bytes())?,
+            HeaderFormat::Tutorial => hasher.sha256(header.specified_preimage().as_bytes())?,
+            HeaderFormat::Bitcoin => {
+                let first = hasher.sha256(&header.bitcoin_bytes())?;
+                let mut digest = hasher.sha256(&first)?;
+                digest.reverse();
+                digest
+            }
+        };
+        Ok(Hash256(digest))
+    }
+
+    /// Tries the nonces in order from the given one until the hash computed
+    /// by `hash` is valid, failing if the largest nonce is reached without
+    /// one being valid.
+    fn search_in_order(
+        height: u32,
+        first_nonce: u32,
+        difficulty: usize,
+        interrupted: &dyn Fn() -> bool,
+        mut hash: impl FnMut(u32) -> Result<Hash256, SimulatorError>,
+    ) -> Result<Option<NonceFound>, SimulatorError> {
+        let mut nonce = first_nonce;
+        while !is_valid_block_header_hash(&hash(nonce)?, difficulty) {
+            nonce = nonce
+                .checked_add(1)
+                .ok_or(SimulatorError::NonceSpaceExhausted {
+                    height,
+                    first_nonce,
+                })?;
+            if u64::from(nonce).is_multiple_of(LOG_EVERY_N_NONCES) {
+                info!("Tested nonce number: {}", nonce);
+            }
+            if u64::from(nonce).is_multiple_of(CHECK_INTERRUPTION_EVERY_N_NONCES) && interrupted() {
+                return Ok(None);
+            }
+        }
+        Ok(Some(NonceFound {
+            nonce,
+            nonces_tried: u64::from(nonce - first_nonce) + 1,
+        }))
+    }
+
+    /// Increments the nonce of the header until its hash is valid. The
+    /// builtin hasher resumes from the midstate of the header, other hashers
+    /// are given the whole preimage for every nonce.
+    pub struct SequentialNonces;
+
+    impl MiningStrategy for SequentialNonces {
+        fn find_nonce(
+            &self,
+            header: &Header,
+            format: HeaderFormat,
+            difficulty: usize,
+            hasher: &mut dyn PowHasher,
+            interrupted: &dyn Fn() -> bool,
+        ) -> Result<Option<NonceFound>, SimulatorError> {
+            if hasher.is_builtin() {
+                return Midstate.find_nonce(header, format, difficulty, hasher, interrupted);
+            }
+            let mut header = header.clone();
+            search_in_order(
+                header.height,
+                header.nonce,
+                difficulty,
+                interrupted,
+                |nonce| {
+                    header.nonce = nonce;
+                    hash_header(hasher, &header, format)
+                },
+            )
+        }
+    }
+
+    /// Draws the nonces at random until the hash of the header is valid.
+    /// Nonces may be drawn more than once, so the search takes as many
+    /// hashes as the sequential one on average but varies more.
+    pub struct RandomNonces;
+
+    impl MiningStrategy for RandomNonces {
+        fn find_nonce(
+            &self,
+            header: &Header,
+            format: HeaderFormat,
+            difficulty: usize,
+            hasher: &mut dyn PowHasher,
+            interrupted: &dyn Fn() -> bool,
+        ) -> Result<Option<NonceFound>, SimulatorError> {
+            let mut header = header.clone();
+            let mut rng = ChaCha8Rng::from_seed(format.header_hash(&header).0);
+            let mut tested: u64 = 0;
+            loop {
+                header.nonce = rng.gen();
+                tested += 1;
+                if is_valid_block_header_hash(&hash_header(hasher, &header, format)?, difficulty) {
+                    return Ok(Some(NonceFound {
+                        nonce: header.nonce,
+                        nonces_tried: tested,
+                    }));
+                }
+                if tested.is_multiple_of(LOG_EVERY_N_NONCES) {
+                    info!("Tested {} random nonces", tested);
+                }
+                if tested.is_multiple_of(CHECK_INTERRUPTION_EVERY_N_NONCES) && interrupted() {
+                    return Ok(None);
+                }
+            }
+        }
+    }
+
+    /// Hashes the part of the preimage before the nonce once and resumes
+    /// SHA-256 from that state for every nonce, trying the nonces in order.
+    /// It is what the sequential search does with the builtin hasher, and
+    /// fails instead of falling back to hashing whole preimages with other
+    /// hashers.
+    pub struct Midstate;
+
+    impl MiningStrategy for Midstate {
+        fn find_nonce(
+            &self,
+            header: &Header,
+            format: HeaderFormat,
+            difficulty: usize,
+            _hasher: &mut dyn PowHasher,
+            interrupted: &dyn Fn() -> bool,
+        ) -> Result<Option<NonceFound>, SimulatorError> {
+            let midstate = HeaderMidstate::new(header, format);
+            search_in_order(
+                header.height,
+                header.nonce,
+                difficulty,
+                interrupted,
+                |nonce| Ok(midstate.hash(nonce)),
+            )
+        }
+
+        fn uses_builtin_sha256(&self) -> bool {
+            true
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use crate::{error::error::SimulatorError, model::blockchain::Hash256};
+
+        use super::{search_in_order, NonceFound};
+
+        #[test]
+        fn search_fails_past_the_largest_nonce() {
+            let result =
+                search_in_order(7, u32::MAX - 2, 1, &|| false, |_| Ok(Hash256([0xff; 32])));
+            assert!(matches!(
+                result,
+                Err(SimulatorError::NonceSpaceExhausted {
+                    height: 7,
+                    first_nonce
+                }) if first_nonce == u32::MAX - 2
+            ));
+        }
+
+        #[test]
+        fn search_finds_the_largest_nonce() {
+            let hash = |nonce| match nonce {
+                u32::MAX => Ok(Hash256([0; 32])),
+                _ => Ok(Hash256([0xff; 32])),
+            };
+            let found = search_in_order(7, u32::MAX - 2, 1, &|| false, hash).unwrap();
+            assert_eq!(
+                found,
+                Some(NonceFound {
+                    nonce: u32::MAX,
+                    nonces_tried: 3
+                })
+            );
+        }
+    }
+}
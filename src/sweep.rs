@@ -0,0 +1,227 @@
+// This module runs the network simulation many times over a grid of
+// parameters
+pub mod sweep {
+    use std::{
+        num::NonZeroUsize,
+        sync::atomic::{AtomicUsize, Ordering},
+        thread,
+    };
+
+    use log::info;
+    use serde::Serialize;
+
+    use crate::{
+        aggregate::aggregate::{AggregateReport, RunMetrics},
+        args::args::{SimulateNetworkArgs, SweepArgs},
+        config::config::SimulatorContext,
+        data_sourcing::data_provider::{write_report, ReportFormat},
+        error::error::SimulatorError,
+        model::blockchain::Address,
+        network::network::{block_rows, simulate},
+        simulation::fleet::{load_fleet, VirtualMiner},
+    };
+
+    /// Combination of the swept parameters.
+    #[derive(Debug, Clone, Copy)]
+    struct ParameterPoint {
+        difficulty: u32,
+        latency: f64,
+        /// Share of the total hashrate given to the attacker, the hashrates
+        /// of the fleet file are kept if not set
+        attacker_hashrate: Option<f64>,
+    }
+
+    #[derive(Serialize)]
+    struct SweepRow {
+        difficulty: u32,
+        latency: f64,
+        attacker_hashrate: Option<f64>,
+        runs: usize,
+        orphan_rate_mean: f64,
+        orphan_rate_stddev: f64,
+        /// Missing if no run of the point confirmed any transactions
+        confirmation_time_mean: Option<f64>,
+        confirmation_time_stddev: Option<f64>,
+        /// Revenue share of the attacker, missing without an attacker
+        attacker_revenue_share_mean: Option<f64>,
+        attacker_revenue_share_stddev: Option<f64>,
+    }
+
+    /// Miners of the fleet with the attacker's hashrate changed so that it
+    /// controls the given share of the total hashrate.
+    fn with_attacker_share(
+        miners: &[VirtualMiner],
+        attacker: Address,
+        share: f64,
+    ) -> Vec<VirtualMiner> {
+        let honest_hashrate: f64 = miners
+            .iter()
+            .filter(|miner| miner.address != attacker)
+            .map(|miner| miner.hashrate)
+            .sum();
+        miners
+            .iter()
+            .cloned()
+            .map(|mut miner| {
+                if miner.address == attacker {
+                    miner.hashrate = honest_hashrate * share / (1.0 - share);
+                }
+                miner
+            })
+            .collect()
+    }
+
+    /// Runs the network simulation for every combination of the swept
+    /// difficulties, latencies and attacker hashrates, each with as many
+    /// seeds as there are runs. Run `r` of every point uses the seed
+    /// `seed + r`, so that the points are compared on the same random
+    /// draws. The runs are spread over worker threads and summarised in one
+    /// row per point.
+    pub fn sweep(args: SweepArgs, context: &SimulatorContext) -> Result<(), SimulatorError> {
+        info!("Loading the fleet of miners from {}", args.fleet);
+        let fleet = load_fleet(&args.fleet)?;
+        if let Some(attacker) = args.attacker {
+            if !fleet.miners.iter().any(|miner| miner.address == attacker) {
+                return Err(SimulatorError::NodeNotFound(attacker));
+            }
+        }
+
+        let attacker_hashrates: Vec<Option<f64>> = if args.attacker_hashrates.is_empty() {
+            vec![None]
+        } else {
+            args.attacker_hashrates.iter().copied().map(Some).collect()
+        };
+        let mut points = vec![];
+        for &difficulty in &args.difficulties {
+            for &latency in &args.latencies {
+                for &attacker_hashrate in &attacker_hashrates {
+                    points.push(ParameterPoint {
+                        difficulty,
+                        latency,
+                        attacker_hashrate,
+                    });
+                }
+            }
+        }
+        let fleets: Vec<Vec<VirtualMiner>> = points
+            .iter()
+            .map(|point| match (args.attacker, point.attacker_hashrate) {
+                (Some(attacker), Some(share)) => {
+                    with_attacker_share(&fleet.miners, attacker, share)
+                }
+                _ => fleet.miners.clone(),
+            })
+            .collect();
+
+        let runs = points.len() * args.runs as usize;
+        let threads = args
+            .threads
+            .map(|threads| threads as usize)
+            .or_else(|| thread::available_parallelism().ok().map(NonZeroUsize::get))
+            .unwrap_or(1)
+            .min(runs);
+        info!(
+            "Simulating {} parameter points with {} runs each on {} threads...",
+            points.len(),
+            args.runs,
+            threads
+        );
+
+        // Every worker picks the next run until all runs are taken.
+        let next_run = AtomicUsize::new(0);
+        let run = |index: usize| -> Result<RunMetrics, SimulatorError> {
+            let point = points[index / args.runs as usize];
+            let network_args = SimulateNetworkArgs {
+                fleet: args.fleet.clone(),
+                blocks_to_mine: Some(args.blocks_to_mine),
+                duration: None,
+                difficulty: point.difficulty,
+                seed: args.seed.wrapping_add((index % args.runs as usize) as u64),
+                latency: point.latency,
+                latency_spread: 0.0,
+                topology: args.topology,
+                degree: args.degree,
+                links: None,
+                transaction_rate: args.transaction_rate,
+                block_capacity: args.block_capacity,
+                partition_at: None,
+                partition_duration: None,
+                eclipse_victim: None,
+                eclipse_attacker: None,
+                divergence_output: None,
+                reorgs_output: None,
+                trace_output: None,
+                report_output: String::new(),
+                format: ReportFormat::Json,
+                output_options: args.output_options,
+            };
+            let (network, _) = simulate(&network_args, &fleets[index / args.runs as usize], None)?;
+            Ok(RunMetrics::from_report(&block_rows(&network)))
+        };
+        let results = thread::scope(|scope| {
+            let workers: Vec<_> = (0..threads)
+                .map(|_| {
+                    scope.spawn(|| {
+                        let mut results = vec![];
+                        loop {
+                            let index = next_run.fetch_add(1, Ordering::Relaxed);
+                            if index >= runs {
+                                return results;
+                            }
+                            results.push((index, run(index)));
+                        }
+                    })
+                })
+                .collect();
+            let mut results: Vec<(usize, Result<RunMetrics, SimulatorError>)> = workers
+                .into_iter()
+                .flat_map(|worker| worker.join().unwrap())
+                .collect();
+            results.sort_by_key(|(index, _)| *index);
+            results
+        });
+        let metrics = results
+            .into_iter()
+            .map(|(_, metrics)| metrics)
+            .collect::<Result<Vec<RunMetrics>, SimulatorError>>()?;
+
+        let rows: Vec<SweepRow> = points
+            .iter()
+            .zip(metrics.chunks(args.runs as usize))
+            .map(|(point, runs)| {
+                // Every point has at least one run.
+                let summary = AggregateReport::of(runs).unwrap();
+                let attacker_share = args.attacker.and_then(|attacker| {
+                    summary
+                        .revenue_shares
+                        .iter()
+                        .find(|miner| miner.miner == attacker)
+                        .map(|miner| &miner.revenue_share)
+                });
+                SweepRow {
+                    difficulty: point.difficulty,
+                    latency: point.latency,
+                    attacker_hashrate: point.attacker_hashrate,
+                    runs: summary.runs,
+                    orphan_rate_mean: summary.orphan_rate.mean,
+                    orphan_rate_stddev: summary.orphan_rate.stddev,
+                    confirmation_time_mean: summary
+                        .mean_confirmation_time
+                        .as_ref()
+                        .map(|time| time.mean),
+                    confirmation_time_stddev: summary
+                        .mean_confirmation_time
+                        .as_ref()
+                        .map(|time| time.stddev),
+                    attacker_revenue_share_mean: args
+                        .attacker
+                        .map(|_| attacker_share.map_or(0.0, |share| share.mean)),
+                    attacker_revenue_share_stddev: args
+                        .attacker
+                        .map(|_| attacker_share.map_or(0.0, |share| share.stddev)),
+                }
+            })
+            .collect();
+        write_report(&args.report_output, context.output, args.format, &rows)
+    }
+}
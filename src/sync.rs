@@ -0,0 +1,482 @@
+// This module synchronises a chain with the chain of another node, fetching
+// the headers first and the blocks of the heavier branch afterwards
+pub mod sync {
+    use std::collections::HashSet;
+
+    use crypto_bigint::U256;
+    use log::{info, warn};
+
+    use crate::{
+        args::args::SyncFromArgs,
+        config::config::SimulatorContext,
+        data_sourcing::data_provider::{load_blockchain, write_blockchain},
+        error::error::SimulatorError,
+        hashing::hashing::Hashable,
+        model::blockchain::{
+            conflicting_branch_checkpoint, conflicting_checkpoint, is_valid_block_header_hash,
+            Block, Blockchain, Checkpoint, Hash256, Header, HeaderFormat, Transaction,
+            NULL_ADDRESS,
+        },
+        node::validator::check_block,
+        p2p::p2p::RemotePeer,
+        upgrades::upgrades::RuleTracker,
+    };
+
+    /// Maximum number of headers returned for a single request.
+    pub const MAX_HEADERS: usize = 2000;
+    /// Maximum number of blocks requested or sent in a single message.
+    pub const MAX_BLOCKS: usize = 100;
+
+    /// Node or file the chain is synchronised from.
+    pub trait BlockSource {
+        /// Headers following the first block of the locator the source knows,
+        /// or following the genesis block if it knows none of them.
+        fn headers(&mut self, locator: &[Hash256]) -> Result<Vec<Header>, SimulatorError>;
+
+        /// The blocks with the given hashes, in the same order.
+        fn blocks(&mut self, hashes: &[Hash256]) -> Result<Vec<Block>, SimulatorError>;
+    }
+
+    impl BlockSource for Blockchain {
+        fn headers(&mut self, locator: &[Hash256]) -> Result<Vec<Header>, SimulatorError> {
+            Ok(headers_after(self, locator))
+        }
+
+        fn blocks(&mut self, hashes: &[Hash256]) -> Result<Vec<Block>, SimulatorError> {
+            Ok(blocks_by_hash(self, hashes))
+        }
+    }
+
+    /// Hashes of blocks of the chain, starting with the ten most recent ones
+    /// and then going back exponentially further, ending with the genesis
+    /// block. The first hash the other node knows is where the chains fork.
+    pub fn locator(chain: &Blockchain) -> Vec<Hash256> {
+        let blocks = chain.blocks();
+        let mut locator = vec![];
+        let mut index = blocks.len().saturating_sub(1);
+        let mut step = 1;
+        while index > 0 {
+            locator.push(blocks[index].header.hash);
+            if locator.len() >= 10 {
+                step *= 2;
+            }
+            index = index.saturating_sub(step);
+        }
+        if let Some(genesis) = blocks.first() {
+            locator.push(genesis.header.hash);
+        }
+        locator
+    }
+
+    /// Answers a headers request from the chain.
+    pub fn headers_after(chain: &Blockchain, locator: &[Hash256]) -> Vec<Header> {
+        let start = locator
+            .iter()
+            .find_map(|hash| chain.get_by_hash(hash))
+            .map_or(0, |block| {
+                chain
+                    .iter()
+                    .position(|b| b.header.hash == block.header.hash)
+                    .unwrap()
+                    + 1
+            });
+        chain
+            .iter()
+            .skip(start)
+            .take(MAX_HEADERS)
+            .map(|block| block.header.clone())
+            .collect()
+    }
+
+    /// Answers a blocks request from the chain, skipping unknown blocks.
+    pub fn blocks_by_hash(chain: &Blockchain, hashes: &[Hash256]) -> Vec<Block> {
+        hashes
+            .iter()
+            .filter_map(|hash| chain.get_by_hash(hash).cloned())
+            .collect()
+    }
+
+    /// Blocks abandoned and adopted when switching to another branch.
+    pub struct SyncOutcome {
+        pub fork_height: u32,
+        pub reverted: Vec<Block>,
+        pub appended: Vec<Block>,
+    }
+
+    /// Updates the mempool after the chain switched to another branch and
+    /// returns the hashes of the transactions the branch confirmed. The
+    /// transactions of the reverted blocks become unconfirmed again unless
+    /// the new branch contains them too.
+    pub fn update_mempool(
+        mempool: &mut Vec<Transaction>,
+        outcome: &SyncOutcome,
+    ) -> HashSet<Hash256> {
+        let included: HashSet<Hash256> = outcome
+            .appended
+            .iter()
+            .flat_map(|block| &block.transactions)
+            .map(|t| t.hash())
+            .collect();
+        let returned = outcome
+            .reverted
+            .iter()
+            .flat_map(|block| &block.transactions)
+            .filter(|t| !included.contains(&t.hash()) && t.sender != NULL_ADDRESS);
+        let mut updated: Vec<Transaction> = returned.cloned().collect();
+        updated.append(mempool);
+        updated.retain(|t| !included.contains(&t.hash()));
+        *mempool = updated;
+        included
+    }
+
+    /// Progress of synchronising with one source. The headers are collected
+    /// and checked first, and the blocks of the branch are only downloaded if
+    /// it has more work than the local blocks it would replace.
+    #[derive(Default)]
+    pub struct ChainSync {
+        headers: Vec<Header>,
+        blocks: Vec<Block>,
+        checkpoints: Vec<Checkpoint>,
+        format: HeaderFormat,
+    }
+
+    impl ChainSync {
+        /// Synchronisation accepting only the branches of blocks hashed in the
+        /// format that agree with the checkpoints.
+        pub fn new(checkpoints: &[Checkpoint], format: HeaderFormat) -> Self {
+            ChainSync {
+                checkpoints: checkpoints.to_vec(),
+                format,
+                ..ChainSync::default()
+            }
+        }
+
+        /// Locator of the next headers request, which continues from the last
+        /// received header.
+        pub fn next_locator(&self, chain: &Blockchain) -> Vec<Hash256> {
+            match self.headers.last() {
+                Some(header) => vec![header.hash],
+                None => locator(chain),
+            }
+        }
+
+        /// Checks that the headers extend the local chain or the previously
+        /// received headers and returns whether more headers may follow.
+        pub fn add_headers(
+            &mut self,
+            chain: &Blockchain,
+            headers: Vec<Header>,
+        ) -> Result<bool, SimulatorError> {
+            let more = headers.len() >= MAX_HEADERS;
+            for header in headers {
+                let invalid = |reason: &str| SimulatorError::InvalidHeader {
+                    height: header.height,
+                    reason: reason.to_string(),
+                };
+                let parent = match self.headers.last() {
+                    Some(parent) => parent,
+                    None => match chain.get_by_hash(&header.previous_block_header_hash) {
+                        Some(parent) => &parent.header,
+                        None => return Err(invalid("it doesn't link to the local chain")),
+                    },
+                };
+                if header.previous_block_header_hash != parent.hash {
+                    return Err(invalid("it doesn't link to the previous header"));
+                }
+                if header.height != parent.height + 1 {
+                    return Err(invalid(&format!("expected height {}", parent.height + 1)));
+                }
+                if !header.has_valid_hash(self.format) {
+                    return Err(invalid("the hash does not match the header"));
+                }
+                if !is_valid_block_header_hash(&header.hash, header.difficulty as usize) {
+                    return Err(invalid("the hash doesn't satisfy the proof of work"));
+                }
+                if conflicting_checkpoint(&self.checkpoints, &header).is_some() {
+                    return Err(invalid("it conflicts with a checkpoint"));
+                }
+                self.headers.push(header);
+            }
+            Ok(more)
+        }
+
+        /// Received headers of blocks that are not in the local chain.
+        fn branch(&self, chain: &Blockchain) -> &[Header] {
+            let known = self
+                .headers
+                .iter()
+                .take_while(|header| chain.get_by_hash(&header.hash).is_some())
+                .count();
+            &self.headers[known..]
+        }
+
+        /// Hashes of the blocks to download, none unless the branch has more
+        /// work than the local blocks above the fork and doesn't conflict with
+        /// a finalized checkpoint.
+        pub fn wanted_blocks(&self, chain: &Blockchain) -> Vec<Hash256> {
+            let branch = self.branch(chain);
+            let (Some(first), Some(last)) = (branch.first(), branch.last()) else {
+                return vec![];
+            };
+            let fork_height = first.height - 1;
+            let hash_at = |height: u32| match height.checked_sub(first.height) {
+                Some(index) => branch.get(index as usize).map(|header| &header.hash),
+                None => chain.get_by_height(height).map(|block| &block.header.hash),
+            };
+            let finalized = |checkpoint: &Checkpoint| chain.get_by_hash(&checkpoint.hash).is_some();
+            if let Some(checkpoint) =
+                conflicting_branch_checkpoint(&self.checkpoints, last.height, hash_at, finalized)
+            {
+                warn!(
+                    "Ignoring the branch forking at {} as it conflicts with the checkpoint {}",
+                    fork_height, checkpoint.hash
+                );
+                return vec![];
+            }
+            let work = |headers: &mut dyn Iterator<Item = &Header>| {
+                headers.fold(U256::ZERO, |work, header| {
+                    work.saturating_add(&header.work())
+                })
+            };
+            let local_work = work(
+                &mut chain
+                    .iter()
+                    .map(|block| &block.header)
+                    .filter(|header| header.height > fork_height),
+            );
+            if work(&mut branch.iter()) <= local_work {
+                return vec![];
+            }
+            branch.iter().map(|header| header.hash).collect()
+        }
+
+        /// Adds downloaded blocks and returns whether all wanted blocks have
+        /// been received.
+        pub fn add_blocks(&mut self, chain: &Blockchain, blocks: Vec<Block>) -> bool {
+            self.blocks.extend(blocks);
+            self.blocks.len() >= self.branch(chain).len()
+        }
+
+        /// Switches the chain to the downloaded branch, leaving the chain
+        /// unchanged if any of the blocks is invalid.
+        pub fn apply(
+            self,
+            chain: &mut Blockchain,
+            tracker: &mut RuleTracker,
+        ) -> Result<SyncOutcome, SimulatorError> {
+            let branch = self.branch(chain);
+            let Some(first) = branch.first() else {
+                return Err(SimulatorError::EmptyBlockchain);
+            };
+            let fork_height = first.height - 1;
+            for (header, block) in branch.iter().zip(&self.blocks) {
+                if block.header.hash != header.hash {
+                    return Err(SimulatorError::InvalidBlock {
+                        height: block.header.height,
+                        reason: "it is not the block of the announced header".to_string(),
+                    });
+                }
+            }
+            let count = branch.len();
+
+            let mut candidate = chain.clone();
+            let reverted = candidate.truncate(fork_height);
+            let mut candidate_tracker = RuleTracker::new(tracker.deployments());
+            for block in &candidate {
+                candidate_tracker.observe(&block.header);
+            }
+            let appended: Vec<Block> = self.blocks.into_iter().take(count).collect();
+            for block in &appended {
+                check_block(block, self.format)?;
+                candidate_tracker.check_rules(block, candidate.tip_header().ok())?;
+                candidate.append(block.clone(), self.format, &self.checkpoints)?;
+                candidate_tracker.observe(&block.header);
+            }
+            *chain = candidate;
+            *tracker = candidate_tracker;
+            Ok(SyncOutcome {
+                fork_height,
+                reverted,
+                appended,
+            })
+        }
+    }
+
+    /// Synchronises the chain with the source, downloading the headers first
+    /// and then the blocks of its branch if it has more work, agrees with the
+    /// checkpoints and is hashed in the format.
+    pub fn sync_with(
+        chain: &mut Blockchain,
+        tracker: &mut RuleTracker,
+        source: &mut dyn BlockSource,
+        checkpoints: &[Checkpoint],
+        format: HeaderFormat,
+    ) -> Result<Option<SyncOutcome>, SimulatorError> {
+        let mut sync = ChainSync::new(checkpoints, format);
+        loop {
+            let headers = source.headers(&sync.next_locator(chain))?;
+            info!("Received {} headers", headers.len());
+            if !sync.add_headers(chain, headers)? {
+                break;
+            }
+        }
+        let wanted = sync.wanted_blocks(chain);
+        if wanted.is_empty() {
+            return Ok(None);
+        }
+        for hashes in wanted.chunks(MAX_BLOCKS) {
+            let blocks = source.blocks(hashes)?;
+            info!("Received {} blocks", blocks.len());
+            sync.add_blocks(chain, blocks);
+        }
+        sync.apply(chain, tracker).map(Some)
+    }
+
+    /// Synchronises the local chain from another chain file or a running
+    /// node and writes the resulting chain.
+    pub fn sync_from(args: SyncFromArgs, context: &SimulatorContext) -> Result<(), SimulatorError> {
+        info!("Loading the blockchain from {}", args.blockchain_state);
+        let mut chain = load_blockchain(&args.blockchain_state, context)?;
+        let mut tracker = RuleTracker::new(&context.deployments);
+        for block in &chain {
+            tracker.observe(&block.header);
+        }
+
+        let mut source: Box<dyn BlockSource> = match (&args.from_file, &args.from_peer) {
+            (Some(file), _) => {
+                info!("Loading the blockchain to sync from {}", file);
+                Box::new(load_blockchain(file, context)?)
+            }
+            (None, Some(peer)) => {
+                info!("Connecting to the peer {}", peer);
+                Box::new(RemotePeer::connect(peer)?)
+            }
+            (None, None) => unreachable!("clap requires a source"),
+        };
+        let outcome = sync_with(
+            &mut chain,
+            &mut tracker,
+            source.as_mut(),
+            &context.checkpoints,
+            context.header_format,
+        )?;
+        match outcome {
+            Some(outcome) => info!(
+                "Switched to the branch forking at height {}, reverting {} blocks and appending {} blocks",
+                outcome.fork_height,
+                outcome.reverted.len(),
+                outcome.appended.len()
+            ),
+            None => info!("The local chain already has the most work"),
+        }
+        write_blockchain(
+            &args.blockchain_state_output,
+            context.output,
+            context.data_format,
+            &chain,
+        )
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use crate::{
+            config::config::SimulatorContext,
+            model::blockchain::{Address, Block, Blockchain, HeaderFormat},
+            node::miner::BlockBuilder,
+            upgrades::upgrades::RuleTracker,
+        };
+
+        use super::{blocks_by_hash, headers_after, locator, sync_with, ChainSync};
+
+        /// The blocks followed by empty blocks mined by the miner at
+        /// difficulty 0, each adding the same work.
+        fn extend(blocks: &[Block], count: usize, miner: u8) -> Vec<Block> {
+            let context = SimulatorContext::default();
+            let mut blocks = blocks.to_vec();
+            for _ in 0..count {
+                let builder = match blocks.last() {
+                    Some(previous) => BlockBuilder::on_top_of(&previous.header),
+                    None => BlockBuilder::genesis(),
+                };
+                let block = builder
+                    .difficulty(0)
+                    .miner(Address([miner; 20]))
+                    .build()
+                    .mine(0, &context.nonce_search())
+                    .unwrap();
+                blocks.push(block);
+            }
+            blocks
+        }
+
+        #[test]
+        fn locator_goes_back_exponentially_to_the_genesis_block() {
+            let chain = Blockchain::from(extend(&[], 20, 1));
+            let heights: Vec<u32> = locator(&chain)
+                .iter()
+                .map(|hash| chain.get_by_hash(hash).unwrap().header.height)
+                .collect();
+            assert_eq!(heights, [19, 18, 17, 16, 15, 14, 13, 12, 11, 10, 8, 4, 0]);
+        }
+
+        #[test]
+        fn heavier_branch_replaces_the_local_blocks() {
+            let genesis = extend(&[], 1, 0);
+            let mut chain = Blockchain::from(extend(&genesis, 2, 1));
+            let mut source = Blockchain::from(extend(&genesis, 3, 2));
+            let mut tracker = RuleTracker::new(&[]);
+            let outcome = sync_with(
+                &mut chain,
+                &mut tracker,
+                &mut source,
+                &[],
+                HeaderFormat::Simulator,
+            )
+            .unwrap()
+            .unwrap();
+            assert_eq!(outcome.fork_height, 0);
+            assert_eq!(outcome.reverted.len(), 2);
+            assert_eq!(outcome.appended.len(), 3);
+            assert_eq!(
+                chain.tip_header().unwrap().hash,
+                source.tip_header().unwrap().hash
+            );
+        }
+
+        #[test]
+        fn lighter_branch_is_not_downloaded() {
+            let genesis = extend(&[], 1, 0);
+            let mut chain = Blockchain::from(extend(&genesis, 3, 1));
+            let tip = chain.tip_header().unwrap().hash;
+            let mut source = Blockchain::from(extend(&genesis, 2, 2));
+            let mut tracker = RuleTracker::new(&[]);
+            let outcome = sync_with(
+                &mut chain,
+                &mut tracker,
+                &mut source,
+                &[],
+                HeaderFormat::Simulator,
+            )
+            .unwrap();
+            assert!(outcome.is_none());
+            assert_eq!(chain.tip_header().unwrap().hash, tip);
+        }
+
+        #[test]
+        fn short_reply_leaves_the_branch_incomplete() {
+            let genesis = extend(&[], 1, 0);
+            let chain = Blockchain::from(genesis.clone());
+            let source = Blockchain::from(extend(&genesis, 3, 2));
+            let mut sync = ChainSync::new(&[], HeaderFormat::Simulator);
+            let headers = headers_after(&source, &sync.next_locator(&chain));
+            assert!(!sync.add_headers(&chain, headers).unwrap());
+            let wanted = sync.wanted_blocks(&chain);
+            assert_eq!(wanted.len(), 3);
+
+            let blocks = blocks_by_hash(&source, &wanted[..2]);
+            assert!(!sync.add_blocks(&chain, blocks));
+            let blocks = blocks_by_hash(&source, &wanted[2..]);
+            assert!(sync.add_blocks(&chain, blocks));
+        }
+    }
+}
@@ -0,0 +1,163 @@
+// This module schedules the activation of new consensus rules
+pub mod upgrades {
+    use clap::ValueEnum;
+    use serde::{de::Error, Deserialize, Deserializer};
+
+    use crate::{
+        error::error::SimulatorError,
+        model::blockchain::{Block, Header, NULL_ADDRESS},
+        wallet::wallet::verify_signature,
+    };
+
+    /// Top bits of the version of headers signaling for deployments, the
+    /// remaining 29 bits are the signaling bits.
+    pub const VERSION_BITS_TOP: u32 = 0x2000_0000;
+    const VERSION_BITS_TOP_MASK: u32 = 0xE000_0000;
+    const SIGNALING_BITS: u8 = 29;
+
+    /// Consensus rules that tighten the validity of blocks and can be
+    /// activated as soft forks.
+    #[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+    #[serde(rename_all = "snake_case")]
+    pub enum Rule {
+        /// Every block has a later timestamp than its predecessor
+        MonotonicTimestamps,
+        /// Every transaction except the genesis allocations is validly signed
+        SignedTransactions,
+    }
+
+    /// Activation of a rule declared in the configuration file as a
+    /// `[[deployments]]` table. The rule activates either at a fixed height or
+    /// once `threshold` of the `window` blocks of a signaling period set the
+    /// deployment's bit in their version, starting with the next period.
+    #[derive(Deserialize, Debug, Clone)]
+    #[serde(deny_unknown_fields)]
+    pub struct Deployment {
+        pub rule: Rule,
+        #[serde(deserialize_with = "deserialize_bit")]
+        pub bit: u8,
+        pub activation_height: Option<u32>,
+        #[serde(default = "default_window")]
+        pub window: u32,
+        #[serde(default = "default_threshold")]
+        pub threshold: u32,
+    }
+
+    fn default_window() -> u32 {
+        100
+    }
+
+    fn default_threshold() -> u32 {
+        95
+    }
+
+    fn deserialize_bit<'de, D: Deserializer<'de>>(deserializer: D) -> Result<u8, D::Error> {
+        let bit = u8::deserialize(deserializer)?;
+        if bit >= SIGNALING_BITS {
+            return Err(D::Error::custom(format!(
+                "the signaling bit must be below {}",
+                SIGNALING_BITS
+            )));
+        }
+        Ok(bit)
+    }
+
+    /// Whether the header uses version bits and sets the given bit.
+    pub fn signals(header: &Header, bit: u8) -> bool {
+        header.version & VERSION_BITS_TOP_MASK == VERSION_BITS_TOP
+            && header.version & (1 << bit) != 0
+    }
+
+    /// Follows the headers of a chain in order to find out at which heights
+    /// the deployments activate.
+    pub struct RuleTracker {
+        deployments: Vec<Deployment>,
+        /// Number of signaling blocks in the current period, per deployment.
+        signals: Vec<u32>,
+        activation_heights: Vec<Option<u32>>,
+    }
+
+    impl RuleTracker {
+        /// Tracker of the scheduled deployments, none of which has activated
+        /// by signaling yet.
+        pub fn new(deployments: &[Deployment]) -> Self {
+            RuleTracker {
+                deployments: deployments.to_vec(),
+                signals: vec![0; deployments.len()],
+                activation_heights: deployments.iter().map(|d| d.activation_height).collect(),
+            }
+        }
+
+        pub fn deployments(&self) -> &[Deployment] {
+            &self.deployments
+        }
+
+        /// Counts the signal of the next header of the chain.
+        pub fn observe(&mut self, header: &Header) {
+            for (i, deployment) in self.deployments.iter().enumerate() {
+                if self.activation_heights[i].is_some() {
+                    continue;
+                }
+                if signals(header, deployment.bit) {
+                    self.signals[i] += 1;
+                }
+                if (header.height + 1).is_multiple_of(deployment.window.max(1)) {
+                    if self.signals[i] >= deployment.threshold {
+                        self.activation_heights[i] = Some(header.height + 1);
+                    }
+                    self.signals[i] = 0;
+                }
+            }
+        }
+
+        pub fn is_active(&self, rule: Rule, height: u32) -> bool {
+            self.deployments.iter().zip(&self.activation_heights).any(
+                |(deployment, activation_height)| {
+                    deployment.rule == rule && activation_height.is_some_and(|h| h <= height)
+                },
+            )
+        }
+
+        /// Version of a block signaling for the deployments of the rules, or
+        /// the legacy version 0 if no rules are signaled.
+        pub fn signaling_version(&self, rules: &[Rule]) -> u32 {
+            if rules.is_empty() {
+                return 0;
+            }
+            self.deployments
+                .iter()
+                .filter(|deployment| rules.contains(&deployment.rule))
+                .fold(VERSION_BITS_TOP, |version, deployment| {
+                    version | 1 << deployment.bit
+                })
+        }
+
+        /// Checks the block against the rules active at its height.
+        pub fn check_rules(
+            &self,
+            block: &Block,
+            previous_header: Option<&Header>,
+        ) -> Result<(), SimulatorError> {
+            let height = block.header.height;
+            let invalid = |reason: &str| SimulatorError::InvalidBlock {
+                height,
+                reason: reason.to_string(),
+            };
+            if self.is_active(Rule::MonotonicTimestamps, height)
+                && previous_header
+                    .is_some_and(|previous| block.header.timestamp <= previous.timestamp)
+            {
+                return Err(invalid("the timestamp does not exceed the previous one"));
+            }
+            if self.is_active(Rule::SignedTransactions, height)
+                && block
+                    .transactions
+                    .iter()
+                    .any(|t| t.sender != NULL_ADDRESS && !verify_signature(t))
+            {
+                return Err(invalid("it contains transactions with invalid signatures"));
+            }
+            Ok(())
+        }
+    }
+}
@@ -0,0 +1,304 @@
+// This module checks the constraints on the values of the data files that
+// their types don't enforce, and locates malformed values by their path
+pub mod validation {
+    use crate::model::blockchain::{
+        Blockchain, Header, HeaderChain, InclusionProof, Transaction, NULL_HASH,
+    };
+
+    /// A malformed value of a data file and its path, such as
+    /// `blocks[3].header.nonce`.
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    pub struct FieldError {
+        pub path: String,
+        pub reason: String,
+    }
+
+    /// Implemented by the contents of the data files.
+    pub trait CheckFields {
+        /// Name of the list the contents are stored as in the protobuf
+        /// message, which starts the paths of the values. It is empty for
+        /// files holding a single object.
+        const ROOT: &'static str;
+
+        fn check_fields(&self) -> Result<(), FieldError>;
+    }
+
+    fn field_error(path: &str, reason: String) -> FieldError {
+        FieldError {
+            path: path.to_string(),
+            reason,
+        }
+    }
+
+    /// Checks that the string is a 0x-prefixed hex number of whole bytes.
+    fn check_hex(path: &str, value: &str) -> Result<(), FieldError> {
+        let Some(hex) = value.strip_prefix("0x") else {
+            return Err(field_error(
+                path,
+                format!("expected a hex number prefixed with 0x, found {:?}", value),
+            ));
+        };
+        if let Some(invalid) = hex.chars().find(|c| !c.is_ascii_hexdigit()) {
+            return Err(field_error(
+                path,
+                format!("{:?} is not a hex digit in {:?}", invalid, value),
+            ));
+        }
+        if hex.len() % 2 != 0 {
+            return Err(field_error(
+                path,
+                format!("expected whole bytes of hex, found {} digits", hex.len()),
+            ));
+        }
+        Ok(())
+    }
+
+    /// The signature is either empty or the public key and the signature
+    /// separated by a comma, both as 0x-prefixed hex.
+    fn check_transaction(path: &str, transaction: &Transaction) -> Result<(), FieldError> {
+        if transaction.signature.is_empty() {
+            return Ok(());
+        }
+        let path = format!("{}.signature", path);
+        let parts: Vec<&str> = transaction.signature.split(',').collect();
+        if parts.len() != 2 {
+            return Err(field_error(
+                &path,
+                format!(
+                    "expected the public key and the signature separated by a comma, found {} parts",
+                    parts.len()
+                ),
+            ));
+        }
+        for part in parts {
+            check_hex(&path, part)?;
+        }
+        Ok(())
+    }
+
+    fn check_transactions(path: &str, transactions: &[Transaction]) -> Result<(), FieldError> {
+        for (index, transaction) in transactions.iter().enumerate() {
+            check_transaction(&format!("{}[{}]", path, index), transaction)?;
+        }
+        Ok(())
+    }
+
+    impl CheckFields for Blockchain {
+        const ROOT: &'static str = "blocks";
+
+        fn check_fields(&self) -> Result<(), FieldError> {
+            for (index, block) in self.iter().enumerate() {
+                let path = format!("{}[{}].transactions", Self::ROOT, index);
+                check_transactions(&path, &block.transactions)?;
+            }
+            Ok(())
+        }
+    }
+
+    impl CheckFields for HeaderChain {
+        const ROOT: &'static str = "blocks";
+
+        /// The hashes of the headers are checked as they are parsed.
+        fn check_fields(&self) -> Result<(), FieldError> {
+            Ok(())
+        }
+    }
+
+    impl CheckFields for Vec<Transaction> {
+        const ROOT: &'static str = "transactions";
+
+        fn check_fields(&self) -> Result<(), FieldError> {
+            check_transactions(Self::ROOT, self)
+        }
+    }
+
+    impl CheckFields for InclusionProof {
+        const ROOT: &'static str = "";
+
+        /// The hashes of the proof are checked as they are parsed.
+        fn check_fields(&self) -> Result<(), FieldError> {
+            Ok(())
+        }
+    }
+
+    /// Problems with the structure of a chain of headers: heights that aren't
+    /// contiguous, headers that don't link to the previous one and
+    /// timestamps going back in time. Chain segments may start at any
+    /// height, only a genesis block needs to link to the null hash.
+    pub fn structural_problems<'a>(headers: impl IntoIterator<Item = &'a Header>) -> Vec<String> {
+        let mut problems = vec![];
+        let mut previous: Option<&Header> = None;
+        for header in headers {
+            let height = header.height;
+            match previous {
+                Some(previous) => {
+                    if height != previous.height.wrapping_add(1) {
+                        problems.push(format!(
+                            "Block {} follows block {} instead of block {}",
+                            height,
+                            previous.height,
+                            height.wrapping_sub(1)
+                        ));
+                    }
+                    if header.previous_block_header_hash != previous.hash {
+                        problems.push(format!(
+                            "Block {} links to {} instead of the hash {} of block {}",
+                            height,
+                            header.previous_block_header_hash,
+                            previous.hash,
+                            previous.height
+                        ));
+                    }
+                    if header.timestamp < previous.timestamp {
+                        problems.push(format!(
+                            "Block {} has the timestamp {}, earlier than the timestamp {} of block {}",
+                            height, header.timestamp, previous.timestamp, previous.height
+                        ));
+                    }
+                }
+                None if height == 0 && header.previous_block_header_hash != NULL_HASH => {
+                    problems.push(format!(
+                        "The genesis block links to {} instead of the null hash",
+                        header.previous_block_header_hash
+                    ));
+                }
+                None => {}
+            }
+            previous = Some(header);
+        }
+        problems
+    }
+
+    /// Position in a JSON value while scanning it.
+    enum Frame {
+        Array { index: usize },
+        Object { key: Option<String> },
+    }
+
+    /// Path of the value of the JSON document that was being parsed at the
+    /// line and column (both counted from 1, as serde_json reports them),
+    /// starting with the name of the top-level value.
+    pub fn json_path(contents: &[u8], line: usize, column: usize, root: &str) -> String {
+        let line_start: usize = contents
+            .split_inclusive(|byte| *byte == b'\n')
+            .take(line.saturating_sub(1))
+            .map(<[u8]>::len)
+            .sum();
+        let end = (line_start + column).min(contents.len());
+
+        let mut frames: Vec<Frame> = vec![];
+        let mut position = 0;
+        while position < end {
+            match contents[position] {
+                b'[' => frames.push(Frame::Array { index: 0 }),
+                b'{' => frames.push(Frame::Object { key: None }),
+                b']' | b'}' => {
+                    frames.pop();
+                }
+                b',' => match frames.last_mut() {
+                    Some(Frame::Array { index }) => *index += 1,
+                    Some(Frame::Object { key }) => *key = None,
+                    None => {}
+                },
+                b'"' => {
+                    let start = position + 1;
+                    position = start;
+                    while position < end && contents[position] != b'"' {
+                        if contents[position] == b'\\' {
+                            position += 1;
+                        }
+                        position += 1;
+                    }
+                    if let Some(Frame::Object { key: key @ None }) = frames.last_mut() {
+                        let name = &contents[start..position.min(end)];
+                        *key = Some(String::from_utf8_lossy(name).to_string());
+                    }
+                }
+                _ => {}
+            }
+            position += 1;
+        }
+
+        let mut path = root.to_string();
+        for frame in frames {
+            match frame {
+                Frame::Array { index } => path += &format!("[{}]", index),
+                Frame::Object { key: Some(key) } if path.is_empty() => path = key,
+                Frame::Object { key: Some(key) } => path += &format!(".{}", key),
+                Frame::Object { key: None } => {}
+            }
+        }
+        path
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use std::collections::HashMap;
+
+        use crate::model::blockchain::{Address, Amount, Hash256, Header, Transaction, NULL_HASH};
+
+        use super::{json_path, structural_problems, CheckFields, FieldError};
+
+        fn header(height: u32, hash: u8, previous: Hash256, timestamp: u64) -> Header {
+            Header {
+                difficulty: 0,
+                height,
+                miner: Address([0; 20]),
+                nonce: 0,
+                hash: Hash256([hash; 32]),
+                previous_block_header_hash: previous,
+                timestamp,
+                transactions_count: 0,
+                transactions_merkle_root: NULL_HASH,
+                version: 0,
+            }
+        }
+
+        #[test]
+        fn sound_chain_has_no_structural_problems() {
+            let genesis = header(0, 1, NULL_HASH, 10);
+            let next = header(1, 2, genesis.hash, 10);
+            assert!(structural_problems([&genesis, &next]).is_empty());
+        }
+
+        #[test]
+        fn structural_problems_report_gaps_links_and_timestamps() {
+            let genesis = header(0, 1, Hash256([9; 32]), 20);
+            let skipped = header(2, 2, Hash256([8; 32]), 10);
+            let problems = structural_problems([&genesis, &skipped]);
+            assert_eq!(problems.len(), 4, "{:?}", problems);
+            assert!(problems[0].starts_with("The genesis block links to"));
+            assert!(problems[1].starts_with("Block 2 follows block 0"));
+            assert!(problems[2].starts_with("Block 2 links to"));
+            assert!(problems[3].starts_with("Block 2 has the timestamp 10"));
+        }
+
+        #[test]
+        fn json_path_locates_the_value_being_parsed() {
+            let contents = b"[\n  {\"a\": [1]},\n  {\"b\": [2, -3]}\n]";
+            let error =
+                serde_json::from_slice::<Vec<HashMap<String, Vec<u32>>>>(contents).unwrap_err();
+            let path = json_path(contents, error.line(), error.column(), "blocks");
+            assert_eq!(path, "blocks[1].b[1]");
+        }
+
+        #[test]
+        fn malformed_signature_is_reported_with_its_path() {
+            let mut transaction = Transaction::builder()
+                .sender(Address([1; 20]))
+                .receiver(Address([2; 20]))
+                .amount(Amount(1))
+                .build()
+                .unwrap();
+            transaction.signature = "0x12,0xzz".to_string();
+            let error = vec![transaction].check_fields().unwrap_err();
+            assert_eq!(
+                error,
+                FieldError {
+                    path: "transactions[0].signature".to_string(),
+                    reason: "'z' is not a hex digit in \"0xzz\"".to_string(),
+                }
+            );
+        }
+    }
+}
@@ -1,32 +1,1376 @@
 // This module provides functionality for inspecting the blockchain
 pub mod views {
-    use log::info;
+    use std::{
+        collections::{HashMap, HashSet},
+        fmt::Write,
+    };
+
+    use clap::ValueEnum;
+    use log::{info, warn};
+    use serde::Serialize;
 
     use crate::{
-        args::args::GetTransactionHashArgs, data_sourcing::data_provider::load_blockchain,
-        hashing::hashing::Hashable, model::blockchain::Block,
+        aggregate::aggregate::percentile,
+        args::args::{
+            AuditHeadersArgs, BalanceHistoryArgs, DifficultyReportArgs, ExportHeadersArgs,
+            FeeHistogramArgs, GetConfirmationsArgs, GetTransactionHashArgs, ListBlocksArgs,
+            MempoolStatsArgs, MinerRevenueArgs, MiningStatsArgs, ShowHeaderArgs,
+            ShowMerkleTreeArgs, SupplyReportArgs, TrackTransactionsArgs,
+        },
+        config::config::SimulatorContext,
+        data_sourcing::data_provider::{
+            load_blockchain, load_headers, load_json, load_transactions, print_result,
+            write_headers, write_json, write_report, write_text, ReportFormat,
+        },
+        error::error::SimulatorError,
+        hashing::hashing::{HashScheme, Hashable},
+        model::blockchain::{
+            format_timestamp, is_valid_block_header_hash, Address, Amount, Block, Blockchain,
+            Denomination, Hash256, Header, MerkleNode, MerkleTree, MiningStats, Transaction,
+            NULL_HASH,
+        },
+        node::{
+            miner::{compute_transaction_hashes, construct_merkle_tree, TransactionSelection},
+            validator::path_to_transaction,
+        },
+        rlp::rlp::RlpEncodable,
+        state::state::{apply_block, Balances},
     };
 
-    pub fn show_transaction_hash(args: GetTransactionHashArgs) {
+    #[derive(Serialize)]
+    struct TransactionHashRow {
+        block_number: usize,
+        transaction_number: usize,
+        hash: Hash256,
+        /// Hex-encoded RLP encoding of the transaction, only for the Keccak
+        /// hash scheme.
+        rlp: Option<String>,
+    }
+
+    /// Gets the hashes of the transactions at the requested block and
+    /// transaction numbers, and writes them to the output file or prints
+    /// them one per line.
+    pub fn show_transaction_hash(
+        args: GetTransactionHashArgs,
+        context: &SimulatorContext,
+    ) -> Result<(), SimulatorError> {
         info!("Loading the blockchain from {}", args.blockchain_state);
-        let blockchain = load_blockchain(&args.blockchain_state).unwrap();
-        let block_number: usize = args.block_number;
-        let transaction_number: usize = args.transaction_number_in_block;
-        if let Some(hash) = get_transaction_hash(&blockchain, block_number, transaction_number) {
-            info!(
-                "Hash of the transaction {} in block {}: \n{}",
-                transaction_number, block_number, hash
-            );
+        let blockchain = load_blockchain(&args.blockchain_state, context)?;
+        let rows = args
+            .coordinates()
+            .into_iter()
+            .map(|(block_number, transaction_number)| {
+                let transaction = get_transaction(&blockchain, block_number, transaction_number)?;
+                let rlp = (args.hash_scheme == HashScheme::Keccak)
+                    .then(|| format!("0x{}", hex::encode(transaction.rlp_bytes())));
+                if let Some(rlp) = &rlp {
+                    info!(
+                        "RLP encoding of the transaction {} in block {}: \n{}",
+                        transaction_number, block_number, rlp
+                    );
+                }
+                let hash = args.hash_scheme.hash(transaction);
+                info!(
+                    "Hash of the transaction {} in block {}: \n{}",
+                    transaction_number, block_number, hash
+                );
+                Ok(TransactionHashRow {
+                    block_number,
+                    transaction_number,
+                    hash,
+                    rlp,
+                })
+            })
+            .collect::<Result<Vec<_>, SimulatorError>>()?;
+
+        match &args.output {
+            Some(output) => {
+                info!("Writing {} transaction hashes to {}", rows.len(), output);
+                write_report(output, context.output, args.format, &rows)
+            }
+            None => rows
+                .iter()
+                .try_for_each(|row| print_result(context.result_format, row.hash, row)),
         }
     }
 
-    fn get_transaction_hash(
-        blockchain: &Vec<Block>,
+    fn get_transaction(
+        blockchain: &Blockchain,
         block_number: usize,
         transaction_number: usize,
-    ) -> Option<String> {
-        let block = blockchain.get(block_number - 1)?;
-        let transaction = block.transactions.get(transaction_number - 1)?;
-        Some(transaction.hash().to_owned())
+    ) -> Result<&Transaction, SimulatorError> {
+        let block = blockchain.get_by_number(block_number)?;
+        transaction_number
+            .checked_sub(1)
+            .and_then(|index| block.transactions.get(index))
+            .ok_or(SimulatorError::TransactionNotFound {
+                block_number,
+                transaction_number,
+            })
+    }
+
+    #[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+    pub enum MerkleTreeFormat {
+        /// Indented listing of the nodes with the path marked by arrows
+        Text,
+        /// Nested nodes together with the hashes on the path
+        Json,
+        /// Graphviz graph of the tree with the path in red
+        Dot,
+    }
+
+    #[derive(Serialize)]
+    struct MerkleTreeView<'a> {
+        block_number: usize,
+        merkle_root: Hash256,
+        leaves_count: usize,
+        /// Hashes of the nodes from the root to the highlighted transaction.
+        path: Vec<Hash256>,
+        tree: &'a MerkleTree,
+    }
+
+    /// Builds the Merkle tree of the transactions of a block and renders it,
+    /// highlighting the nodes from the root to the given transaction.
+    pub fn show_merkle_tree(
+        args: ShowMerkleTreeArgs,
+        context: &SimulatorContext,
+    ) -> Result<(), SimulatorError> {
+        info!("Loading the blockchain from {}", args.blockchain_state);
+        let blockchain = load_blockchain(&args.blockchain_state, context)?;
+        let block = blockchain.get_by_number(args.block_number)?;
+        info!("Assembling the Merkle tree...");
+        let merkle_tree = construct_merkle_tree(compute_transaction_hashes(&block.transactions))
+            .ok_or(SimulatorError::EmptyBlock(args.block_number))?;
+        if merkle_tree.root().hash() != block.header.transactions_merkle_root {
+            warn!(
+                "The root of the tree doesn't match the merkle root {} of the block, which was \
+                 computed in another layout",
+                block.header.transactions_merkle_root
+            );
+        }
+        let path = match &args.transaction_hash {
+            Some(transaction_hash) => path_to_transaction(&merkle_tree, transaction_hash)
+                .ok_or_else(|| {
+                    SimulatorError::TransactionNotInBlock(transaction_hash.to_string())
+                })?,
+            None => vec![],
+        };
+        let on_path: HashSet<usize> = path.iter().map(MerkleNode::index).collect();
+
+        let mut rendered = String::new();
+        match args.format {
+            MerkleTreeFormat::Text => {
+                render_merkle_node(&mut rendered, merkle_tree.root(), &on_path, 0)
+            }
+            MerkleTreeFormat::Json => {
+                let view = MerkleTreeView {
+                    block_number: args.block_number,
+                    merkle_root: merkle_tree.root().hash(),
+                    leaves_count: merkle_tree.leaves_count(),
+                    path: path.iter().map(MerkleNode::hash).collect(),
+                    tree: &merkle_tree,
+                };
+                return write_json(&args.output, context.output, &view);
+            }
+            MerkleTreeFormat::Dot => {
+                writeln!(rendered, "digraph merkle_tree {{").unwrap();
+                writeln!(rendered, "  node [shape=box, fontname=monospace];").unwrap();
+                let mut nodes = vec![merkle_tree.root()];
+                while let Some(node) = nodes.pop() {
+                    let hash = node.hash().to_string();
+                    writeln!(
+                        rendered,
+                        "  n{} [label=\"{}…{}\"{}];",
+                        node.index(),
+                        &hash[..10],
+                        &hash[hash.len() - 6..],
+                        if on_path.contains(&node.index()) {
+                            ", color=red, penwidth=2"
+                        } else {
+                            ""
+                        }
+                    )
+                    .unwrap();
+                    for child in node.left().into_iter().chain(node.right()) {
+                        writeln!(
+                            rendered,
+                            "  n{} -> n{}{};",
+                            node.index(),
+                            child.index(),
+                            if on_path.contains(&child.index()) {
+                                " [color=red, penwidth=2]"
+                            } else {
+                                ""
+                            }
+                        )
+                        .unwrap();
+                        nodes.push(child);
+                    }
+                }
+                writeln!(rendered, "}}").unwrap();
+            }
+        }
+
+        write_text(&args.output, context.output, &rendered)
+    }
+
+    /// Lists the node and its descendants indented by their depth as the
+    /// `Display` of the tree does, marking the nodes on the path.
+    fn render_merkle_node(
+        rendered: &mut String,
+        node: MerkleNode,
+        on_path: &HashSet<usize>,
+        depth: usize,
+    ) {
+        let kind = match node.left() {
+            Some(_) => "Node",
+            None => "Leaf",
+        };
+        let marker = if on_path.contains(&node.index()) {
+            " <=="
+        } else {
+            ""
+        };
+        writeln!(
+            rendered,
+            "{}{}: {}{}",
+            "    ".repeat(depth),
+            kind,
+            node.hash(),
+            marker
+        )
+        .unwrap();
+        for child in node.left().into_iter().chain(node.right()) {
+            render_merkle_node(rendered, child, on_path, depth + 1);
+        }
+    }
+
+    #[derive(Serialize)]
+    struct HeaderResult<'a> {
+        header: &'a Header,
+        /// Hash of the header recomputed in the configured header format.
+        recomputed_hash: Hash256,
+        /// Whether the recomputed hash matches the stored one.
+        header_hash: bool,
+        /// Whether the stored hash matches the header and has the leading
+        /// zeros required by the difficulty.
+        proof_of_work: bool,
+        /// Seconds since the previous block, missing for the first block.
+        block_interval: Option<i64>,
+        total_fees: Amount,
+    }
+
+    /// Prints the header of a block together with the information derived
+    /// from it and from the previous block and the transactions.
+    pub fn show_header(
+        args: ShowHeaderArgs,
+        context: &SimulatorContext,
+    ) -> Result<(), SimulatorError> {
+        info!("Loading the blockchain from {}", args.blockchain_state);
+        let blockchain = load_blockchain(&args.blockchain_state, context)?;
+        let block = blockchain.get_by_number(args.block_number)?;
+        let header = &block.header;
+
+        let recomputed_hash = context.header_format.header_hash(header);
+        let header_hash = recomputed_hash == header.hash;
+        let proof_of_work =
+            header_hash && is_valid_block_header_hash(&header.hash, header.difficulty as usize);
+        let block_interval = blockchain
+            .get_by_number(args.block_number - 1)
+            .ok()
+            .map(|previous| header.timestamp as i64 - previous.header.timestamp as i64);
+        let total_fees = total_fees(block)?;
+        if !header_hash {
+            warn!(
+                "The stored hash {} of block {} doesn't match the header, which hashes to {}",
+                header.hash, header.height, recomputed_hash
+            );
+        }
+
+        let mut rendered = String::new();
+        writeln!(rendered, "height: {}", header.height).unwrap();
+        writeln!(rendered, "hash: {}", header.hash).unwrap();
+        writeln!(
+            rendered,
+            "previous block hash: {}",
+            header.previous_block_header_hash
+        )
+        .unwrap();
+        writeln!(rendered, "merkle root: {}", header.transactions_merkle_root).unwrap();
+        writeln!(rendered, "miner: {}", header.miner).unwrap();
+        writeln!(
+            rendered,
+            "timestamp: {} ({})",
+            header.timestamp,
+            format_timestamp(header.timestamp)
+        )
+        .unwrap();
+        writeln!(rendered, "difficulty: {}", header.difficulty).unwrap();
+        writeln!(rendered, "nonce: {}", header.nonce).unwrap();
+        writeln!(rendered, "version: {}", header.version).unwrap();
+        writeln!(rendered, "transactions: {}", header.transactions_count).unwrap();
+        writeln!(
+            rendered,
+            "recomputed hash: {} ({})",
+            recomputed_hash,
+            if header_hash { "match" } else { "mismatch" }
+        )
+        .unwrap();
+        writeln!(rendered, "proof of work: {}", proof_of_work).unwrap();
+        match block_interval {
+            Some(block_interval) => {
+                writeln!(rendered, "time since previous block: {}s", block_interval).unwrap()
+            }
+            None => writeln!(rendered, "time since previous block: -").unwrap(),
+        }
+        write!(
+            rendered,
+            "total fees: {}",
+            total_fees.format(context.denomination)
+        )
+        .unwrap();
+
+        let result = HeaderResult {
+            header,
+            recomputed_hash,
+            header_hash,
+            proof_of_work,
+            block_interval,
+            total_fees,
+        };
+        print_result(context.result_format, rendered, &result)
+    }
+
+    fn total_fees(block: &Block) -> Result<Amount, SimulatorError> {
+        block
+            .transactions
+            .iter()
+            .try_fold(Amount::ZERO, |total, t| {
+                total.checked_add(t.transaction_fee)
+            })
+            .ok_or_else(|| {
+                SimulatorError::AmountOverflow(format!("fees of block {}", block.header.height))
+            })
+    }
+
+    /// Field of a block shown by `ListBlocks`.
+    #[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+    pub enum BlockColumn {
+        Height,
+        Hash,
+        PreviousHash,
+        MerkleRoot,
+        Miner,
+        /// The timestamp as an RFC 3339 date
+        Time,
+        Timestamp,
+        Difficulty,
+        Nonce,
+        Version,
+        Transactions,
+        /// Sum of the fees of the transactions of the block
+        Fees,
+    }
+
+    impl BlockColumn {
+        fn name(self) -> String {
+            self.to_possible_value()
+                .unwrap()
+                .get_name()
+                .replace('-', "_")
+        }
+
+        /// Value of the column for the block, together with how it is shown
+        /// in the table.
+        fn value(
+            self,
+            block: &Block,
+            denomination: Denomination,
+        ) -> Result<(serde_json::Value, String), SimulatorError> {
+            let header = &block.header;
+            let value: serde_json::Value = match self {
+                BlockColumn::Height => header.height.into(),
+                BlockColumn::Hash => header.hash.to_string().into(),
+                BlockColumn::PreviousHash => header.previous_block_header_hash.to_string().into(),
+                BlockColumn::MerkleRoot => header.transactions_merkle_root.to_string().into(),
+                BlockColumn::Miner => header.miner.to_string().into(),
+                BlockColumn::Time => format_timestamp(header.timestamp).into(),
+                BlockColumn::Timestamp => header.timestamp.into(),
+                BlockColumn::Difficulty => header.difficulty.into(),
+                BlockColumn::Nonce => header.nonce.into(),
+                BlockColumn::Version => header.version.into(),
+                BlockColumn::Transactions => header.transactions_count.into(),
+                BlockColumn::Fees => {
+                    let fees = total_fees(block)?;
+                    return Ok((fees.0.into(), fees.format(denomination)));
+                }
+            };
+            let shown = match &value {
+                serde_json::Value::String(string) => string.clone(),
+                value => value.to_string(),
+            };
+            Ok((value, shown))
+        }
+    }
+
+    #[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+    pub enum BlockListFormat {
+        /// Aligned table with one line per block
+        Table,
+        /// JSON array of the blocks with the selected columns
+        Json,
+    }
+
+    /// Lists the blocks passing all the filters with the selected columns,
+    /// so that big chains can be sliced without other tools.
+    pub fn list_blocks(
+        args: ListBlocksArgs,
+        context: &SimulatorContext,
+    ) -> Result<(), SimulatorError> {
+        if let (Some(from), Some(to)) = (args.from_height, args.to_height) {
+            if from > to {
+                return Err(SimulatorError::EmptyRange { from, to });
+            }
+        }
+        info!("Loading the blockchain from {}", args.blockchain_state);
+        let blockchain = load_blockchain(&args.blockchain_state, context)?;
+
+        let blocks: Vec<&Block> = blockchain
+            .iter()
+            .filter(|block| {
+                let header = &block.header;
+                args.from_height.is_none_or(|from| header.height >= from)
+                    && args.to_height.is_none_or(|to| header.height <= to)
+                    && args.miner.is_none_or(|miner| header.miner == miner)
+                    && args
+                        .min_transactions
+                        .is_none_or(|min| header.transactions_count >= min)
+                    && args.since.is_none_or(|since| header.timestamp >= since)
+                    && args.until.is_none_or(|until| header.timestamp <= until)
+            })
+            .collect();
+        info!(
+            "{} of {} blocks match the filters",
+            blocks.len(),
+            blockchain.len()
+        );
+
+        let rows = blocks
+            .iter()
+            .map(|block| {
+                args.columns
+                    .iter()
+                    .map(|column| column.value(block, context.denomination))
+                    .collect::<Result<Vec<_>, SimulatorError>>()
+            })
+            .collect::<Result<Vec<_>, SimulatorError>>()?;
+
+        match args.format {
+            BlockListFormat::Json => {
+                let objects: Vec<serde_json::Map<String, serde_json::Value>> = rows
+                    .into_iter()
+                    .map(|row| {
+                        args.columns
+                            .iter()
+                            .zip(row)
+                            .map(|(column, (value, _))| (column.name(), value))
+                            .collect()
+                    })
+                    .collect();
+                write_json(&args.output, context.output, &objects)
+            }
+            BlockListFormat::Table => {
+                let names: Vec<String> = args.columns.iter().map(|column| column.name()).collect();
+                let widths: Vec<usize> = names
+                    .iter()
+                    .enumerate()
+                    .map(|(i, name)| {
+                        rows.iter()
+                            .map(|row| row[i].1.chars().count())
+                            .fold(name.len(), usize::max)
+                    })
+                    .collect();
+                let mut table = String::new();
+                let cells: Vec<String> = names
+                    .iter()
+                    .zip(&widths)
+                    .map(|(name, width)| format!("{:<width$}", name))
+                    .collect();
+                writeln!(table, "{}", cells.join("  ").trim_end()).unwrap();
+                for row in &rows {
+                    // Numbers are aligned to the right and text to the left.
+                    let cells: Vec<String> = row
+                        .iter()
+                        .zip(&widths)
+                        .map(|((value, shown), width)| {
+                            if value.is_number() {
+                                format!("{:>width$}", shown)
+                            } else {
+                                format!("{:<width$}", shown)
+                            }
+                        })
+                        .collect();
+                    writeln!(table, "{}", cells.join("  ").trim_end()).unwrap();
+                }
+                write_text(&args.output, context.output, &table)
+            }
+        }
+    }
+
+    #[derive(Serialize)]
+    struct ConfirmationsResult {
+        transaction_hash: Hash256,
+        block_height: u32,
+        confirmations: u32,
+        attacker_hashrate: f64,
+        reversal_probability: f64,
+    }
+
+    /// Reports how many blocks bury the transaction, counting the block that
+    /// includes it, and the probability that an attacker with the given share
+    /// of the hashrate ever catches up with the honest chain to reverse it.
+    pub fn show_confirmations(
+        args: GetConfirmationsArgs,
+        context: &SimulatorContext,
+    ) -> Result<(), SimulatorError> {
+        info!("Loading the blockchain from {}", args.blockchain_state);
+        let blockchain = load_blockchain(&args.blockchain_state, context)?;
+
+        let block = blockchain
+            .iter()
+            .find(|block| {
+                block
+                    .transactions
+                    .iter()
+                    .any(|t| t.hash() == args.transaction_hash)
+            })
+            .ok_or_else(|| {
+                SimulatorError::TransactionNotInChain(args.transaction_hash.to_string())
+            })?;
+
+        let tip_height = blockchain.tip_header()?.height;
+        let confirmations = tip_height.checked_sub(block.header.height).ok_or_else(|| {
+            SimulatorError::InvalidHeader {
+                height: block.header.height,
+                reason: format!("it is above the tip of the chain at height {}", tip_height),
+            }
+        })? + 1;
+        info!(
+            "Transaction {} is included in block {} ({}) and has {} confirmations",
+            args.transaction_hash,
+            block.header.height,
+            format_timestamp(block.header.timestamp),
+            confirmations
+        );
+        let reversal_probability = reversal_probability(args.attacker_hashrate, confirmations);
+        info!(
+            "Probability of reversal by an attacker with {:.1}% of the hashrate: {:.6}",
+            args.attacker_hashrate * 100.0,
+            reversal_probability
+        );
+        let result = ConfirmationsResult {
+            transaction_hash: args.transaction_hash,
+            block_height: block.header.height,
+            confirmations,
+            attacker_hashrate: args.attacker_hashrate,
+            reversal_probability,
+        };
+        print_result(context.result_format, confirmations, &result)
+    }
+
+    /// Probability that an attacker with the share `q` of the hashrate catches
+    /// up from `z` blocks behind, following section 11 of the Bitcoin paper:
+    /// the attacker's progress while the honest miners find `z` blocks is
+    /// Poisson distributed, after which it has to close the remaining gap.
+    fn reversal_probability(q: f64, z: u32) -> f64 {
+        let p = 1.0 - q;
+        if q >= p {
+            return 1.0;
+        }
+        let lambda = z as f64 * q / p;
+        let mut poisson = (-lambda).exp();
+        let mut probability = 1.0;
+        for k in 0..=z {
+            if k > 0 {
+                poisson *= lambda / k as f64;
+            }
+            probability -= poisson * (1.0 - (q / p).powi((z - k) as i32));
+        }
+        probability.max(0.0)
+    }
+
+    pub fn export_headers(
+        args: ExportHeadersArgs,
+        context: &SimulatorContext,
+    ) -> Result<(), SimulatorError> {
+        info!("Loading the block headers from {}", args.blockchain_state);
+        let headers = load_headers(&args.blockchain_state, context)?;
+        info!(
+            "Writing {} headers to {}",
+            headers.len(),
+            args.headers_output
+        );
+        write_headers(
+            &args.headers_output,
+            context.output,
+            context.data_format,
+            &headers,
+        )
+    }
+
+    #[derive(Serialize)]
+    struct SupplyReportRow {
+        height: u32,
+        subsidy: Amount,
+        /// Coins issued by the subsidies of the blocks up to this height.
+        issued_supply: Amount,
+    }
+
+    /// Reports the coinbase subsidy of every block and the cumulative supply
+    /// issued by the subsidies, only the headers of the blocks are loaded.
+    pub fn supply_report(
+        args: SupplyReportArgs,
+        context: &SimulatorContext,
+    ) -> Result<(), SimulatorError> {
+        info!("Loading the block headers from {}", args.blockchain_state);
+        let headers = load_headers(&args.blockchain_state, context)?;
+
+        let schedule = context.subsidy_schedule;
+        let mut issued_supply = Amount::ZERO;
+        let rows: Vec<SupplyReportRow> = headers
+            .iter()
+            .map(|header| {
+                let subsidy = schedule.subsidy_at(header.height);
+                issued_supply = issued_supply.checked_add(subsidy).ok_or_else(|| {
+                    SimulatorError::AmountOverflow(format!(
+                        "subsidies up to block {}",
+                        header.height
+                    ))
+                })?;
+                Ok(SupplyReportRow {
+                    height: header.height,
+                    subsidy,
+                    issued_supply,
+                })
+            })
+            .collect::<Result<_, SimulatorError>>()?;
+
+        info!(
+            "Total issued supply: {}",
+            issued_supply.format(context.denomination)
+        );
+        write_report(&args.report_output, context.output, args.format, &rows)
+    }
+
+    #[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+    pub enum RevenueFormat {
+        /// Table with one line per miner for reading in the terminal
+        Table,
+        Json,
+        Csv,
+    }
+
+    #[derive(Serialize)]
+    struct MinerRevenueRow {
+        miner: Address,
+        blocks: usize,
+        subsidy: Amount,
+        fees: Amount,
+        /// Sum of the subsidy and the fees.
+        revenue: Amount,
+        /// Share of the blocks of the chain mined by the miner.
+        block_share: f64,
+    }
+
+    /// Reports the blocks mined by every miner of the chain with the
+    /// subsidies and fees they earned, ordered from the miner with the most
+    /// blocks.
+    pub fn miner_revenue(
+        args: MinerRevenueArgs,
+        context: &SimulatorContext,
+    ) -> Result<(), SimulatorError> {
+        info!("Loading the blockchain from {}", args.blockchain_state);
+        let blockchain = load_blockchain(&args.blockchain_state, context)?;
+
+        let overflow = |miner: Address| {
+            move || SimulatorError::AmountOverflow(format!("revenue of the miner {}", miner))
+        };
+        let mut revenues: HashMap<Address, MinerRevenueRow> = HashMap::new();
+        for block in &blockchain {
+            let miner = block.header.miner;
+            let row = revenues.entry(miner).or_insert(MinerRevenueRow {
+                miner,
+                blocks: 0,
+                subsidy: Amount::ZERO,
+                fees: Amount::ZERO,
+                revenue: Amount::ZERO,
+                block_share: 0.0,
+            });
+            let subsidy = context.subsidy_schedule.subsidy_at(block.header.height);
+            let fees = total_fees(block)?;
+            row.blocks += 1;
+            row.subsidy = row
+                .subsidy
+                .checked_add(subsidy)
+                .ok_or_else(overflow(miner))?;
+            row.fees = row.fees.checked_add(fees).ok_or_else(overflow(miner))?;
+            row.revenue = row
+                .subsidy
+                .checked_add(row.fees)
+                .ok_or_else(overflow(miner))?;
+        }
+
+        let mut rows: Vec<MinerRevenueRow> = revenues.into_values().collect();
+        for row in &mut rows {
+            row.block_share = row.blocks as f64 / blockchain.len() as f64;
+        }
+        rows.sort_by(|a, b| b.blocks.cmp(&a.blocks).then(a.miner.cmp(&b.miner)));
+        info!(
+            "{} miners mined the {} blocks of the chain",
+            rows.len(),
+            blockchain.len()
+        );
+
+        let format = match args.format {
+            RevenueFormat::Table => {
+                let amount = |amount: Amount| amount.format(context.denomination);
+                let mut table = String::new();
+                writeln!(
+                    table,
+                    "{:<42} {:>7} {:>7} {:>20} {:>20} {:>20}",
+                    "miner", "blocks", "share", "subsidy", "fees", "revenue"
+                )
+                .unwrap();
+                for row in &rows {
+                    writeln!(
+                        table,
+                        "{:<42} {:>7} {:>6.2}% {:>20} {:>20} {:>20}",
+                        row.miner,
+                        row.blocks,
+                        row.block_share * 100.0,
+                        amount(row.subsidy),
+                        amount(row.fees),
+                        amount(row.revenue)
+                    )
+                    .unwrap();
+                }
+                return write_text(&args.report_output, context.output, &table);
+            }
+            RevenueFormat::Json => ReportFormat::Json,
+            RevenueFormat::Csv => ReportFormat::Csv,
+        };
+        write_report(&args.report_output, context.output, format, &rows)
+    }
+
+    #[derive(Serialize)]
+    struct BalanceHistoryRow {
+        height: u32,
+        hash: Hash256,
+        /// Balance of the address after the block, which is negative for the
+        /// senders of the tutorial transactions that were never funded.
+        balance: i64,
+        /// Change of the balance in the block.
+        change: i64,
+    }
+
+    /// Replays the balances of the chain and reports the balance of the
+    /// address after every block, to follow how the wealth of a miner or a
+    /// trader evolves.
+    pub fn balance_history(
+        args: BalanceHistoryArgs,
+        context: &SimulatorContext,
+    ) -> Result<(), SimulatorError> {
+        info!("Loading the blockchain from {}", args.blockchain_state);
+        let blockchain = load_blockchain(&args.blockchain_state, context)?;
+
+        let mut balances = Balances::new();
+        let mut previous = 0;
+        let rows = blockchain
+            .iter()
+            .map(|block| {
+                apply_block(&mut balances, block, &context.subsidy_schedule)?;
+                let balance = balances.get(&args.address).copied().unwrap_or(0);
+                let change = balance - previous;
+                previous = balance;
+                Ok(BalanceHistoryRow {
+                    height: block.header.height,
+                    hash: block.header.hash,
+                    balance,
+                    change,
+                })
+            })
+            .collect::<Result<Vec<_>, SimulatorError>>()?;
+
+        info!(
+            "The final balance of {} is {}",
+            args.address,
+            match u64::try_from(previous) {
+                Ok(balance) => Amount(balance).format(context.denomination),
+                Err(_) => format!("{} base units", previous),
+            }
+        );
+        write_report(&args.report_output, context.output, args.format, &rows)
+    }
+
+    #[derive(Serialize, Clone, Copy, PartialEq, Eq)]
+    #[serde(rename_all = "snake_case")]
+    enum TransactionStatus {
+        Included,
+        /// Not included yet but still minable after the tip of the chain.
+        Pending,
+        /// Not included and its lock time has passed the tip of the chain.
+        Expired,
+    }
+
+    #[derive(Serialize)]
+    struct TransactionLifecycleRow {
+        transaction_hash: Hash256,
+        transaction_fee: Amount,
+        status: TransactionStatus,
+        block_height: Option<u32>,
+        /// Number of the transaction in its block, starting from 1.
+        position: Option<usize>,
+        /// Blocks mined from the starting height until the one including the
+        /// transaction, which is 0 if it was included in the first of them.
+        waited_blocks: Option<u32>,
+    }
+
+    /// Follows every transaction of a mempool through the chain mined from
+    /// it, reporting the block and position it was included at and how many
+    /// blocks it waited, or whether it is still pending or has expired.
+    pub fn track_transactions(
+        args: TrackTransactionsArgs,
+        context: &SimulatorContext,
+    ) -> Result<(), SimulatorError> {
+        info!("Loading the mempool from {}", args.mempool);
+        let transactions = load_transactions(&args.mempool)?;
+        info!("Loading the blockchain from {}", args.blockchain_state);
+        let blockchain = load_blockchain(&args.blockchain_state, context)?;
+
+        let mempool_hashes: HashSet<Hash256> = transactions.iter().map(|t| t.hash()).collect();
+        let mut inclusions: HashMap<Hash256, (u32, usize)> = HashMap::new();
+        for block in &blockchain {
+            for (i, transaction) in block.transactions.iter().enumerate() {
+                let hash = transaction.hash();
+                if mempool_hashes.contains(&hash) {
+                    inclusions
+                        .entry(hash)
+                        .or_insert((block.header.height, i + 1));
+                }
+            }
+        }
+        let from_height = args
+            .from_height
+            .or_else(|| inclusions.values().map(|&(height, _)| height).min())
+            .unwrap_or(0);
+        let tip_timestamp = blockchain.tip_header()?.timestamp;
+
+        let rows: Vec<TransactionLifecycleRow> = transactions
+            .iter()
+            .map(|transaction| {
+                let transaction_hash = transaction.hash();
+                let inclusion = inclusions.get(&transaction_hash).copied();
+                let status = match inclusion {
+                    Some(_) => TransactionStatus::Included,
+                    None if u64::from(transaction.lock_time) > tip_timestamp => {
+                        TransactionStatus::Pending
+                    }
+                    None => TransactionStatus::Expired,
+                };
+                TransactionLifecycleRow {
+                    transaction_hash,
+                    transaction_fee: transaction.transaction_fee,
+                    status,
+                    block_height: inclusion.map(|(height, _)| height),
+                    position: inclusion.map(|(_, position)| position),
+                    waited_blocks: inclusion.map(|(height, _)| height.saturating_sub(from_height)),
+                }
+            })
+            .collect();
+
+        let count = |status| rows.iter().filter(|row| row.status == status).count();
+        info!(
+            "Of the {} transactions of the mempool {} were included, {} are pending and {} expired",
+            rows.len(),
+            count(TransactionStatus::Included),
+            count(TransactionStatus::Pending),
+            count(TransactionStatus::Expired)
+        );
+        write_report(&args.report_output, context.output, args.format, &rows)
+    }
+
+    #[derive(Serialize)]
+    struct DifficultyReportRow {
+        height: u32,
+        timestamp: u64,
+        /// The timestamp as an RFC 3339 date.
+        time: String,
+        difficulty: u32,
+        /// Seconds since the previous block, missing for the genesis block.
+        block_interval: Option<i64>,
+        /// Hashes per second needed to find the block in the observed
+        /// interval, a valid hash takes 16^difficulty attempts on average.
+        implied_hashrate: Option<f64>,
+    }
+
+    /// Reports how the difficulty and the block intervals evolve along the
+    /// chain, only the headers of the blocks are loaded.
+    pub fn difficulty_report(
+        args: DifficultyReportArgs,
+        context: &SimulatorContext,
+    ) -> Result<(), SimulatorError> {
+        info!("Loading the block headers from {}", args.blockchain_state);
+        let headers = load_headers(&args.blockchain_state, context)?;
+
+        let mut previous_timestamp = None;
+        let rows: Vec<DifficultyReportRow> = headers
+            .iter()
+            .map(|header| {
+                let block_interval = previous_timestamp
+                    .map(|previous: u64| header.timestamp as i64 - previous as i64);
+                previous_timestamp = Some(header.timestamp);
+                DifficultyReportRow {
+                    height: header.height,
+                    timestamp: header.timestamp,
+                    time: format_timestamp(header.timestamp),
+                    difficulty: header.difficulty,
+                    block_interval,
+                    implied_hashrate: block_interval
+                        .filter(|&interval| interval > 0)
+                        .map(|interval| 16f64.powi(header.difficulty as i32) / interval as f64),
+                }
+            })
+            .collect();
+
+        info!("Writing the difficulty report of {} blocks", rows.len());
+        write_report(&args.report_output, context.output, args.format, &rows)
+    }
+
+    #[derive(Serialize)]
+    struct MiningStatsSummary<'a> {
+        blocks: usize,
+        nonces_tried: u64,
+        seconds: f64,
+        /// Nonces tried per second over all the blocks.
+        hashrate: f64,
+        /// Block whose nonce took the longest to find.
+        slowest_block: Option<&'a MiningStats>,
+    }
+
+    /// Summarises the mining statistics written by produce-blocks.
+    pub fn mining_stats(
+        args: MiningStatsArgs,
+        context: &SimulatorContext,
+    ) -> Result<(), SimulatorError> {
+        info!("Loading the mining statistics from {}", args.mining_stats);
+        let stats: Vec<MiningStats> = load_json(&args.mining_stats)?;
+
+        let nonces_tried = stats.iter().map(|block| block.nonces_tried).sum();
+        let seconds: f64 = stats.iter().map(|block| block.seconds).sum();
+        let summary = MiningStatsSummary {
+            blocks: stats.len(),
+            nonces_tried,
+            seconds,
+            hashrate: nonces_tried as f64 / seconds.max(f64::EPSILON),
+            slowest_block: stats.iter().max_by(|a, b| a.seconds.total_cmp(&b.seconds)),
+        };
+        info!(
+            "Mining {} blocks took {} nonces in {:.3} s ({:.0} nonces per second)",
+            summary.blocks, summary.nonces_tried, summary.seconds, summary.hashrate
+        );
+        write_json(&args.report_output, context.output, &summary)
+    }
+
+    #[derive(Serialize)]
+    struct FeeSummary {
+        total: Amount,
+        mean: f64,
+        min: Amount,
+        p5: f64,
+        p50: f64,
+        p95: f64,
+        max: Amount,
+    }
+
+    /// Number of transactions whose lock times lie in a range.
+    #[derive(Serialize)]
+    struct LockTimeBucket {
+        from: u64,
+        /// Last lock time of the range, which is included in it.
+        to: u64,
+        from_time: String,
+        to_time: String,
+        transactions: usize,
+    }
+
+    #[derive(Serialize)]
+    struct LockTimeDistribution {
+        /// Transactions with the lock time zero, which can't be mined at all.
+        zero: usize,
+        /// Transactions with the largest lock time, which never expire.
+        never_expiring: usize,
+        /// Ranges of the other lock times.
+        buckets: Vec<LockTimeBucket>,
+    }
+
+    impl LockTimeDistribution {
+        fn of(transactions: &[Transaction], buckets: u32) -> Self {
+            let lock_times: Vec<u64> = transactions
+                .iter()
+                .map(|t| u64::from(t.lock_time))
+                .filter(|&lock_time| lock_time != 0 && lock_time != u64::from(u32::MAX))
+                .collect();
+            let buckets = match (lock_times.iter().min(), lock_times.iter().max()) {
+                (Some(&min), Some(&max)) => {
+                    let width = (max - min) / u64::from(buckets) + 1;
+                    let mut counts = vec![0; buckets as usize];
+                    for lock_time in &lock_times {
+                        counts[((lock_time - min) / width) as usize] += 1;
+                    }
+                    counts
+                        .into_iter()
+                        .enumerate()
+                        .map(|(i, transactions)| {
+                            let from = min + i as u64 * width;
+                            let to = (from + width - 1).min(max);
+                            LockTimeBucket {
+                                from,
+                                to,
+                                from_time: format_timestamp(from),
+                                to_time: format_timestamp(to),
+                                transactions,
+                            }
+                        })
+                        .filter(|bucket| bucket.from <= max)
+                        .collect()
+                }
+                _ => vec![],
+            };
+            LockTimeDistribution {
+                zero: transactions.iter().filter(|t| t.lock_time == 0).count(),
+                never_expiring: transactions
+                    .iter()
+                    .filter(|t| t.lock_time == u32::MAX)
+                    .count(),
+                buckets,
+            }
+        }
+    }
+
+    #[derive(Serialize)]
+    struct MempoolStats {
+        transactions: usize,
+        /// Missing for an empty mempool.
+        fees: Option<FeeSummary>,
+        lock_times: LockTimeDistribution,
+        distinct_senders: usize,
+        block_capacity: usize,
+        /// Blocks needed to include every transaction at the block capacity,
+        /// regardless of their lock times.
+        blocks_to_clear: usize,
+    }
+
+    /// Summarises the transactions of a mempool file.
+    pub fn mempool_stats(
+        args: MempoolStatsArgs,
+        context: &SimulatorContext,
+    ) -> Result<(), SimulatorError> {
+        info!("Loading the mempool from {}", args.mempool);
+        let transactions = load_transactions(&args.mempool)?;
+
+        let mut fees: Vec<Amount> = transactions.iter().map(|t| t.transaction_fee).collect();
+        fees.sort();
+        let fees = match (fees.first(), fees.last()) {
+            (Some(&min), Some(&max)) => {
+                let total = fees
+                    .iter()
+                    .try_fold(Amount::ZERO, |total, &fee| total.checked_add(fee))
+                    .ok_or_else(|| SimulatorError::AmountOverflow("fees of the mempool".into()))?;
+                let sorted: Vec<f64> = fees.iter().map(|fee| fee.0 as f64).collect();
+                Some(FeeSummary {
+                    total,
+                    mean: total.0 as f64 / fees.len() as f64,
+                    min,
+                    p5: percentile(&sorted, 5.0),
+                    p50: percentile(&sorted, 50.0),
+                    p95: percentile(&sorted, 95.0),
+                    max,
+                })
+            }
+            _ => None,
+        };
+        let distinct_senders = transactions
+            .iter()
+            .map(|t| t.sender)
+            .collect::<HashSet<Address>>()
+            .len();
+        let stats = MempoolStats {
+            transactions: transactions.len(),
+            fees,
+            lock_times: LockTimeDistribution::of(&transactions, args.lock_time_buckets),
+            distinct_senders,
+            block_capacity: args.block_capacity,
+            blocks_to_clear: transactions.len().div_ceil(args.block_capacity.max(1)),
+        };
+        info!(
+            "The mempool holds {} transactions from {} senders, which take {} blocks to clear",
+            stats.transactions, stats.distinct_senders, stats.blocks_to_clear
+        );
+        write_json(&args.report_output, context.output, &stats)
+    }
+
+    #[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+    pub enum HistogramFormat {
+        /// Bars of the bucket counts for reading in the terminal
+        Text,
+        Json,
+        Csv,
+    }
+
+    /// Number of transactions whose fee lies in a range.
+    #[derive(Serialize)]
+    struct FeeBucket {
+        from: f64,
+        /// End of the range, which is only included in the last bucket.
+        to: f64,
+        transactions: usize,
+        /// Share of the transactions of the mempool in the bucket.
+        share: f64,
+    }
+
+    /// Length of the bar of the largest bucket in the text histogram.
+    const HISTOGRAM_WIDTH: usize = 50;
+
+    /// Counts the transactions of a mempool in equally wide ranges of fees or
+    /// fees per byte, to see whether the generated fees look realistic.
+    pub fn fee_histogram(
+        args: FeeHistogramArgs,
+        context: &SimulatorContext,
+    ) -> Result<(), SimulatorError> {
+        info!("Loading the mempool from {}", args.mempool);
+        let transactions = load_transactions(&args.mempool)?;
+
+        let fees: Vec<f64> = transactions
+            .iter()
+            .map(|t| match args.by {
+                TransactionSelection::Fee => t.transaction_fee.0 as f64,
+                TransactionSelection::FeePerByte => t.fee_per_byte(),
+            })
+            .collect();
+        let min = fees.iter().copied().fold(f64::INFINITY, f64::min);
+        let max = fees.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+        let buckets = args.buckets as usize;
+        // All the fees fall in a single bucket if they are equal, and there
+        // are no buckets for an empty mempool.
+        let (width, mut counts) = if fees.is_empty() {
+            (0.0, vec![])
+        } else if max == min {
+            (0.0, vec![0])
+        } else {
+            ((max - min) / buckets as f64, vec![0; buckets])
+        };
+        for fee in &fees {
+            let bucket = if width > 0.0 {
+                (((fee - min) / width) as usize).min(buckets - 1)
+            } else {
+                0
+            };
+            counts[bucket] += 1;
+        }
+        let rows: Vec<FeeBucket> = counts
+            .into_iter()
+            .enumerate()
+            .map(|(i, transactions)| FeeBucket {
+                from: min + i as f64 * width,
+                to: min + (i + 1) as f64 * width,
+                transactions,
+                share: transactions as f64 / fees.len() as f64,
+            })
+            .collect();
+        info!(
+            "Counted {} transactions in {} buckets",
+            fees.len(),
+            rows.len()
+        );
+
+        let format = match args.format {
+            HistogramFormat::Text => {
+                let largest = rows.iter().map(|row| row.transactions).max().unwrap_or(0);
+                let precision = match args.by {
+                    TransactionSelection::Fee => 2,
+                    TransactionSelection::FeePerByte => 4,
+                };
+                let mut histogram = String::new();
+                for row in &rows {
+                    let bar = row.transactions * HISTOGRAM_WIDTH / largest.max(1);
+                    writeln!(
+                        histogram,
+                        "{:>12.precision$} - {:<12.precision$} {:<width$} {:>7} ({:.1}%)",
+                        row.from,
+                        row.to,
+                        "#".repeat(bar),
+                        row.transactions,
+                        row.share * 100.0,
+                        width = HISTOGRAM_WIDTH
+                    )
+                    .unwrap();
+                }
+                return write_text(&args.output, context.output, &histogram);
+            }
+            HistogramFormat::Json => ReportFormat::Json,
+            HistogramFormat::Csv => ReportFormat::Csv,
+        };
+        write_report(&args.output, context.output, format, &rows)
+    }
+
+    #[derive(Serialize)]
+    struct HeaderAuditRow {
+        height: u32,
+        hash: Hash256,
+        difficulty: u32,
+        /// Leading zero hex digits of the stored hash.
+        leading_zeros: usize,
+        /// Whether the stored hash is the hash of the header in the
+        /// configured header format.
+        header_hash: bool,
+        proof_of_work: bool,
+        /// Whether the header links to the hash of the previous header, or to
+        /// the null hash for the genesis block.
+        linked: bool,
+        passed: bool,
+    }
+
+    /// Recomputes the hash of every header in the configured header format,
+    /// checks that it satisfies the difficulty and that the header links to
+    /// the previous one, and reports the outcome of each check per block. The first header of a chain
+    /// segment that doesn't start at the genesis block is linked by
+    /// definition.
+    pub fn audit_headers(
+        args: AuditHeadersArgs,
+        context: &SimulatorContext,
+    ) -> Result<(), SimulatorError> {
+        info!("Loading the block headers from {}", args.blockchain_state);
+        let headers = load_headers(&args.blockchain_state, context)?;
+
+        let mut previous: Option<&Header> = None;
+        let rows: Vec<HeaderAuditRow> = headers
+            .iter()
+            .map(|header| {
+                let header_hash = header.has_valid_hash(context.header_format);
+                let proof_of_work =
+                    is_valid_block_header_hash(&header.hash, header.difficulty as usize);
+                let linked = match previous {
+                    Some(previous) => header.previous_block_header_hash == previous.hash,
+                    None => header.height != 0 || header.previous_block_header_hash == NULL_HASH,
+                };
+                previous = Some(header);
+                let passed = header_hash && proof_of_work && linked;
+                if !passed {
+                    warn!(
+                        "Block {} with hash {} failed the audit: hash {}, proof of work {}, link {}",
+                        header.height,
+                        header.hash,
+                        if header_hash { "valid" } else { "invalid" },
+                        if proof_of_work { "valid" } else { "invalid" },
+                        if linked { "valid" } else { "invalid" }
+                    );
+                }
+                HeaderAuditRow {
+                    height: header.height,
+                    hash: header.hash,
+                    difficulty: header.difficulty,
+                    leading_zeros: header.hash.leading_zero_digits(),
+                    header_hash,
+                    proof_of_work,
+                    linked,
+                    passed,
+                }
+            })
+            .collect();
+
+        let failed = rows.iter().filter(|row| !row.passed).count();
+        info!(
+            "{} of {} headers passed the audit, {} failed",
+            rows.len() - failed,
+            rows.len(),
+            failed
+        );
+        write_report(&args.report_output, context.output, args.format, &rows)?;
+        if failed > 0 {
+            return Err(SimulatorError::InvalidHeaders(failed));
+        }
+        Ok(())
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use crate::{
+            config::config::SimulatorContext,
+            error::error::SimulatorError,
+            model::blockchain::{Address, Amount, Block, Blockchain, Transaction},
+            node::miner::BlockBuilder,
+        };
+
+        use super::{get_transaction, reversal_probability, total_fees};
+
+        /// Genesis block with transactions paying the fees, mined at
+        /// difficulty 0.
+        fn block_with_fees(fees: &[u64]) -> Block {
+            let transactions = fees
+                .iter()
+                .map(|&fee| {
+                    Transaction::builder()
+                        .sender(Address([1; 20]))
+                        .receiver(Address([2; 20]))
+                        .amount(Amount(1))
+                        .transaction_fee(Amount(fee))
+                        .build()
+                        .unwrap()
+                })
+                .collect();
+            BlockBuilder::genesis()
+                .difficulty(0)
+                .transactions(transactions)
+                .build()
+                .mine(0, &SimulatorContext::default().nonce_search())
+                .unwrap()
+        }
+
+        #[test]
+        fn reversal_probability_matches_the_bitcoin_paper() {
+            // Values listed in section 11 of the paper for q = 0.1.
+            assert!((reversal_probability(0.1, 0) - 1.0).abs() < 1e-7);
+            assert!((reversal_probability(0.1, 1) - 0.2045873).abs() < 1e-7);
+            assert!((reversal_probability(0.1, 5) - 0.0009137).abs() < 1e-7);
+            assert_eq!(reversal_probability(0.5, 10), 1.0);
+        }
+
+        #[test]
+        fn transactions_are_numbered_from_one() {
+            let blockchain = Blockchain::from(vec![block_with_fees(&[3, 4])]);
+            let transaction = get_transaction(&blockchain, 1, 2).unwrap();
+            assert_eq!(transaction.transaction_fee, Amount(4));
+            for (block_number, transaction_number) in [(1, 0), (1, 3)] {
+                assert!(matches!(
+                    get_transaction(&blockchain, block_number, transaction_number),
+                    Err(SimulatorError::TransactionNotFound { .. })
+                ));
+            }
+            assert!(matches!(
+                get_transaction(&blockchain, 2, 1),
+                Err(SimulatorError::BlockNotFound(2))
+            ));
+        }
+
+        #[test]
+        fn total_fees_report_the_overflow() {
+            assert_eq!(total_fees(&block_with_fees(&[3, 4])).unwrap(), Amount(7));
+            let mut block = block_with_fees(&[1, 1]);
+            block.transactions[0].transaction_fee = Amount(u64::MAX);
+            assert!(matches!(
+                total_fees(&block),
+                Err(SimulatorError::AmountOverflow(_))
+            ));
+        }
     }
 }
@@ -0,0 +1,570 @@
+pub mod wallet {
+    #[cfg(feature = "cli")]
+    use std::collections::BTreeMap;
+
+    #[cfg(feature = "cli")]
+    use log::info;
+    use p256::{
+        ecdsa::{
+            signature::hazmat::{PrehashSigner, PrehashVerifier},
+            Signature, SigningKey, VerifyingKey,
+        },
+        pkcs8::{DecodePublicKey, EncodePublicKey},
+        SecretKey,
+    };
+    use sha2::{Digest, Sha256};
+
+    #[cfg(feature = "cli")]
+    use crate::{
+        args::args::{CreateWalletArgs, SignTransactionArgs},
+        config::config::SimulatorContext,
+        data_sourcing::data_provider::{load_json, write_json, write_text, OutputOptions},
+    };
+    use crate::{
+        error::error::SimulatorError,
+        hashing::hashing::Signable,
+        model::blockchain::{Address, Transaction, NULL_ADDRESS},
+    };
+
+    #[cfg(feature = "cli")]
+    use super::hd::{derive_accounts, generate_mnemonic, parse_mnemonic};
+
+    /// Collection of private keys indexed by the address they control. Wallet
+    /// files use the same format as keys.json, mapping each address to its
+    /// DER-encoded private key which is then hex-encoded and prefixed by 0x.
+    #[cfg(feature = "cli")]
+    #[derive(Default)]
+    pub struct Wallet {
+        keys: BTreeMap<Address, String>,
+    }
+
+    #[cfg(feature = "cli")]
+    impl Wallet {
+        pub fn load(file_name: &str) -> Result<Wallet, SimulatorError> {
+            Ok(Wallet {
+                keys: load_json(file_name)?,
+            })
+        }
+
+        pub fn signing_key(&self, address: &Address) -> Result<SigningKey, SimulatorError> {
+            let private_key = self
+                .keys
+                .get(address)
+                .ok_or(SimulatorError::KeyNotFound(*address))?;
+            parse_signing_key(private_key)
+        }
+
+        /// Adds the key to the wallet and returns the address it controls.
+        pub fn insert(&mut self, signing_key: &SigningKey) -> Address {
+            let address = address_of(signing_key.verifying_key());
+            let der = SecretKey::from(signing_key.as_nonzero_scalar())
+                .to_sec1_der()
+                .expect("P-256 private keys can always be DER-encoded");
+            self.keys
+                .insert(address, "0x".to_string() + &hex::encode(der));
+            address
+        }
+
+        pub fn save(&self, file_name: &str, output: OutputOptions) -> Result<(), SimulatorError> {
+            write_json(file_name, output, &self.keys)
+        }
+    }
+
+    /// Parses a hex-encoded SEC1 DER private key as stored in the wallet files.
+    pub fn parse_signing_key(private_key: &str) -> Result<SigningKey, SimulatorError> {
+        let malformed_key = || SimulatorError::MalformedKey(private_key.to_string());
+        let der = hex::decode(private_key.trim_start_matches("0x")).map_err(|_| malformed_key())?;
+        let secret_key = SecretKey::from_sec1_der(&der).map_err(|_| malformed_key())?;
+        Ok(SigningKey::from(secret_key))
+    }
+
+    /// DER encoding of the public key (as a SubjectPublicKeyInfo structure).
+    pub fn public_key_der(public_key: &VerifyingKey) -> Vec<u8> {
+        public_key
+            .to_public_key_der()
+            .expect("P-256 public keys can always be DER-encoded")
+            .into_vec()
+    }
+
+    /// The address is made of the last 20 bytes of the SHA-256 hash of the
+    /// DER-encoded public key.
+    pub fn address_of(public_key: &VerifyingKey) -> Address {
+        let hash = Sha256::digest(public_key_der(public_key));
+        let mut address = [0; 20];
+        address.copy_from_slice(&hash[hash.len() - 20..]);
+        Address(address)
+    }
+
+    /// The signature field is made of the hex-encoded DER public key of the
+    /// sender and the hex-encoded DER signature of the transaction's signing
+    /// hash, separated by a comma.
+    pub fn sign(
+        mut transaction: Transaction,
+        signing_key: &SigningKey,
+    ) -> Result<Transaction, SimulatorError> {
+        let address = address_of(signing_key.verifying_key());
+        if address != transaction.sender {
+            return Err(SimulatorError::KeyMismatch {
+                address,
+                sender: transaction.sender,
+            });
+        }
+
+        let signature: Signature = signing_key
+            .sign_prehash(&transaction.signing_hash())
+            .map_err(|_| SimulatorError::SigningFailed)?;
+
+        transaction.signature = format!(
+            "0x{},0x{}",
+            hex::encode(public_key_der(signing_key.verifying_key())),
+            hex::encode(signature.to_der())
+        );
+        Ok(transaction)
+    }
+
+    /// Verifies that the signature of the transaction is valid for its signing
+    /// hash and that the public key included in it belongs to the sender.
+    /// Transactions sent from the null address (genesis allocations) carry no
+    /// signature and are always considered valid.
+    pub fn verify_signature(transaction: &Transaction) -> bool {
+        if transaction.sender == NULL_ADDRESS {
+            return true;
+        }
+
+        let Some((public_key, signature)) = transaction.signature.split_once(',') else {
+            return false;
+        };
+        let decode = |value: &str| hex::decode(value.trim_start_matches("0x")).ok();
+        let (Some(public_key), Some(signature)) = (decode(public_key), decode(signature)) else {
+            return false;
+        };
+        let (Ok(public_key), Ok(signature)) = (
+            VerifyingKey::from_public_key_der(&public_key),
+            Signature::from_der(&signature),
+        ) else {
+            return false;
+        };
+
+        address_of(&public_key) == transaction.sender
+            && public_key
+                .verify_prehash(&transaction.signing_hash(), &signature)
+                .is_ok()
+    }
+
+    #[cfg(feature = "cli")]
+    pub fn sign_transaction(
+        args: SignTransactionArgs,
+        context: &SimulatorContext,
+    ) -> Result<(), SimulatorError> {
+        info!("Loading the unsigned transaction from {}", args.transaction);
+        let transaction: Transaction = load_json(&args.transaction)?;
+
+        info!("Loading the wallet from {}", args.wallet);
+        let wallet = Wallet::load(&args.wallet)?;
+        let signing_key = wallet.signing_key(&transaction.sender)?;
+
+        info!("Signing the transaction from {}...", transaction.sender);
+        let signed_transaction = sign(transaction, &signing_key)?;
+
+        write_json(
+            &args.signed_transaction,
+            context.output,
+            &signed_transaction,
+        )
+    }
+
+    /// Creates a wallet holding the keys of the first addresses derived from a
+    /// mnemonic phrase. A new phrase is generated unless one is given, so
+    /// that the same wallet can later be recreated from its backup phrase.
+    #[cfg(feature = "cli")]
+    pub fn create_wallet(
+        args: CreateWalletArgs,
+        context: &SimulatorContext,
+    ) -> Result<(), SimulatorError> {
+        let mnemonic = match &args.mnemonic {
+            Some(phrase) => parse_mnemonic(phrase)?,
+            None => {
+                let mnemonic = generate_mnemonic(args.words)?;
+                info!("Generated a new mnemonic, write it down to be able to restore the wallet");
+                let phrase = format!("{}\n", mnemonic);
+                write_text(&args.mnemonic_output, context.output, &phrase)?;
+                mnemonic
+            }
+        };
+
+        info!("Deriving the keys of {} accounts...", args.accounts);
+        let mut wallet = Wallet::default();
+        for key in derive_accounts(&mnemonic.to_seed(args.passphrase.as_str()), args.accounts)? {
+            let address = wallet.insert(&key.signing_key);
+            info!("Derived the address {}", address.checksummed());
+        }
+
+        wallet.save(&args.wallet_output, context.output)
+    }
+}
+
+/// Hierarchical deterministic key derivation, allowing any number of wallet
+/// keys to be recreated from a single seed. BIP32 is only defined for
+/// secp256k1, so the keys are derived following SLIP-10 which applies the same
+/// scheme to the P-256 curve used for signing the transactions.
+pub mod hd {
+    use bip39::Mnemonic;
+    use hmac::{Hmac, Mac};
+    use p256::{ecdsa::SigningKey, elliptic_curve::PrimeField, FieldBytes, Scalar};
+    use rand::{rngs::OsRng, RngCore};
+    use sha2::Sha512;
+
+    use crate::error::error::SimulatorError;
+
+    /// Indices from this offset onwards derive hardened children, which are
+    /// written with a trailing apostrophe in the derivation paths.
+    pub const HARDENED_OFFSET: u32 = 1 << 31;
+
+    /// HMAC key used to derive the master key from the seed, as defined by
+    /// SLIP-10 for the P-256 curve.
+    const MASTER_KEY_SALT: &[u8] = b"Nist256p1 seed";
+
+    /// BIP44 coin type reserved for testnets, which is what the simulated
+    /// coins are.
+    pub const BIP44_COIN_TYPE: u32 = 1;
+
+    /// Private key together with the chain code needed to derive its children.
+    #[derive(Clone)]
+    pub struct ExtendedKey {
+        pub signing_key: SigningKey,
+        pub chain_code: [u8; 32],
+    }
+
+    impl ExtendedKey {
+        /// Derives the master key of the tree from a seed of 16 to 64 bytes.
+        pub fn master(seed: &[u8]) -> ExtendedKey {
+            let mut digest = hmac_sha512(MASTER_KEY_SALT, &[seed]);
+            loop {
+                let (key, chain_code) = digest.split_at(32);
+                if let Ok(signing_key) = SigningKey::from_bytes(FieldBytes::from_slice(key)) {
+                    return ExtendedKey {
+                        signing_key,
+                        chain_code: chain_code.try_into().unwrap(),
+                    };
+                }
+                // The key is zero or not smaller than the curve order, SLIP-10
+                // retries with the HMAC of the rejected digest.
+                digest = hmac_sha512(MASTER_KEY_SALT, &[&digest]);
+            }
+        }
+
+        /// Derives the child key with the given index, indices from
+        /// `HARDENED_OFFSET` onwards produce hardened keys.
+        pub fn derive_child(&self, index: u32) -> ExtendedKey {
+            let mut data = if index >= HARDENED_OFFSET {
+                [&[0u8][..], &self.signing_key.to_bytes()].concat()
+            } else {
+                self.signing_key
+                    .verifying_key()
+                    .to_encoded_point(true)
+                    .as_bytes()
+                    .to_vec()
+            };
+            let parent_scalar: Scalar = *self.signing_key.as_nonzero_scalar().as_ref();
+            loop {
+                let digest = hmac_sha512(&self.chain_code, &[&data, &index.to_be_bytes()]);
+                let (tweak, chain_code) = digest.split_at(32);
+                let tweak: Option<Scalar> =
+                    Scalar::from_repr(*FieldBytes::from_slice(tweak)).into();
+                if let Some(tweak) = tweak {
+                    let child_scalar = tweak + parent_scalar;
+                    if let Ok(signing_key) = SigningKey::from_bytes(&child_scalar.to_repr()) {
+                        return ExtendedKey {
+                            signing_key,
+                            chain_code: chain_code.try_into().unwrap(),
+                        };
+                    }
+                }
+                // The derived key is invalid, SLIP-10 retries with the right
+                // half of the digest in place of the parent key.
+                data = [&[1u8][..], chain_code].concat();
+            }
+        }
+
+        /// Derives the key at a path such as m/44'/1'/0'/0/7 from the master key.
+        pub fn derive_path(&self, path: &str) -> Result<ExtendedKey, SimulatorError> {
+            let invalid_path = || SimulatorError::InvalidDerivationPath(path.to_string());
+            let mut segments = path.split('/');
+            if segments.next() != Some("m") {
+                return Err(invalid_path());
+            }
+            segments.try_fold(self.clone(), |key, segment| {
+                let (index, offset) = match segment.strip_suffix('\'') {
+                    Some(index) => (index, HARDENED_OFFSET),
+                    None => (segment, 0),
+                };
+                let index: u32 = index.parse().map_err(|_| invalid_path())?;
+                if index >= HARDENED_OFFSET {
+                    return Err(invalid_path());
+                }
+                Ok(key.derive_child(index + offset))
+            })
+        }
+    }
+
+    /// BIP44 path of the external chain of an account, the receiving
+    /// addresses of the account are its non-hardened children.
+    pub fn bip44_receiving_path(account: u32) -> String {
+        format!("m/44'/{}'/{}'/0", BIP44_COIN_TYPE, account)
+    }
+
+    /// Keys of the first `count` receiving addresses of the first BIP44
+    /// account of the wallet with the given seed.
+    pub fn derive_accounts(seed: &[u8], count: u32) -> Result<Vec<ExtendedKey>, SimulatorError> {
+        let receiving_chain = ExtendedKey::master(seed).derive_path(&bip44_receiving_path(0))?;
+        Ok((0..count)
+            .map(|index| receiving_chain.derive_child(index))
+            .collect())
+    }
+
+    /// Generates a new BIP39 mnemonic from fresh system entropy, each word
+    /// encodes 11 bits of which 32 / 3 per word are entropy.
+    pub fn generate_mnemonic(word_count: usize) -> Result<Mnemonic, SimulatorError> {
+        let mut entropy = vec![0u8; word_count * 4 / 3];
+        OsRng.fill_bytes(&mut entropy);
+        Mnemonic::from_entropy(&entropy)
+            .map_err(|e| SimulatorError::MalformedMnemonic(e.to_string()))
+    }
+
+    /// Parses an English BIP39 mnemonic, checking its words and checksum.
+    pub fn parse_mnemonic(phrase: &str) -> Result<Mnemonic, SimulatorError> {
+        Mnemonic::parse(phrase).map_err(|e| SimulatorError::MalformedMnemonic(e.to_string()))
+    }
+
+    fn hmac_sha512(key: &[u8], data: &[&[u8]]) -> [u8; 64] {
+        let mut mac = Hmac::<Sha512>::new_from_slice(key).expect("HMAC accepts keys of any size");
+        for chunk in data {
+            mac.update(chunk);
+        }
+        mac.finalize().into_bytes().into()
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::{ExtendedKey, HARDENED_OFFSET};
+
+        /// Chain code, private key and compressed public key of a derived key,
+        /// in hex.
+        struct Derived {
+            path: &'static str,
+            chain_code: &'static str,
+            private_key: &'static str,
+            public_key: &'static str,
+        }
+
+        fn check_vector(seed: &str, derivations: &[Derived]) {
+            let master = ExtendedKey::master(&hex::decode(seed).unwrap());
+            for expected in derivations {
+                let key = master.derive_path(expected.path).unwrap();
+                assert_eq!(
+                    hex::encode(key.chain_code),
+                    expected.chain_code,
+                    "chain code of {}",
+                    expected.path
+                );
+                assert_eq!(
+                    hex::encode(key.signing_key.to_bytes()),
+                    expected.private_key,
+                    "private key of {}",
+                    expected.path
+                );
+                assert_eq!(
+                    hex::encode(key.signing_key.verifying_key().to_encoded_point(true)),
+                    expected.public_key,
+                    "public key of {}",
+                    expected.path
+                );
+            }
+        }
+
+        /// Test vector 1 for nist256p1 of SLIP-10.
+        #[test]
+        fn slip10_test_vector_1() {
+            check_vector(
+                "000102030405060708090a0b0c0d0e0f",
+                &[
+                    Derived {
+                        path: "m",
+                        chain_code:
+                            "beeb672fe4621673f722f38529c07392fecaa61015c80c34f29ce8b41b3cb6ea",
+                        private_key:
+                            "612091aaa12e22dd2abef664f8a01a82cae99ad7441b7ef8110424915c268bc2",
+                        public_key:
+                            "0266874dc6ade47b3ecd096745ca09bcd29638dd52c2c12117b11ed3e458cfa9e8",
+                    },
+                    Derived {
+                        path: "m/0'",
+                        chain_code:
+                            "3460cea53e6a6bb5fb391eeef3237ffd8724bf0a40e94943c98b83825342ee11",
+                        private_key:
+                            "6939694369114c67917a182c59ddb8cafc3004e63ca5d3b84403ba8613debc0c",
+                        public_key:
+                            "0384610f5ecffe8fda089363a41f56a5c7ffc1d81b59a612d0d649b2d22355590c",
+                    },
+                    Derived {
+                        path: "m/0'/1",
+                        chain_code:
+                            "4187afff1aafa8445010097fb99d23aee9f599450c7bd140b6826ac22ba21d0c",
+                        private_key:
+                            "284e9d38d07d21e4e281b645089a94f4cf5a5a81369acf151a1c3a57f18b2129",
+                        public_key:
+                            "03526c63f8d0b4bbbf9c80df553fe66742df4676b241dabefdef67733e070f6844",
+                    },
+                    Derived {
+                        path: "m/0'/1/2'",
+                        chain_code:
+                            "98c7514f562e64e74170cc3cf304ee1ce54d6b6da4f880f313e8204c2a185318",
+                        private_key:
+                            "694596e8a54f252c960eb771a3c41e7e32496d03b954aeb90f61635b8e092aa7",
+                        public_key:
+                            "0359cf160040778a4b14c5f4d7b76e327ccc8c4a6086dd9451b7482b5a4972dda0",
+                    },
+                    Derived {
+                        path: "m/0'/1/2'/2",
+                        chain_code:
+                            "ba96f776a5c3907d7fd48bde5620ee374d4acfd540378476019eab70790c63a0",
+                        private_key:
+                            "5996c37fd3dd2679039b23ed6f70b506c6b56b3cb5e424681fb0fa64caf82aaa",
+                        public_key:
+                            "029f871f4cb9e1c97f9f4de9ccd0d4a2f2a171110c61178f84430062230833ff20",
+                    },
+                    Derived {
+                        path: "m/0'/1/2'/2/1000000000",
+                        chain_code:
+                            "b9b7b82d326bb9cb5b5b121066feea4eb93d5241103c9e7a18aad40f1dde8059",
+                        private_key:
+                            "21c4f269ef0a5fd1badf47eeacebeeaa3de22eb8e5b0adcd0f27dd99d34d0119",
+                        public_key:
+                            "02216cd26d31147f72427a453c443ed2cde8a1e53c9cc44e5ddf739725413fe3f4",
+                    },
+                ],
+            );
+        }
+
+        /// Test vector 2 for nist256p1 of SLIP-10.
+        #[test]
+        fn slip10_test_vector_2() {
+            check_vector(
+                "fffcf9f6f3f0edeae7e4e1dedbd8d5d2cfccc9c6c3c0bdbab7b4b1aeaba8a5a2\
+                 9f9c999693908d8a8784817e7b7875726f6c696663605d5a5754514e4b484542",
+                &[
+                    Derived {
+                        path: "m",
+                        chain_code:
+                            "96cd4465a9644e31528eda3592aa35eb39a9527769ce1855beafc1b81055e75d",
+                        private_key:
+                            "eaa31c2e46ca2962227cf21d73a7ef0ce8b31c756897521eb6c7b39796633357",
+                        public_key:
+                            "02c9e16154474b3ed5b38218bb0463e008f89ee03e62d22fdcc8014beab25b48fa",
+                    },
+                    Derived {
+                        path: "m/0",
+                        chain_code:
+                            "84e9c258bb8557a40e0d041115b376dd55eda99c0042ce29e81ebe4efed9b86a",
+                        private_key:
+                            "d7d065f63a62624888500cdb4f88b6d59c2927fee9e6d0cdff9cad555884df6e",
+                        public_key:
+                            "039b6df4bece7b6c81e2adfeea4bcf5c8c8a6e40ea7ffa3cf6e8494c61a1fc82cc",
+                    },
+                    Derived {
+                        path: "m/0/2147483647'",
+                        chain_code:
+                            "f235b2bc5c04606ca9c30027a84f353acf4e4683edbd11f635d0dcc1cd106ea6",
+                        private_key:
+                            "96d2ec9316746a75e7793684ed01e3d51194d81a42a3276858a5b7376d4b94b9",
+                        public_key:
+                            "02f89c5deb1cae4fedc9905f98ae6cbf6cbab120d8cb85d5bd9a91a72f4c068c76",
+                    },
+                    Derived {
+                        path: "m/0/2147483647'/1",
+                        chain_code:
+                            "7c0b833106235e452eba79d2bdd58d4086e663bc8cc55e9773d2b5eeda313f3b",
+                        private_key:
+                            "974f9096ea6873a915910e82b29d7c338542ccde39d2064d1cc228f371542bbc",
+                        public_key:
+                            "03abe0ad54c97c1d654c1852dfdc32d6d3e487e75fa16f0fd6304b9ceae4220c64",
+                    },
+                    Derived {
+                        path: "m/0/2147483647'/1/2147483646'",
+                        chain_code:
+                            "5794e616eadaf33413aa309318a26ee0fd5163b70466de7a4512fd4b1a5c9e6a",
+                        private_key:
+                            "da29649bbfaff095cd43819eda9a7be74236539a29094cd8336b07ed8d4eff63",
+                        public_key:
+                            "03cb8cb067d248691808cd6b5a5a06b48e34ebac4d965cba33e6dc46fe13d9b933",
+                    },
+                    Derived {
+                        path: "m/0/2147483647'/1/2147483646'/2",
+                        chain_code:
+                            "3bfb29ee8ac4484f09db09c2079b520ea5616df7820f071a20320366fbe226a7",
+                        private_key:
+                            "bb0a77ba01cc31d77205d51d08bd313b979a71ef4de9b062f8958297e746bd67",
+                        public_key:
+                            "020ee02e18967237cf62672983b253ee62fa4dd431f8243bfeccdf39dbe181387f",
+                    },
+                ],
+            );
+        }
+
+        /// SLIP-10 vectors where the first candidate child key is invalid, and
+        /// where the first candidate master key is.
+        #[test]
+        fn slip10_retries_invalid_keys() {
+            check_vector(
+                "000102030405060708090a0b0c0d0e0f",
+                &[
+                    Derived {
+                        path: "m/28578'",
+                        chain_code:
+                            "e94c8ebe30c2250a14713212f6449b20f3329105ea15b652ca5bdfc68f6c65c2",
+                        private_key:
+                            "06f0db126f023755d0b8d86d4591718a5210dd8d024e3e14b6159d63f53aa669",
+                        public_key:
+                            "02519b5554a4872e8c9c1c847115363051ec43e93400e030ba3c36b52a3e70a5b7",
+                    },
+                    Derived {
+                        path: "m/28578'/33941",
+                        chain_code:
+                            "9e87fe95031f14736774cd82f25fd885065cb7c358c1edf813c72af535e83071",
+                        private_key:
+                            "092154eed4af83e078ff9b84322015aefe5769e31270f62c3f66c33888335f3a",
+                        public_key:
+                            "0235bfee614c0d5b2cae260000bb1d0d84b270099ad790022c1ae0b2e782efe120",
+                    },
+                ],
+            );
+            check_vector(
+                "a7305bc8df8d0951f0cb224c0e95d7707cbdf2c6ce7e8d481fec69c7ff5e9446",
+                &[Derived {
+                    path: "m",
+                    chain_code: "7762f9729fed06121fd13f326884c82f59aa95c57ac492ce8c9654e60efd130c",
+                    private_key: "3b8c18469a4634517d6d0b65448f8e6c62091b45540a1743c5846be55d47d88f",
+                    public_key:
+                        "0383619fadcde31063d8c5cb00dbfe1713f3e6fa169d8541a798752a1c1ca0cb20",
+                }],
+            );
+        }
+
+        #[test]
+        fn derive_path_matches_derive_child() {
+            let master = ExtendedKey::master(&[7; 32]);
+            let by_path = master.derive_path("m/44'/1'/0'/0/7").unwrap();
+            let by_child = [
+                44 + HARDENED_OFFSET,
+                1 + HARDENED_OFFSET,
+                HARDENED_OFFSET,
+                0,
+                7,
+            ]
+            .into_iter()
+            .fold(master, |key, index| key.derive_child(index));
+            assert_eq!(by_path.chain_code, by_child.chain_code);
+            assert_eq!(by_path.signing_key, by_child.signing_key);
+        }
+    }
+}
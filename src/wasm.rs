@@ -0,0 +1,50 @@
+// This module exposes the verification of inclusion proofs and headers to
+// JavaScript through wasm-bindgen
+pub mod wasm {
+    use serde::de::DeserializeOwned;
+    use wasm_bindgen::prelude::*;
+
+    use crate::{
+        error::error::SimulatorError,
+        model::blockchain::{Header, HeaderChain, HeaderFormat, InclusionProof},
+    };
+
+    fn parse<T: DeserializeOwned>(json: &str) -> Result<T, JsError> {
+        serde_json::from_str(json).map_err(|error| JsError::new(&error.to_string()))
+    }
+
+    fn to_js_error(error: SimulatorError) -> JsError {
+        JsError::new(&error.to_string())
+    }
+
+    /// Verifies an inclusion proof given as JSON, in the format written by
+    /// `GenerateInclusionProof`. Throws if the proof doesn't lead to its
+    /// merkle root.
+    #[wasm_bindgen(js_name = verifyInclusionProof)]
+    pub fn verify_inclusion_proof(proof: &str) -> Result<(), JsError> {
+        parse::<InclusionProof>(proof)?
+            .verify()
+            .map(|_| ())
+            .map_err(to_js_error)
+    }
+
+    /// Checks that a header given as JSON hashes to its stored hash in the
+    /// simulator header format and satisfies the proof of work required by
+    /// its difficulty.
+    #[wasm_bindgen(js_name = validateHeader)]
+    pub fn validate_header(header: &str) -> Result<(), JsError> {
+        parse::<Header>(header)?
+            .validate(HeaderFormat::Simulator)
+            .map_err(to_js_error)
+    }
+
+    /// Checks a header chain given as JSON, in the format written by
+    /// `ExportHeaders` in the simulator header format, from the genesis block
+    /// onwards.
+    #[wasm_bindgen(js_name = verifyHeaderChain)]
+    pub fn verify_header_chain(headers: &str) -> Result<(), JsError> {
+        parse::<HeaderChain>(headers)?
+            .verify(HeaderFormat::Simulator)
+            .map_err(to_js_error)
+    }
+}